@@ -0,0 +1,313 @@
+use std::cmp::Ordering;
+
+use crate::date::parse_date_time;
+use crate::lookup::wildcard_match;
+use crate::range::CellRange;
+use crate::spreadsheet::Spreadsheet;
+
+/// A COUNTIF-style criterion: `10`, `">=10"`, `"<>apple"`, `"app*"` or `">2024-01-31"`
+pub struct Criterion {
+    op: &'static str,
+    operand: String,
+    number: Option<f64>,
+}
+
+/// A value as a number, reading text dates and times as their serials
+fn criteria_number(value: &str) -> Option<f64> {
+    let value = value.trim();
+    value.parse().ok().or_else(|| parse_date_time(value).map(|(serial, _)| serial))
+}
+
+impl Criterion {
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let text = text
+            .strip_prefix('"')
+            .and_then(|t| t.strip_suffix('"'))
+            .unwrap_or(text);
+        let op = [">=", "<=", "<>", ">", "<", "="]
+            .into_iter()
+            .find(|op| text.starts_with(op))
+            .unwrap_or("");
+        let operand = text[op.len()..].trim().to_string();
+        let number = criteria_number(&operand);
+        Self { op, operand, number }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        let value = value.trim();
+        let equal = || match self.number {
+            _ if self.operand.is_empty() => value.is_empty(),
+            Some(n) => criteria_number(value).is_some_and(|v| (v - n).abs() < 1e-9),
+            None => criteria_number(value).is_none() && wildcard_match(&self.operand, value),
+        };
+        let ordering = || match self.number {
+            Some(n) => criteria_number(value).and_then(|v| v.partial_cmp(&n)),
+            // Text compares only with text
+            None if value.is_empty() || criteria_number(value).is_some() => None,
+            None => Some(value.to_lowercase().cmp(&self.operand.to_lowercase())),
+        };
+        match self.op {
+            "" | "=" => equal(),
+            "<>" => !equal(),
+            ">" => ordering() == Some(Ordering::Greater),
+            "<" => ordering() == Some(Ordering::Less),
+            ">=" => matches!(ordering(), Some(Ordering::Greater | Ordering::Equal)),
+            _ => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+        }
+    }
+}
+
+/// What a *IFS function does with the values its criteria pick out
+#[derive(Clone, Copy, PartialEq)]
+enum IfsAggregate {
+    Count,
+    Sum,
+    Average,
+    Max,
+    Min,
+}
+
+impl Spreadsheet {
+    pub fn cell_matches_criteria(&self, cell_value: &str, criteria: &str, _current_row: usize, _current_col: usize) -> bool {
+        Criterion::parse(criteria).matches(cell_value)
+    }
+
+    /// Positions (row-major offsets) where every range matches its criterion. All ranges
+    /// must be the same shape as `shape`.
+    fn matching_positions(&mut self, pairs: &[&str], shape: Option<CellRange>, current_row: usize, current_col: usize) -> Result<(CellRange, Vec<usize>), String> {
+        if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+            return Err("#ERROR".to_string());
+        }
+        let mut shape = shape;
+        let mut tests = Vec::new();
+        for pair in pairs.chunks(2) {
+            let range = self.resolve_range(pair[0], current_row, current_col)?;
+            let expected = *shape.get_or_insert(range);
+            if (range.rows(), range.cols()) != (expected.rows(), expected.cols()) {
+                return Err("#VALUE!".to_string());
+            }
+            let criterion = Criterion::parse(&self.evaluate_arg(pair[1], current_row, current_col));
+            tests.push((range.cells(), criterion));
+        }
+
+        let shape = shape.expect("at least one criteria range");
+        let mut positions = Vec::new();
+        for index in 0..shape.rows() * shape.cols() {
+            let mut matched = true;
+            for (cells, criterion) in &tests {
+                let (row, col) = cells[index];
+                if !criterion.matches(&self.evaluate_cell(row, col)) {
+                    matched = false;
+                    break;
+                }
+            }
+            if matched {
+                positions.push(index);
+            }
+        }
+        Ok((shape, positions))
+    }
+
+    /// Aggregate the numbers in `values` at the matched positions
+    fn aggregate_matches(&mut self, values: CellRange, positions: &[usize], aggregate: IfsAggregate) -> String {
+        if aggregate == IfsAggregate::Count {
+            return format!("{}", positions.len());
+        }
+        let cells = values.cells();
+        let numbers: Vec<f64> = positions
+            .iter()
+            .filter_map(|&i| self.get_cell_value_at(cells[i].0, cells[i].1))
+            .collect();
+        let result = match aggregate {
+            IfsAggregate::Average if numbers.is_empty() => return "#DIV/0!".to_string(),
+            IfsAggregate::Average => numbers.iter().sum::<f64>() / numbers.len() as f64,
+            // MAXIFS and MINIFS give 0 when nothing matches, as Excel does
+            _ if numbers.is_empty() => 0.0,
+            IfsAggregate::Max => numbers.iter().copied().fold(f64::MIN, f64::max),
+            IfsAggregate::Min => numbers.iter().copied().fold(f64::MAX, f64::min),
+            _ => numbers.iter().sum(),
+        };
+        format!("{}", result)
+    }
+
+    /// COUNTIF(range, criteria), SUMIF(range, criteria, [sum_range]) and AVERAGEIF(range,
+    /// criteria, [average_range]). A value range of a different size is resized from its
+    /// top-left cell to match the criteria range, as Excel does.
+    fn evaluate_if_aggregate(&mut self, args: &str, current_row: usize, current_col: usize, aggregate: IfsAggregate) -> String {
+        let parts = self.split_function_args(args);
+        let max_args = if aggregate == IfsAggregate::Count { 2 } else { 3 };
+        if !(2..=max_args).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let (shape, positions) = match self.matching_positions(&parts[..2], None, current_row, current_col) {
+            Ok(matches) => matches,
+            Err(e) => return e,
+        };
+        let values = match parts.get(2) {
+            Some(part) => match self.resolve_range(part, current_row, current_col) {
+                Ok(range) => range.offset(0, 0, Some(shape.rows()), Some(shape.cols())).unwrap_or(range),
+                Err(e) => return e,
+            },
+            None => shape,
+        };
+        self.aggregate_matches(values, &positions, aggregate)
+    }
+
+    /// COUNTIFS(range1, criteria1, [range2, criteria2], ...)
+    fn evaluate_countifs(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        match self.matching_positions(&parts, None, current_row, current_col) {
+            Ok((shape, positions)) => self.aggregate_matches(shape, &positions, IfsAggregate::Count),
+            Err(e) => e,
+        }
+    }
+
+    /// SUMIFS, AVERAGEIFS, MAXIFS and MINIFS(value_range, range1, criteria1, [range2, criteria2], ...)
+    fn evaluate_ifs_aggregate(&mut self, args: &str, current_row: usize, current_col: usize, aggregate: IfsAggregate) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() < 3 {
+            return "#ERROR".to_string();
+        }
+        let values = match self.resolve_range(parts[0], current_row, current_col) {
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+        match self.matching_positions(&parts[1..], Some(values), current_row, current_col) {
+            Ok((_, positions)) => self.aggregate_matches(values, &positions, aggregate),
+            Err(e) => e,
+        }
+    }
+
+    pub fn evaluate_countif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        self.evaluate_if_aggregate(args, current_row, current_col, IfsAggregate::Count)
+    }
+
+    pub fn evaluate_sumif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        self.evaluate_if_aggregate(args, current_row, current_col, IfsAggregate::Sum)
+    }
+
+    pub fn evaluate_averageif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        self.evaluate_if_aggregate(args, current_row, current_col, IfsAggregate::Average)
+    }
+
+    /// The *IFS family by name: COUNTIFS, SUMIFS, AVERAGEIFS, MAXIFS or MINIFS
+    pub fn evaluate_ifs(&mut self, name: &str, args: &str, current_row: usize, current_col: usize) -> String {
+        let aggregate = match name {
+            "COUNTIFS" => return self.evaluate_countifs(args, current_row, current_col),
+            "SUMIFS" => IfsAggregate::Sum,
+            "AVERAGEIFS" => IfsAggregate::Average,
+            "MAXIFS" => IfsAggregate::Max,
+            _ => IfsAggregate::Min,
+        };
+        self.evaluate_ifs_aggregate(args, current_row, current_col, aggregate)
+    }
+
+    /// SUMPRODUCT(array1, [array2], ...) - multiply corresponding cells of equally-shaped
+    /// ranges or arrays, such as `(A1:A9="x")*B1:B9`, and add up the products; text, blanks
+    /// and TRUE/FALSE count as 0
+    pub fn evaluate_sumproduct(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.is_empty() {
+            return "#ERROR".to_string();
+        }
+        let mut arrays = Vec::new();
+        for part in &parts {
+            let array = match self.resolve_range(part, current_row, current_col) {
+                Ok(range) => Ok(self.range_array(range)),
+                Err(_) => self.evaluate_array_expression(part, current_row, current_col),
+            };
+            match array {
+                Ok(array) => arrays.push(array),
+                Err(e) => return e,
+            }
+        }
+        let shape = (arrays[0].len(), arrays[0][0].len());
+        if arrays.iter().any(|array| (array.len(), array[0].len()) != shape) {
+            return "#VALUE!".to_string();
+        }
+        let mut sum = 0.0;
+        for row in 0..shape.0 {
+            for col in 0..shape.1 {
+                let mut product = 1.0;
+                for array in &arrays {
+                    let value = &array[row][col];
+                    if value.starts_with('#') {
+                        return value.clone();
+                    }
+                    product *= value.parse::<f64>().unwrap_or(0.0);
+                }
+                sum += product;
+            }
+        }
+        format!("{}", sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_criterion_matching() {
+        assert!(Criterion::parse("\">=10\"").matches("10"));
+        assert!(!Criterion::parse(">=10").matches("apple"));
+        assert!(Criterion::parse("<>apple").matches("Banana"));
+        assert!(!Criterion::parse("<>APPLE").matches("apple"));
+        assert!(Criterion::parse("<>").matches("x"));
+        assert!(!Criterion::parse("<>").matches(""));
+        assert!(Criterion::parse("").matches(""));
+        assert!(Criterion::parse("a*e").matches("Apple"));
+        assert!(!Criterion::parse("a*e").matches("Apples"));
+        assert!(Criterion::parse("?pple").matches("apple"));
+        assert!(Criterion::parse("50~%").matches("50%"));
+        assert!(Criterion::parse(">b").matches("cherry"));
+        assert!(!Criterion::parse(">b").matches("5"));
+        assert!(Criterion::parse(">2024-01-31").matches("2024-02-01"));
+        assert!(Criterion::parse("<2024-01-31").matches("45000"));
+    }
+
+    #[test]
+    fn test_ifs_functions() {
+        let mut sheet = Spreadsheet::new();
+        let rows = [("East", "Apple", "10"), ("West", "Apple", "20"), ("East", "Pear", "30"), ("East", "Apricot", "40")];
+        for (row, (region, fruit, amount)) in rows.iter().enumerate() {
+            sheet.set_cell(row, 0, region.to_string());
+            sheet.set_cell(row, 1, fruit.to_string());
+            sheet.set_cell(row, 2, amount.to_string());
+        }
+        sheet.set_cell(0, 4, "East".to_string());
+        let eval = |sheet: &mut Spreadsheet, formula: &str| sheet.evaluate_formula(formula, 9, 9);
+        assert_eq!(eval(&mut sheet, "=SUMIFS(C1:C4,A1:A4,\"East\",B1:B4,\"Ap*\")"), "50");
+        assert_eq!(eval(&mut sheet, "=SUMIFS(C1:C4,A1:A4,E1,C1:C4,\">15\")"), "70");
+        assert_eq!(eval(&mut sheet, "=COUNTIFS(A1:A4,\"east\",C1:C4,\"<>30\")"), "2");
+        assert_eq!(eval(&mut sheet, "=AVERAGEIFS(C1:C4,B1:B4,\"A*\")"), "23.333333333333332");
+        assert_eq!(eval(&mut sheet, "=AVERAGEIFS(C1:C4,B1:B4,\"Kiwi\")"), "#DIV/0!");
+        assert_eq!(eval(&mut sheet, "=MAXIFS(C1:C4,A1:A4,\"East\")"), "40");
+        assert_eq!(eval(&mut sheet, "=MINIFS(C1:C4,A1:A4,\"East\",B1:B4,\"P*\")"), "30");
+        assert_eq!(eval(&mut sheet, "=MAXIFS(C1:C4,A1:A4,\"North\")"), "0");
+        assert_eq!(eval(&mut sheet, "=SUMIFS(C1:C4,A1:A3,\"East\")"), "#VALUE!");
+        assert_eq!(eval(&mut sheet, "=SUMIF(B1:B4,\"Ap?le\",C1)"), "30");
+    }
+
+    #[test]
+    fn test_evaluate_sumproduct() {
+        let mut sheet = Spreadsheet::new();
+        for (row, (qty, price)) in [("2", "1.5"), ("3", "2"), ("x", "4")].iter().enumerate() {
+            sheet.set_cell(row, 0, qty.to_string());
+            sheet.set_cell(row, 1, price.to_string());
+        }
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(A1:A3,B1:B3)", 5, 5), "9");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(B1:B3)", 5, 5), "7.5");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(A1:A3,B1:B2)", 5, 5), "#VALUE!");
+
+        // Conditions and arithmetic work element by element
+        sheet.set_cell(3, 0, "ab".to_string());
+        sheet.set_cell(4, 0, "5".to_string());
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT((A1:A6=\"ab\")*1)", 5, 5), "1");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(--(A1:A6=\"ab\"))", 5, 5), "1");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(A1:A6=\"ab\")", 5, 5), "0");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT(A5:A5*2)", 5, 5), "10");
+        assert_eq!(sheet.evaluate_formula("=SUMPRODUCT((A1:A2>2)*B1:B2)", 5, 5), "2");
+    }
+}
@@ -0,0 +1,543 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::expression::value_number;
+use crate::range::CellRange;
+use crate::sort::SortKey;
+use crate::spreadsheet::Spreadsheet;
+
+/// A formula result with several rows and columns, row by row. Never empty or ragged.
+pub type Array = Vec<Vec<String>>;
+
+/// Functions that return arrays; a formula made of one spills into the cells around it
+pub const ARRAY_FUNCTIONS: [&str; 9] = [
+    "FILTER", "SORT", "SORTBY", "UNIQUE", "SEQUENCE", "TRANSPOSE", "SHELL", "TEXTSPLIT", "REGEXEXTRACT",
+];
+
+/// Functions that total up their arguments, whose arguments may be computed arrays
+pub const AGGREGATES: [&str; 9] = ["SUM", "AVG", "AVERAGE", "MIN", "MAX", "COUNT", "COUNTA", "PRODUCT", "MEDIAN"];
+
+/// The most cells a single array may hold
+const MAX_ARRAY_CELLS: usize = 1_000_000;
+
+/// Spilled array formulas, worked out on demand and kept until the next `refresh_spills`
+#[derive(Default)]
+pub struct Spills {
+    /// Cells holding array formulas, row by row; found on first use
+    anchors: Option<Vec<(usize, usize)>>,
+    /// Each formula cell looked at so far: None for a plain formula, else the array it
+    /// spills or the error it shows instead
+    results: HashMap<(usize, usize), Option<Result<Array, String>>>,
+}
+
+/// Combine two arrays element by element. A single row or column stretches to match the
+/// other side; positions beyond a shorter array give #N/A.
+pub fn broadcast(left: &Array, right: &Array, op: impl Fn(&str, &str) -> String) -> Array {
+    fn element(array: &Array, row: usize, col: usize) -> Option<&str> {
+        let values = array.get(if array.len() == 1 { 0 } else { row })?;
+        values.get(if values.len() == 1 { 0 } else { col }).map(String::as_str)
+    }
+    let size = |a: usize, b: usize| if a == 1 { b } else if b == 1 { a } else { a.max(b) };
+    let rows = size(left.len(), right.len());
+    let cols = size(left[0].len(), right[0].len());
+    (0..rows)
+        .map(|row| {
+            (0..cols)
+                .map(|col| match (element(left, row, col), element(right, row, col)) {
+                    (Some(a), Some(b)) => op(a, b),
+                    _ => "#N/A".to_string(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Rows become columns
+pub fn transpose(array: &Array) -> Array {
+    (0..array[0].len()).map(|col| array.iter().map(|row| row[col].clone()).collect()).collect()
+}
+
+/// Whether a FILTER include value keeps its row
+fn is_included(value: &str) -> Result<bool, String> {
+    if value.starts_with('#') {
+        return Err(value.to_string());
+    }
+    value_number(value).map(|n| n != 0.0).ok_or_else(|| "#VALUE!".to_string())
+}
+
+impl Spreadsheet {
+    /// Forget every spill worked out so far, so array formulas are evaluated afresh
+    pub fn refresh_spills(&mut self) {
+        self.spills = Spills::default();
+    }
+
+    /// Evaluate a function that gives an array; None for any other function
+    pub fn evaluate_array_function(
+        &mut self,
+        name: &str,
+        args: &str,
+        expr: &str,
+        current_row: usize,
+        current_col: usize,
+    ) -> Option<Result<Array, String>> {
+        Some(match name {
+            "FILTER" => self.evaluate_filter(args, current_row, current_col),
+            "SORT" => self.evaluate_sort(args, current_row, current_col),
+            "SORTBY" => self.evaluate_sortby(args, current_row, current_col),
+            "UNIQUE" => self.evaluate_unique(args, current_row, current_col),
+            "SEQUENCE" => self.evaluate_sequence(args, current_row, current_col),
+            "TRANSPOSE" => self.evaluate_transpose(args, current_row, current_col),
+            "SHELL" => self.evaluate_shell(args, current_row, current_col),
+            "TEXTSPLIT" => self.textsplit_grid(args, current_row, current_col),
+            "REGEXEXTRACT" => self.regexextract_array(args, current_row, current_col),
+            "OFFSET" | "INDIRECT" | "INDEX" => {
+                let range = self.resolve_range(expr, current_row, current_col).ok()?;
+                Ok(self.range_array(range))
+            }
+            _ => return None,
+        })
+    }
+
+    /// One of the AGGREGATES when an argument is a computed array: every value of every
+    /// argument, with text and blanks skipped and the first error given back (COUNT and
+    /// COUNTA count rather than fail)
+    pub fn evaluate_array_aggregate(&mut self, name: &str, parts: &[&str], current_row: usize, current_col: usize) -> String {
+        let mut values = Vec::new();
+        for part in parts {
+            match self.evaluate_array_expression(part, current_row, current_col) {
+                Ok(array) => values.extend(array.into_iter().flatten()),
+                Err(e) if name.starts_with("COUNT") => values.push(e),
+                Err(e) => return e,
+            }
+        }
+        let numbers: Vec<String> = values.iter().filter(|value| value.parse::<f64>().is_ok()).cloned().collect();
+        match name {
+            "COUNT" => numbers.len().to_string(),
+            "COUNTA" => values.iter().filter(|value| !value.is_empty()).count().to_string(),
+            _ => match values.iter().find(|value| value.starts_with('#')) {
+                Some(error) => error.clone(),
+                // The plain functions work the numbers out the same way as for a range
+                None => {
+                    let numbers = numbers.join(",");
+                    match name {
+                        "SUM" => self.evaluate_sum(&numbers),
+                        "MIN" => self.evaluate_min(&numbers),
+                        "MAX" => self.evaluate_max(&numbers),
+                        "PRODUCT" => self.evaluate_product(&numbers),
+                        "MEDIAN" => self.evaluate_median(&numbers),
+                        _ => self.evaluate_avg(&numbers),
+                    }
+                }
+            },
+        }
+    }
+
+    /// The values in a range, as shown
+    pub fn range_array(&mut self, range: CellRange) -> Array {
+        (range.start_row..=range.end_row)
+            .map(|row| (range.start_col..=range.end_col).map(|col| self.evaluate_cell(row, col)).collect())
+            .collect()
+    }
+
+    /// Replace each spill reference (`A1#`) with the range its formula currently fills.
+    /// #REF! when a referenced cell doesn't spill.
+    pub fn expand_spill_refs(&mut self, expr: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(expr.len());
+        let mut quoted = false;
+        for c in expr.chars() {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            if c != '#' || quoted {
+                expanded.push(c);
+                continue;
+            }
+            let start = expanded
+                .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '$'))
+                .map_or(0, |i| i + 1);
+            let anchor = Self::parse_cell_reference(&expanded[start..].replace('$', ""));
+            match anchor.and_then(|(row, col)| self.spill_range(row, col)) {
+                Some(range) => {
+                    expanded.truncate(start);
+                    expanded.push_str(&format!(
+                        "{}{}:{}{}",
+                        Self::col_name(range.start_col),
+                        range.start_row + 1,
+                        Self::col_name(range.end_col),
+                        range.end_row + 1
+                    ));
+                }
+                None if anchor.is_some() => return Err("#REF!".to_string()),
+                None => expanded.push(c),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// The array a formula cell spills, or the error it shows; None when the cell doesn't
+    /// hold an array formula
+    pub fn spill(&mut self, row: usize, col: usize) -> Option<&Result<Array, String>> {
+        if !self.spills.results.contains_key(&(row, col)) {
+            let content = self.get_cell(row, col).to_string();
+            let expr = content.strip_prefix('=').map(str::trim).filter(|expr| self.is_array_expression(expr));
+            let result = match expr {
+                Some(expr) => {
+                    // Anything that reads this cell while it is being worked out sees #REF!
+                    self.spills.results.insert((row, col), Some(Err("#REF!".to_string())));
+                    let result = self
+                        .expand_names(expr)
+                        .and_then(|expr| self.expand_spill_refs(&expr))
+                        .and_then(|expr| self.evaluate_array_expression(&expr, row, col))
+                        .and_then(|array| self.place_spill(row, col, array));
+                    Some(result)
+                }
+                None => None,
+            };
+            self.spills.results.insert((row, col), result);
+        }
+        self.spills.results.get(&(row, col)).and_then(Option::as_ref)
+    }
+
+    /// Check an array fits below and right of its formula: #SPILL! when a cell in the way
+    /// has content or already shows an earlier formula's spill
+    fn place_spill(&mut self, row: usize, col: usize, array: Array) -> Result<Array, String> {
+        if array.len() * array[0].len() > MAX_ARRAY_CELLS {
+            return Err("#NUM!".to_string());
+        }
+        let area = CellRange::new((row, col), (row + array.len() - 1, col + array[0].len() - 1));
+        if area.cells().into_iter().any(|cell| cell != (row, col) && !self.get_cell(cell.0, cell.1).is_empty()) {
+            return Err("#SPILL!".to_string());
+        }
+        for anchor in self.spill_anchors() {
+            if anchor >= (row, col) {
+                break;
+            }
+            if let Some(other) = self.spill_range(anchor.0, anchor.1)
+                && other.start_row <= area.end_row
+                && other.end_row >= area.start_row
+                && other.start_col <= area.end_col
+                && other.end_col >= area.start_col
+            {
+                return Err("#SPILL!".to_string());
+            }
+        }
+        self.num_rows = self.num_rows.max(area.end_row + 1);
+        self.num_cols = self.num_cols.max(area.end_col + 1);
+        Ok(array)
+    }
+
+    /// Every cell holding an array formula, row by row
+    fn spill_anchors(&mut self) -> Vec<(usize, usize)> {
+        if self.spills.anchors.is_none() {
+            let mut anchors: Vec<(usize, usize)> = self
+                .cells
+                .iter()
+                .filter(|(_, content)| content.strip_prefix('=').is_some_and(|expr| self.is_array_expression(expr)))
+                .map(|(&cell, _)| cell)
+                .collect();
+            anchors.sort();
+            self.spills.anchors = Some(anchors);
+        }
+        self.spills.anchors.clone().unwrap_or_default()
+    }
+
+    /// The cells a formula's array fills, its own cell included
+    pub fn spill_range(&mut self, row: usize, col: usize) -> Option<CellRange> {
+        match self.spill(row, col) {
+            Some(Ok(array)) => Some(CellRange::new((row, col), (row + array.len() - 1, col + array[0].len() - 1))),
+            _ => None,
+        }
+    }
+
+    /// The array formula whose spill covers a cell, if any
+    pub fn spill_anchor(&mut self, row: usize, col: usize) -> Option<(usize, usize)> {
+        self.spill_anchors()
+            .into_iter()
+            .take_while(|&(anchor_row, _)| anchor_row <= row)
+            .filter(|&(_, anchor_col)| anchor_col <= col)
+            .find(|&(anchor_row, anchor_col)| {
+                self.spill_range(anchor_row, anchor_col)
+                    .is_some_and(|range| range.end_row >= row && range.end_col >= col)
+            })
+    }
+
+    /// The value an array formula spills into an otherwise empty cell
+    pub fn spilled_value(&mut self, row: usize, col: usize) -> Option<String> {
+        let (anchor_row, anchor_col) = self.spill_anchor(row, col)?;
+        match self.spill(anchor_row, anchor_col) {
+            Some(Ok(array)) => Some(array[row - anchor_row][col - anchor_col].clone()),
+            _ => None,
+        }
+    }
+
+    /// SEQUENCE(rows, [columns], [start], [step])
+    pub fn evaluate_sequence(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if parts.len() > 4 || parts[0].trim().is_empty() {
+            return Err("#ERROR".to_string());
+        }
+        let mut numbers = [1.0; 4];
+        for (number, part) in numbers.iter_mut().zip(&parts) {
+            if !part.trim().is_empty() {
+                *number = self
+                    .evaluate_arg_as_number(part, current_row, current_col)
+                    .ok_or_else(|| "#VALUE!".to_string())?;
+            }
+        }
+        let [rows, cols, start, step] = numbers;
+        let (rows, cols) = (rows.trunc(), cols.trunc());
+        if rows < 1.0 || cols < 1.0 {
+            return Err("#VALUE!".to_string());
+        }
+        if rows * cols > MAX_ARRAY_CELLS as f64 {
+            return Err("#NUM!".to_string());
+        }
+        let (rows, cols) = (rows as usize, cols as usize);
+        Ok((0..rows)
+            .map(|row| (0..cols).map(|col| format!("{}", start + step * (row * cols + col) as f64)).collect())
+            .collect())
+    }
+
+    /// TRANSPOSE(array)
+    pub fn evaluate_transpose(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if parts.len() != 1 {
+            return Err("#ERROR".to_string());
+        }
+        Ok(transpose(&self.evaluate_array_expression(parts[0], current_row, current_col)?))
+    }
+
+    /// UNIQUE(array, [by_col], [exactly_once]) - distinct rows (or columns), ignoring case,
+    /// in the order they first appear
+    pub fn evaluate_unique(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if !(1..=3).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let array = self.evaluate_array_expression(parts[0], current_row, current_col)?;
+        let by_col = self.optional_bool(parts.get(1), false, current_row, current_col)?;
+        let exactly_once = self.optional_bool(parts.get(2), false, current_row, current_col)?;
+
+        let lines = if by_col { transpose(&array) } else { array };
+        let key = |line: &Vec<String>| line.iter().map(|value| value.to_lowercase()).collect::<Vec<_>>();
+        let mut counts: HashMap<Vec<String>, usize> = HashMap::new();
+        for line in &lines {
+            *counts.entry(key(line)).or_default() += 1;
+        }
+        let mut seen = Vec::new();
+        let unique: Array = lines
+            .into_iter()
+            .filter(|line| {
+                let key = key(line);
+                let keep = !seen.contains(&key) && (!exactly_once || counts[&key] == 1);
+                seen.push(key);
+                keep
+            })
+            .collect();
+        if unique.is_empty() {
+            return Err("#CALC!".to_string());
+        }
+        Ok(if by_col { transpose(&unique) } else { unique })
+    }
+
+    /// SORT(array, [sort_index], [sort_order], [by_col]) - sort order 1 is ascending, -1
+    /// descending. Numbers sort before text and blanks go last.
+    pub fn evaluate_sort(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if !(1..=4).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let array = self.evaluate_array_expression(parts[0], current_row, current_col)?;
+        let index = self.optional_int(parts.get(1), 1, current_row, current_col)?;
+        let order = self.optional_int(parts.get(2), 1, current_row, current_col)?;
+        let by_col = self.optional_bool(parts.get(3), false, current_row, current_col)?;
+
+        let mut lines = if by_col { transpose(&array) } else { array };
+        if index < 1 || index as usize > lines[0].len() || (order != 1 && order != -1) {
+            return Err("#VALUE!".to_string());
+        }
+        let key = SortKey { descending: order == -1, ..SortKey::new(index as usize - 1) };
+        lines.sort_by(|a, b| key.compare(&a[key.col], &b[key.col]));
+        Ok(if by_col { transpose(&lines) } else { lines })
+    }
+
+    /// SORTBY(array, by_array1, [sort_order1], [by_array2, sort_order2], ...) - sort rows by
+    /// other columns of the same height
+    pub fn evaluate_sortby(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if parts.len() < 2 {
+            return Err("#ERROR".to_string());
+        }
+        let array = self.evaluate_array_expression(parts[0], current_row, current_col)?;
+        let mut keys = Vec::new();
+        for pair in parts[1..].chunks(2) {
+            let by = self.evaluate_array_expression(pair[0], current_row, current_col)?;
+            let values: Vec<String> = by.iter().flatten().cloned().collect();
+            if (by.len() > 1 && by[0].len() > 1) || values.len() != array.len() {
+                return Err("#VALUE!".to_string());
+            }
+            let order = self.optional_int(pair.get(1), 1, current_row, current_col)?;
+            if order != 1 && order != -1 {
+                return Err("#VALUE!".to_string());
+            }
+            keys.push((values, SortKey { descending: order == -1, ..SortKey::new(0) }));
+        }
+
+        let mut positions: Vec<usize> = (0..array.len()).collect();
+        positions.sort_by(|&a, &b| {
+            keys.iter()
+                .map(|(values, key)| key.compare(&values[a], &values[b]))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(positions.into_iter().map(|i| array[i].clone()).collect())
+    }
+
+    /// FILTER(array, include, [if_empty]) - the rows whose include value is TRUE or non-zero.
+    /// An include row as wide as the array picks columns instead.
+    pub fn evaluate_filter(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let array = self.evaluate_array_expression(parts[0], current_row, current_col)?;
+        let include = self.evaluate_array_expression(parts[1], current_row, current_col)?;
+
+        let by_col = include.len() == 1 && include[0].len() == array[0].len() && array.len() != 1;
+        let flags: Vec<String> = include.iter().flatten().cloned().collect();
+        let lines = if by_col { transpose(&array) } else { array };
+        if (include.len() > 1 && include[0].len() > 1) || flags.len() != lines.len() {
+            return Err("#VALUE!".to_string());
+        }
+        let mut kept = Vec::new();
+        for (line, flag) in lines.into_iter().zip(&flags) {
+            if is_included(flag)? {
+                kept.push(line);
+            }
+        }
+        if kept.is_empty() {
+            return match parts.get(2) {
+                Some(part) => self.evaluate_array_expression(part, current_row, current_col),
+                None => Err("#CALC!".to_string()),
+            };
+        }
+        Ok(if by_col { transpose(&kept) } else { kept })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet_with(cells: &[(&str, &str)]) -> Spreadsheet {
+        let mut sheet = Spreadsheet::new();
+        for (cell, value) in cells {
+            let (row, col) = Spreadsheet::parse_cell_reference(cell).unwrap();
+            sheet.set_cell(row, col, value.to_string());
+        }
+        sheet
+    }
+
+    fn array(rows: &[&[&str]]) -> Array {
+        rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn test_array_arithmetic_broadcasts() {
+        let mut sheet = sheet_with(&[("A1", "1"), ("A2", "2"), ("A3", "3"), ("B1", "10"), ("B2", "20"), ("B3", "x")]);
+        let mut eval = |expr: &str| sheet.evaluate_array_expression(expr, 9, 9);
+
+        assert_eq!(eval("A1:A3*B1:B3"), Ok(array(&[&["10"], &["40"], &["#VALUE!"]])));
+        assert_eq!(eval("A1:A3*10+1"), Ok(array(&[&["11"], &["21"], &["31"]])));
+        assert_eq!(eval("-A1:A2"), Ok(array(&[&["-1"], &["-2"]])));
+        assert_eq!(eval("A1:A3>=2"), Ok(array(&[&["FALSE"], &["TRUE"], &["TRUE"]])));
+        assert_eq!(eval("(A1:A3>1)*(A1:A3<3)"), Ok(array(&[&["0"], &["1"], &["0"]])));
+        assert_eq!(eval("A1:A2+B1:B3"), Ok(array(&[&["11"], &["22"], &["#N/A"]])));
+        assert_eq!(eval("A1:A2*TRANSPOSE(A1:A3)"), Ok(array(&[&["1", "2", "3"], &["2", "4", "6"]])));
+        assert_eq!(eval("A1:A2/0"), Ok(array(&[&["#DIV/0!"], &["#DIV/0!"]])));
+    }
+
+    #[test]
+    fn test_array_functions() {
+        let mut sheet = sheet_with(&[
+            ("A1", "pear"), ("A2", "Apple"), ("A3", "pear"), ("A4", "fig"),
+            ("B1", "3"), ("B2", "1"), ("B3", "2"), ("B4", "5"),
+        ]);
+        let mut eval = |expr: &str| sheet.evaluate_array_expression(expr, 9, 9);
+
+        assert_eq!(eval("SEQUENCE(2,3)"), Ok(array(&[&["1", "2", "3"], &["4", "5", "6"]])));
+        assert_eq!(eval("SEQUENCE(3,,10,-5)"), Ok(array(&[&["10"], &["5"], &["0"]])));
+        assert_eq!(eval("SEQUENCE(0)"), Err("#VALUE!".to_string()));
+        assert_eq!(eval("TRANSPOSE(B1:B3)"), Ok(array(&[&["3", "1", "2"]])));
+        assert_eq!(eval("UNIQUE(A1:A4)"), Ok(array(&[&["pear"], &["Apple"], &["fig"]])));
+        assert_eq!(eval("UNIQUE(A1:A4,FALSE,TRUE)"), Ok(array(&[&["Apple"], &["fig"]])));
+        assert_eq!(eval("SORT(A1:B4,2,-1)"), Ok(array(&[&["fig", "5"], &["pear", "3"], &["pear", "2"], &["Apple", "1"]])));
+        assert_eq!(eval("SORT(A1:A4)"), Ok(array(&[&["Apple"], &["fig"], &["pear"], &["pear"]])));
+        assert_eq!(eval("SORT(A1:B4,3)"), Err("#VALUE!".to_string()));
+        assert_eq!(eval("SORTBY(A1:A4,A1:A4,1,B1:B4,-1)"), Ok(array(&[&["Apple"], &["fig"], &["pear"], &["pear"]])));
+        assert_eq!(eval("SORTBY(B1:B4,A1:A4,1,B1:B4,-1)"), Ok(array(&[&["1"], &["5"], &["3"], &["2"]])));
+        assert_eq!(eval("FILTER(A1:B4,B1:B4>2)"), Ok(array(&[&["pear", "3"], &["fig", "5"]])));
+        assert_eq!(eval("FILTER(A1:A4,(A1:A4=\"pear\")*(B1:B4<3))"), Ok(array(&[&["pear"]])));
+        assert_eq!(eval("FILTER(A1:A4,B1:B4>9,\"none\")"), Ok(array(&[&["none"]])));
+        assert_eq!(eval("FILTER(A1:A4,B1:B4>9)"), Err("#CALC!".to_string()));
+        assert_eq!(eval("FILTER(A1:A4,B1:B2>1)"), Err("#VALUE!".to_string()));
+        assert_eq!(sheet.evaluate_formula("=LARGE(FILTER(B1:B4,B1:B4>2),2)", 9, 9), "3");
+        assert_eq!(sheet.evaluate_formula("=SORT(B1:B4)", 9, 9), "1");
+    }
+
+    #[test]
+    fn test_aggregates_over_arrays() {
+        let mut sheet = sheet_with(&[("A1", "5"), ("A2", "20"), ("A3", "30"), ("B1", "x"), ("B2", "#N/A")]);
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 9, 9);
+        assert_eq!(eval("=SUM(FILTER(A1:A3,A1:A3>10))"), "50");
+        assert_eq!(eval("=SUM(A1:A3*2)"), "110");
+        assert_eq!(eval("=SUM(SEQUENCE(4), 1)"), "11");
+        assert_eq!(eval("=COUNT(SEQUENCE(4))"), "4");
+        assert_eq!(eval("=COUNTA(FILTER(B1:B2,A1:A2>0))"), "2");
+        assert_eq!(eval("=AVERAGE(UNIQUE(A1:A3))"), "18.333333333333332");
+        assert_eq!(eval("=AVERAGE(A1:A3)"), "18.333333333333332");
+        assert_eq!(eval("=MAX(A1:A3-1)"), "29");
+        assert_eq!(eval("=MEDIAN(SEQUENCE(3,1,10))"), "11");
+        // Errors inside the array come through
+        assert_eq!(eval("=SUM(B1:B2&\"\")"), "#N/A");
+    }
+
+    #[test]
+    fn test_spill_ranges() {
+        let mut sheet = sheet_with(&[("A1", "=SEQUENCE(3)"), ("B1", "=SUM(A1#)"), ("C1", "=A1#*2")]);
+
+        assert_eq!(sheet.evaluate_cell(0, 0), "1");
+        assert_eq!(sheet.evaluate_cell(2, 0), "3");
+        assert_eq!(sheet.evaluate_cell(3, 0), "");
+        assert_eq!(sheet.evaluate_cell(0, 1), "6");
+        assert_eq!(sheet.evaluate_cell(2, 2), "6");
+        assert_eq!(sheet.get_cell_value_at(1, 2), Some(4.0));
+        assert_eq!(sheet.spill_anchor(1, 2), Some((0, 2)));
+        assert_eq!(sheet.spill_range(0, 0), sheet.parse_range("A1:A3"));
+
+        // Content in the way blocks the spill, and the spill reference with it
+        sheet.set_cell(1, 0, "x".to_string());
+        assert_eq!(sheet.evaluate_cell(0, 0), "#SPILL!");
+        assert_eq!(sheet.evaluate_cell(2, 0), "");
+        assert_eq!(sheet.evaluate_cell(0, 1), "#REF!");
+
+        // So does an earlier formula's spill
+        let mut sheet = sheet_with(&[("B1", "=SEQUENCE(3)"), ("A2", "=SEQUENCE(1,2)")]);
+        assert_eq!(sheet.evaluate_cell(1, 0), "#SPILL!");
+        assert_eq!(sheet.evaluate_cell(1, 1), "2");
+        sheet.set_cell(1, 0, "=SEQUENCE(2)".to_string());
+        assert_eq!(sheet.evaluate_cell(2, 0), "2");
+
+        // SHELL output spills as a table instead of overwriting cells
+        if cfg!(unix) {
+            let mut sheet = sheet_with(&[("A1", "=SHELL(\"printf 'a 1\\nb 2\\nc'\")")]);
+            assert_eq!(sheet.evaluate_cell(0, 0), "#BUSY!");
+            sheet.wait_for_shell();
+            assert_eq!(sheet.evaluate_cell(0, 0), "a");
+            assert_eq!(sheet.evaluate_cell(1, 1), "2");
+            assert_eq!(sheet.evaluate_cell(2, 0), "c");
+            assert!(sheet.get_cell(0, 0).starts_with("=SHELL("));
+            assert!(sheet.get_cell(1, 1).is_empty());
+        }
+    }
+}
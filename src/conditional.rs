@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+
+use ratatui::style::Color;
+
+use crate::aggregate::Criterion;
+use crate::expression::value_bool;
+use crate::spreadsheet::Spreadsheet;
+use crate::theme::{color_to_rgb, color_to_string, parse_color};
+
+/// Excel's "light red fill with dark red text", used when a rule names no colours
+const DEFAULT_RULE_BG: Color = Color::Rgb(255, 199, 206);
+const DEFAULT_RULE_FG: Color = Color::Rgb(156, 0, 6);
+const DEFAULT_BAR: Color = Color::Rgb(99, 142, 198);
+const DEFAULT_STRIPE: Color = Color::Rgb(250, 250, 250);
+
+/// Partial blocks for the fractional end of a data bar, in eighths
+const BAR_EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// When a conditional rule applies to a cell
+#[derive(Clone, PartialEq, Debug)]
+pub enum RuleCondition {
+    /// `>100`, `<=0`, `=done`, `<>x` against the evaluated value
+    Compare(String, String),
+    Between(f64, f64),
+    /// Case-insensitive substring
+    Contains(String),
+    /// The N largest (or smallest) numbers in the range
+    Top { count: usize, bottom: bool },
+    Duplicates,
+    Unique,
+    /// A formula written for the range's top-left cell, e.g. `=$C1>100`
+    Formula(String),
+    /// Every other row of the range, starting with the first
+    Stripes,
+    /// Background shaded between two or three colours from the lowest to the highest number
+    ColorScale(Vec<Color>),
+    /// A bar of block characters proportional to the number
+    DataBar(Color),
+}
+
+/// A conditional format attached to a range
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConditionalRule {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub condition: RuleCondition,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+}
+
+/// What the matching rules do to one cell
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ConditionalFormat {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    /// Bar length as a fraction of the cell, and its colour
+    pub bar: Option<(f64, Color)>,
+}
+
+/// Range-wide numbers a rule needs (top N, duplicates, scales), gathered once per frame
+#[derive(Default)]
+pub struct RuleStats {
+    /// Numbers in the range, sorted ascending
+    numbers: Vec<f64>,
+    /// How often each (lowercased) value appears
+    counts: HashMap<String, usize>,
+}
+
+fn parse_range(token: &str) -> Option<((usize, usize), (usize, usize))> {
+    let (start, end) = token.split_once(':').unwrap_or((token, token));
+    let start = Spreadsheet::parse_cell_reference(start)?;
+    let end = Spreadsheet::parse_cell_reference(end)?;
+    Some(((start.0.min(end.0), start.1.min(end.1)), (start.0.max(end.0), start.1.max(end.1))))
+}
+
+fn cell_name((row, col): (usize, usize)) -> String {
+    format!("{}{}", Spreadsheet::col_name(col), row + 1)
+}
+
+/// Mix two colours, `t` of the way from `a` to `b`
+fn blend(a: Color, b: Color, t: f64) -> Color {
+    let ((ar, ag, ab), (br, bg, bb)) = (color_to_rgb(a), color_to_rgb(b));
+    let mix = |x: u8, y: u8| (x as f64 + (y as f64 - x as f64) * t).round() as u8;
+    Color::Rgb(mix(ar, br), mix(ag, bg), mix(ab, bb))
+}
+
+/// Split a data-bar cell into the bar (block characters) and the right-aligned value after it.
+/// `width` is the cell's full width including its leading space.
+pub fn data_bar(fraction: f64, text: &str, width: usize) -> (String, String) {
+    // Leave room for the value when it fits, otherwise the bar gets the whole cell
+    let (bar_width, text) = match crate::text::display_width(text) {
+        text_width if text_width + 2 < width => (width - text_width - 2, text),
+        _ => (width.saturating_sub(1), ""),
+    };
+    let text_width = crate::text::display_width(text);
+    let eighths = (fraction.clamp(0.0, 1.0) * bar_width as f64 * 8.0).round() as usize;
+    let mut bar = format!(" {}", "█".repeat(eighths / 8));
+    if !eighths.is_multiple_of(8) {
+        bar.push(BAR_EIGHTHS[eighths % 8]);
+    }
+    let used = 1 + eighths.div_ceil(8);
+    let rest = format!("{}{}", " ".repeat(width.saturating_sub(used + text_width)), text);
+    (bar, rest)
+}
+
+impl ConditionalRule {
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        row >= self.start.0 && row <= self.end.0 && col >= self.start.1 && col <= self.end.1
+    }
+
+    /// Parse `[range] <condition> [fg=colour] [bg=colour] [bold]`; the range defaults to `range`
+    pub fn parse(spec: &str, range: Option<((usize, usize), (usize, usize))>) -> Result<Self, String> {
+        let mut tokens: Vec<&str> = spec.split_whitespace().collect();
+        let ((start, end), skip) = match tokens.first().and_then(|token| parse_range(token)) {
+            Some(parsed) => (parsed, 1),
+            None => (range.ok_or("No range for the rule")?, 0),
+        };
+        tokens.drain(..skip);
+
+        let (mut fg, mut bg, mut bold) = (None, None, false);
+        let mut words = Vec::new();
+        for token in tokens {
+            let lower = token.to_lowercase();
+            if let Some(color) = lower.strip_prefix("fg=") {
+                fg = Some(parse_color(color).ok_or_else(|| format!("Unknown colour: {}", color))?);
+            } else if let Some(color) = lower.strip_prefix("bg=") {
+                bg = Some(parse_color(color).ok_or_else(|| format!("Unknown colour: {}", color))?);
+            } else if lower == "bold" {
+                bold = true;
+            } else {
+                words.push(token);
+            }
+        }
+
+        let condition = Self::parse_condition(&words)?;
+        // Highlighting rules with no formatting of their own get Excel's default red
+        let highlights = !matches!(condition, RuleCondition::ColorScale(_) | RuleCondition::DataBar(_));
+        if highlights && fg.is_none() && bg.is_none() && !bold {
+            if condition == RuleCondition::Stripes {
+                bg = Some(DEFAULT_STRIPE);
+            } else {
+                fg = Some(DEFAULT_RULE_FG);
+                bg = Some(DEFAULT_RULE_BG);
+            }
+        }
+        Ok(Self { start, end, condition, fg, bg, bold })
+    }
+
+    fn parse_condition(words: &[&str]) -> Result<RuleCondition, String> {
+        let first = words.first().ok_or("Missing rule condition")?;
+        let rest = words[1..].join(" ");
+        let number = |word: Option<&&str>| word.and_then(|w| w.parse::<f64>().ok());
+        let colors = || -> Result<Vec<Color>, String> {
+            words[1..].iter().map(|w| parse_color(w).ok_or_else(|| format!("Unknown colour: {}", w))).collect()
+        };
+
+        Ok(match first.to_lowercase().as_str() {
+            "between" => match (number(words.get(1)), number(words.get(2))) {
+                (Some(low), Some(high)) => RuleCondition::Between(low.min(high), low.max(high)),
+                _ => return Err("Usage: between <low> <high>".to_string()),
+            },
+            "contains" if !rest.is_empty() => RuleCondition::Contains(rest),
+            kind @ ("top" | "bottom") => RuleCondition::Top {
+                count: words.get(1).and_then(|w| w.parse().ok()).unwrap_or(10),
+                bottom: kind == "bottom",
+            },
+            "duplicates" | "dupes" => RuleCondition::Duplicates,
+            "unique" => RuleCondition::Unique,
+            "stripes" => RuleCondition::Stripes,
+            "formula" if !rest.is_empty() => {
+                RuleCondition::Formula(if rest.starts_with('=') { rest } else { format!("={}", rest) })
+            }
+            "scale" => match colors()?.as_slice() {
+                [] => RuleCondition::ColorScale(vec![Color::Rgb(248, 105, 107), Color::Rgb(255, 235, 132), Color::Rgb(99, 190, 123)]),
+                [low, high] => RuleCondition::ColorScale(vec![*low, *high]),
+                [low, mid, high] => RuleCondition::ColorScale(vec![*low, *mid, *high]),
+                _ => return Err("Usage: scale [low] [mid] high".to_string()),
+            },
+            "bar" => RuleCondition::DataBar(match colors()?.as_slice() {
+                [] => DEFAULT_BAR,
+                [color] => *color,
+                _ => return Err("Usage: bar [colour]".to_string()),
+            }),
+            _ => {
+                let text = words.join(" ");
+                let op = [">=", "<=", "<>", ">", "<", "="]
+                    .into_iter()
+                    .find(|op| text.starts_with(op))
+                    .ok_or_else(|| format!("Unknown rule: {}", text))?;
+                RuleCondition::Compare(op.to_string(), text[op.len()..].trim().to_string())
+            }
+        })
+    }
+
+    /// The rule written back in `:cf` syntax
+    pub fn to_spec(&self) -> String {
+        let condition = match &self.condition {
+            RuleCondition::Compare(op, value) => format!("{}{}", op, value),
+            RuleCondition::Between(low, high) => format!("between {} {}", low, high),
+            RuleCondition::Contains(text) => format!("contains {}", text),
+            RuleCondition::Top { count, bottom } => format!("{} {}", if *bottom { "bottom" } else { "top" }, count),
+            RuleCondition::Duplicates => "duplicates".to_string(),
+            RuleCondition::Unique => "unique".to_string(),
+            RuleCondition::Formula(formula) => format!("formula {}", formula),
+            RuleCondition::Stripes => "stripes".to_string(),
+            RuleCondition::ColorScale(colors) => {
+                format!("scale {}", colors.iter().map(|c| color_to_string(*c)).collect::<Vec<_>>().join(" "))
+            }
+            RuleCondition::DataBar(color) => format!("bar {}", color_to_string(*color)),
+        };
+        let mut spec = format!("{}:{} {}", cell_name(self.start), cell_name(self.end), condition);
+        if let Some(fg) = self.fg {
+            spec.push_str(&format!(" fg={}", color_to_string(fg)));
+        }
+        if let Some(bg) = self.bg {
+            spec.push_str(&format!(" bg={}", color_to_string(bg)));
+        }
+        if self.bold {
+            spec.push_str(" bold");
+        }
+        spec
+    }
+
+    fn needs_stats(&self) -> bool {
+        matches!(
+            self.condition,
+            RuleCondition::Top { .. }
+                | RuleCondition::Duplicates
+                | RuleCondition::Unique
+                | RuleCondition::ColorScale(_)
+                | RuleCondition::DataBar(_)
+        )
+    }
+
+    /// Where a number sits between the range's lowest and highest, from 0 to 1
+    fn position(stats: &RuleStats, value: f64, from_zero: bool) -> f64 {
+        let (Some(&min), Some(&max)) = (stats.numbers.first(), stats.numbers.last()) else {
+            return 0.0;
+        };
+        // Data bars grow from zero, like Excel's
+        let (low, high) = if from_zero { (min.min(0.0), max.max(0.0)) } else { (min, max) };
+        if high > low { (value - low) / (high - low) } else { 0.5 }
+    }
+}
+
+/// Keep a rule's range attached to its cells when a row or column is inserted or deleted at `at`
+fn shift_span(start: &mut usize, end: &mut usize, at: usize, inserted: bool) -> bool {
+    if inserted {
+        if *start >= at {
+            *start += 1;
+        }
+        if *end >= at {
+            *end += 1;
+        }
+        true
+    } else if *start == at && *end == at {
+        false
+    } else {
+        if *start > at {
+            *start -= 1;
+        }
+        if *end >= at {
+            *end -= 1;
+        }
+        true
+    }
+}
+
+impl Spreadsheet {
+    /// `:cf [range] <condition> [fg=..] [bg=..] [bold]` - add a rule to the selection (or the cursor
+    /// cell), or replace the rule being edited from Visual mode
+    pub fn conditional_command(&mut self, arg: &str) -> Result<(), String> {
+        let range = self
+            .get_selection_range()
+            .unwrap_or(((self.cursor_row, self.cursor_col), (self.cursor_row, self.cursor_col)));
+        let rule = ConditionalRule::parse(arg, Some(range))?;
+        match self.editing_rule.take() {
+            Some(index) if index < self.conditional_rules.len() => self.conditional_rules[index] = rule,
+            _ => self.conditional_rules.push(rule),
+        }
+        Ok(())
+    }
+
+    /// Open the command line to edit a rule, or to write a new one for the selection
+    pub fn edit_conditional_rule(&mut self, index: Option<usize>) {
+        let spec = match index.and_then(|i| self.conditional_rules.get(i)) {
+            Some(rule) => rule.to_spec(),
+            None => {
+                let ((min_row, min_col), (max_row, max_col)) = self
+                    .get_selection_range()
+                    .unwrap_or(((self.cursor_row, self.cursor_col), (self.cursor_row, self.cursor_col)));
+                format!("{}:{} ", cell_name((min_row, min_col)), cell_name((max_row, max_col)))
+            }
+        };
+        self.exit_visual_mode();
+        self.enter_command_mode();
+        self.command_buffer = format!("cf {}", spec);
+        self.editing_rule = index.filter(|&i| i < self.conditional_rules.len());
+    }
+
+    pub fn delete_conditional_rule(&mut self, index: usize) {
+        if index < self.conditional_rules.len() {
+            self.conditional_rules.remove(index);
+        }
+        self.rule_index = self.rule_index.min(self.conditional_rules.len().saturating_sub(1));
+    }
+
+    /// Gather the range statistics each rule needs; call once per frame
+    pub fn conditional_stats(&mut self) -> Vec<Option<RuleStats>> {
+        let rules = self.conditional_rules.clone();
+        rules
+            .iter()
+            .map(|rule| {
+                if !rule.needs_stats() {
+                    return None;
+                }
+                let mut stats = RuleStats::default();
+                let (data_max_row, data_max_col) = self.get_data_bounds();
+                for row in rule.start.0..=rule.end.0.min(data_max_row) {
+                    for col in rule.start.1..=rule.end.1.min(data_max_col) {
+                        let value = self.evaluate_cell(row, col);
+                        if value.is_empty() {
+                            continue;
+                        }
+                        if let Ok(number) = value.trim().parse::<f64>() {
+                            stats.numbers.push(number);
+                        }
+                        *stats.counts.entry(value.to_lowercase()).or_default() += 1;
+                    }
+                }
+                stats.numbers.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                Some(stats)
+            })
+            .collect()
+    }
+
+    /// Combine every rule covering a cell. Earlier rules take precedence for each property.
+    pub fn conditional_format(
+        &mut self,
+        row: usize,
+        col: usize,
+        value: &str,
+        stats: &[Option<RuleStats>],
+    ) -> ConditionalFormat {
+        let mut format = ConditionalFormat::default();
+        if self.conditional_rules.is_empty() {
+            return format;
+        }
+        let number = value.trim().parse::<f64>().ok();
+        // Formula rules need the sheet mutably, so lend the rules out rather than copying them
+        let rules = std::mem::take(&mut self.conditional_rules);
+        for (rule, stats) in rules.iter().zip(stats) {
+            if !rule.contains(row, col) {
+                continue;
+            }
+            let empty = RuleStats::default();
+            let stats = stats.as_ref().unwrap_or(&empty);
+            let matched = match &rule.condition {
+                // Same rules as COUNTIF: case-insensitive text, numbers only against numbers
+                RuleCondition::Compare(op, operand) => Criterion::parse(&format!("{}{}", op, operand)).matches(value),
+                RuleCondition::Between(low, high) => number.is_some_and(|n| n >= *low && n <= *high),
+                RuleCondition::Contains(text) => value.to_lowercase().contains(&text.to_lowercase()),
+                RuleCondition::Top { count, bottom } => number.is_some_and(|n| {
+                    let numbers = &stats.numbers;
+                    let count = (*count).clamp(1, numbers.len().max(1));
+                    if *bottom {
+                        numbers.get(count - 1).is_some_and(|&threshold| n <= threshold)
+                    } else {
+                        numbers.len() >= count && n >= numbers[numbers.len() - count]
+                    }
+                }),
+                RuleCondition::Duplicates => {
+                    !value.is_empty() && stats.counts.get(&value.to_lowercase()).is_some_and(|&n| n > 1)
+                }
+                RuleCondition::Unique => {
+                    !value.is_empty() && stats.counts.get(&value.to_lowercase()).is_some_and(|&n| n == 1)
+                }
+                RuleCondition::Formula(formula) => {
+                    let adjusted = Self::adjust_formula_references(
+                        formula,
+                        row as isize - rule.start.0 as isize,
+                        col as isize - rule.start.1 as isize,
+                    );
+                    value_bool(&self.evaluate_formula(&adjusted, row, col)).unwrap_or(false)
+                }
+                RuleCondition::Stripes => (row - rule.start.0).is_multiple_of(2),
+                RuleCondition::ColorScale(colors) => {
+                    if let (Some(n), None) = (number, format.bg) {
+                        let t = ConditionalRule::position(stats, n, false);
+                        format.bg = Some(match colors.as_slice() {
+                            [low, mid, _] if t < 0.5 => blend(*low, *mid, t * 2.0),
+                            [_, mid, high] => blend(*mid, *high, (t - 0.5) * 2.0),
+                            [low, high] => blend(*low, *high, t),
+                            _ => continue,
+                        });
+                    }
+                    continue;
+                }
+                RuleCondition::DataBar(color) => {
+                    if let (Some(n), None) = (number, format.bar) {
+                        format.bar = Some((ConditionalRule::position(stats, n, true), *color));
+                    }
+                    continue;
+                }
+            };
+            if matched {
+                format.fg = format.fg.or(rule.fg);
+                format.bg = format.bg.or(rule.bg);
+                format.bold |= rule.bold;
+            }
+        }
+        self.conditional_rules = rules;
+        format
+    }
+
+    /// Move rule ranges along with inserted or deleted rows
+    pub fn shift_conditional_rows(&mut self, at: usize, inserted: bool) {
+        self.conditional_rules.retain_mut(|rule| shift_span(&mut rule.start.0, &mut rule.end.0, at, inserted));
+    }
+
+    pub fn shift_conditional_cols(&mut self, at: usize, inserted: bool) {
+        self.conditional_rules.retain_mut(|rule| shift_span(&mut rule.start.1, &mut rule.end.1, at, inserted));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(sheet: &mut Spreadsheet, cells: &[(usize, usize)]) -> Vec<ConditionalFormat> {
+        let stats = sheet.conditional_stats();
+        cells
+            .iter()
+            .map(|&(row, col)| {
+                let value = sheet.evaluate_cell(row, col);
+                sheet.conditional_format(row, col, &value, &stats)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_and_round_trip_rules() {
+        let rule = ConditionalRule::parse("A1:B10 >100 bg=red bold", None).unwrap();
+        assert_eq!(rule.condition, RuleCondition::Compare(">".to_string(), "100".to_string()));
+        assert_eq!((rule.start, rule.end), ((0, 0), (9, 1)));
+        assert_eq!(ConditionalRule::parse(&rule.to_spec(), None).unwrap(), rule);
+
+        let range = Some(((1, 1), (3, 1)));
+        let rule = ConditionalRule::parse("formula =$A2>5", range).unwrap();
+        assert_eq!(rule.condition, RuleCondition::Formula("=$A2>5".to_string()));
+        assert_eq!(rule.bg, Some(DEFAULT_RULE_BG));
+        let rule = ConditionalRule::parse("scale red green", range).unwrap();
+        assert_eq!(ConditionalRule::parse(&rule.to_spec(), None).unwrap(), rule);
+        assert!(ConditionalRule::parse("sideways", range).is_err());
+        assert!(ConditionalRule::parse(">5", None).is_err());
+    }
+
+    #[test]
+    fn test_highlight_rules() {
+        let mut sheet = Spreadsheet::new();
+        for (row, value) in ["5", "50", "500", "50", "apple pie"].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+        }
+        sheet.conditional_command("A1:A5 >=50 fg=red").unwrap();
+        sheet.conditional_command("A1:A5 duplicates bg=yellow").unwrap();
+        sheet.conditional_command("A1:A5 contains PIE bold").unwrap();
+        sheet.conditional_command("A1:A5 bottom 1 bg=blue").unwrap();
+        let result = formats(&mut sheet, &[(0, 0), (1, 0), (2, 0), (4, 0)]);
+        assert_eq!((result[0].fg, result[0].bg), (None, Some(Color::Blue)));
+        assert_eq!((result[1].fg, result[1].bg), (Some(Color::Red), Some(Color::Yellow)));
+        assert_eq!((result[2].fg, result[2].bg), (Some(Color::Red), None));
+        assert!(result[3].bold);
+
+        let mut sheet = Spreadsheet::new();
+        for (row, value) in ["apple", "Cherry", "50", ""].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+        }
+        sheet.conditional_command("A1:A4 >b bg=green").unwrap();
+        sheet.conditional_command("A1:A4 <>cherry bold").unwrap();
+        let result = formats(&mut sheet, &[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        assert_eq!(result.iter().map(|f| f.bg).collect::<Vec<_>>(), [None, Some(Color::Green), None, None]);
+        assert_eq!(result.iter().map(|f| f.bold).collect::<Vec<_>>(), [true, false, true, true]);
+        assert_eq!(sheet.conditional_rules.len(), 2);
+    }
+
+    #[test]
+    fn test_formula_stripes_scale_and_bars() {
+        let mut sheet = Spreadsheet::new();
+        for row in 0..4 {
+            sheet.set_cell(row, 0, (row * 10).to_string());
+            sheet.set_cell(row, 1, "x".to_string());
+        }
+        sheet.conditional_command("B1:B4 formula =$A1>15 fg=green").unwrap();
+        sheet.conditional_command("B1:B4 stripes").unwrap();
+        sheet.conditional_command("A1:A4 scale black white").unwrap();
+        sheet.conditional_command("A1:A4 bar").unwrap();
+        let result = formats(&mut sheet, &[(0, 1), (1, 1), (2, 1), (0, 0), (3, 0)]);
+        assert_eq!((result[0].fg, result[0].bg), (None, Some(DEFAULT_STRIPE)));
+        assert_eq!((result[1].fg, result[1].bg), (None, None));
+        assert_eq!(result[2].fg, Some(Color::Green));
+        assert_eq!(result[3].bg, Some(Color::Rgb(0, 0, 0)));
+        assert_eq!(result[4].bar, Some((1.0, DEFAULT_BAR)));
+
+        // Rules follow their cells when a row is inserted above them
+        sheet.shift_conditional_rows(0, true);
+        assert_eq!(sheet.conditional_rules[0].start, (1, 1));
+    }
+
+    #[test]
+    fn test_data_bar_text() {
+        assert_eq!(data_bar(1.0, "10", 8), (" ████".to_string(), " 10".to_string()));
+        let (bar, rest) = data_bar(0.5, "10", 8);
+        assert_eq!((bar.as_str(), rest.as_str()), (" ██", "   10"));
+        let (bar, rest) = data_bar(0.25, "12345678", 5);
+        assert_eq!((bar.as_str(), rest.as_str()), (" █", "   "));
+    }
+}
@@ -0,0 +1,311 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+pub const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December",
+];
+
+/// Excel serial number of 1970-01-01 in the 1900 date system
+pub const EXCEL_EPOCH_OFFSET: i64 = 25569;
+
+/// Excel serial number of 9999-12-31, the last date Excel can show
+pub const MAX_SERIAL: i64 = 2_958_465;
+
+/// Whether a serial number, time of day included, is one Excel can show as a date
+pub fn is_date_serial(serial: f64) -> bool {
+    (0.0..(MAX_SERIAL + 1) as f64).contains(&serial)
+}
+
+/// Excel's 1900-02-29, serial 60: a day that never happened, kept for Lotus 1-2-3 compatibility
+const EXCEL_LEAP_DAY: Date = Date { year: 1900, month: 2, day: 29 };
+
+/// A calendar date (proleptic Gregorian)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+/// How a date was written, so generated dates can be written the same way
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DateStyle {
+    /// 2024-01-31
+    Iso,
+    /// 1/31/2024
+    Us,
+}
+
+pub fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl Date {
+    /// A valid date, or Excel's 1900-02-29 so that serial 60 reads back as itself
+    pub fn new(year: i32, month: u32, day: u32) -> Option<Self> {
+        let date = Self { year, month, day };
+        if ((1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month)) || date == EXCEL_LEAP_DAY {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    /// Read `YYYY-MM-DD` or `M/D/YYYY`
+    pub fn parse(s: &str) -> Option<(Self, DateStyle)> {
+        let s = s.trim();
+        let (parts, style): (Vec<&str>, DateStyle) = if s.contains('-') {
+            (s.split('-').collect(), DateStyle::Iso)
+        } else {
+            (s.split('/').collect(), DateStyle::Us)
+        };
+        if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) {
+            return None;
+        }
+        let numbers: Vec<u32> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+        let date = match style {
+            DateStyle::Iso if parts[0].len() == 4 => Self::new(numbers[0] as i32, numbers[1], numbers[2]),
+            DateStyle::Us if parts[2].len() == 4 => Self::new(numbers[2] as i32, numbers[0], numbers[1]),
+            _ => None,
+        }?;
+        Some((date, style))
+    }
+
+    pub fn format(&self, style: DateStyle) -> String {
+        match style {
+            DateStyle::Iso => format!("{:04}-{:02}-{:02}", self.year, self.month, self.day),
+            DateStyle::Us => format!("{}/{}/{}", self.month, self.day, self.year),
+        }
+    }
+
+    /// Days since 1970-01-01
+    pub fn to_days(self) -> i64 {
+        // Howard Hinnant's days_from_civil
+        let y = if self.month <= 2 { self.year - 1 } else { self.year } as i64;
+        let era = if y >= 0 { y } else { y - 399 } / 400;
+        let yoe = y - era * 400;
+        let m = self.month as i64;
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    pub fn from_days(days: i64) -> Self {
+        let z = days + 719468;
+        let era = if z >= 0 { z } else { z - 146096 } / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+        Self { year, month, day }
+    }
+
+    /// Excel 1900-system serial number. Excel counts a 1900-02-29 that never happened,
+    /// so serials before March 1900 are one lower than the day count suggests.
+    pub fn to_serial(self) -> i64 {
+        if self == EXCEL_LEAP_DAY {
+            return 60;
+        }
+        let serial = self.to_days() + EXCEL_EPOCH_OFFSET;
+        if serial < 61 { serial - 1 } else { serial }
+    }
+
+    pub fn from_serial(serial: i64) -> Self {
+        if serial == 60 {
+            return EXCEL_LEAP_DAY;
+        }
+        Self::from_days(if serial < 60 { serial + 1 } else { serial } - EXCEL_EPOCH_OFFSET)
+    }
+
+    /// Day of the week, 0 = Monday
+    pub fn weekday(self) -> usize {
+        // 1970-01-01 was a Thursday
+        (self.to_days() + 3).rem_euclid(7) as usize
+    }
+
+    pub fn add_days(&self, days: i64) -> Self {
+        Self::from_days(self.to_days() + days)
+    }
+
+    /// Move by whole months, clamping the day to the end of shorter months
+    pub fn add_months(&self, months: i64) -> Self {
+        let index = self.year as i64 * 12 + (self.month as i64 - 1) + months;
+        let year = index.div_euclid(12) as i32;
+        let month = index.rem_euclid(12) as u32 + 1;
+        Self {
+            year,
+            month,
+            day: self.day.min(days_in_month(year, month)),
+        }
+    }
+
+    /// Whole months from `self` to `other` when both fall on the same day of the month
+    pub fn months_until(&self, other: &Self) -> Option<i64> {
+        (self.day == other.day)
+            .then(|| (other.year as i64 * 12 + other.month as i64) - (self.year as i64 * 12 + self.month as i64))
+    }
+}
+
+/// Seconds since midnight of a serial number's time of day
+pub fn seconds_of_day(serial: f64) -> i64 {
+    ((serial - serial.floor()) * 86400.0).round() as i64 % 86400
+}
+
+/// The local date and time as an Excel serial number
+pub fn now_serial() -> f64 {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64());
+    let local = timestamp + utc_offset(timestamp as i64) as f64;
+    EXCEL_EPOCH_OFFSET as f64 + local / 86400.0
+}
+
+/// Seconds the local time zone is ahead of UTC at a Unix timestamp
+#[cfg(unix)]
+fn utc_offset(timestamp: i64) -> i64 {
+    let time = timestamp as libc::time_t;
+    // SAFETY: localtime_r only writes to the tm we pass it, which is plain data
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        0
+    } else {
+        tm.tm_gmtoff as i64
+    }
+}
+
+/// Elsewhere (Windows) the time zone isn't read, so TODAY and NOW are in UTC
+#[cfg(not(unix))]
+fn utc_offset(_timestamp: i64) -> i64 {
+    0
+}
+
+/// Read `14:30`, `14:30:15` or `2:30 PM` as a fraction of a day, with the format code it was written in
+pub fn parse_time(s: &str) -> Option<(f64, &'static str)> {
+    let s = s.trim();
+    let lower = s.to_lowercase();
+    let (clock, meridiem) = match lower.strip_suffix("am").or_else(|| lower.strip_suffix('a')) {
+        Some(clock) => (clock.trim_end(), Some(false)),
+        None => match lower.strip_suffix("pm").or_else(|| lower.strip_suffix('p')) {
+            Some(clock) => (clock.trim_end(), Some(true)),
+            None => (lower.as_str(), None),
+        },
+    };
+    let parts: Vec<&str> = clock.split(':').collect();
+    if !(2..=3).contains(&parts.len())
+        || parts.iter().any(|p| p.is_empty() || p.len() > 2 || !p.chars().all(|c| c.is_ascii_digit()))
+        || parts[1..].iter().any(|p| p.len() != 2)
+    {
+        return None;
+    }
+    let numbers: Vec<u32> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (mut hour, minute, second) = (numbers[0], numbers[1], numbers.get(2).copied().unwrap_or(0));
+    if minute > 59 || second > 59 {
+        return None;
+    }
+    match meridiem {
+        Some(pm) if (1..=12).contains(&hour) => hour = hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None if hour > 23 => return None,
+        None => {}
+    }
+    let code = match (meridiem.is_some(), parts.len() == 3) {
+        (false, false) => "h:mm",
+        (false, true) => "h:mm:ss",
+        (true, false) => "h:mm AM/PM",
+        (true, true) => "h:mm:ss AM/PM",
+    };
+    Some(((hour * 3600 + minute * 60 + second) as f64 / 86400.0, code))
+}
+
+/// Read a date, a time or a date followed by a time as an Excel serial number, with the
+/// format code that shows it the way it was typed
+pub fn parse_date_time(s: &str) -> Option<(f64, &'static str)> {
+    let s = s.trim();
+    if let Some((date, style)) = Date::parse(s) {
+        let code = match style {
+            DateStyle::Iso => "yyyy-mm-dd",
+            DateStyle::Us => "m/d/yyyy",
+        };
+        return Some((date.to_serial() as f64, code));
+    }
+    if let Some(time) = parse_time(s) {
+        return Some(time);
+    }
+    let (date, time) = s.split_once([' ', 'T'])?;
+    let (date, style) = Date::parse(date)?;
+    let (fraction, time_code) = parse_time(time)?;
+    let code = match (style, time_code) {
+        (DateStyle::Iso, "h:mm") => "yyyy-mm-dd hh:mm",
+        (DateStyle::Iso, "h:mm:ss") => "yyyy-mm-dd hh:mm:ss",
+        (DateStyle::Iso, _) => "yyyy-mm-dd h:mm AM/PM",
+        (DateStyle::Us, "h:mm") => "m/d/yyyy h:mm",
+        (DateStyle::Us, "h:mm:ss") => "m/d/yyyy h:mm:ss",
+        (DateStyle::Us, _) => "m/d/yyyy h:mm AM/PM",
+    };
+    Some((date.to_serial() as f64 + fraction, code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let (date, style) = Date::parse("2024-02-29").unwrap();
+        assert_eq!(date, Date { year: 2024, month: 2, day: 29 });
+        assert_eq!(date.format(style), "2024-02-29");
+        let (date, style) = Date::parse("1/5/2024").unwrap();
+        assert_eq!((date.month, date.day), (1, 5));
+        assert_eq!(date.format(style), "1/5/2024");
+        assert!(Date::parse("2023-02-29").is_none());
+        assert!(Date::parse("12-5").is_none());
+    }
+
+    #[test]
+    fn test_day_and_month_arithmetic() {
+        let date = Date::new(2024, 1, 31).unwrap();
+        assert_eq!(Date::from_days(date.to_days()), date);
+        assert_eq!(Date::new(1970, 1, 1).unwrap().to_days(), 0);
+        assert_eq!(date.add_days(1), Date::new(2024, 2, 1).unwrap());
+        assert_eq!(date.add_months(1), Date::new(2024, 2, 29).unwrap());
+        assert_eq!(date.add_months(-2), Date::new(2023, 11, 30).unwrap());
+        assert_eq!(date.months_until(&Date::new(2024, 3, 31).unwrap()), Some(2));
+        assert_eq!(WEEKDAYS[date.weekday()], "Wednesday");
+        assert_eq!(Date::new(2024, 3, 5).unwrap().to_serial(), 45356);
+        assert_eq!(Date::new(1900, 1, 1).unwrap().to_serial(), 1);
+        assert_eq!(Date::from_serial(45356), Date::new(2024, 3, 5).unwrap());
+        assert_eq!(Date::from_serial(1), Date::new(1900, 1, 1).unwrap());
+        // Excel's 1900-02-29 sits between the 28th and March 1st
+        assert_eq!(Date::from_serial(59), Date::new(1900, 2, 28).unwrap());
+        assert_eq!(Date::from_serial(60), Date { year: 1900, month: 2, day: 29 });
+        assert_eq!(Date::from_serial(61), Date::new(1900, 3, 1).unwrap());
+        assert_eq!(Date::from_serial(60).to_serial(), 60);
+    }
+
+    #[test]
+    fn test_parse_date_time() {
+        assert_eq!(parse_date_time("2024-03-05"), Some((45356.0, "yyyy-mm-dd")));
+        assert_eq!(parse_date_time("3/5/2024"), Some((45356.0, "m/d/yyyy")));
+        assert_eq!(parse_date_time("1900-02-29"), Some((60.0, "yyyy-mm-dd")));
+        assert_eq!(parse_date_time("1900-03-01"), Some((61.0, "yyyy-mm-dd")));
+        assert_eq!(parse_date_time("14:30"), Some((0.6041666666666666, "h:mm")));
+        assert_eq!(parse_date_time("2:30 PM"), Some((0.6041666666666666, "h:mm AM/PM")));
+        assert_eq!(parse_date_time("12:00 am"), Some((0.0, "h:mm AM/PM")));
+        assert_eq!(parse_date_time("2024-03-05 06:00"), Some((45356.25, "yyyy-mm-dd hh:mm")));
+        assert_eq!(parse_date_time("25:00"), None);
+        assert_eq!(parse_date_time("1:5"), None);
+        assert_eq!(parse_date_time("hello"), None);
+    }
+}
@@ -0,0 +1,386 @@
+use std::cmp::Ordering;
+
+use crate::array::{broadcast, Array, ARRAY_FUNCTIONS};
+use crate::lookup::compare_values;
+use crate::spreadsheet::Spreadsheet;
+
+/// Binary operators from the loosest binding to the tightest. Every level groups left to
+/// right, so `2^3^2` is `(2^3)^2` as in Excel.
+const PRECEDENCE: [&[&str]; 5] = [
+    &["=", "<>", "<", ">", "<=", ">="],
+    &["&"],
+    &["+", "-"],
+    &["*", "/"],
+    &["^"],
+];
+
+/// The position of the parenthesis closing the one at `open`, skipping quoted text
+pub fn matching_paren(expr: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quoted = false;
+    for (i, c) in expr.char_indices().skip_while(|&(i, _)| i < open) {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split function arguments at the commas outside parentheses and quoted text
+pub fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut quoted = false;
+
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+/// `NAME(args)` when the whole expression is a single function call: the upper-case name
+/// and the argument text
+pub fn single_call(expr: &str) -> Option<(String, &str)> {
+    let open = expr.find('(')?;
+    let name = &expr[..open];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+        return None;
+    }
+    let close = matching_paren(expr, open)?;
+    (close == expr.len() - 1).then(|| (name.to_uppercase(), &expr[open + 1..close]))
+}
+
+/// Whether the text before a `+` or `-` is the mantissa of a number like `1.5E+3`
+fn is_exponent_sign(before: &str) -> bool {
+    let start = before.rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '.')).map_or(0, |i| i + 1);
+    let token = &before[start..];
+    token.len() > 1
+        && token.ends_with(['e', 'E'])
+        && token[..token.len() - 1].chars().all(|c| c.is_ascii_digit() || c == '.')
+        && token.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+}
+
+/// Where an expression splits when evaluated: the rightmost binary operator outside quotes
+/// and parentheses at the loosest precedence level present. A sign with no operand before
+/// it is unary, and `%` is postfix, so neither splits.
+pub fn split_operator(expr: &str) -> Option<(usize, &str)> {
+    let mut operators = Vec::new();
+    let (mut depth, mut quoted, mut after_operand) = (0, false, false);
+    let mut i = 0;
+    while i < expr.len() {
+        let c = expr.as_bytes()[i] as char;
+        match c {
+            '"' => {
+                quoted = !quoted;
+                after_operand = true;
+            }
+            _ if quoted => {}
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                after_operand = true;
+            }
+            ' ' => {}
+            '+' | '-' if depth == 0 && is_exponent_sign(&expr[..i]) => {}
+            '<' | '>' | '=' | '&' | '+' | '-' | '*' | '/' | '^' if depth == 0 => {
+                let op = expr.get(i..i + 2).filter(|op| ["<>", "<=", ">="].contains(op)).unwrap_or(&expr[i..i + 1]);
+                if after_operand {
+                    operators.push((i, op));
+                }
+                after_operand = false;
+                i += op.len();
+                continue;
+            }
+            _ => after_operand = true,
+        }
+        i += 1;
+    }
+    PRECEDENCE
+        .iter()
+        .find_map(|level| operators.iter().rev().find(|(_, op)| level.contains(op)).copied())
+}
+
+/// The expression inside one pair of parentheses wrapping all of it
+pub fn strip_parens(expr: &str) -> Option<&str> {
+    let inner = expr.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+    for c in inner.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return None,
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    Some(inner)
+}
+
+/// Whether an argument needs evaluating as an expression rather than read as a single
+/// value: it has an operator, a sign, a percent sign or wrapping parentheses
+pub fn has_operators(expr: &str) -> bool {
+    let expr = expr.trim();
+    split_operator(expr).is_some()
+        || expr.starts_with(['-', '+'])
+        || (expr.ends_with('%') && !expr.starts_with(['"', '\'']))
+        || strip_parens(expr).is_some()
+}
+
+/// A value as a number for arithmetic: blanks are 0 and TRUE/FALSE are 1/0
+pub fn value_number(value: &str) -> Option<f64> {
+    match value.trim().to_uppercase().as_str() {
+        "" | "FALSE" => Some(0.0),
+        "TRUE" => Some(1.0),
+        other => other.parse().ok(),
+    }
+}
+
+/// A value as TRUE/FALSE: the literals, or a number (non-zero is TRUE)
+pub fn value_bool(value: &str) -> Option<bool> {
+    match value.trim().to_uppercase().as_str() {
+        "TRUE" => Some(true),
+        "FALSE" => Some(false),
+        other => other.parse::<f64>().ok().map(|n| n != 0.0),
+    }
+}
+
+/// Order two values for a comparison: numbers before text before TRUE/FALSE, text ignoring
+/// case, and a blank equal to 0 or ""
+fn compare_operands(a: &str, b: &str) -> Ordering {
+    let rank = |value: &str| match value.trim().to_uppercase().as_str() {
+        "TRUE" | "FALSE" => 2,
+        other if other.parse::<f64>().is_ok() => 0,
+        _ => 1,
+    };
+    let (a, b) = match (a.trim().is_empty(), b.trim().is_empty()) {
+        (true, false) if rank(b) == 0 => ("0", b),
+        (false, true) if rank(a) == 0 => (a, "0"),
+        _ => (a, b),
+    };
+    rank(a).cmp(&rank(b)).then_with(|| compare_values(a, b).unwrap_or(Ordering::Equal))
+}
+
+/// Apply a binary operator to two values. Errors pass through; comparisons give TRUE/FALSE
+/// and `&` joins text.
+pub fn apply_operator(op: &str, a: &str, b: &str) -> String {
+    if let Some(error) = [a, b].into_iter().find(|value| value.starts_with('#')) {
+        return error.to_string();
+    }
+    let compared = match op {
+        "=" => compare_operands(a, b) == Ordering::Equal,
+        "<>" => compare_operands(a, b) != Ordering::Equal,
+        "<" => compare_operands(a, b) == Ordering::Less,
+        ">" => compare_operands(a, b) == Ordering::Greater,
+        "<=" => compare_operands(a, b) != Ordering::Greater,
+        ">=" => compare_operands(a, b) != Ordering::Less,
+        "&" => return format!("{}{}", a, b),
+        _ => {
+            let (Some(x), Some(y)) = (value_number(a), value_number(b)) else {
+                return "#VALUE!".to_string();
+            };
+            let result = match op {
+                "+" => x + y,
+                "-" => x - y,
+                "*" => x * y,
+                "/" if y == 0.0 => return "#DIV/0!".to_string(),
+                "/" => x / y,
+                _ if x == 0.0 && y < 0.0 => return "#DIV/0!".to_string(),
+                _ => x.powf(y),
+            };
+            return if result.is_finite() { format!("{}", result) } else { "#NUM!".to_string() };
+        }
+    };
+    if compared { "TRUE" } else { "FALSE" }.to_string()
+}
+
+impl Spreadsheet {
+    /// Evaluate an expression to a single value; an array result gives its first value
+    pub fn evaluate_expression(&mut self, expr: &str, current_row: usize, current_col: usize) -> String {
+        match self.evaluate_array_expression(expr, current_row, current_col) {
+            Ok(array) => array[0][0].clone(),
+            Err(e) => e,
+        }
+    }
+
+    /// Whether an expression gives an array: a multi-cell range, a spill reference such as
+    /// `A1#`, an array function, or operators applied to any of those
+    pub fn is_array_expression(&self, expr: &str) -> bool {
+        let expr = expr.trim();
+        if self.has_names()
+            && let Ok(expanded) = self.expand_names(expr)
+            && expanded != expr
+        {
+            return self.is_array_expression(&expanded);
+        }
+        if let Some(body) = self.bound_formula(expr) {
+            return self.is_array_expression(&body);
+        }
+        if let Some((pos, op)) = split_operator(expr) {
+            return self.is_array_expression(&expr[..pos]) || self.is_array_expression(&expr[pos + op.len()..]);
+        }
+        if let Some(rest) = expr.strip_prefix(['-', '+']).or_else(|| expr.strip_suffix('%')) {
+            return self.is_array_expression(rest);
+        }
+        if let Some(inner) = strip_parens(expr) {
+            return self.is_array_expression(inner);
+        }
+        match single_call(expr) {
+            Some((name, _)) => ARRAY_FUNCTIONS.contains(&name.as_str()),
+            None => {
+                expr.strip_suffix('#').is_some_and(|anchor| self.parse_range(anchor).is_some_and(|r| r.is_single_cell()))
+                    || (expr.contains(':') && self.parse_range(expr).is_some_and(|range| !range.is_single_cell()))
+            }
+        }
+    }
+
+    /// Evaluate an expression element by element. Single values are 1x1 arrays, and a
+    /// single row or column stretches to match the other side of an operator.
+    pub fn evaluate_array_expression(&mut self, expr: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let expr = expr.trim();
+        if expr.is_empty() {
+            return Err("#ERROR".to_string());
+        }
+        let single = |value: &str| vec![vec![value.to_string()]];
+
+        if let Some((pos, op)) = split_operator(expr) {
+            let left = self.evaluate_array_expression(&expr[..pos], current_row, current_col)?;
+            let right = self.evaluate_array_expression(&expr[pos + op.len()..], current_row, current_col)?;
+            return Ok(broadcast(&left, &right, |a, b| apply_operator(op, a, b)));
+        }
+        // Signs bind tighter than any binary operator, so -2^2 is 4
+        if let Some(rest) = expr.strip_prefix('-') {
+            let value = self.evaluate_array_expression(rest, current_row, current_col)?;
+            return Ok(broadcast(&single("0"), &value, |a, b| apply_operator("-", a, b)));
+        }
+        if let Some(rest) = expr.strip_prefix('+') {
+            return self.evaluate_array_expression(rest, current_row, current_col);
+        }
+        if let Some(rest) = expr.strip_suffix('%').filter(|_| !expr.starts_with(['"', '\''])) {
+            let value = self.evaluate_array_expression(rest, current_row, current_col)?;
+            return Ok(broadcast(&value, &single("100"), |a, b| apply_operator("/", a, b)));
+        }
+        if let Some(inner) = strip_parens(expr) {
+            return self.evaluate_array_expression(inner, current_row, current_col);
+        }
+
+        if let Some(result) = self.evaluate_lambda_call(expr, current_row, current_col) {
+            return result;
+        }
+        if let Some((name, args)) = single_call(expr) {
+            match name.as_str() {
+                "LET" => return self.evaluate_let(args, current_row, current_col),
+                // A LAMBDA that isn't called has no value
+                "LAMBDA" => return Err("#CALC!".to_string()),
+                _ => {}
+            }
+            if let Some(result) = self.evaluate_array_function(&name, args, expr, current_row, current_col) {
+                return result;
+            }
+            return Ok(single(&self.evaluate_formula(&format!("={}", expr), current_row, current_col)));
+        }
+        if let Some(range) = self.parse_range(expr).filter(|_| expr.contains(':')) {
+            return Ok(self.range_array(range));
+        }
+        Ok(single(&self.evaluate_operand(expr)))
+    }
+
+    /// A single operand: a text, number, TRUE/FALSE or error literal, or a cell reference
+    fn evaluate_operand(&mut self, operand: &str) -> String {
+        for quote in ['"', '\''] {
+            if operand.len() >= 2 && operand.starts_with(quote) && operand.ends_with(quote) {
+                let doubled = format!("{}{}", quote, quote);
+                return operand[1..operand.len() - 1].replace(&doubled, &quote.to_string());
+            }
+        }
+        let upper = operand.to_uppercase();
+        if upper == "TRUE" || upper == "FALSE" {
+            return upper;
+        }
+        if let Ok(number) = operand.parse::<f64>() {
+            return format!("{}", number);
+        }
+        if operand.starts_with('#') {
+            return operand.to_string();
+        }
+        match Self::parse_cell_reference(&operand.replace('$', "")) {
+            Some((row, col)) => self.evaluate_cell(row, col),
+            None => "#NAME?".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_precedence() {
+        let mut sheet = Spreadsheet::new();
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 0, 0);
+
+        assert_eq!(eval("=1+2*3"), "7");
+        assert_eq!(eval("=(1+2)*3"), "9");
+        assert_eq!(eval("=10-4-3"), "3");
+        assert_eq!(eval("=2^3^2"), "64");
+        assert_eq!(eval("=-2^2"), "4");
+        assert_eq!(eval("=0-2^2"), "-4");
+        assert_eq!(eval("=2^-1"), "0.5");
+        assert_eq!(eval("=2*-3"), "-6");
+        assert_eq!(eval("=--5"), "5");
+        assert_eq!(eval("=50%"), "0.5");
+        assert_eq!(eval("=200*10%+1"), "21");
+        assert_eq!(eval("=1.5E+3-1e-1"), "1499.9");
+        assert_eq!(eval("=1+2&\"x\""), "3x");
+        assert_eq!(eval("=1+2=3"), "TRUE");
+        assert_eq!(eval("=\"a\"&\"b\"=\"AB\""), "TRUE");
+    }
+
+    #[test]
+    fn test_operators_and_literals() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "10".to_string());
+        sheet.set_cell(0, 1, "abc".to_string());
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 5, 5);
+
+        assert_eq!(eval("=TRUE"), "TRUE");
+        assert_eq!(eval("=TRUE+1"), "2");
+        assert_eq!(eval("=NOT(FALSE)&\"!\""), "TRUE!");
+        assert_eq!(eval("=A1>=10"), "TRUE");
+        assert_eq!(eval("=A1<>10"), "FALSE");
+        assert_eq!(eval("=B1=\"ABC\""), "TRUE");
+        assert_eq!(eval("=B1<A1"), "FALSE");
+        assert_eq!(eval("=C1=0"), "TRUE");
+        assert_eq!(eval("=C1=\"\""), "TRUE");
+        assert_eq!(eval("=B1"), "abc");
+        assert_eq!(eval("=B1&\" \"&A1*2"), "abc 20");
+        assert_eq!(eval("=\"say \"\"hi\"\"\""), "say \"hi\"");
+        assert_eq!(eval("=B1+1"), "#VALUE!");
+        assert_eq!(eval("=1/0"), "#DIV/0!");
+        assert_eq!(eval("=(-8)^0.5"), "#NUM!");
+        assert_eq!(eval("=SUM(A1,1)*2+ABS(-1)"), "23");
+        assert_eq!(eval("=IF(A1*2>15,A1&\"!\",\"no\")"), "10!");
+        assert_eq!(eval("=A1&\" is \"&TEXT(A1/40,\"0%\")"), "10 is 25%");
+        assert_eq!(eval("=bogus+1"), "#NAME?");
+        assert_eq!(eval("=1+"), "#ERROR");
+    }
+}
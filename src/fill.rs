@@ -0,0 +1,305 @@
+use crate::date::{Date, DateStyle, MAX_SERIAL, MONTHS, WEEKDAYS};
+use crate::number_format::is_date_format;
+use crate::spreadsheet::Spreadsheet;
+
+/// Format a series value without float noise (0.1 + 0.2 stays "0.3")
+fn format_number(value: f64) -> String {
+    let rounded = (value * 1e10).round() / 1e10;
+    if rounded == 0.0 { "0".to_string() } else { rounded.to_string() }
+}
+
+/// Average step between the seeds, or 1 from a single seed
+fn step_of(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        1.0
+    } else {
+        (values[values.len() - 1] - values[0]) / (values.len() - 1) as f64
+    }
+}
+
+/// Position of a day or month name (full or three-letter) in `names`, and whether it was abbreviated
+fn name_index(names: &[&str], value: &str) -> Option<(usize, bool)> {
+    let lower = value.to_lowercase();
+    names.iter().enumerate().find_map(|(i, name)| {
+        let name = name.to_lowercase();
+        if lower == name {
+            Some((i, false))
+        } else if lower == name[..3] {
+            Some((i, true))
+        } else {
+            None
+        }
+    })
+}
+
+/// Write a day/month name in the same case and length as `like`
+fn styled_name(name: &str, abbreviated: bool, like: &str) -> String {
+    let name = if abbreviated { &name[..3] } else { name };
+    if like.chars().all(|c| c.is_uppercase()) {
+        name.to_uppercase()
+    } else if like.chars().all(|c| c.is_lowercase()) {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Split "Item007" into ("Item", 7, 3)
+fn split_trailing_number(value: &str) -> Option<(&str, i64, usize)> {
+    let digits = value.chars().rev().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || digits == value.len() {
+        return None;
+    }
+    let (prefix, number) = value.split_at(value.len() - digits);
+    Some((prefix, number.parse().ok()?, digits))
+}
+
+/// Extend a run of seed values by `count` more, recognising numbers, dates, day and month names
+/// and text ending in a number. Anything else repeats the seeds.
+pub fn extend_series(seeds: &[String], count: usize) -> Vec<String> {
+    if seeds.is_empty() {
+        return Vec::new();
+    }
+
+    // Numbers: 1, 2, 3 or 10, 20, 30
+    if let Some(values) = seeds.iter().map(|s| s.trim().parse::<f64>().ok()).collect::<Option<Vec<_>>>() {
+        let (last, step) = (values[values.len() - 1], step_of(&values));
+        return (1..=count).map(|i| format_number(last + step * i as f64)).collect();
+    }
+
+    // Dates: daily steps, or monthly when the seeds share a day of the month
+    if let Some(dates) = seeds.iter().map(|s| Date::parse(s)).collect::<Option<Vec<_>>>() {
+        let (last, style) = dates[dates.len() - 1];
+        let months: Option<Vec<i64>> = dates.windows(2).map(|w| w[0].0.months_until(&w[1].0)).collect();
+        if let Some(months) = months.filter(|m| !m.is_empty() && m[0] != 0 && m.iter().all(|&step| step == m[0])) {
+            return (1..=count).map(|i| last.add_months(months[0] * i as i64).format(style)).collect();
+        }
+        let days: Vec<f64> = dates.iter().map(|(date, _)| date.to_days() as f64).collect();
+        let step = step_of(&days).round() as i64;
+        return (1..=count).map(|i| last.add_days(step * i as i64).format(style)).collect();
+    }
+
+    // Day and month names wrap around: Fri, Sat, Sun, Mon
+    for names in [&WEEKDAYS[..], &MONTHS[..]] {
+        if let Some(indices) = seeds.iter().map(|s| name_index(names, s.trim())).collect::<Option<Vec<_>>>() {
+            let positions: Vec<f64> = indices.iter().map(|&(i, _)| i as f64).collect();
+            let step = step_of(&positions).round() as i64;
+            let (last, abbreviated) = indices[indices.len() - 1];
+            let like = seeds[seeds.len() - 1].trim();
+            return (1..=count)
+                .map(|i| {
+                    let index = (last as i64 + step * i as i64).rem_euclid(names.len() as i64) as usize;
+                    styled_name(names[index], abbreviated, like)
+                })
+                .collect();
+        }
+    }
+
+    // Text with a trailing number: Item1, Item2 (keeping any zero padding)
+    if let Some(parts) = seeds.iter().map(|s| split_trailing_number(s)).collect::<Option<Vec<_>>>()
+        && parts.iter().all(|(prefix, _, _)| *prefix == parts[0].0)
+    {
+        let numbers: Vec<f64> = parts.iter().map(|&(_, n, _)| n as f64).collect();
+        let step = step_of(&numbers).round() as i64;
+        let (prefix, last, width) = parts[parts.len() - 1];
+        return (1..=count)
+            .map(|i| format!("{}{:0width$}", prefix, (last + step * i as i64).max(0), width = width))
+            .collect();
+    }
+
+    seeds.iter().cycle().take(count).cloned().collect()
+}
+
+impl Spreadsheet {
+    /// Copy one cell into another, moving relative references by the distance between them
+    fn fill_cell(&mut self, (src_row, src_col): (usize, usize), (row, col): (usize, usize)) {
+        let value = self.get_cell(src_row, src_col).to_string();
+        let value = if value.starts_with('=') {
+            Self::adjust_formula_references(&value, row as isize - src_row as isize, col as isize - src_col as isize)
+        } else {
+            value
+        };
+        self.set_cell(row, col, value);
+        match self.cell_styles.get(&(src_row, src_col)).cloned() {
+            Some(style) => self.cell_styles.insert((row, col), style),
+            None => self.cell_styles.remove(&(row, col)),
+        };
+    }
+
+    /// Ctrl+D - copy the top row of the selection down through it (or the cell above into the cursor)
+    pub fn fill_down(&mut self) {
+        let ((min_row, min_col), (max_row, max_col)) = match self.get_selection_range() {
+            Some(range) if range.0.0 < range.1.0 => range,
+            _ if self.cursor_row > 0 => ((self.cursor_row - 1, self.cursor_col), (self.cursor_row, self.cursor_col)),
+            _ => return,
+        };
+        for row in min_row + 1..=max_row {
+            // Like Excel, rows hidden by a filter are left alone
+            if self.is_row_filtered(row) {
+                continue;
+            }
+            for col in min_col..=max_col {
+                self.fill_cell((min_row, col), (row, col));
+            }
+        }
+    }
+
+    /// Ctrl+R - copy the left column of the selection across it (or the cell to the left into the cursor)
+    pub fn fill_right(&mut self) {
+        let ((min_row, min_col), (max_row, max_col)) = match self.get_selection_range() {
+            Some(range) if range.0.1 < range.1.1 => range,
+            _ if self.cursor_col > 0 => ((self.cursor_row, self.cursor_col - 1), (self.cursor_row, self.cursor_col)),
+            _ => return,
+        };
+        for row in min_row..=max_row {
+            if self.is_row_filtered(row) {
+                continue;
+            }
+            for col in min_col + 1..=max_col {
+                self.fill_cell((row, min_col), (row, col));
+            }
+        }
+    }
+
+    /// Continue the series started by the filled cells at the top (or left) of each column (or row)
+    /// of the selection. Tall selections fill down, wide ones fill right.
+    pub fn fill_series(&mut self) -> Result<(), String> {
+        let ((min_row, min_col), (max_row, max_col)) =
+            self.get_selection_range().ok_or("Select the seed cells and the cells to fill")?;
+        let down = max_row - min_row >= max_col - min_col;
+        let (lines, length) = if down {
+            (min_col..=max_col, max_row - min_row + 1)
+        } else {
+            (min_row..=max_row, max_col - min_col + 1)
+        };
+
+        for line in lines {
+            let cell = |i: usize| if down { (min_row + i, line) } else { (line, min_col + i) };
+            let seed_count = (0..length).take_while(|&i| !self.get_cell(cell(i).0, cell(i).1).is_empty()).count();
+            if seed_count == 0 || seed_count == length {
+                continue;
+            }
+
+            // Formulas can't be extrapolated, so they repeat with their references moved along
+            let has_formula = (0..seed_count).any(|i| self.get_cell(cell(i).0, cell(i).1).starts_with('='));
+            if has_formula {
+                for i in seed_count..length {
+                    self.fill_cell(cell(i % seed_count), cell(i));
+                }
+                continue;
+            }
+
+            let seeds: Vec<String> = (0..seed_count).map(|i| self.get_cell(cell(i).0, cell(i).1).to_string()).collect();
+            // Dates stored as serial numbers step by calendar, so months stay months
+            let date_serials = self
+                .get_cell_style(cell(0).0, cell(0).1)
+                .number_format
+                .is_some_and(|code| is_date_format(&code))
+                && seeds.iter().all(|seed| seed.parse::<i64>().is_ok_and(|serial| (0..=MAX_SERIAL).contains(&serial)));
+            let values = if date_serials {
+                let dates: Vec<String> = seeds
+                    .iter()
+                    .filter_map(|seed| seed.parse().ok())
+                    .map(|serial| Date::from_serial(serial).format(DateStyle::Iso))
+                    .collect();
+                extend_series(&dates, length - seed_count)
+                    .iter()
+                    .filter_map(|date| Date::parse(date))
+                    .map(|(date, _)| date.to_serial().to_string())
+                    .collect()
+            } else {
+                extend_series(&seeds, length - seed_count)
+            };
+            for (offset, value) in values.into_iter().enumerate() {
+                let i = seed_count + offset;
+                let (row, col) = cell(i);
+                self.set_cell(row, col, value);
+                match self.cell_styles.get(&cell(i % seed_count)).cloned() {
+                    Some(style) => self.cell_styles.insert((row, col), style),
+                    None => self.cell_styles.remove(&(row, col)),
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// `:fill` - `down`, `right`, or (by default) `series`
+    pub fn fill_command(&mut self, arg: &str) -> Result<(), String> {
+        match arg.to_lowercase().as_str() {
+            "down" => self.fill_down(),
+            "right" => self.fill_right(),
+            "" | "series" => self.fill_series()?,
+            _ => return Err(format!("Unknown fill: {} (use down, right or series)", arg)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(seeds: &[&str], count: usize) -> Vec<String> {
+        extend_series(&seeds.iter().map(|s| s.to_string()).collect::<Vec<_>>(), count)
+    }
+
+    #[test]
+    fn test_extend_series_patterns() {
+        assert_eq!(series(&["1", "2"], 3), ["3", "4", "5"]);
+        assert_eq!(series(&["10", "20", "30"], 2), ["40", "50"]);
+        assert_eq!(series(&["0.1", "0.2"], 1), ["0.3"]);
+        assert_eq!(series(&["5"], 2), ["6", "7"]);
+        assert_eq!(series(&["2024-01-30"], 3), ["2024-01-31", "2024-02-01", "2024-02-02"]);
+        assert_eq!(series(&["1/15/2024", "2/15/2024"], 2), ["3/15/2024", "4/15/2024"]);
+        assert_eq!(series(&["2024-01-01", "2024-01-08"], 1), ["2024-01-15"]);
+        assert_eq!(series(&["Fri"], 3), ["Sat", "Sun", "Mon"]);
+        assert_eq!(series(&["JANUARY", "MARCH"], 2), ["MAY", "JULY"]);
+        assert_eq!(series(&["Item1", "Item2"], 2), ["Item3", "Item4"]);
+        assert_eq!(series(&["Q09"], 2), ["Q10", "Q11"]);
+        assert_eq!(series(&["a", "b"], 3), ["a", "b", "a"]);
+    }
+
+    #[test]
+    fn test_fill_down_and_right_adjust_references() {
+        let mut sheet = Spreadsheet::new();
+        for row in 0..3 {
+            sheet.set_cell(row, 0, (row + 1).to_string());
+        }
+        sheet.set_cell(0, 1, "=A1*2".to_string());
+        sheet.set_cell_bold(0, 1, true);
+        sheet.selection_anchor = Some((0, 1));
+        sheet.cursor_row = 2;
+        sheet.cursor_col = 1;
+        sheet.fill_down();
+        assert_eq!(sheet.get_cell(2, 1), "=A3*2");
+        assert_eq!(sheet.evaluate_cell(1, 1), "4");
+        assert!(sheet.get_cell_style(2, 1).bold);
+
+        // With no selection, Ctrl+R copies the cell to the left
+        sheet.clear_selection();
+        sheet.cursor_row = 0;
+        sheet.cursor_col = 2;
+        sheet.fill_right();
+        assert_eq!(sheet.get_cell(0, 2), "=B1*2");
+    }
+
+    #[test]
+    fn test_fill_series_over_selection() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "Mon".to_string());
+        sheet.set_cell(0, 1, "1".to_string());
+        sheet.set_cell(1, 1, "3".to_string());
+        sheet.set_cell(0, 2, "=B1+1".to_string());
+        sheet.set_cell_from_input(0, 3, "1/15/2024".to_string());
+        sheet.set_cell_from_input(1, 3, "2/15/2024".to_string());
+        sheet.selection_anchor = Some((0, 0));
+        sheet.cursor_row = 3;
+        sheet.cursor_col = 3;
+        sheet.fill_command("").unwrap();
+        assert_eq!(sheet.get_cell(3, 0), "Thu");
+        assert_eq!(sheet.get_cell(3, 1), "7");
+        assert_eq!(sheet.get_cell(3, 2), "=B4+1");
+        assert_eq!(sheet.display_value(3, 3), "4/15/2024");
+        assert!(sheet.fill_command("sideways").is_err());
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::outline::shift_index;
+use crate::sort::SortKey;
+use crate::spreadsheet::Spreadsheet;
+
+/// What a filtered column lets through
+#[derive(Clone, PartialEq, Debug)]
+pub enum FilterCriterion {
+    /// Only rows whose displayed value is one of these ("" stands for blanks)
+    Values(BTreeSet<String>),
+    /// A COUNTIF-style condition such as `>10`, `<>done` or `app*`
+    Condition(String),
+}
+
+/// Excel-style AutoFilter on the header row of a table
+#[derive(Clone, PartialEq, Debug)]
+pub struct AutoFilter {
+    pub header_row: usize,
+    pub min_col: usize,
+    pub max_col: usize,
+    /// Last data row covered by the filter
+    pub max_row: usize,
+    pub criteria: BTreeMap<usize, FilterCriterion>,
+}
+
+/// The checklist of distinct values shown by `:filter`
+#[derive(Clone, PartialEq, Debug)]
+pub struct FilterPopup {
+    pub col: usize,
+    /// Distinct displayed values and whether each is ticked
+    pub items: Vec<(String, bool)>,
+    pub index: usize,
+}
+
+impl Spreadsheet {
+    pub fn is_row_filtered(&self, row: usize) -> bool {
+        self.filtered_rows.contains(&row)
+    }
+
+    /// Turn the AutoFilter on for the selection (or the table around the cursor), or off again
+    pub fn toggle_auto_filter(&mut self) -> Result<(), String> {
+        if self.auto_filter.take().is_some() {
+            self.filtered_rows.clear();
+            return Ok(());
+        }
+
+        let ((min_row, min_col), (max_row, max_col)) = self
+            .get_selection_range()
+            .or_else(|| self.detect_table_region(self.cursor_row, self.cursor_col))
+            .ok_or_else(|| "No table here to filter".to_string())?;
+        self.auto_filter = Some(AutoFilter {
+            header_row: min_row,
+            min_col,
+            max_col,
+            max_row,
+            criteria: BTreeMap::new(),
+        });
+        self.clear_selection();
+        Ok(())
+    }
+
+    /// The AutoFilter column under the cursor
+    fn filter_column(&self) -> Result<usize, String> {
+        let filter = self.auto_filter.as_ref().ok_or("No AutoFilter (use :autofilter)")?;
+        if self.cursor_col < filter.min_col || self.cursor_col > filter.max_col {
+            return Err("Cursor is outside the filtered table".to_string());
+        }
+        Ok(self.cursor_col)
+    }
+
+    /// Recompute which rows the AutoFilter hides
+    pub fn apply_filters(&mut self) {
+        self.filtered_rows.clear();
+        let Some(mut filter) = self.auto_filter.clone() else {
+            return;
+        };
+
+        // Pick up rows typed directly below the table since the filter was set
+        if let Some((_, (max_row, _))) = self.detect_table_region(filter.header_row, filter.min_col) {
+            filter.max_row = filter.max_row.max(max_row);
+        }
+
+        for row in filter.header_row + 1..=filter.max_row {
+            let mut visible = true;
+            for (&col, criterion) in &filter.criteria {
+                visible = match criterion {
+                    FilterCriterion::Values(values) => values.contains(&self.display_value(row, col)),
+                    FilterCriterion::Condition(condition) => {
+                        let value = self.evaluate_cell(row, col);
+                        !value.is_empty() && self.cell_matches_criteria(&value, condition, row, col)
+                    }
+                };
+                if !visible {
+                    break;
+                }
+            }
+            if !visible {
+                self.filtered_rows.insert(row);
+            }
+        }
+
+        self.auto_filter = Some(filter);
+        self.ensure_cursor_visible();
+    }
+
+    pub fn set_filter(&mut self, col: usize, criterion: Option<FilterCriterion>) {
+        if let Some(filter) = self.auto_filter.as_mut() {
+            match criterion {
+                Some(criterion) => filter.criteria.insert(col, criterion),
+                None => filter.criteria.remove(&col),
+            };
+        }
+        self.apply_filters();
+    }
+
+    /// Distinct displayed values in a filter column, in sort order
+    pub fn filter_column_values(&mut self, col: usize) -> Vec<String> {
+        let Some(filter) = self.auto_filter.clone() else {
+            return Vec::new();
+        };
+        let values: BTreeSet<String> = (filter.header_row + 1..=filter.max_row)
+            .map(|row| self.display_value(row, col))
+            .collect();
+        let mut values: Vec<String> = values.into_iter().collect();
+        let key = SortKey::new(col);
+        values.sort_by(|a, b| key.compare(a, b));
+        values
+    }
+
+    pub fn open_filter_popup(&mut self) -> Result<(), String> {
+        let col = self.filter_column()?;
+        let current = self.auto_filter.as_ref().and_then(|filter| filter.criteria.get(&col).cloned());
+        let items = self
+            .filter_column_values(col)
+            .into_iter()
+            .map(|value| {
+                let checked = match &current {
+                    Some(FilterCriterion::Values(values)) => values.contains(&value),
+                    _ => true,
+                };
+                (value, checked)
+            })
+            .collect();
+        self.filter_popup = Some(FilterPopup { col, items, index: 0 });
+        Ok(())
+    }
+
+    /// Apply the ticked values from the popup; ticking everything clears the column's filter
+    pub fn confirm_filter_popup(&mut self) {
+        if let Some(popup) = self.filter_popup.take() {
+            let criterion = if popup.items.iter().all(|(_, checked)| *checked) {
+                None
+            } else {
+                Some(FilterCriterion::Values(
+                    popup.items.into_iter().filter(|(_, checked)| *checked).map(|(value, _)| value).collect(),
+                ))
+            };
+            self.set_filter(popup.col, criterion);
+        }
+    }
+
+    /// `:filter` - open the value list for the cursor column. With an argument: `value` keeps
+    /// rows matching the cursor cell, `clear` removes the column's filter, `clear all` every
+    /// filter, and anything else is used as a condition (`>10`, `<>done`, `app*`).
+    pub fn filter_command(&mut self, arg: &str) -> Result<(), String> {
+        if arg.eq_ignore_ascii_case("clear all") {
+            if let Some(filter) = self.auto_filter.as_mut() {
+                filter.criteria.clear();
+            }
+            self.apply_filters();
+            return Ok(());
+        }
+
+        let col = self.filter_column()?;
+        match arg.to_lowercase().as_str() {
+            "" => self.open_filter_popup()?,
+            "value" => {
+                let value = self.display_value(self.cursor_row, col);
+                self.set_filter(col, Some(FilterCriterion::Values(BTreeSet::from([value]))));
+            }
+            "clear" => self.set_filter(col, None),
+            _ => self.set_filter(col, Some(FilterCriterion::Condition(arg.to_string()))),
+        }
+        Ok(())
+    }
+
+    /// Keep the AutoFilter and the rows it hides on their rows when one is inserted or
+    /// deleted at `at`. Deleting the header row turns the filter off.
+    pub fn shift_filter_rows(&mut self, at: usize, inserted: bool) {
+        let Some(filter) = self.auto_filter.as_mut() else {
+            return;
+        };
+        if !inserted && filter.header_row == at {
+            self.auto_filter = None;
+            self.filtered_rows.clear();
+            return;
+        }
+        filter.header_row = shift_index(filter.header_row, at, inserted).unwrap_or(at);
+        filter.max_row = shift_index(filter.max_row, at, inserted).unwrap_or(at - 1).max(filter.header_row);
+        self.filtered_rows = self.filtered_rows.iter().filter_map(|&row| shift_index(row, at, inserted)).collect();
+    }
+
+    /// Keep the AutoFilter's columns and their criteria in place when a column is inserted
+    /// or deleted at `at`. Deleting its only column turns the filter off; a deleted column's
+    /// criterion is dropped, so the filter needs applying again afterwards.
+    pub fn shift_filter_cols(&mut self, at: usize, inserted: bool) {
+        let Some(filter) = self.auto_filter.as_mut() else {
+            return;
+        };
+        if !inserted && filter.min_col == at && filter.max_col == at {
+            self.auto_filter = None;
+            self.filtered_rows.clear();
+            return;
+        }
+        filter.min_col = shift_index(filter.min_col, at, inserted).unwrap_or(at);
+        filter.max_col = shift_index(filter.max_col, at, inserted).unwrap_or(at - 1);
+        let criteria = std::mem::take(&mut filter.criteria);
+        filter.criteria =
+            criteria.into_iter().filter_map(|(col, criterion)| Some((shift_index(col, at, inserted)?, criterion))).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn sample_sheet() -> Spreadsheet {
+        let mut sheet = Spreadsheet::new();
+        let data = [["Fruit", "Qty"], ["apple", "5"], ["pear", "12"], ["apple", "20"], ["plum", "1"]];
+        for (row, values) in data.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                sheet.set_cell(row, col, value.to_string());
+            }
+        }
+        sheet.toggle_auto_filter().unwrap();
+        sheet
+    }
+
+    #[test]
+    fn test_filter_by_value_and_condition() {
+        let mut sheet = sample_sheet();
+        sheet.cursor_row = 1;
+        sheet.filter_command("value").unwrap();
+        assert!(sheet.is_row_hidden(2));
+        assert!(sheet.is_row_hidden(4));
+        assert!(!sheet.is_row_hidden(3));
+
+        sheet.cursor_col = 1;
+        sheet.filter_command(">10").unwrap();
+        assert!(sheet.is_row_hidden(1));
+        assert!(!sheet.is_row_hidden(3));
+
+        sheet.filter_command("clear all").unwrap();
+        assert!(sheet.filtered_rows.is_empty());
+    }
+
+    #[test]
+    fn test_filter_popup_and_cursor_movement() {
+        let mut sheet = sample_sheet();
+        sheet.filter_command("").unwrap();
+        let popup = sheet.filter_popup.as_mut().unwrap();
+        assert_eq!(popup.items.iter().map(|(v, _)| v.as_str()).collect::<Vec<_>>(), ["apple", "pear", "plum"]);
+        popup.items[1].1 = false;
+        sheet.confirm_filter_popup();
+        assert!(sheet.is_row_hidden(2));
+
+        // Moving down from row 1 skips the hidden row
+        sheet.cursor_row = 1;
+        sheet.move_cursor(1, 0, false);
+        assert_eq!(sheet.cursor_row, 3);
+
+        // Turning the filter off shows everything again
+        sheet.toggle_auto_filter().unwrap();
+        assert!(!sheet.is_row_hidden(2));
+    }
+
+    #[test]
+    fn test_filter_follows_inserted_and_deleted_rows() {
+        let mut sheet = sample_sheet();
+        sheet.cursor_col = 1;
+        sheet.filter_command(">10").unwrap();
+        assert_eq!(sheet.filtered_rows, HashSet::from([1, 4]));
+
+        // A row inserted under the header moves the hidden rows down
+        sheet.cursor_row = 0;
+        sheet.enter_row_select_mode();
+        sheet.selected_rows = Some((0, 0));
+        sheet.insert_rows_after_selected();
+        let filter = sheet.auto_filter.clone().unwrap();
+        assert_eq!((filter.header_row, filter.max_row), (0, 5));
+        assert_eq!(sheet.filtered_rows, HashSet::from([2, 5]));
+
+        sheet.selected_rows = Some((2, 2));
+        sheet.delete_selected_rows();
+        assert_eq!(sheet.filtered_rows, HashSet::from([4]));
+        assert_eq!(sheet.auto_filter.as_ref().unwrap().max_row, 4);
+
+        // Deleting the filtered column drops its condition
+        sheet.enter_column_select_mode();
+        sheet.selected_cols = Some((1, 1));
+        sheet.delete_selected_columns();
+        assert!(sheet.filtered_rows.is_empty());
+        assert_eq!(sheet.auto_filter.as_ref().unwrap().max_col, 0);
+    }
+
+    #[test]
+    fn test_subtotal_ignores_filtered_rows() {
+        let mut sheet = sample_sheet();
+        sheet.set_cell(6, 1, "=SUBTOTAL(9,B2:B5)".to_string());
+        sheet.set_cell(7, 1, "=SUM(B2:B5)".to_string());
+        assert_eq!(sheet.evaluate_cell(6, 1), "38");
+        sheet.cursor_col = 1;
+        sheet.filter_command(">=5").unwrap();
+        assert_eq!(sheet.evaluate_cell(6, 1), "37");
+        sheet.cursor_col = 0;
+        sheet.filter_command("apple").unwrap();
+        assert_eq!(sheet.evaluate_cell(6, 1), "25");
+        assert_eq!(sheet.evaluate_cell(7, 1), "38");
+        sheet.set_cell(8, 1, "=SUBTOTAL(2,B2:B5)".to_string());
+        assert_eq!(sheet.evaluate_cell(8, 1), "2");
+    }
+}
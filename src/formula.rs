@@ -1,10 +1,92 @@
+use std::collections::HashSet;
+
+use crate::array::AGGREGATES;
+use crate::date::{days_in_month, is_date_serial, now_serial, parse_date_time, seconds_of_day, Date, MAX_SERIAL};
+use crate::expression::{has_operators, single_call, split_args, value_bool};
 use crate::spreadsheet::Spreadsheet;
 
+/// The cell references in a formula, each with the operator (or other character) before it.
+/// Text in quotes is skipped.
+pub fn formula_references(formula: &str) -> Vec<(char, (usize, usize))> {
+    let mut references = Vec::new();
+    let (mut token, mut before, mut last, mut quoted) = (String::new(), '=', '=', false);
+    for c in formula.chars().chain(std::iter::once(' ')) {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if !quoted && (c.is_ascii_alphanumeric() || c == '$') {
+            if token.is_empty() {
+                before = last;
+            }
+            token.push(c);
+            continue;
+        }
+        // A name followed by "(" is a function, even one that looks like a cell (LOG10)
+        if c != '('
+            && let Some(cell) = Spreadsheet::parse_cell_reference(&token.replace('$', "").to_uppercase())
+        {
+            references.push((before, cell));
+        }
+        token.clear();
+        if !c.is_whitespace() {
+            last = c;
+        }
+    }
+    references
+}
+
+/// Find a root of `f` by Newton's method from `guess`, using a numeric derivative. None when
+/// the iteration doesn't converge.
+fn newton_solve(f: impl Fn(f64) -> f64, guess: f64) -> Option<f64> {
+    let mut x = guess;
+    for _ in 0..100 {
+        let h = 1e-7 * x.abs().max(1e-3);
+        let slope = (f(x + h) - f(x - h)) / (2.0 * h);
+        if slope == 0.0 || !slope.is_finite() {
+            return None;
+        }
+        let next = x - f(x) / slope;
+        if !next.is_finite() {
+            return None;
+        }
+        if (next - x).abs() < 1e-12 * next.abs().max(1.0) {
+            return Some(next);
+        }
+        x = next;
+    }
+    None
+}
+
+/// What is left after `nper` periods of a loan or investment; the time-value-of-money
+/// functions each solve this for zero. Payments come at the end of each period, or at the
+/// start when `due`.
+fn tvm_balance(rate: f64, nper: f64, pmt: f64, pv: f64, fv: f64, due: bool) -> f64 {
+    if rate == 0.0 {
+        return pv + pmt * nper + fv;
+    }
+    let growth = (1.0 + rate).powf(nper);
+    pv * growth + pmt * (1.0 + if due { rate } else { 0.0 }) * (growth - 1.0) / rate + fv
+}
+
 impl Spreadsheet {
+    /// A cell's value as shown. Array formulas show their first value and spill the rest
+    /// into the empty cells below and to the right. A formula that depends on its own value
+    /// shows #REF!.
     pub fn evaluate_cell(&mut self, row: usize, col: usize) -> String {
         let content = self.get_cell(row, col).to_string();
         if content.starts_with('=') {
-            self.evaluate_formula(&content, row, col)
+            if !self.evaluating.insert((row, col)) {
+                return "#REF!".to_string();
+            }
+            let value = match self.spill(row, col) {
+                Some(Ok(array)) => array[0][0].clone(),
+                Some(Err(e)) => e.clone(),
+                None => self.evaluate_formula(&content, row, col),
+            };
+            self.evaluating.remove(&(row, col));
+            value
+        } else if content.is_empty() {
+            self.spilled_value(row, col).unwrap_or_default()
         } else {
             content
         }
@@ -12,12 +94,49 @@ impl Spreadsheet {
 
     pub fn evaluate_formula(&mut self, formula: &str, row: usize, col: usize) -> String {
         let expr = formula.strip_prefix('=').unwrap_or(formula).trim();
+
+        // Defined names stand for the range, value or LAMBDA they refer to, and table
+        // references for a column of the table
+        let named;
+        let expr = if !self.has_names() {
+            expr
+        } else {
+            named = match self.expand_names(expr) {
+                Ok(named) => named,
+                Err(e) => return e,
+            };
+            named.as_str()
+        };
+
+        // Spill references (A1#) stand for the range their formula fills
+        let expanded;
+        let expr = if expr.contains('#') {
+            expanded = match self.expand_spill_refs(expr) {
+                Ok(expanded) => expanded,
+                Err(e) => return e,
+            };
+            expanded.as_str()
+        } else {
+            expr
+        };
+
+        // Operators, literals, references, LET, LAMBDA and array results; the chain below
+        // handles a single call to any other function
+        if self.is_array_expression(expr)
+            || single_call(expr).is_none_or(|(name, _)| name == "LET" || name == "LAMBDA")
+        {
+            return self.evaluate_expression(expr, row, col);
+        }
         let expr_upper = expr.to_uppercase();
 
-        // Handle SHELL function (case-insensitive)
-        if expr_upper.starts_with("SHELL(") && expr_upper.ends_with(')') {
-            let inner = &expr[6..expr.len() - 1];
-            return self.evaluate_shell(inner, row, col);
+        // Aggregates over computed arrays, such as SUM(FILTER(...)) or SUM(A1:A3*2)
+        if let Some((name, args)) = single_call(expr)
+            && AGGREGATES.contains(&name.as_str())
+        {
+            let parts = self.split_function_args(args);
+            if parts.iter().any(|part| self.is_array_expression(part) && self.parse_range(part.trim()).is_none()) {
+                return self.evaluate_array_aggregate(&name, &parts, row, col);
+            }
         }
 
         // Handle SUM function (case-insensitive)
@@ -32,6 +151,12 @@ impl Spreadsheet {
             return self.evaluate_avg(inner);
         }
 
+        // AVERAGE is AVG by its Excel name
+        if expr_upper.starts_with("AVERAGE(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_avg(inner);
+        }
+
         // Handle MIN function (case-insensitive)
         if expr_upper.starts_with("MIN(") && expr_upper.ends_with(')') {
             let inner = &expr[4..expr.len() - 1];
@@ -86,6 +211,20 @@ impl Spreadsheet {
             return self.evaluate_averageif(inner, row, col);
         }
 
+        // Handle COUNTIFS, SUMIFS, AVERAGEIFS, MAXIFS and MINIFS functions (case-insensitive)
+        for name in ["COUNTIFS", "SUMIFS", "AVERAGEIFS", "MAXIFS", "MINIFS"] {
+            if expr_upper.starts_with(name) && expr_upper[name.len()..].starts_with('(') && expr_upper.ends_with(')') {
+                let inner = &expr[name.len() + 1..expr.len() - 1];
+                return self.evaluate_ifs(name, inner, row, col);
+            }
+        }
+
+        // Handle SUMPRODUCT function (case-insensitive)
+        if expr_upper.starts_with("SUMPRODUCT(") && expr_upper.ends_with(')') {
+            let inner = &expr[11..expr.len() - 1];
+            return self.evaluate_sumproduct(inner, row, col);
+        }
+
         // Handle ROUND function (case-insensitive)
         if expr_upper.starts_with("ROUND(") && expr_upper.ends_with(')') {
             let inner = &expr[6..expr.len() - 1];
@@ -128,10 +267,66 @@ impl Spreadsheet {
             return self.evaluate_len(inner, row, col);
         }
 
-        // Handle VLOOKUP function (case-insensitive)
-        if expr_upper.starts_with("VLOOKUP(") && expr_upper.ends_with(')') {
+        // Handle VLOOKUP and HLOOKUP functions (case-insensitive)
+        if (expr_upper.starts_with("VLOOKUP(") || expr_upper.starts_with("HLOOKUP(")) && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_table_lookup(inner, row, col, expr_upper.starts_with('V'));
+        }
+
+        // Handle XLOOKUP function (case-insensitive)
+        if expr_upper.starts_with("XLOOKUP(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_xlookup(inner, row, col);
+        }
+
+        // Handle LOOKUP function (case-insensitive)
+        if expr_upper.starts_with("LOOKUP(") && expr_upper.ends_with(')') {
+            let inner = &expr[7..expr.len() - 1];
+            return self.evaluate_lookup(inner, row, col);
+        }
+
+        // Handle INDEX function (case-insensitive)
+        if expr_upper.starts_with("INDEX(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_index(inner, row, col);
+        }
+
+        // Handle MATCH function (case-insensitive)
+        if expr_upper.starts_with("MATCH(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_match(inner, row, col);
+        }
+
+        // Handle OFFSET function (case-insensitive)
+        if expr_upper.starts_with("OFFSET(") && expr_upper.ends_with(')') {
+            let inner = &expr[7..expr.len() - 1];
+            return self.evaluate_offset(inner, row, col);
+        }
+
+        // Handle INDIRECT function (case-insensitive)
+        if expr_upper.starts_with("INDIRECT(") && expr_upper.ends_with(')') {
+            let inner = &expr[9..expr.len() - 1];
+            return self.evaluate_indirect(inner, row, col);
+        }
+
+        // Handle CHOOSE function (case-insensitive)
+        if expr_upper.starts_with("CHOOSE(") && expr_upper.ends_with(')') {
+            let inner = &expr[7..expr.len() - 1];
+            return self.evaluate_choose(inner, row, col);
+        }
+
+        // Handle ROW, ROWS, COLUMN and COLUMNS functions (case-insensitive)
+        for (name, rows, count) in [("ROW(", true, false), ("ROWS(", true, true), ("COLUMN(", false, false), ("COLUMNS(", false, true)] {
+            if expr_upper.starts_with(name) && expr_upper.ends_with(')') {
+                let inner = &expr[name.len()..expr.len() - 1];
+                return self.evaluate_row_col(inner, row, col, rows, count);
+            }
+        }
+
+        // Handle ADDRESS function (case-insensitive)
+        if expr_upper.starts_with("ADDRESS(") && expr_upper.ends_with(')') {
             let inner = &expr[8..expr.len() - 1];
-            return self.evaluate_vlookup(inner, row, col);
+            return self.evaluate_address(inner, row, col);
         }
 
         // Handle AND function (case-insensitive)
@@ -212,26 +407,239 @@ impl Spreadsheet {
             return self.evaluate_proper(inner, row, col);
         }
 
+        // Handle FIND and SEARCH functions (case-insensitive)
+        if (expr_upper.starts_with("FIND(") || expr_upper.starts_with("SEARCH(")) && expr_upper.ends_with(')') {
+            let search = expr_upper.starts_with("SEARCH(");
+            let inner = &expr[if search { 7 } else { 5 }..expr.len() - 1];
+            return self.evaluate_find(inner, row, col, search);
+        }
+
+        // Handle SUBSTITUTE function (case-insensitive)
+        if expr_upper.starts_with("SUBSTITUTE(") && expr_upper.ends_with(')') {
+            let inner = &expr[11..expr.len() - 1];
+            return self.evaluate_substitute(inner, row, col);
+        }
+
+        // Handle REPLACE function (case-insensitive)
+        if expr_upper.starts_with("REPLACE(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_replace(inner, row, col);
+        }
+
+        // Handle REPT function (case-insensitive)
+        if expr_upper.starts_with("REPT(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_rept(inner, row, col);
+        }
+
+        // Handle TEXT function (case-insensitive)
+        if expr_upper.starts_with("TEXT(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_text(inner, row, col);
+        }
+
+        // Handle VALUE function (case-insensitive)
+        if expr_upper.starts_with("VALUE(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_value(inner, row, col);
+        }
+
+        // Handle TEXTJOIN function (case-insensitive)
+        if expr_upper.starts_with("TEXTJOIN(") && expr_upper.ends_with(')') {
+            let inner = &expr[9..expr.len() - 1];
+            return self.evaluate_textjoin(inner, row, col);
+        }
+
+        // Handle TEXTBEFORE and TEXTAFTER functions (case-insensitive)
+        if (expr_upper.starts_with("TEXTBEFORE(") || expr_upper.starts_with("TEXTAFTER(")) && expr_upper.ends_with(')') {
+            let after = expr_upper.starts_with("TEXTAFTER(");
+            let inner = &expr[if after { 10 } else { 11 }..expr.len() - 1];
+            return self.evaluate_text_before_after(inner, row, col, after);
+        }
+
+        // Handle EXACT function (case-insensitive)
+        if expr_upper.starts_with("EXACT(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_exact(inner, row, col);
+        }
+
+        // Handle CHAR function (case-insensitive)
+        if expr_upper.starts_with("CHAR(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_char(inner, row, col);
+        }
+
+        // Handle CODE and UNICODE functions (case-insensitive)
+        if (expr_upper.starts_with("CODE(") || expr_upper.starts_with("UNICODE(")) && expr_upper.ends_with(')') {
+            let unicode = expr_upper.starts_with("UNICODE(");
+            let inner = &expr[if unicode { 8 } else { 5 }..expr.len() - 1];
+            return self.evaluate_code(inner, row, col, unicode);
+        }
+
+        // Handle CLEAN function (case-insensitive)
+        if expr_upper.starts_with("CLEAN(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_clean(inner, row, col);
+        }
+
+        // Handle REGEXMATCH function (case-insensitive); REGEXTEST is Excel's name for it
+        if (expr_upper.starts_with("REGEXMATCH(") || expr_upper.starts_with("REGEXTEST(")) && expr_upper.ends_with(')') {
+            let inner = &expr[expr.find('(').unwrap_or(0) + 1..expr.len() - 1];
+            return self.evaluate_regexmatch(inner, row, col);
+        }
+
+        // Handle REGEXREPLACE function (case-insensitive)
+        if expr_upper.starts_with("REGEXREPLACE(") && expr_upper.ends_with(')') {
+            let inner = &expr[13..expr.len() - 1];
+            return self.evaluate_regexreplace(inner, row, col);
+        }
+
         // Handle PRODUCT function (case-insensitive)
         if expr_upper.starts_with("PRODUCT(") && expr_upper.ends_with(')') {
             let inner = &expr[8..expr.len() - 1];
             return self.evaluate_product(inner);
         }
 
+        // Handle PMT, PV, FV, NPER and RATE functions (case-insensitive)
+        for name in ["PMT", "PV", "FV", "NPER", "RATE"] {
+            if expr_upper.starts_with(name) && expr_upper[name.len()..].starts_with('(') && expr_upper.ends_with(')') {
+                let inner = &expr[name.len() + 1..expr.len() - 1];
+                return self.evaluate_tvm(name, inner, row, col);
+            }
+        }
+
+        // Handle NPV function (case-insensitive)
+        if expr_upper.starts_with("NPV(") && expr_upper.ends_with(')') {
+            let inner = &expr[4..expr.len() - 1];
+            return self.evaluate_npv(inner, row, col);
+        }
+
+        // Handle IRR function (case-insensitive)
+        if expr_upper.starts_with("IRR(") && expr_upper.ends_with(')') {
+            let inner = &expr[4..expr.len() - 1];
+            return self.evaluate_irr(inner, row, col);
+        }
+
+        // Handle XNPV and XIRR functions (case-insensitive)
+        if (expr_upper.starts_with("XNPV(") || expr_upper.starts_with("XIRR(")) && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_xnpv(inner, row, col, expr_upper.starts_with("XIRR"));
+        }
+
+        // Handle SUBTOTAL function (case-insensitive)
+        if expr_upper.starts_with("SUBTOTAL(") && expr_upper.ends_with(')') {
+            let inner = &expr[9..expr.len() - 1];
+            return self.evaluate_subtotal(inner, row, col);
+        }
+
         // Handle MEDIAN function (case-insensitive)
         if expr_upper.starts_with("MEDIAN(") && expr_upper.ends_with(')') {
             let inner = &expr[7..expr.len() - 1];
             return self.evaluate_median(inner);
         }
 
-        // Handle simple arithmetic
-        if let Ok(val) = self.evaluate_arithmetic(expr) {
-            return format!("{}", val);
+        // Date and time functions work on Excel serial numbers
+        if expr_upper.starts_with("TODAY(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return Self::evaluate_today(inner, false);
+        }
+
+        if expr_upper.starts_with("NOW(") && expr_upper.ends_with(')') {
+            let inner = &expr[4..expr.len() - 1];
+            return Self::evaluate_today(inner, true);
+        }
+
+        if expr_upper.starts_with("DATE(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_date(inner, row, col);
+        }
+
+        if expr_upper.starts_with("TIME(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_time(inner, row, col);
+        }
+
+        if expr_upper.starts_with("YEAR(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| Date::from_serial(serial as i64).year as i64);
+        }
+
+        if expr_upper.starts_with("MONTH(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| Date::from_serial(serial as i64).month as i64);
+        }
+
+        if expr_upper.starts_with("DAY(") && expr_upper.ends_with(')') {
+            let inner = &expr[4..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| Date::from_serial(serial as i64).day as i64);
+        }
+
+        if expr_upper.starts_with("HOUR(") && expr_upper.ends_with(')') {
+            let inner = &expr[5..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| seconds_of_day(serial) / 3600);
+        }
+
+        if expr_upper.starts_with("MINUTE(") && expr_upper.ends_with(')') {
+            let inner = &expr[7..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| seconds_of_day(serial) / 60 % 60);
+        }
+
+        if expr_upper.starts_with("SECOND(") && expr_upper.ends_with(')') {
+            let inner = &expr[7..expr.len() - 1];
+            return self.evaluate_date_part(inner, row, col, |serial| seconds_of_day(serial) % 60);
+        }
+
+        if expr_upper.starts_with("WEEKDAY(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_weekday(inner, row, col);
+        }
+
+        if expr_upper.starts_with("WEEKNUM(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_weeknum(inner, row, col);
+        }
+
+        if expr_upper.starts_with("EDATE(") && expr_upper.ends_with(')') {
+            let inner = &expr[6..expr.len() - 1];
+            return self.evaluate_edate(inner, row, col, false);
+        }
+
+        if expr_upper.starts_with("EOMONTH(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_edate(inner, row, col, true);
+        }
+
+        if expr_upper.starts_with("DATEDIF(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_datedif(inner, row, col);
+        }
+
+        if expr_upper.starts_with("NETWORKDAYS(") && expr_upper.ends_with(')') {
+            let inner = &expr[12..expr.len() - 1];
+            return self.evaluate_networkdays(inner, row, col);
+        }
+
+        if expr_upper.starts_with("WORKDAY(") && expr_upper.ends_with(')') {
+            let inner = &expr[8..expr.len() - 1];
+            return self.evaluate_workday(inner, row, col);
+        }
+
+        if expr_upper.starts_with("DATEVALUE(") && expr_upper.ends_with(')') {
+            let inner = &expr[10..expr.len() - 1];
+            return self.evaluate_datevalue(inner, row, col, false);
+        }
+
+        if expr_upper.starts_with("TIMEVALUE(") && expr_upper.ends_with(')') {
+            let inner = &expr[10..expr.len() - 1];
+            return self.evaluate_datevalue(inner, row, col, true);
         }
 
-        // Try to parse as cell reference
-        if let Some(val) = self.get_cell_value(expr) {
-            return format!("{}", val);
+        // Handle statistical functions (STDEV.S, PERCENTILE.INC, NORM.DIST, ...)
+        if let Some(open) = expr_upper.find('(')
+            && expr_upper.ends_with(')')
+            && let Some(result) = self.evaluate_statistic(&expr_upper[..open], &expr[open + 1..expr.len() - 1], row, col)
+        {
+            return result;
         }
 
         "#ERROR".to_string()
@@ -243,19 +651,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                sum += val;
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            sum += val;
                         }
                     }
                 } else {
@@ -278,20 +678,12 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                sum += val;
-                                count += 1.0;
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            sum += val;
+                            count += 1.0;
                         }
                     }
                 } else {
@@ -325,19 +717,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                min = Some(min.map_or(val, |m| m.min(val)));
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            min = Some(min.map_or(val, |m| m.min(val)));
                         }
                     }
                 } else {
@@ -368,19 +752,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                max = Some(max.map_or(val, |m| m.max(val)));
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            max = Some(max.map_or(val, |m| m.max(val)));
                         }
                     }
                 } else {
@@ -459,31 +835,24 @@ impl Spreadsheet {
         format!("{:.6}", correlation)
     }
 
-    fn collect_range_values(&mut self, range: &str) -> Option<Vec<f64>> {
+    /// The numbers in a range, cell reference or literal. Text and blank cells are skipped.
+    pub fn collect_range_values(&mut self, range: &str) -> Option<Vec<f64>> {
         let mut values = Vec::new();
 
-        if let Some((start, end)) = range.split_once(':') {
-            if let (Some((sr, sc)), Some((er, ec))) =
-                (self.parse_cell_ref(start), self.parse_cell_ref(end))
-            {
-                let min_row = sr.min(er);
-                let max_row = sr.max(er);
-                let min_col = sc.min(ec);
-                let max_col = sc.max(ec);
-                for row in min_row..=max_row {
-                    for col in min_col..=max_col {
-                        if let Some(val) = self.get_cell_value_at(row, col) {
-                            values.push(val);
-                        }
+        if range.contains(':') {
+            if let Some(range) = self.parse_range(range) {
+                for (row, col) in range.cells() {
+                    if let Some(val) = self.get_cell_value_at(row, col) {
+                        values.push(val);
                     }
                 }
             } else {
                 return None;
             }
-        } else if let Some(val) = self.get_cell_value(range) {
-            values.push(val);
         } else if let Ok(val) = range.parse::<f64>() {
             values.push(val);
+        } else if let Some((row, col)) = Self::parse_cell_reference(&range.replace('$', "")) {
+            values.extend(self.get_cell_value_at(row, col));
         } else {
             return None;
         }
@@ -491,6 +860,26 @@ impl Spreadsheet {
         Some(values)
     }
 
+    /// The numbers in a list of arguments: ranges, references and arrays skip text and
+    /// blanks, literals and nested functions must be numbers
+    pub fn collect_number_args(&mut self, parts: &[&str], current_row: usize, current_col: usize) -> Option<Vec<f64>> {
+        let mut values = Vec::new();
+        for part in parts {
+            if self.is_array_expression(part) {
+                let array = self.evaluate_array_expression(part, current_row, current_col).ok()?;
+                values.extend(array.iter().flatten().filter_map(|value| value.parse::<f64>().ok()));
+            } else if part.contains('(') {
+                match self.resolve_range(part, current_row, current_col) {
+                    Ok(range) => values.extend(range.cells().into_iter().filter_map(|(row, col)| self.get_cell_value_at(row, col))),
+                    Err(_) => values.push(self.evaluate_arg_as_number(part, current_row, current_col)?),
+                }
+            } else {
+                values.extend(self.collect_range_values(part.trim())?);
+            }
+        }
+        Some(values)
+    }
+
     pub fn evaluate_if(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
         let parts = self.split_function_args(args);
         if parts.len() != 3 {
@@ -511,54 +900,27 @@ impl Spreadsheet {
     }
 
     pub fn split_function_args<'a>(&self, args: &'a str) -> Vec<&'a str> {
-        let mut parts = Vec::new();
-        let mut depth = 0;
-        let mut start = 0;
-
-        for (i, c) in args.char_indices() {
-            match c {
-                '(' => depth += 1,
-                ')' => depth -= 1,
-                ',' if depth == 0 => {
-                    parts.push(&args[start..i]);
-                    start = i + 1;
-                }
-                _ => {}
-            }
-        }
-        parts.push(&args[start..]);
-        parts
+        split_args(args)
     }
 
+    /// Whether a condition holds: any expression giving TRUE/FALSE or a number (non-zero
+    /// is TRUE)
     pub fn evaluate_condition(&mut self, condition: &str, current_row: usize, current_col: usize) -> Option<bool> {
-        let operators = [">=", "<=", "<>", ">", "<", "="];
-
-        for op in operators {
-            if let Some(pos) = condition.find(op) {
-                let left = condition[..pos].trim();
-                let right = condition[pos + op.len()..].trim();
-
-                let left_val = self.evaluate_arg_as_number(left, current_row, current_col)?;
-                let right_val = self.evaluate_arg_as_number(right, current_row, current_col)?;
-
-                return Some(match op {
-                    ">=" => left_val >= right_val,
-                    "<=" => left_val <= right_val,
-                    "<>" => (left_val - right_val).abs() > f64::EPSILON,
-                    ">" => left_val > right_val,
-                    "<" => left_val < right_val,
-                    "=" => (left_val - right_val).abs() < f64::EPSILON,
-                    _ => return None,
-                });
-            }
-        }
+        value_bool(&self.evaluate_formula(&format!("={}", condition), current_row, current_col))
+    }
 
-        self.evaluate_arg_as_number(condition, current_row, current_col).map(|v| v.abs() > f64::EPSILON)
+    /// A TRUE/FALSE argument: the literals, a number (non-zero is TRUE) or a comparison
+    pub fn evaluate_arg_as_bool(&mut self, arg: &str, current_row: usize, current_col: usize) -> Option<bool> {
+        self.evaluate_condition(arg, current_row, current_col)
     }
 
     pub fn evaluate_arg(&mut self, arg: &str, current_row: usize, current_col: usize) -> String {
         let arg = arg.trim();
 
+        if has_operators(arg) && !(arg.len() >= 2 && arg.starts_with('\'') && arg.ends_with('\'')) {
+            return self.evaluate_formula(&format!("={}", arg), current_row, current_col);
+        }
+
         // Check for matching quotes (single or double)
         if arg.len() >= 2 {
             let first_char = arg.chars().next().unwrap();
@@ -586,7 +948,7 @@ impl Spreadsheet {
     pub fn evaluate_arg_as_number(&mut self, arg: &str, current_row: usize, current_col: usize) -> Option<f64> {
         let arg = arg.trim();
 
-        if arg.contains('(') {
+        if arg.contains('(') || has_operators(arg) {
             let result = self.evaluate_formula(&format!("={}", arg), current_row, current_col);
             return result.parse().ok();
         }
@@ -598,39 +960,6 @@ impl Spreadsheet {
         arg.parse().ok()
     }
 
-    pub fn evaluate_arithmetic(&mut self, expr: &str) -> Result<f64, ()> {
-        let expr = expr.trim();
-
-        for (i, c) in expr.char_indices().rev() {
-            if c == '+' || c == '-' {
-                if i == 0 {
-                    continue;
-                }
-                let left = self.evaluate_arithmetic(&expr[..i])?;
-                let right = self.evaluate_arithmetic(&expr[i + 1..])?;
-                return Ok(if c == '+' { left + right } else { left - right });
-            }
-        }
-
-        for (i, c) in expr.char_indices().rev() {
-            if c == '*' || c == '/' {
-                let left = self.evaluate_arithmetic(&expr[..i])?;
-                let right = self.evaluate_arithmetic(&expr[i + 1..])?;
-                return Ok(if c == '*' { left * right } else { left / right });
-            }
-        }
-
-        if let Ok(n) = expr.parse::<f64>() {
-            return Ok(n);
-        }
-
-        if let Some(val) = self.get_cell_value(expr) {
-            return Ok(val);
-        }
-
-        Err(())
-    }
-
     pub fn parse_cell_ref(&self, cell_ref: &str) -> Option<(usize, usize)> {
         let cell_ref = cell_ref.trim().to_uppercase();
         let mut col_str = String::new();
@@ -663,16 +992,10 @@ impl Spreadsheet {
     }
 
     pub fn get_cell_value_at(&mut self, row: usize, col: usize) -> Option<f64> {
-        let content = self.get_cell(row, col).to_string();
-        if content.starts_with('=') {
-            // SHELL formulas don't return numeric values, so skip them
-            let expr = content.strip_prefix('=').unwrap_or(&content).trim();
-            let expr_upper = expr.to_uppercase();
-            if expr_upper.starts_with("SHELL(") {
-                return None;
-            }
-            // For other formulas, evaluate and try to parse as number
-            self.evaluate_formula(&content, row, col).parse().ok()
+        let content = self.get_cell(row, col);
+        if content.starts_with('=') || content.is_empty() {
+            // Formulas and spilled values are evaluated, then read as a number
+            self.evaluate_cell(row, col).parse().ok()
         } else {
             content.parse().ok()
         }
@@ -684,19 +1007,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if self.get_cell_value_at(row, col).is_some() {
-                                count += 1;
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if self.get_cell_value_at(row, col).is_some() {
+                            count += 1;
                         }
                     }
                 } else {
@@ -718,19 +1033,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if !self.get_cell(row, col).is_empty() {
-                                count += 1;
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if !self.get_cell(row, col).is_empty() {
+                            count += 1;
                         }
                     }
                 } else {
@@ -751,198 +1058,6 @@ impl Spreadsheet {
         format!("{}", count)
     }
 
-    pub fn evaluate_countif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
-        let parts = self.split_function_args(args);
-        if parts.len() != 2 {
-            return "#ERROR".to_string();
-        }
-
-        let range = parts[0].trim();
-        let criteria = parts[1].trim();
-
-        let mut count = 0;
-
-        // Collect all cells from the range
-        let cells = self.collect_range_cells(range);
-        for (row, col) in cells {
-            let cell_value = self.get_cell(row, col).to_string();
-            if self.cell_matches_criteria(&cell_value, criteria, current_row, current_col) {
-                count += 1;
-            }
-        }
-
-        format!("{}", count)
-    }
-
-    pub fn evaluate_sumif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
-        let parts = self.split_function_args(args);
-        if parts.len() < 2 || parts.len() > 3 {
-            return "#ERROR".to_string();
-        }
-
-        let range = parts[0].trim();
-        let criteria = parts[1].trim();
-        let sum_range = if parts.len() == 3 {
-            Some(parts[2].trim())
-        } else {
-            None
-        };
-
-        // Handle ranges the same way COUNTIF does - iterate directly
-        let mut sum = 0.0;
-        
-        // Process range similar to how COUNTIF does it
-        if let Some((start, end)) = range.split_once(':') {
-            let start = start.trim();
-            let end = end.trim();
-            if let (Some((sr, sc)), Some((er, ec))) =
-                (self.parse_cell_ref(start), self.parse_cell_ref(end))
-            {
-                let min_row = sr.min(er);
-                let max_row = sr.max(er);
-                let min_col = sc.min(ec);
-                let max_col = sc.max(ec);
-                
-                // Determine sum range cells
-                let sum_cells: Vec<(usize, usize)> = if let Some(sr) = sum_range {
-                    // Parse sum range
-                    if let Some((sum_start, sum_end)) = sr.split_once(':') {
-                        let sum_start = sum_start.trim();
-                        let sum_end = sum_end.trim();
-                        if let (Some((ssr, ssc)), Some((ser, sec))) =
-                            (self.parse_cell_ref(sum_start), self.parse_cell_ref(sum_end))
-                        {
-                            let mut cells = Vec::new();
-                            let sum_min_row = ssr.min(ser);
-                            let sum_max_row = ssr.max(ser);
-                            let sum_min_col = ssc.min(sec);
-                            let sum_max_col = ssc.max(sec);
-                            for row in sum_min_row..=sum_max_row {
-                                for col in sum_min_col..=sum_max_col {
-                                    cells.push((row, col));
-                                }
-                            }
-                            cells
-                        } else {
-                            return "#ERROR".to_string();
-                        }
-                    } else if let Some((row, col)) = self.parse_cell_ref(sr) {
-                        vec![(row, col)]
-                    } else {
-                        return "#ERROR".to_string();
-                    }
-                } else {
-                    // Use criteria range as sum range
-                    let mut cells = Vec::new();
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            cells.push((row, col));
-                        }
-                    }
-                    cells
-                };
-                
-                // Check if ranges have same size
-                let criteria_size = (max_row - min_row + 1) * (max_col - min_col + 1);
-                if criteria_size != sum_cells.len() {
-                    return "#ERROR".to_string();
-                }
-                
-                // Iterate through criteria range and sum matching cells
-                let mut criteria_idx = 0;
-                for row in min_row..=max_row {
-                    for col in min_col..=max_col {
-                        let cell_value = self.get_cell(row, col).to_string();
-                        if self.cell_matches_criteria(&cell_value, criteria, current_row, current_col) {
-                            if let Some((sum_row, sum_col)) = sum_cells.get(criteria_idx) {
-                                if let Some(val) = self.get_cell_value_at(*sum_row, *sum_col) {
-                                    sum += val;
-                                }
-                            }
-                        }
-                        criteria_idx += 1;
-                    }
-                }
-            } else {
-                return "#ERROR".to_string();
-            }
-        } else if let Some((row, col)) = self.parse_cell_ref(range) {
-            // Single cell range
-            let cell_value = self.get_cell(row, col).to_string();
-            if self.cell_matches_criteria(&cell_value, criteria, current_row, current_col) {
-                if let Some(sr) = sum_range {
-                    if let Some((sum_row, sum_col)) = self.parse_cell_ref(sr) {
-                        if let Some(val) = self.get_cell_value_at(sum_row, sum_col) {
-                            sum += val;
-                        }
-                    }
-                } else {
-                    if let Some(val) = self.get_cell_value_at(row, col) {
-                        sum += val;
-                    }
-                }
-            }
-        } else {
-            return "#ERROR".to_string();
-        }
-
-        format!("{}", sum)
-    }
-
-    pub fn evaluate_averageif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
-        let parts = self.split_function_args(args);
-        if parts.len() < 2 || parts.len() > 3 {
-            return "#ERROR".to_string();
-        }
-
-        let range = parts[0].trim();
-        let criteria = parts[1].trim();
-        let avg_range = if parts.len() == 3 {
-            Some(parts[2].trim())
-        } else {
-            None
-        };
-
-        let mut sum = 0.0;
-        let mut count = 0;
-
-        // Collect cells from criteria range
-        let criteria_cells = self.collect_range_cells(range);
-        let avg_cells = if let Some(ar) = avg_range {
-            self.collect_range_cells(ar)
-        } else {
-            criteria_cells.clone()
-        };
-
-        // Both ranges must have the same size
-        if criteria_cells.len() != avg_cells.len() {
-            return "#ERROR".to_string();
-        }
-
-        for (i, (row, col)) in criteria_cells.iter().enumerate() {
-            let cell_value = self.get_cell(*row, *col).to_string();
-            if self.cell_matches_criteria(&cell_value, criteria, current_row, current_col) {
-                if let Some((avg_row, avg_col)) = avg_cells.get(i) {
-                    if let Some(val) = self.get_cell_value_at(*avg_row, *avg_col) {
-                        sum += val;
-                        count += 1;
-                    }
-                }
-            }
-        }
-
-        if count == 0 {
-            return "#DIV/0!".to_string();
-        }
-
-        let avg = sum / (count as f64);
-        if avg.fract() == 0.0 {
-            format!("{:.0}", avg)
-        } else {
-            format!("{}", avg)
-        }
-    }
-
     pub fn evaluate_round(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
         let parts = self.split_function_args(args);
         if parts.len() != 2 {
@@ -1108,308 +1223,16 @@ impl Spreadsheet {
                 text[1..text.len() - 1].to_string()
             } else {
                 text
-            }
-        } else {
-            text
-        };
-
-        format!("{}", text.chars().count())
-    }
-
-    pub fn evaluate_vlookup(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
-        let parts = self.split_function_args(args);
-        if parts.len() < 3 || parts.len() > 4 {
-            return "#ERROR".to_string();
-        }
-
-        let lookup_value_str = parts[0].trim();
-        let table_range = parts[1].trim();
-        let col_index_num_str = parts[2].trim();
-        let range_lookup = if parts.len() == 4 {
-            self.evaluate_arg_as_number(parts[3].trim(), current_row, current_col)
-                .map(|v| v.abs() < f64::EPSILON)
-                .unwrap_or(true)
-        } else {
-            true
-        };
-
-        let lookup_value = self.evaluate_arg(lookup_value_str, current_row, current_col);
-        let col_index = match self.evaluate_arg_as_number(col_index_num_str, current_row, current_col) {
-            Some(n) => n as usize,
-            None => return "#ERROR".to_string(),
-        };
-
-        if col_index == 0 {
-            return "#ERROR".to_string();
-        }
-
-        // Collect table cells
-        let table_cells = self.collect_range_cells(table_range);
-        if table_cells.is_empty() {
-            return "#N/A".to_string();
-        }
-
-        // Find the number of columns in the table
-        let first_row = table_cells[0].0;
-        let mut max_col = table_cells[0].1;
-        for (row, col) in &table_cells {
-            if *row == first_row {
-                max_col = max_col.max(*col);
-            }
-        }
-        let num_cols = max_col - table_cells[0].1 + 1;
-
-        if col_index > num_cols {
-            return "#ERROR".to_string();
-        }
-
-        // Find matching row
-        let target_col = table_cells[0].1; // First column for lookup
-        let mut matching_row = None;
-
-        for (row, col) in &table_cells {
-            if *col == target_col {
-                let cell_value = self.get_cell(*row, *col);
-                if range_lookup {
-                    // Approximate match - find the largest value <= lookup_value
-                    if let Some(cell_num) = cell_value.parse::<f64>().ok() {
-                        if let Some(lookup_num) = lookup_value.parse::<f64>().ok() {
-                            if cell_num <= lookup_num {
-                                matching_row = Some(*row);
-                            } else {
-                                break;
-                            }
-                        }
-                    } else if cell_value == lookup_value {
-                        matching_row = Some(*row);
-                        break;
-                    }
-                } else {
-                    // Exact match
-                    if let Some(cell_num) = cell_value.parse::<f64>().ok() {
-                        if let Some(lookup_num) = lookup_value.parse::<f64>().ok() {
-                            if (cell_num - lookup_num).abs() < f64::EPSILON {
-                                matching_row = Some(*row);
-                                break;
-                            }
-                        }
-                    } else if cell_value == lookup_value {
-                        matching_row = Some(*row);
-                        break;
-                    }
-                }
-            }
-        }
-
-        if let Some(row) = matching_row {
-            let result_col = target_col + col_index - 1;
-            self.evaluate_cell(row, result_col)
-        } else {
-            "#N/A".to_string()
-        }
-    }
-
-    fn collect_range_cells(&self, range: &str) -> Vec<(usize, usize)> {
-        let mut cells = Vec::new();
-        let range = range.trim();
-
-        if let Some((start, end)) = range.split_once(':') {
-            let start = start.trim();
-            let end = end.trim();
-            if let (Some((sr, sc)), Some((er, ec))) =
-                (self.parse_cell_ref(start), self.parse_cell_ref(end))
-            {
-                let min_row = sr.min(er);
-                let max_row = sr.max(er);
-                let min_col = sc.min(ec);
-                let max_col = sc.max(ec);
-                for row in min_row..=max_row {
-                    for col in min_col..=max_col {
-                        cells.push((row, col));
-                    }
-                }
-            }
-        } else if let Some((row, col)) = self.parse_cell_ref(range) {
-            cells.push((row, col));
-        }
-
-        cells
-    }
-
-    fn cell_matches_criteria(&self, cell_value: &str, criteria: &str, _current_row: usize, _current_col: usize) -> bool {
-        let criteria = criteria.trim();
-
-        // Remove quotes if present
-        let criteria = if criteria.len() >= 2 {
-            let first_char = criteria.chars().next().unwrap();
-            let last_char = criteria.chars().last().unwrap();
-            if (first_char == '"' && last_char == '"') || (first_char == '\'' && last_char == '\'') {
-                &criteria[1..criteria.len() - 1]
-            } else {
-                criteria
-            }
-        } else {
-            criteria
-        };
-
-        // Check for comparison operators
-        let operators = [">=", "<=", "<>", ">", "<", "="];
-        for op in operators {
-            if let Some(pos) = criteria.find(op) {
-                let _left = criteria[..pos].trim();
-                let right = criteria[pos + op.len()..].trim();
-
-                // Try to parse cell_value as number
-                let cell_num = cell_value.parse::<f64>().ok();
-                let right_num = right.parse::<f64>().ok();
-
-                if let (Some(cn), Some(rn)) = (cell_num, right_num) {
-                    return match op {
-                        ">=" => cn >= rn,
-                        "<=" => cn <= rn,
-                        "<>" => (cn - rn).abs() > f64::EPSILON,
-                        ">" => cn > rn,
-                        "<" => cn < rn,
-                        "=" => (cn - rn).abs() < f64::EPSILON,
-                        _ => false,
-                    };
-                }
-            }
-        }
-
-        // String comparison (exact match or wildcard)
-        if criteria.contains('*') || criteria.contains('?') {
-            // Simple wildcard matching
-            let _pattern = criteria.replace('*', ".*").replace('?', ".");
-            // For now, just do simple contains check
-            cell_value.contains(&criteria.replace('*', "").replace('?', ""))
-        } else {
-            cell_value == criteria
-        }
-    }
-
-    pub fn evaluate_shell(&mut self, args: &str, start_row: usize, start_col: usize) -> String {
-        // Parse the command argument - handle quoted strings (single or double quotes)
-        let command = if args.len() >= 2 {
-            let first_char = args.chars().next().unwrap();
-            let last_char = args.chars().last().unwrap();
-            if (first_char == '"' && last_char == '"') || (first_char == '\'' && last_char == '\'') {
-                args[1..args.len() - 1].to_string()
-            } else {
-                args.trim().to_string()
-            }
-        } else {
-            args.trim().to_string()
-        };
-
-        if command.is_empty() {
-            return "#ERROR".to_string();
-        }
-
-        // Execute the shell command
-        let output = if cfg!(target_os = "windows") {
-            std::process::Command::new("cmd")
-                .args(["/C", &command])
-                .output()
+            }
         } else {
-            std::process::Command::new("sh")
-                .arg("-c")
-                .arg(&command)
-                .output()
+            text
         };
 
-        match output {
-            Ok(output) => {
-                if !output.status.success() {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return format!("#ERROR: {}", stderr.trim());
-                }
-
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let output_text = stdout.trim();
-
-                if output_text.is_empty() {
-                    return "OK".to_string();
-                }
-
-                // Try to detect if output is tabular (multiple columns)
-                let lines: Vec<&str> = output_text.lines().collect();
-                if lines.is_empty() {
-                    return "OK".to_string();
-                }
-
-                // Check if it looks like a table (multiple columns separated by whitespace)
-                // Consider it tabular if at least 50% of non-empty lines have multiple columns
-                let non_empty_lines: Vec<&str> = lines.iter()
-                    .filter(|line| !line.trim().is_empty())
-                    .copied()
-                    .collect();
-                
-                if non_empty_lines.is_empty() {
-                    return "OK".to_string();
-                }
+        format!("{}", text.chars().count())
+    }
 
-                let multi_col_lines = non_empty_lines.iter()
-                    .filter(|line| {
-                        let parts: Vec<&str> = line.split_whitespace().collect();
-                        parts.len() > 1
-                    })
-                    .count();
-                
-                let is_tabular = multi_col_lines * 2 >= non_empty_lines.len(); // At least 50%
-
-                if is_tabular {
-                    // Parse as table - split by whitespace
-                    let mut current_row = start_row;
-                    let mut max_cols = 0;
-                    
-                    for line in lines {
-                        let trimmed = line.trim();
-                        if trimmed.is_empty() {
-                            continue;
-                        }
-                        
-                        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-                        if !parts.is_empty() {
-                            max_cols = max_cols.max(parts.len());
-                            let mut current_col = start_col;
-                            for part in parts {
-                                // Only write if we're in the first row or if the cell is empty
-                                // This prevents overwriting existing data in subsequent rows
-                                if current_row == start_row || self.get_cell(current_row, current_col).is_empty() {
-                                    self.set_cell(current_row, current_col, part.to_string());
-                                }
-                                current_col += 1;
-                            }
-                            current_row += 1;
-                        }
-                    }
-                    
-                    // Update dimensions if needed
-                    if current_row > self.num_rows {
-                        self.num_rows = current_row;
-                    }
-                    if start_col + max_cols > self.num_cols {
-                        self.num_cols = start_col + max_cols;
-                    }
-                    "OK".to_string()
-                } else {
-                    // Simple text output - write to current cell
-                    // If it's multi-line but not tabular, join with spaces or keep as-is
-                    let text = if lines.len() > 1 {
-                        // For multi-line non-tabular output, join with newlines
-                        output_text.to_string()
-                    } else {
-                        output_text.to_string()
-                    };
-                    self.set_cell(start_row, start_col, text);
-                    "OK".to_string()
-                }
-            }
-            Err(e) => {
-                format!("#ERROR: {}", e)
-            }
-        }
+    fn collect_range_cells(&self, range: &str) -> Vec<(usize, usize)> {
+        self.parse_range(range).map(|range| range.cells()).unwrap_or_default()
     }
 
     pub fn evaluate_and(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
@@ -1814,20 +1637,12 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                product *= val;
-                                has_values = true;
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            product *= val;
+                            has_values = true;
                         }
                     }
                 } else {
@@ -1859,19 +1674,11 @@ impl Spreadsheet {
         for arg in args.split(',') {
             let arg = arg.trim();
 
-            if let Some((start, end)) = arg.split_once(':') {
-                if let (Some((sr, sc)), Some((er, ec))) =
-                    (self.parse_cell_ref(start), self.parse_cell_ref(end))
-                {
-                    let min_row = sr.min(er);
-                    let max_row = sr.max(er);
-                    let min_col = sc.min(ec);
-                    let max_col = sc.max(ec);
-                    for row in min_row..=max_row {
-                        for col in min_col..=max_col {
-                            if let Some(val) = self.get_cell_value_at(row, col) {
-                                values.push(val);
-                            }
+            if arg.contains(':') {
+                if let Some(range) = self.parse_range(arg) {
+                    for (row, col) in range.cells() {
+                        if let Some(val) = self.get_cell_value_at(row, col) {
+                            values.push(val);
                         }
                     }
                 } else {
@@ -1906,6 +1713,637 @@ impl Spreadsheet {
             format!("{}", median)
         }
     }
+
+    /// A date argument as a serial number: a number, a cell, a nested formula or date text
+    fn evaluate_arg_as_date(&mut self, arg: &str, current_row: usize, current_col: usize) -> Option<f64> {
+        if let Some(value) = self.evaluate_arg_as_number(arg, current_row, current_col) {
+            return Some(value);
+        }
+        parse_date_time(&self.evaluate_arg(arg, current_row, current_col)).map(|(serial, _)| serial)
+    }
+
+    /// Evaluate between `min` and `max` date or number arguments
+    fn date_function_args(&mut self, args: &str, min: usize, max: usize, current_row: usize, current_col: usize) -> Option<Vec<f64>> {
+        let parts = if args.trim().is_empty() { Vec::new() } else { self.split_function_args(args) };
+        if parts.len() < min || parts.len() > max {
+            return None;
+        }
+        parts
+            .iter()
+            .map(|part| self.evaluate_arg_as_date(part, current_row, current_col))
+            .collect()
+    }
+
+    /// Serial numbers of the dates in a range (or a single date), for holiday lists
+    fn date_list(&mut self, arg: &str, current_row: usize, current_col: usize) -> HashSet<i64> {
+        if !arg.contains(':') {
+            return self.evaluate_arg_as_date(arg, current_row, current_col).map(|d| d as i64).into_iter().collect();
+        }
+        let mut dates = HashSet::new();
+        for (row, col) in self.collect_range_cells(arg) {
+            let value = self.evaluate_cell(row, col);
+            let serial = value.parse::<f64>().ok().or_else(|| parse_date_time(&value).map(|(serial, _)| serial));
+            dates.extend(serial.map(|serial| serial as i64));
+        }
+        dates
+    }
+
+    /// TODAY() or NOW(), in local time
+    pub fn evaluate_today(args: &str, with_time: bool) -> String {
+        if !args.trim().is_empty() {
+            return "#ERROR".to_string();
+        }
+        let now = now_serial();
+        format!("{}", if with_time { now } else { now.floor() })
+    }
+
+    /// DATE(year, month, day) - months and days past the end roll over, as in Excel
+    pub fn evaluate_date(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let Some(values) = self.date_function_args(args, 3, 3, current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        let (year, month) = (values[0].trunc() as i64, values[1].trunc() as i64);
+        // Years below 1900 count from 1900
+        let year = if (0..1900).contains(&year) { year + 1900 } else { year };
+        if !(1900..=9999).contains(&year) || month.abs() > 120_000 {
+            return "#NUM!".to_string();
+        }
+        // Count the days on from the first of the month in serials, so they step over Excel's 1900-02-29
+        let month_start = Date { year: year as i32, month: 1, day: 1 }.add_months(month - 1);
+        match month_start.to_serial() as f64 + values[2].trunc() - 1.0 {
+            serial if !(0.0..=MAX_SERIAL as f64).contains(&serial) => "#NUM!".to_string(),
+            serial => format!("{}", serial),
+        }
+    }
+
+    /// TIME(hour, minute, second) - the fraction of a day, wrapping at 24 hours
+    pub fn evaluate_time(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let Some(values) = self.date_function_args(args, 3, 3, current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        let seconds = values[0].trunc() * 3600.0 + values[1].trunc() * 60.0 + values[2].trunc();
+        if seconds < 0.0 {
+            return "#NUM!".to_string();
+        }
+        format!("{}", (seconds % 86400.0) / 86400.0)
+    }
+
+    /// YEAR, MONTH, DAY, HOUR, MINUTE and SECOND: one part of a date or time
+    pub fn evaluate_date_part(&mut self, args: &str, current_row: usize, current_col: usize, part: fn(f64) -> i64) -> String {
+        match self.date_function_args(args, 1, 1, current_row, current_col) {
+            Some(values) if is_date_serial(values[0]) => part(values[0]).to_string(),
+            Some(_) => "#NUM!".to_string(),
+            None => "#ERROR".to_string(),
+        }
+    }
+
+    /// WEEKDAY(date, [return_type]) - 1 counts Sunday=1, 2 Monday=1, 3 Monday=0, and 11-17
+    /// count from Monday..Sunday as 1
+    pub fn evaluate_weekday(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let Some(values) = self.date_function_args(args, 1, 2, current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        if !is_date_serial(values[0]) {
+            return "#NUM!".to_string();
+        }
+        // Days from Monday
+        let weekday = Date::from_serial(values[0] as i64).weekday() as i64;
+        let (first_day, base) = match values.get(1).map_or(1, |&t| t as i64) {
+            1 => (6, 1),
+            2 => (0, 1),
+            3 => (0, 0),
+            t @ 11..=17 => (t - 11, 1),
+            _ => return "#NUM!".to_string(),
+        };
+        format!("{}", (weekday - first_day).rem_euclid(7) + base)
+    }
+
+    /// WEEKNUM(date, [return_type]) - weeks start on Sunday (1), Monday (2) or the day 11-17
+    /// picks; 21 is the ISO week number
+    pub fn evaluate_weeknum(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let Some(values) = self.date_function_args(args, 1, 2, current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        if !is_date_serial(values[0]) {
+            return "#NUM!".to_string();
+        }
+        let date = Date::from_serial(values[0] as i64);
+        let first_day = match values.get(1).map_or(1, |&t| t as i64) {
+            1 => 6,
+            2 => 0,
+            t @ 11..=17 => t - 11,
+            21 => {
+                // ISO weeks belong to the year their Thursday falls in
+                let thursday = date.add_days(3 - date.weekday() as i64);
+                let jan1 = Date { year: thursday.year, month: 1, day: 1 };
+                return format!("{}", (thursday.to_days() - jan1.to_days()) / 7 + 1);
+            }
+            _ => return "#NUM!".to_string(),
+        };
+        let jan1 = Date { year: date.year, month: 1, day: 1 };
+        let offset = (jan1.weekday() as i64 - first_day).rem_euclid(7);
+        format!("{}", (date.to_days() - jan1.to_days() + offset) / 7 + 1)
+    }
+
+    /// EDATE(start, months), or EOMONTH(start, months) for the last day of that month
+    pub fn evaluate_edate(&mut self, args: &str, current_row: usize, current_col: usize, end_of_month: bool) -> String {
+        let Some(values) = self.date_function_args(args, 2, 2, current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        // Anything over 10,000 years away is past the last date anyway
+        if !(0.0..=MAX_SERIAL as f64).contains(&values[0]) || values[1].abs() > 120_000.0 {
+            return "#NUM!".to_string();
+        }
+        let mut date = Date::from_serial(values[0] as i64).add_months(values[1].trunc() as i64);
+        if end_of_month {
+            date.day = days_in_month(date.year, date.month);
+        }
+        match date.to_serial() {
+            serial @ 1..=MAX_SERIAL => format!("{}", serial),
+            _ => "#NUM!".to_string(),
+        }
+    }
+
+    /// DATEDIF(start, end, unit) - whole "Y"ears, "M"onths or "D"ays between two dates, or
+    /// "YM" months, "MD" days and "YD" days ignoring the larger units
+    pub fn evaluate_datedif(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() != 3 {
+            return "#ERROR".to_string();
+        }
+        let (Some(start), Some(end)) = (
+            self.evaluate_arg_as_date(parts[0], current_row, current_col),
+            self.evaluate_arg_as_date(parts[1], current_row, current_col),
+        ) else {
+            return "#ERROR".to_string();
+        };
+        if start > end || !is_date_serial(start) || !is_date_serial(end) {
+            return "#NUM!".to_string();
+        }
+        let (start, end) = (Date::from_serial(start as i64), Date::from_serial(end as i64));
+        let months = (end.year as i64 * 12 + end.month as i64) - (start.year as i64 * 12 + start.month as i64)
+            - if end.day < start.day { 1 } else { 0 };
+        let result = match self.evaluate_arg(parts[2], current_row, current_col).to_uppercase().as_str() {
+            "Y" => months / 12,
+            "M" => months,
+            "D" => end.to_days() - start.to_days(),
+            "YM" => months % 12,
+            "MD" if end.day >= start.day => (end.day - start.day) as i64,
+            "MD" => {
+                let previous = end.add_months(-1);
+                days_in_month(previous.year, previous.month) as i64 - start.day as i64 + end.day as i64
+            }
+            "YD" => {
+                let mut anniversary = start.add_months((end.year - start.year) as i64 * 12);
+                if anniversary > end {
+                    anniversary = anniversary.add_months(-12);
+                }
+                end.to_days() - anniversary.to_days()
+            }
+            _ => return "#NUM!".to_string(),
+        };
+        format!("{}", result)
+    }
+
+    fn is_workday(serial: i64, holidays: &HashSet<i64>) -> bool {
+        Date::from_serial(serial).weekday() < 5 && !holidays.contains(&serial)
+    }
+
+    /// NETWORKDAYS(start, end, [holidays]) - Monday-Friday days from start to end, inclusive,
+    /// leaving out the holidays; negative when end is before start
+    pub fn evaluate_networkdays(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let (Some(start), Some(end)) = (
+            self.evaluate_arg_as_date(parts[0], current_row, current_col),
+            self.evaluate_arg_as_date(parts[1], current_row, current_col),
+        ) else {
+            return "#ERROR".to_string();
+        };
+        let holidays = match parts.get(2) {
+            Some(arg) => self.date_list(arg, current_row, current_col),
+            None => HashSet::new(),
+        };
+        if !is_date_serial(start) || !is_date_serial(end) {
+            return "#NUM!".to_string();
+        }
+        let (first, last) = (start.min(end) as i64, start.max(end) as i64);
+        // Any seven days in a row hold five workdays, so count whole weeks and walk the rest
+        let weeks = (last - first + 1) / 7;
+        let no_holidays = HashSet::new();
+        let rest = (first + 7 * weeks..=last).filter(|&serial| Self::is_workday(serial, &no_holidays)).count() as i64;
+        let off = holidays
+            .iter()
+            .filter(|&&serial| (first..=last).contains(&serial) && Self::is_workday(serial, &no_holidays))
+            .count() as i64;
+        let days = 5 * weeks + rest - off;
+        format!("{}", if start > end { -days } else { days })
+    }
+
+    /// WORKDAY(start, days, [holidays]) - the date that many workdays after (or before) start
+    pub fn evaluate_workday(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let (Some(start), Some(days)) = (
+            self.evaluate_arg_as_date(parts[0], current_row, current_col),
+            self.evaluate_arg_as_number(parts[1], current_row, current_col),
+        ) else {
+            return "#ERROR".to_string();
+        };
+        let holidays = match parts.get(2) {
+            Some(arg) => self.date_list(arg, current_row, current_col),
+            None => HashSet::new(),
+        };
+        // Every workday is at least a day, so a count beyond the date range can't land in it
+        if !(0.0..=MAX_SERIAL as f64).contains(&start) || days.abs() > MAX_SERIAL as f64 {
+            return "#NUM!".to_string();
+        }
+        let step = if days < 0.0 { -1 } else { 1 };
+        let mut serial = start as i64;
+        let mut remaining = days.trunc().abs() as i64;
+        // Any seven days in a row hold five workdays, so without holidays skip whole weeks
+        if holidays.is_empty() && remaining > 5 {
+            let weeks = (remaining - 1) / 5;
+            serial += 7 * weeks * step;
+            remaining -= 5 * weeks;
+        }
+        while remaining > 0 {
+            serial += step;
+            if !(1..=MAX_SERIAL).contains(&serial) {
+                return "#NUM!".to_string();
+            }
+            if Self::is_workday(serial, &holidays) {
+                remaining -= 1;
+            }
+        }
+        format!("{}", serial)
+    }
+
+    /// DATEVALUE(text) or TIMEVALUE(text): the date or time part of text such as "2024-03-05 14:30"
+    pub fn evaluate_datevalue(&mut self, args: &str, current_row: usize, current_col: usize, time: bool) -> String {
+        let text = self.evaluate_arg(args, current_row, current_col);
+        match parse_date_time(&text) {
+            Some((serial, code)) if time && code.contains('h') => format!("{}", serial.fract()),
+            Some((serial, code)) if !time && code.contains('y') => format!("{}", serial.floor()),
+            _ => "#ERROR".to_string(),
+        }
+    }
+
+    /// Arguments to a financial function, between `min` and `max` of them. Omitted optional
+    /// arguments (including empty ones, as in `PV(rate, nper, pmt, , 1)`) are None.
+    fn financial_args(&mut self, args: &str, min: usize, max: usize, current_row: usize, current_col: usize) -> Result<Vec<Option<f64>>, String> {
+        let parts = self.split_function_args(args);
+        if parts.len() < min || parts.len() > max {
+            return Err("#ERROR".to_string());
+        }
+        let mut values = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            if i >= min && part.trim().is_empty() {
+                values.push(None);
+                continue;
+            }
+            match self.evaluate_arg_as_number(part, current_row, current_col) {
+                Some(value) => values.push(Some(value)),
+                None => return Err("#VALUE!".to_string()),
+            }
+        }
+        values.resize(max, None);
+        Ok(values)
+    }
+
+    /// PMT(rate, nper, pv, [fv], [type]), PV(rate, nper, pmt, [fv], [type]),
+    /// FV(rate, nper, pmt, [pv], [type]), NPER(rate, pmt, pv, [fv], [type]) and
+    /// RATE(nper, pmt, pv, [fv], [type], [guess]). Money paid out is negative and money
+    /// received positive; type 1 means payments at the start of each period.
+    pub fn evaluate_tvm(&mut self, name: &str, args: &str, current_row: usize, current_col: usize) -> String {
+        let max = if name == "RATE" { 6 } else { 5 };
+        let values = match self.financial_args(args, 3, max, current_row, current_col) {
+            Ok(values) => values,
+            Err(e) => return e,
+        };
+        let (a, b, c) = (values[0].unwrap_or(0.0), values[1].unwrap_or(0.0), values[2].unwrap_or(0.0));
+        let d = values[3].unwrap_or(0.0);
+        let due = values[4].unwrap_or(0.0) != 0.0;
+        let due_factor = |rate: f64| 1.0 + if due { rate } else { 0.0 };
+
+        let result = match name {
+            "PMT" => {
+                let (rate, nper, pv, fv) = (a, b, c, d);
+                if rate == 0.0 {
+                    -(pv + fv) / nper
+                } else {
+                    let growth = (1.0 + rate).powf(nper);
+                    -(pv * growth + fv) * rate / (due_factor(rate) * (growth - 1.0))
+                }
+            }
+            "PV" => {
+                let (rate, nper, pmt, fv) = (a, b, c, d);
+                if rate == 0.0 {
+                    -(fv + pmt * nper)
+                } else {
+                    let growth = (1.0 + rate).powf(nper);
+                    -(fv + pmt * due_factor(rate) * (growth - 1.0) / rate) / growth
+                }
+            }
+            "FV" => {
+                let (rate, nper, pmt, pv) = (a, b, c, d);
+                -tvm_balance(rate, nper, pmt, pv, 0.0, due)
+            }
+            "NPER" => {
+                let (rate, pmt, pv, fv) = (a, b, c, d);
+                if rate == 0.0 {
+                    -(pv + fv) / pmt
+                } else {
+                    let payment = pmt * due_factor(rate);
+                    ((payment - fv * rate) / (payment + pv * rate)).ln() / (1.0 + rate).ln()
+                }
+            }
+            _ => {
+                let (nper, pmt, pv, fv) = (a, b, c, d);
+                let guess = values[5].unwrap_or(0.1);
+                match newton_solve(|rate| tvm_balance(rate, nper, pmt, pv, fv, due), guess) {
+                    Some(rate) => rate,
+                    None => return "#NUM!".to_string(),
+                }
+            }
+        };
+        if result.is_finite() { format!("{}", result) } else { "#NUM!".to_string() }
+    }
+
+    /// NPV(rate, value1, [value2], ...) - cash flows at the end of periods 1, 2, ...
+    pub fn evaluate_npv(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() < 2 {
+            return "#ERROR".to_string();
+        }
+        let (Some(rate), Some(values)) = (
+            self.evaluate_arg_as_number(parts[0], current_row, current_col),
+            self.collect_number_args(&parts[1..], current_row, current_col),
+        ) else {
+            return "#VALUE!".to_string();
+        };
+        let npv: f64 = values.iter().zip(1..).map(|(value, period)| value / (1.0 + rate).powi(period)).sum();
+        if npv.is_finite() { format!("{}", npv) } else { "#NUM!".to_string() }
+    }
+
+    /// IRR(values, [guess]) - the rate at which the cash flows' NPV is zero
+    pub fn evaluate_irr(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(1..=2).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let Some(values) = self.collect_number_args(&parts[..1], current_row, current_col) else {
+            return "#VALUE!".to_string();
+        };
+        let guess = match parts.get(1) {
+            Some(part) => match self.evaluate_arg_as_number(part, current_row, current_col) {
+                Some(guess) => guess,
+                None => return "#VALUE!".to_string(),
+            },
+            None => 0.1,
+        };
+        // A rate of return needs money both going out and coming in
+        if !values.iter().any(|&v| v > 0.0) || !values.iter().any(|&v| v < 0.0) {
+            return "#NUM!".to_string();
+        }
+        let npv = |rate: f64| values.iter().zip(0..).map(|(value, period)| value / (1.0 + rate).powi(period)).sum::<f64>();
+        match newton_solve(npv, guess) {
+            Some(rate) if rate > -1.0 => format!("{}", rate),
+            _ => "#NUM!".to_string(),
+        }
+    }
+
+    /// Cash flows and their dates (as serials) from two equally-sized ranges, for XNPV and XIRR
+    fn dated_cash_flows(&mut self, values: &str, dates: &str, current_row: usize, current_col: usize) -> Result<Vec<(f64, f64)>, String> {
+        let values = self.resolve_range(values, current_row, current_col)?.cells();
+        let dates = self.resolve_range(dates, current_row, current_col)?.cells();
+        if values.len() != dates.len() {
+            return Err("#NUM!".to_string());
+        }
+        let mut flows = Vec::new();
+        for (&(value_row, value_col), &(date_row, date_col)) in values.iter().zip(&dates) {
+            let value = self.get_cell_value_at(value_row, value_col).ok_or_else(|| "#VALUE!".to_string())?;
+            let date = self.evaluate_cell(date_row, date_col);
+            let date = date
+                .parse::<f64>()
+                .ok()
+                .or_else(|| parse_date_time(&date).map(|(serial, _)| serial))
+                .ok_or_else(|| "#VALUE!".to_string())?;
+            flows.push((value, date.floor()));
+        }
+        // Every payment falls on or after the first one
+        match flows.first() {
+            Some(&(_, first)) if flows.iter().all(|&(_, date)| date >= first) => Ok(flows),
+            _ => Err("#NUM!".to_string()),
+        }
+    }
+
+    /// XNPV(rate, values, dates) and XIRR(values, dates, [guess]): NPV and IRR for cash flows
+    /// on arbitrary dates, discounted by the years (of 365 days) since the first one
+    pub fn evaluate_xnpv(&mut self, args: &str, current_row: usize, current_col: usize, irr: bool) -> String {
+        let parts = self.split_function_args(args);
+        let (min, max) = if irr { (2, 3) } else { (3, 3) };
+        if parts.len() < min || parts.len() > max {
+            return "#ERROR".to_string();
+        }
+        let (rate_part, flow_parts) = if irr { (parts.get(2).copied(), &parts[..2]) } else { (Some(parts[0]), &parts[1..]) };
+        let rate = match rate_part {
+            Some(part) => match self.evaluate_arg_as_number(part, current_row, current_col) {
+                Some(rate) => rate,
+                None => return "#VALUE!".to_string(),
+            },
+            None => 0.1,
+        };
+        let flows = match self.dated_cash_flows(flow_parts[0], flow_parts[1], current_row, current_col) {
+            Ok(flows) => flows,
+            Err(e) => return e,
+        };
+        let first = flows[0].1;
+        let xnpv = |rate: f64| flows.iter().map(|&(value, date)| value / (1.0 + rate).powf((date - first) / 365.0)).sum::<f64>();
+        if !irr {
+            let npv = xnpv(rate);
+            return if rate > -1.0 && npv.is_finite() { format!("{}", npv) } else { "#NUM!".to_string() };
+        }
+        if !flows.iter().any(|&(v, _)| v > 0.0) || !flows.iter().any(|&(v, _)| v < 0.0) {
+            return "#NUM!".to_string();
+        }
+        match newton_solve(xnpv, rate) {
+            Some(rate) if rate > -1.0 => format!("{}", rate),
+            _ => "#NUM!".to_string(),
+        }
+    }
+
+    /// SUBTOTAL(function_num, ref1, ...) - aggregate only the rows an AutoFilter leaves visible.
+    /// 1-11 pick AVERAGE, COUNT, COUNTA, MAX, MIN, PRODUCT, STDEV, STDEVP, SUM, VAR, VARP;
+    /// 101-111 also leave out rows hidden by hand or folded into a group. Other SUBTOTALs
+    /// in the range are skipped to avoid double counting.
+    pub fn evaluate_subtotal(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let args = self.split_function_args(args);
+        if args.len() < 2 {
+            return "#ERROR".to_string();
+        }
+        let Some(function_num) = self.evaluate_arg_as_number(args[0], current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        let (function, skip_hidden) = match function_num as i64 {
+            n @ 1..=11 => (n, false),
+            n @ 101..=111 => (n - 100, true),
+            _ => return "#ERROR".to_string(),
+        };
+
+        let mut numbers = Vec::new();
+        let mut non_empty = 0;
+        for arg in &args[1..] {
+            for (row, col) in self.collect_range_cells(arg) {
+                if self.is_row_filtered(row) || (skip_hidden && self.is_row_hidden_by_user(row)) {
+                    continue;
+                }
+                let content = self.get_cell(row, col).to_string();
+                if content.is_empty() || content.to_uppercase().starts_with("=SUBTOTAL(") {
+                    continue;
+                }
+                non_empty += 1;
+                if let Ok(val) = self.evaluate_cell(row, col).parse::<f64>() {
+                    numbers.push(val);
+                }
+            }
+        }
+
+        let count = numbers.len() as f64;
+        let sum: f64 = numbers.iter().sum();
+        let variance = |sample: bool| -> Option<f64> {
+            let divisor = if sample { count - 1.0 } else { count };
+            if divisor <= 0.0 {
+                return None;
+            }
+            let mean = sum / count;
+            Some(numbers.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / divisor)
+        };
+        let result = match function {
+            1 if count > 0.0 => Some(sum / count),
+            2 => Some(count),
+            3 => Some(non_empty as f64),
+            4 => Some(numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max)).filter(|v| v.is_finite()).or(Some(0.0)),
+            5 => Some(numbers.iter().cloned().fold(f64::INFINITY, f64::min)).filter(|v| v.is_finite()).or(Some(0.0)),
+            6 => Some(numbers.iter().product()),
+            7 => variance(true).map(f64::sqrt),
+            8 => variance(false).map(f64::sqrt),
+            9 => Some(sum),
+            10 => variance(true),
+            11 => variance(false),
+            _ => None,
+        };
+
+        match result {
+            Some(val) => format!("{}", val),
+            None => "#DIV/0!".to_string(),
+        }
+    }
+
+    /// Shift the relative cell references in a formula by the given offsets, as when the
+    /// formula is moved or copied to another cell. `$`-anchored parts stay put and
+    /// references pushed off the sheet become `#REF!`.
+    pub fn adjust_formula_references(formula: &str, d_row: isize, d_col: isize) -> String {
+        let chars: Vec<char> = formula.chars().collect();
+        let mut result = String::with_capacity(formula.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            // Copy string literals untouched
+            if c == '"' {
+                result.push(c);
+                i += 1;
+                while i < chars.len() {
+                    result.push(chars[i]);
+                    i += 1;
+                    if chars[i - 1] == '"' {
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let starts_word = i == 0 || !(chars[i - 1].is_ascii_alphanumeric() || chars[i - 1] == '_');
+            if starts_word && (c == '$' || c.is_ascii_alphabetic()) {
+                if let Some((len, shifted)) = Self::shift_reference_at(&chars[i..], d_row, d_col) {
+                    result.push_str(&shifted);
+                    i += len;
+                    continue;
+                }
+                // Not a reference (a function or name): copy the whole word
+                result.push(c);
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    result.push(chars[i]);
+                    i += 1;
+                }
+                continue;
+            }
+
+            result.push(c);
+            i += 1;
+        }
+
+        result
+    }
+
+    /// Match an A1-style reference at the start of `chars` and return its length and shifted text
+    fn shift_reference_at(chars: &[char], d_row: isize, d_col: isize) -> Option<(usize, String)> {
+        let mut i = 0;
+        let col_abs = chars.first() == Some(&'$');
+        if col_abs {
+            i += 1;
+        }
+        let letters_start = i;
+        while i < chars.len() && chars[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        let letters: String = chars[letters_start..i].iter().collect::<String>().to_uppercase();
+        if letters.is_empty() || letters.len() > 3 {
+            return None;
+        }
+        let row_abs = chars.get(i) == Some(&'$');
+        if row_abs {
+            i += 1;
+        }
+        let digits_start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == digits_start {
+            return None;
+        }
+        // A reference can't run straight into more name characters or a call
+        if chars.get(i).is_some_and(|&c| c.is_ascii_alphanumeric() || c == '_' || c == '(') {
+            return None;
+        }
+
+        let digits: String = chars[digits_start..i].iter().collect();
+        let row = digits.parse::<isize>().ok()? - 1;
+        let col = letters.chars().fold(0isize, |acc, c| acc * 26 + (c as isize - 'A' as isize + 1)) - 1;
+        let new_row = if row_abs { row } else { row + d_row };
+        let new_col = if col_abs { col } else { col + d_col };
+        if new_row < 0 || new_col < 0 {
+            return Some((i, "#REF!".to_string()));
+        }
+
+        Some((
+            i,
+            format!(
+                "{}{}{}{}",
+                if col_abs { "$" } else { "" },
+                Spreadsheet::col_name(new_col as usize),
+                if row_abs { "$" } else { "" },
+                new_row + 1
+            ),
+        ))
+    }
 }
 
 #[cfg(test)]
@@ -2583,4 +3021,122 @@ mod tests {
         sheet.set_cell(2, 0, "30".to_string());
         assert_eq!(sheet.evaluate_formula("=MEDIAN(A1:A3,40)", 0, 0), "25");
     }
+
+    #[test]
+    fn test_evaluate_date_functions() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "45356".to_string()); // 2024-03-05, a Tuesday
+        sheet.set_cell(1, 0, "2024-01-31".to_string());
+        sheet.set_cell(2, 0, "45356.6875".to_string()); // 16:30
+
+        assert_eq!(sheet.evaluate_formula("=DATE(2024,3,5)", 0, 1), "45356");
+        assert_eq!(sheet.evaluate_formula("=DATE(2024,14,1)", 0, 1), sheet.evaluate_formula("=DATE(2025,2,1)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=DATE(2024,3,0)", 0, 1), "45351");
+        assert_eq!(sheet.evaluate_formula("=DATE(1900,2,29)", 0, 1), "60");
+        assert_eq!(sheet.evaluate_formula("=DATE(1900,3,1)", 0, 1), "61");
+        assert_eq!(sheet.evaluate_formula("=DATE(2024,1,1E20)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=DAY(60)", 0, 1), "29");
+        assert_eq!(sheet.evaluate_formula("=MONTH(60)", 0, 1), "2");
+        assert_eq!(sheet.evaluate_formula("=TIME(18,0,0)", 0, 1), "0.75");
+        assert_eq!(sheet.evaluate_formula("=YEAR(A1)", 0, 1), "2024");
+        assert_eq!(sheet.evaluate_formula("=MONTH(A2)", 0, 1), "1");
+        assert_eq!(sheet.evaluate_formula("=DAY(\"2024-03-05\")", 0, 1), "5");
+        assert_eq!(sheet.evaluate_formula("=HOUR(A3)", 0, 1), "16");
+        assert_eq!(sheet.evaluate_formula("=MINUTE(A3)", 0, 1), "30");
+        assert_eq!(sheet.evaluate_formula("=SECOND(A3)", 0, 1), "0");
+        assert_eq!(sheet.evaluate_formula("=WEEKDAY(A1)", 0, 1), "3");
+        assert_eq!(sheet.evaluate_formula("=WEEKDAY(A1,2)", 0, 1), "2");
+        assert_eq!(sheet.evaluate_formula("=WEEKNUM(A1)", 0, 1), "10");
+        assert_eq!(sheet.evaluate_formula("=WEEKNUM(DATE(2021,1,3),21)", 0, 1), "53");
+        assert_eq!(sheet.evaluate_formula("=EDATE(A2,1)", 0, 1), sheet.evaluate_formula("=DATE(2024,2,29)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=EOMONTH(A1,0)", 0, 1), sheet.evaluate_formula("=DATE(2024,3,31)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=DATEVALUE(\"3/5/2024\")", 0, 1), "45356");
+        assert_eq!(sheet.evaluate_formula("=TIMEVALUE(\"6:00 PM\")", 0, 1), "0.75");
+        assert_eq!(sheet.evaluate_formula("=DATEVALUE(\"soon\")", 0, 1), "#ERROR");
+        let today: f64 = sheet.evaluate_formula("=TODAY()", 0, 1).parse().unwrap();
+        let now: f64 = sheet.evaluate_formula("=NOW()", 0, 1).parse().unwrap();
+        assert!(now >= today && now < today + 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_datedif_and_workdays() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "2020-02-15".to_string());
+        sheet.set_cell(1, 0, "2024-03-05".to_string());
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A1,A2,\"Y\")", 0, 1), "4");
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A1,A2,\"M\")", 0, 1), "48");
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A1,A2,\"YM\")", 0, 1), "0");
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A1,A2,\"MD\")", 0, 1), "19");
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A1,A2,\"YD\")", 0, 1), "19");
+        assert_eq!(sheet.evaluate_formula("=DATEDIF(A2,A1,\"D\")", 0, 1), "#NUM!");
+
+        // Mon 2024-03-04 to Fri 2024-03-15, with a holiday on Wed 03-06
+        sheet.set_cell(0, 2, "2024-03-04".to_string());
+        sheet.set_cell(1, 2, "2024-03-15".to_string());
+        sheet.set_cell(0, 3, "2024-03-06".to_string());
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(C1,C2)", 0, 1), "10");
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(C1,C2,D1:D3)", 0, 1), "9");
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(C2,C1)", 0, 1), "-10");
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,5)", 0, 1), sheet.evaluate_formula("=DATE(2024,3,11)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,5,D1)", 0, 1), sheet.evaluate_formula("=DATE(2024,3,12)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,-1)", 0, 1), sheet.evaluate_formula("=DATE(2024,3,1)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,23)", 0, 1), sheet.evaluate_formula("=DATE(2024,4,4)", 0, 1));
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,-23)", 0, 1), sheet.evaluate_formula("=DATE(2024,1,31)", 0, 1));
+        // Results outside Excel's dates are #NUM!, however far out they are
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(1,-100)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,1E11)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=WORKDAY(C1,2100000)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=EDATE(1,1E9)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=EOMONTH(1,-2)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(1,1E18)", 0, 1), "#NUM!");
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(C1,DATE(2025,3,4))", 0, 1), "262");
+        assert_eq!(sheet.evaluate_formula("=NETWORKDAYS(DATE(2025,3,4),C1,D1:D3)", 0, 1), "-261");
+        for formula in ["=WEEKDAY(1E20)", "=WEEKNUM(1E20)", "=DATEDIF(1,1E20,\"Y\")", "=YEAR(1E20)", "=HOUR(3E6)"] {
+            assert_eq!(sheet.evaluate_formula(formula, 0, 1), "#NUM!", "{}", formula);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_financial_functions() {
+        let mut sheet = Spreadsheet::new();
+        let close = |actual: String, expected: f64| {
+            let value: f64 = actual.parse().unwrap_or_else(|_| panic!("not a number: {}", actual));
+            assert!((value - expected).abs() < 1e-6 * expected.abs().max(1.0), "{} != {}", actual, expected);
+        };
+        sheet.set_cell(0, 0, "=0.08/12".to_string());
+        sheet.set_cell(0, 1, "=0.06/12".to_string());
+        close(sheet.evaluate_formula("=PMT(A1,10,10000)", 9, 9), -1037.0320893591606);
+        close(sheet.evaluate_formula("=PMT(B1,216,0,50000)", 9, 9), -129.0811608679973);
+        close(sheet.evaluate_formula("=PMT(0,10,1000)", 9, 9), -100.0);
+        close(sheet.evaluate_formula("=FV(B1,10,-200,-500,1)", 9, 9), 2581.4033740601185);
+        close(sheet.evaluate_formula("=PV(A1,240,500,,0)", 9, 9), -59777.14585118777);
+        close(sheet.evaluate_formula("=NPER(0.01,-100,-1000,10000,1)", 9, 9), 59.67386567429457);
+        close(sheet.evaluate_formula("=RATE(48,-200,8000)", 9, 9), 0.007701472488246008);
+        close(sheet.evaluate_formula("=NPV(0.1,-10000,3000,4200,6800)", 9, 9), 1188.4434123352207);
+
+        for (row, (value, date)) in [("-70000", "2008-01-01"), ("12000", "2008-03-01"), ("15000", "2008-10-30"), ("18000", "2009-02-15"), ("21000", "2009-04-01"), ("26000", "2009-06-01")].iter().enumerate() {
+            sheet.set_cell(row + 1, 0, value.to_string());
+            sheet.set_cell(row + 1, 1, date.to_string());
+        }
+        close(sheet.evaluate_formula("=IRR(A2:A7)", 9, 9), 0.08663094803653162);
+        close(sheet.evaluate_formula("=IRR(A2:A7,-0.1)", 9, 9), 0.08663094803653162);
+        assert_eq!(sheet.evaluate_formula("=IRR(A3:A7)", 9, 9), "#NUM!");
+        close(sheet.evaluate_formula("=XNPV(0.09,A2:A6,B2:B6)", 9, 9), -9012.489112682575);
+        close(sheet.evaluate_formula("=XIRR(A2:A7,B2:B7)", 9, 9), 0.30026597460482607);
+        assert_eq!(sheet.evaluate_formula("=XNPV(0.09,A2:A6,B2:B5)", 9, 9), "#NUM!");
+    }
+
+    #[test]
+    fn test_adjust_formula_references() {
+        assert_eq!(
+            Spreadsheet::adjust_formula_references("=A1+$B$2+B$3+$C4*SUM(C1:C4)", 2, 1),
+            "=B3+$B$2+C$3+$C6*SUM(D3:D6)"
+        );
+        // Function names and string literals are left alone
+        assert_eq!(
+            Spreadsheet::adjust_formula_references("=IF(A1>0,\"A1\",LOG10(A2))", 1, 0),
+            "=IF(A2>0,\"A1\",LOG10(A3))"
+        );
+        assert_eq!(Spreadsheet::adjust_formula_references("=A1+B2", -1, 0), "=#REF!+B1");
+    }
 }
@@ -2,13 +2,16 @@ use std::io;
 use std::sync::mpsc::Receiver;
 use std::time::Duration;
 
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::{backend::CrosstermBackend, Terminal};
 
 use crate::constants::COLOR_PALETTE;
 use crate::settings;
 use crate::spreadsheet::Spreadsheet;
-use crate::types::{DataType, RowColumnSelectMode, SaveFormat, TextAlignment, VerticalAlignment, VisualSubMode};
+use crate::types::{DataType, GridHit, MouseDrag, RowColumnSelectMode, SaveFormat, TextAlignment, VerticalAlignment, VisualSubMode};
 use crate::ui;
 use crate::update::{self, UpdateMessage};
 
@@ -17,8 +20,19 @@ pub fn run_app(
     mut spreadsheet: Spreadsheet,
     update_rx: Receiver<UpdateMessage>,
 ) -> io::Result<()> {
+    let mut mouse_captured = true;
 
     loop {
+        // Apply :mouse toggles; releasing capture gives back native text selection
+        if spreadsheet.mouse_enabled != mouse_captured {
+            if spreadsheet.mouse_enabled {
+                crossterm::execute!(terminal.backend_mut(), EnableMouseCapture)?;
+            } else {
+                crossterm::execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            }
+            mouse_captured = spreadsheet.mouse_enabled;
+        }
+
         // Check for update messages (non-blocking)
         if let Ok(msg) = update_rx.try_recv() {
             match msg {
@@ -36,6 +50,9 @@ pub fn run_app(
             }
         }
 
+        // Pick up SHELL commands that finished since the last frame
+        spreadsheet.poll_shell_jobs();
+
         terminal.draw(|f| ui::render(f, &mut spreadsheet))?;
 
         // Use poll with timeout to allow checking update messages periodically
@@ -45,6 +62,8 @@ pub fn run_app(
                     if key.kind != KeyEventKind::Press {
                         continue;
                     }
+                    // Any key brings the view back to following the cursor
+                    spreadsheet.mouse_scrolled = false;
 
                     // Handle update prompt first if shown
                     if spreadsheet.update_prompt_shown && !spreadsheet.update_in_progress {
@@ -53,7 +72,9 @@ pub fn run_app(
                         }
                     }
 
-                    if spreadsheet.editing {
+                    if spreadsheet.filter_popup.is_some() {
+                        handle_filter_popup(&mut spreadsheet, key.code);
+                    } else if spreadsheet.editing {
                         handle_editing_mode(&mut spreadsheet, key.code, key.modifiers);
                     } else if spreadsheet.command_mode {
                         if handle_command_mode(&mut spreadsheet, key.code) {
@@ -78,8 +99,21 @@ pub fn run_app(
                             return Ok(());
                         }
                     }
+
+                    // Keep live pivots in step with any edit the key made; cursor moves change nothing
+                    if spreadsheet.cells_changed && !spreadsheet.editing {
+                        if !spreadsheet.pivots.is_empty() {
+                            spreadsheet.refresh_pivots();
+                        }
+                        spreadsheet.cells_changed = false;
+                    }
+                }
+                Ok(Event::Mouse(mouse)) => {
+                    if spreadsheet.mouse_enabled {
+                        handle_mouse(&mut spreadsheet, mouse);
+                    }
                 }
-                Ok(_) => {} // Ignore non-key events
+                Ok(_) => {} // Ignore other events
                 Err(e) => {
                     // If we can't read events, it might be a terminal issue
                     // Try to restore terminal and exit gracefully
@@ -186,12 +220,15 @@ fn handle_normal_editing(spreadsheet: &mut Spreadsheet, code: KeyCode) {
                 // Replace the prefix in edit_buffer with the full formula name
                 let prefix_start = spreadsheet.edit_buffer.find('=').unwrap_or(0) + 1;
                 let prefix_end = prefix_start + spreadsheet.formula_prefix.len();
+                let takes_args = spreadsheet.suggestion_takes_args(selected);
                 spreadsheet.edit_buffer.replace_range(prefix_start..prefix_end, selected);
-                spreadsheet.edit_buffer.push('(');
                 spreadsheet.formula_autocomplete_active = false;
                 spreadsheet.formula_suggestions.clear();
                 spreadsheet.formula_prefix.clear();
-                spreadsheet.enter_ref_selection_mode();
+                if takes_args {
+                    spreadsheet.edit_buffer.push('(');
+                    spreadsheet.enter_ref_selection_mode();
+                }
             } else {
                 spreadsheet.finish_editing();
             }
@@ -251,12 +288,15 @@ fn handle_normal_editing(spreadsheet: &mut Spreadsheet, code: KeyCode) {
                 let selected = &spreadsheet.formula_suggestions[spreadsheet.formula_suggestion_index];
                 let prefix_start = spreadsheet.edit_buffer.find('=').unwrap_or(0) + 1;
                 let prefix_end = prefix_start + spreadsheet.formula_prefix.len();
+                let takes_args = spreadsheet.suggestion_takes_args(selected);
                 spreadsheet.edit_buffer.replace_range(prefix_start..prefix_end, selected);
-                spreadsheet.edit_buffer.push('(');
                 spreadsheet.formula_autocomplete_active = false;
                 spreadsheet.formula_suggestions.clear();
                 spreadsheet.formula_prefix.clear();
-                spreadsheet.enter_ref_selection_mode();
+                if takes_args {
+                    spreadsheet.edit_buffer.push('(');
+                    spreadsheet.enter_ref_selection_mode();
+                }
             } else {
                 spreadsheet.finish_editing_with_move(0, 1);
             }
@@ -298,6 +338,8 @@ fn handle_save_mode(spreadsheet: &mut Spreadsheet, code: KeyCode) -> bool {
     match code {
         KeyCode::Char('1') => spreadsheet.save_format = SaveFormat::Csv,
         KeyCode::Char('2') => spreadsheet.save_format = SaveFormat::Tsv,
+        KeyCode::Char('3') => spreadsheet.save_format = SaveFormat::Xlsx,
+        KeyCode::Char('4') => spreadsheet.save_format = SaveFormat::Native,
         KeyCode::Char(c) if c.is_alphanumeric() || c == '_' || c == '-' => {
             spreadsheet.save_filename.push(c);
             spreadsheet.save_message = None;
@@ -319,6 +361,32 @@ fn handle_save_mode(spreadsheet: &mut Spreadsheet, code: KeyCode) -> bool {
     false
 }
 
+fn handle_filter_popup(spreadsheet: &mut Spreadsheet, code: KeyCode) {
+    let Some(popup) = spreadsheet.filter_popup.as_mut() else {
+        return;
+    };
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => popup.index = popup.index.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => {
+            popup.index = (popup.index + 1).min(popup.items.len().saturating_sub(1));
+        }
+        KeyCode::Char(' ') => {
+            if let Some(item) = popup.items.get_mut(popup.index) {
+                item.1 = !item.1;
+            }
+        }
+        KeyCode::Char('a') => {
+            let all = popup.items.iter().all(|(_, checked)| *checked);
+            for item in &mut popup.items {
+                item.1 = !all;
+            }
+        }
+        KeyCode::Enter => spreadsheet.confirm_filter_popup(),
+        KeyCode::Esc => spreadsheet.filter_popup = None,
+        _ => {}
+    }
+}
+
 fn handle_visual_mode(spreadsheet: &mut Spreadsheet, code: KeyCode) {
     match spreadsheet.visual_sub_mode {
         VisualSubMode::Main => handle_visual_main(spreadsheet, code),
@@ -330,6 +398,7 @@ fn handle_visual_mode(spreadsheet: &mut Spreadsheet, code: KeyCode) {
         VisualSubMode::VerticalAlignment => handle_visual_vertical_alignment(spreadsheet, code),
         VisualSubMode::FontSize => handle_visual_font_size(spreadsheet, code),
         VisualSubMode::DataType => handle_visual_data_type(spreadsheet, code),
+        VisualSubMode::Rules => handle_visual_rules(spreadsheet, code),
     }
 }
 
@@ -359,17 +428,42 @@ fn handle_visual_main(spreadsheet: &mut Spreadsheet, code: KeyCode) {
         KeyCode::Char('t') | KeyCode::Char('T') => {
             spreadsheet.visual_sub_mode = VisualSubMode::DataType;
         }
+        KeyCode::Char('r') | KeyCode::Char('R') => {
+            spreadsheet.toggle_wrap_selection();
+        }
         KeyCode::Char('c') | KeyCode::Char('C') => {
             spreadsheet.clear_formatting_from_selection();
         }
         KeyCode::Char('m') | KeyCode::Char('M') => {
             spreadsheet.toggle_dark_mode();
         }
+        KeyCode::Char('o') | KeyCode::Char('O') => {
+            spreadsheet.rule_index = 0;
+            spreadsheet.visual_sub_mode = VisualSubMode::Rules;
+        }
         KeyCode::Esc | KeyCode::Tab => spreadsheet.exit_visual_mode(),
         _ => {}
     }
 }
 
+/// Conditional formatting rule list: move, edit, delete or add rules
+fn handle_visual_rules(spreadsheet: &mut Spreadsheet, code: KeyCode) {
+    let count = spreadsheet.conditional_rules.len();
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => spreadsheet.rule_index = spreadsheet.rule_index.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => {
+            spreadsheet.rule_index = (spreadsheet.rule_index + 1).min(count.saturating_sub(1));
+        }
+        KeyCode::Enter | KeyCode::Char('e') if count > 0 => {
+            spreadsheet.edit_conditional_rule(Some(spreadsheet.rule_index));
+        }
+        KeyCode::Char('d') => spreadsheet.delete_conditional_rule(spreadsheet.rule_index),
+        KeyCode::Char('n') => spreadsheet.edit_conditional_rule(None),
+        KeyCode::Esc => spreadsheet.visual_sub_mode = VisualSubMode::Main,
+        _ => {}
+    }
+}
+
 fn handle_visual_text_color(spreadsheet: &mut Spreadsheet, code: KeyCode) {
     match code {
         KeyCode::Char(c) if c.is_ascii_digit() => {
@@ -412,6 +506,9 @@ fn handle_visual_column_width(spreadsheet: &mut Spreadsheet, code: KeyCode) {
             let current = spreadsheet.get_col_width(col);
             spreadsheet.set_col_width(col, current + 1);
         }
+        KeyCode::Char('a') | KeyCode::Char('A') => {
+            spreadsheet.autofit_selected_columns();
+        }
         KeyCode::Esc => spreadsheet.visual_sub_mode = VisualSubMode::Main,
         _ => {}
     }
@@ -429,6 +526,9 @@ fn handle_visual_row_height(spreadsheet: &mut Spreadsheet, code: KeyCode) {
             let current = spreadsheet.get_row_height(row);
             spreadsheet.set_row_height(row, current + 1);
         }
+        KeyCode::Char('a') | KeyCode::Char('A') => {
+            spreadsheet.autofit_selected_row_heights();
+        }
         KeyCode::Esc => spreadsheet.visual_sub_mode = VisualSubMode::Main,
         _ => {}
     }
@@ -553,6 +653,13 @@ fn handle_ready_mode(
         KeyCode::Char('v') if ctrl_or_cmd => {
             spreadsheet.paste();
         }
+        // Fill down / fill right (Ctrl+D / Ctrl+R)
+        KeyCode::Char('d') if ctrl_or_cmd => {
+            spreadsheet.fill_down();
+        }
+        KeyCode::Char('r') if ctrl_or_cmd => {
+            spreadsheet.fill_right();
+        }
         // Cmd+Arrow or Alt+Arrow: Jump to last data column/row
         // On macOS, Cmd+Arrow might be intercepted by the system, so Alt+Arrow is more reliable
         KeyCode::Right if cmd || alt => {
@@ -628,6 +735,8 @@ fn handle_ready_mode(
             spreadsheet.jump_to_first_row();
             return false;
         }
+        KeyCode::Char('\x04') => spreadsheet.fill_down(),  // Ctrl+D
+        KeyCode::Char('\x12') => spreadsheet.fill_right(), // Ctrl+R
         KeyCode::Char('q') | KeyCode::Char('Q') => return true,
         KeyCode::Char('o') | KeyCode::Char('O') => spreadsheet.enter_open_mode(),
         KeyCode::Char('s') | KeyCode::Char('S') => spreadsheet.enter_save_mode(),
@@ -664,11 +773,43 @@ fn handle_ready_mode(
     false
 }
 
+/// Hide/unhide and outline keys shared by row and column select mode.
+/// Returns true if the key was handled.
+fn handle_outline_keys(spreadsheet: &mut Spreadsheet, code: KeyCode) -> bool {
+    // Second key of a fold command: zc / zo / za on the group at the cursor, zM / zR for all
+    if spreadsheet.pending_key.take() == Some('z') {
+        match code {
+            KeyCode::Char('c') => spreadsheet.fold_group_at_cursor(Some(true)),
+            KeyCode::Char('o') => spreadsheet.fold_group_at_cursor(Some(false)),
+            KeyCode::Char('a') => spreadsheet.fold_group_at_cursor(None),
+            KeyCode::Char('M') => spreadsheet.fold_all_groups(true),
+            KeyCode::Char('R') => spreadsheet.fold_all_groups(false),
+            _ => {}
+        }
+        return true;
+    }
+
+    match code {
+        KeyCode::Char('h') => spreadsheet.hide_selected(),
+        KeyCode::Char('u') => spreadsheet.unhide_selected(),
+        KeyCode::Char('U') => spreadsheet.unhide_all(),
+        KeyCode::Char('g') => spreadsheet.group_selected(),
+        KeyCode::Char('G') => spreadsheet.ungroup_selected(),
+        KeyCode::Char('z') => spreadsheet.pending_key = Some('z'),
+        _ => return false,
+    }
+    true
+}
+
 fn handle_row_column_select_mode(
     spreadsheet: &mut Spreadsheet,
     code: KeyCode,
     _modifiers: KeyModifiers,
 ) {
+    if handle_outline_keys(spreadsheet, code) {
+        return;
+    }
+
     match spreadsheet.row_column_select_mode {
         RowColumnSelectMode::RowSelect => {
             match code {
@@ -676,13 +817,18 @@ fn handle_row_column_select_mode(
                     // If cursor is at max_row and selection has more than one row, deselect bottom row
                     // Otherwise extend selection up
                     if let Some((min_row, max_row)) = spreadsheet.selected_rows {
+                        // Hidden rows are stepped over
+                        let prev = spreadsheet
+                            .cursor_row
+                            .checked_sub(1)
+                            .and_then(|row| spreadsheet.next_visible_row(row, -1));
                         if spreadsheet.cursor_row == max_row && max_row > min_row {
                             // Deselect the bottom row
-                            spreadsheet.cursor_row -= 1;
-                            spreadsheet.selected_rows = Some((min_row, max_row - 1));
-                        } else if spreadsheet.cursor_row > 0 {
+                            spreadsheet.cursor_row = prev.unwrap_or(min_row).max(min_row);
+                            spreadsheet.selected_rows = Some((min_row, spreadsheet.cursor_row));
+                        } else if let Some(prev) = prev {
                             // Extend selection up
-                            spreadsheet.cursor_row -= 1;
+                            spreadsheet.cursor_row = prev;
                             spreadsheet.selected_rows = Some((
                                 spreadsheet.cursor_row.min(min_row),
                                 max_row,
@@ -694,13 +840,14 @@ fn handle_row_column_select_mode(
                     // If cursor is at min_row and selection has more than one row, deselect top row
                     // Otherwise extend selection down
                     if let Some((min_row, max_row)) = spreadsheet.selected_rows {
+                        let next = spreadsheet.next_visible_row(spreadsheet.cursor_row + 1, 1);
                         if spreadsheet.cursor_row == min_row && max_row > min_row {
                             // Deselect the top row
-                            spreadsheet.cursor_row += 1;
-                            spreadsheet.selected_rows = Some((min_row + 1, max_row));
-                        } else if spreadsheet.cursor_row < spreadsheet.num_rows - 1 {
+                            spreadsheet.cursor_row = next.unwrap_or(max_row).min(max_row);
+                            spreadsheet.selected_rows = Some((spreadsheet.cursor_row, max_row));
+                        } else if let Some(next) = next {
                             // Extend selection down
-                            spreadsheet.cursor_row += 1;
+                            spreadsheet.cursor_row = next;
                             spreadsheet.selected_rows = Some((
                                 min_row,
                                 spreadsheet.cursor_row.max(max_row),
@@ -726,13 +873,18 @@ fn handle_row_column_select_mode(
                     // If cursor is at max_col and selection has more than one column, deselect rightmost column
                     // Otherwise extend selection left
                     if let Some((min_col, max_col)) = spreadsheet.selected_cols {
+                        // Hidden columns are stepped over
+                        let prev = spreadsheet
+                            .cursor_col
+                            .checked_sub(1)
+                            .and_then(|col| spreadsheet.next_visible_col(col, -1));
                         if spreadsheet.cursor_col == max_col && max_col > min_col {
                             // Deselect the rightmost column
-                            spreadsheet.cursor_col -= 1;
-                            spreadsheet.selected_cols = Some((min_col, max_col - 1));
-                        } else if spreadsheet.cursor_col > 0 {
+                            spreadsheet.cursor_col = prev.unwrap_or(min_col).max(min_col);
+                            spreadsheet.selected_cols = Some((min_col, spreadsheet.cursor_col));
+                        } else if let Some(prev) = prev {
                             // Extend selection left
-                            spreadsheet.cursor_col -= 1;
+                            spreadsheet.cursor_col = prev;
                             spreadsheet.selected_cols = Some((
                                 spreadsheet.cursor_col.min(min_col),
                                 max_col,
@@ -744,13 +896,14 @@ fn handle_row_column_select_mode(
                     // If cursor is at min_col and selection has more than one column, deselect leftmost column
                     // Otherwise extend selection right
                     if let Some((min_col, max_col)) = spreadsheet.selected_cols {
+                        let next = spreadsheet.next_visible_col(spreadsheet.cursor_col + 1, 1);
                         if spreadsheet.cursor_col == min_col && max_col > min_col {
                             // Deselect the leftmost column
-                            spreadsheet.cursor_col += 1;
-                            spreadsheet.selected_cols = Some((min_col + 1, max_col));
-                        } else if spreadsheet.cursor_col < spreadsheet.num_cols - 1 {
+                            spreadsheet.cursor_col = next.unwrap_or(max_col).min(max_col);
+                            spreadsheet.selected_cols = Some((spreadsheet.cursor_col, max_col));
+                        } else if let Some(next) = next {
                             // Extend selection right
-                            spreadsheet.cursor_col += 1;
+                            spreadsheet.cursor_col = next;
                             spreadsheet.selected_cols = Some((
                                 min_col,
                                 spreadsheet.cursor_col.max(max_col),
@@ -840,6 +993,140 @@ fn handle_command_mode(spreadsheet: &mut Spreadsheet, code: KeyCode) -> bool {
     false
 }
 
+/// Whether the formula being typed is at a spot where a cell reference can go
+fn expects_reference(buffer: &str) -> bool {
+    matches!(
+        buffer.trim_end().chars().last(),
+        Some('=' | '(' | ',' | '+' | '-' | '*' | '/' | '^' | '&' | '<' | '>' | ':')
+    )
+}
+
+fn handle_mouse(spreadsheet: &mut Spreadsheet, mouse: MouseEvent) {
+    // Prompts that take typed input ignore the mouse
+    if spreadsheet.command_mode
+        || spreadsheet.open_mode
+        || spreadsheet.save_mode
+        || spreadsheet.find_mode
+        || spreadsheet.filter_popup.is_some()
+    {
+        return;
+    }
+
+    let shift = mouse.modifiers.contains(KeyModifiers::SHIFT);
+    let hit = spreadsheet.grid_layout.hit_test(mouse.column, mouse.row);
+
+    match mouse.kind {
+        // Shift+wheel scrolls horizontally, as in most spreadsheet apps
+        MouseEventKind::ScrollDown if shift => spreadsheet.scroll_by(0, 1),
+        MouseEventKind::ScrollUp if shift => spreadsheet.scroll_by(0, -1),
+        MouseEventKind::ScrollDown => spreadsheet.scroll_by(3, 0),
+        MouseEventKind::ScrollUp => spreadsheet.scroll_by(-3, 0),
+        MouseEventKind::ScrollRight => spreadsheet.scroll_by(0, 1),
+        MouseEventKind::ScrollLeft => spreadsheet.scroll_by(0, -1),
+        MouseEventKind::Down(MouseButton::Left) => handle_mouse_down(spreadsheet, hit, mouse.column),
+        MouseEventKind::Drag(MouseButton::Left) => handle_mouse_drag(spreadsheet, hit, mouse.column),
+        MouseEventKind::Up(MouseButton::Left) => spreadsheet.mouse_drag = None,
+        _ => {}
+    }
+}
+
+fn handle_mouse_down(spreadsheet: &mut Spreadsheet, hit: GridHit, x: u16) {
+    match hit {
+        GridHit::Cell(row, col) => {
+            if spreadsheet.editing {
+                // Clicking while typing a formula inserts the clicked cell as a reference
+                if spreadsheet.formula_mode
+                    && (spreadsheet.selecting_ref || expects_reference(&spreadsheet.edit_buffer))
+                {
+                    if !spreadsheet.selecting_ref {
+                        spreadsheet.enter_ref_selection_mode();
+                    }
+                    spreadsheet.ref_cursor_row = row;
+                    spreadsheet.ref_cursor_col = col;
+                    spreadsheet.ref_anchor = None;
+                    spreadsheet.update_ref_in_buffer();
+                    spreadsheet.mouse_drag = Some(MouseDrag::Reference(row, col));
+                    return;
+                }
+                spreadsheet.finish_editing_with_move(0, 0);
+            }
+            if spreadsheet.row_column_select_mode != RowColumnSelectMode::None {
+                spreadsheet.exit_row_column_select_mode();
+            }
+            spreadsheet.cursor_row = row;
+            spreadsheet.cursor_col = col;
+            spreadsheet.selection_anchor = None;
+            spreadsheet.mouse_drag = Some(MouseDrag::Cells(row, col));
+        }
+        GridHit::RowHeader(row) if !spreadsheet.editing => {
+            spreadsheet.exit_visual_mode();
+            spreadsheet.cursor_row = row;
+            spreadsheet.enter_row_select_mode();
+            spreadsheet.mouse_drag = Some(MouseDrag::Rows(row));
+        }
+        GridHit::ColumnHeader(col) if !spreadsheet.editing => {
+            spreadsheet.exit_visual_mode();
+            spreadsheet.cursor_col = col;
+            spreadsheet.enter_column_select_mode();
+            spreadsheet.mouse_drag = Some(MouseDrag::Columns(col));
+        }
+        GridHit::ColumnBorder(col) => {
+            let width = spreadsheet.get_col_width(col);
+            spreadsheet.mouse_drag = Some(MouseDrag::ColumnWidth(col, x, width));
+        }
+        _ => {}
+    }
+}
+
+fn handle_mouse_drag(spreadsheet: &mut Spreadsheet, hit: GridHit, x: u16) {
+    let Some(drag) = spreadsheet.mouse_drag else {
+        return;
+    };
+
+    match (drag, hit) {
+        (MouseDrag::Cells(anchor_row, anchor_col), GridHit::Cell(row, col)) => {
+            spreadsheet.selection_anchor = if (row, col) == (anchor_row, anchor_col) {
+                None
+            } else {
+                Some((anchor_row, anchor_col))
+            };
+            spreadsheet.cursor_row = row;
+            spreadsheet.cursor_col = col;
+        }
+        (MouseDrag::Reference(anchor_row, anchor_col), GridHit::Cell(row, col)) => {
+            if !spreadsheet.selecting_ref {
+                return;
+            }
+            spreadsheet.ref_anchor = if (row, col) == (anchor_row, anchor_col) {
+                None
+            } else {
+                Some((anchor_row, anchor_col))
+            };
+            spreadsheet.ref_cursor_row = row;
+            spreadsheet.ref_cursor_col = col;
+            spreadsheet.update_ref_in_buffer();
+        }
+        (MouseDrag::Rows(start), GridHit::Cell(row, _) | GridHit::RowHeader(row))
+            if spreadsheet.row_column_select_mode == RowColumnSelectMode::RowSelect =>
+        {
+            spreadsheet.selected_rows = Some((start.min(row), start.max(row)));
+            spreadsheet.cursor_row = row;
+        }
+        (
+            MouseDrag::Columns(start),
+            GridHit::Cell(_, col) | GridHit::ColumnHeader(col) | GridHit::ColumnBorder(col),
+        ) if spreadsheet.row_column_select_mode == RowColumnSelectMode::ColumnSelect => {
+            spreadsheet.selected_cols = Some((start.min(col), start.max(col)));
+            spreadsheet.cursor_col = col;
+        }
+        (MouseDrag::ColumnWidth(col, start_x, start_width), _) => {
+            let width = (start_width as i32 + x as i32 - start_x as i32).max(0) as u16;
+            spreadsheet.set_col_width(col, width);
+        }
+        _ => {}
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -873,4 +1160,97 @@ mod tests {
         assert!(!sheet.editing);
         assert_eq!(sheet.get_cell(0, 0), "test");
     }
+
+    fn test_layout() -> crate::types::GridLayout {
+        // Two rows and two columns of width 10, drawn the way render_grid lays them out
+        crate::types::GridLayout {
+            header_y: 4,
+            row_header_x: 1,
+            row_header_width: 5,
+            rows: vec![(0, 5, 1), (1, 6, 1)],
+            cols: vec![(0, 6, 10), (1, 16, 10)],
+        }
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent { kind, column, row, modifiers: KeyModifiers::empty() }
+    }
+
+    #[test]
+    fn test_mouse_click_and_drag_selects() {
+        let mut sheet = Spreadsheet::new();
+        sheet.grid_layout = test_layout();
+
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Down(MouseButton::Left), 17, 5));
+        assert_eq!((sheet.cursor_row, sheet.cursor_col), (0, 1));
+
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Drag(MouseButton::Left), 7, 6));
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Up(MouseButton::Left), 7, 6));
+        assert_eq!(sheet.selection_anchor, Some((0, 1)));
+        assert_eq!((sheet.cursor_row, sheet.cursor_col), (1, 0));
+        assert_eq!(sheet.mouse_drag, None);
+    }
+
+    #[test]
+    fn test_mouse_headers_and_resize() {
+        let mut sheet = Spreadsheet::new();
+        sheet.grid_layout = test_layout();
+
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Down(MouseButton::Left), 2, 6));
+        assert_eq!(sheet.row_column_select_mode, RowColumnSelectMode::RowSelect);
+        assert_eq!(sheet.selected_rows, Some((1, 1)));
+
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Down(MouseButton::Left), 8, 4));
+        assert_eq!(sheet.row_column_select_mode, RowColumnSelectMode::ColumnSelect);
+        assert_eq!(sheet.selected_cols, Some((0, 0)));
+
+        // Drag the right edge of column A four cells to the right
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Down(MouseButton::Left), 15, 4));
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Drag(MouseButton::Left), 19, 4));
+        assert_eq!(sheet.get_col_width(0), 14);
+    }
+
+    #[test]
+    fn test_mouse_click_inserts_reference_in_formula() {
+        let mut sheet = Spreadsheet::new();
+        sheet.grid_layout = test_layout();
+        sheet.start_editing();
+        for c in "=SUM(".chars() {
+            sheet.handle_char_input(c);
+        }
+
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Down(MouseButton::Left), 7, 5));
+        handle_mouse(&mut sheet, mouse(MouseEventKind::Drag(MouseButton::Left), 17, 6));
+        assert_eq!(sheet.edit_buffer, "=SUM(A1:B2");
+        assert!(sheet.editing);
+    }
+
+    #[test]
+    fn test_mouse_wheel_scrolls() {
+        let mut sheet = Spreadsheet::new();
+        handle_mouse(&mut sheet, mouse(MouseEventKind::ScrollDown, 0, 0));
+        assert_eq!(sheet.scroll_row, 3);
+        assert!(sheet.mouse_scrolled);
+        handle_mouse(&mut sheet, mouse(MouseEventKind::ScrollUp, 0, 0));
+        assert_eq!(sheet.scroll_row, 0);
+    }
+
+    #[test]
+    fn test_filter_popup_keys() {
+        let mut sheet = Spreadsheet::new();
+        for (row, value) in ["Status", "open", "done", "open"].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+        }
+        sheet.toggle_auto_filter().unwrap();
+        sheet.filter_command("").unwrap();
+
+        // Untick "done" (first in sorted order), move down a line, and apply
+        handle_filter_popup(&mut sheet, KeyCode::Char(' '));
+        handle_filter_popup(&mut sheet, KeyCode::Char('j'));
+        assert_eq!(sheet.filter_popup.as_ref().unwrap().index, 1);
+        handle_filter_popup(&mut sheet, KeyCode::Enter);
+        assert!(sheet.filter_popup.is_none());
+        assert!(sheet.is_row_hidden(2));
+        assert!(!sheet.is_row_hidden(1));
+    }
 }
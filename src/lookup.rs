@@ -0,0 +1,428 @@
+use std::cmp::Ordering;
+
+use crate::range::CellRange;
+use crate::spreadsheet::Spreadsheet;
+
+/// How a lookup decides that a cell matches
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MatchMode {
+    Exact,
+    /// Exact, but `*`, `?` and `~` in text act as wildcards
+    Wildcard,
+    /// Exact, or else the largest value below
+    NextSmaller,
+    /// Exact, or else the smallest value above
+    NextLarger,
+    /// The last value not above the lookup value in ascending data (VLOOKUP's TRUE, MATCH's 1)
+    SortedAscending,
+    /// The last value not below the lookup value in descending data (MATCH's -1)
+    SortedDescending,
+}
+
+/// Compare two cell values the way lookups do: numbers by value, text case-insensitively.
+/// A number never matches text.
+pub fn compare_values(a: &str, b: &str) -> Option<Ordering> {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b),
+        (Err(_), Err(_)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        _ => None,
+    }
+}
+
+/// Excel wildcard match, ignoring case: `*` is any run of characters, `?` any one, and
+/// `~` makes the next character literal
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|skip| matches(rest, &text[skip..])),
+            Some(('?', rest)) => !text.is_empty() && matches(rest, &text[1..]),
+            Some(('~', rest)) if !rest.is_empty() => {
+                text.first() == Some(&rest[0]) && matches(&rest[1..], &text[1..])
+            }
+            Some((c, rest)) => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    matches(&pattern, &text)
+}
+
+/// Position of the first (or, searching in reverse, last) value that matches `lookup`
+pub fn find_match(lookup: &str, values: &[String], mode: MatchMode, reverse: bool) -> Option<usize> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    if reverse {
+        order.reverse();
+    }
+    let is_pattern = lookup.parse::<f64>().is_err() && lookup.contains(['*', '?', '~']);
+    let exact = order.iter().copied().find(|&i| match mode {
+        MatchMode::Wildcard if is_pattern => wildcard_match(lookup, &values[i]),
+        _ => compare_values(&values[i], lookup) == Some(Ordering::Equal),
+    });
+
+    match mode {
+        MatchMode::Exact | MatchMode::Wildcard => exact,
+        MatchMode::NextSmaller | MatchMode::NextLarger => exact.or_else(|| {
+            let wanted = if mode == MatchMode::NextSmaller { Ordering::Less } else { Ordering::Greater };
+            // The closest value on the wanted side; ties go to the first one in search order
+            order.iter().copied().filter(|&i| compare_values(&values[i], lookup) == Some(wanted)).fold(None, |best, i| {
+                match best {
+                    Some(b) if compare_values(&values[i], &values[b]) != Some(wanted.reverse()) => Some(b),
+                    _ => Some(i),
+                }
+            })
+        }),
+        MatchMode::SortedAscending | MatchMode::SortedDescending => {
+            let past = if mode == MatchMode::SortedAscending { Ordering::Greater } else { Ordering::Less };
+            let mut found = None;
+            for (i, value) in values.iter().enumerate() {
+                match compare_values(value, lookup) {
+                    Some(ordering) if ordering == past => break,
+                    Some(_) => found = Some(i),
+                    // Blanks and values of the other type are skipped
+                    None => {}
+                }
+            }
+            found
+        }
+    }
+}
+
+impl Spreadsheet {
+    fn range_values(&mut self, cells: &[(usize, usize)]) -> Vec<String> {
+        cells.iter().map(|&(row, col)| self.evaluate_cell(row, col)).collect()
+    }
+
+    /// The value of a reference: the cell's value, or #VALUE! for a range of several cells
+    fn reference_value(&mut self, range: Result<CellRange, String>) -> String {
+        match range {
+            Ok(range) if range.is_single_cell() => self.evaluate_cell(range.start_row, range.start_col),
+            Ok(_) => "#VALUE!".to_string(),
+            Err(e) => e,
+        }
+    }
+
+    /// INDEX(range, row_num, [col_num])
+    pub fn evaluate_index(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let range = self.index_range(args, current_row, current_col);
+        self.reference_value(range)
+    }
+
+    /// OFFSET(reference, rows, cols, [height], [width])
+    pub fn evaluate_offset(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let range = self.offset_range(args, current_row, current_col);
+        self.reference_value(range)
+    }
+
+    /// INDIRECT(ref_text, [a1])
+    pub fn evaluate_indirect(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let range = self.indirect_range(args, current_row, current_col);
+        self.reference_value(range)
+    }
+
+    /// MATCH(lookup_value, lookup_range, [match_type]) - the 1-based position of the value.
+    /// Type 1 (default) finds the largest value not above it in ascending data, 0 an exact
+    /// match (with wildcards), -1 the smallest value not below it in descending data.
+    pub fn evaluate_match(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let lookup = self.evaluate_arg(parts[0], current_row, current_col);
+        let range = match self.resolve_range(parts[1], current_row, current_col) {
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+        let Some(cells) = range.vector() else {
+            return "#N/A".to_string();
+        };
+        let mode = match parts.get(2).map(|part| self.evaluate_arg_as_number(part, current_row, current_col)) {
+            None => MatchMode::SortedAscending,
+            Some(Some(t)) if t > 0.0 => MatchMode::SortedAscending,
+            Some(Some(0.0)) => MatchMode::Wildcard,
+            Some(Some(_)) => MatchMode::SortedDescending,
+            Some(None) => return "#ERROR".to_string(),
+        };
+        let values = self.range_values(&cells);
+        match find_match(&lookup, &values, mode, false) {
+            Some(index) => format!("{}", index + 1),
+            None => "#N/A".to_string(),
+        }
+    }
+
+    /// XLOOKUP(lookup_value, lookup_range, return_range, [if_not_found], [match_mode], [search_mode]).
+    /// match_mode: 0 exact, -1 exact or next smaller, 1 exact or next larger, 2 wildcard.
+    /// search_mode: 1 first to last, -1 last to first (2 and -2 are treated the same).
+    pub fn evaluate_xlookup(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(3..=6).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let lookup = self.evaluate_arg(parts[0], current_row, current_col);
+        let (lookup_range, return_range) = match (
+            self.resolve_range(parts[1], current_row, current_col),
+            self.resolve_range(parts[2], current_row, current_col),
+        ) {
+            (Ok(lookup_range), Ok(return_range)) => (lookup_range, return_range),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+        let Some(cells) = lookup_range.vector() else {
+            return "#VALUE!".to_string();
+        };
+        let mut option = |index: usize, default: f64| match parts.get(index).filter(|part| !part.trim().is_empty()) {
+            Some(part) => self.evaluate_arg_as_number(part, current_row, current_col),
+            None => Some(default),
+        };
+        let (Some(match_mode), Some(search_mode)) = (option(4, 0.0), option(5, 1.0)) else {
+            return "#ERROR".to_string();
+        };
+        let mode = match match_mode as i64 {
+            0 => MatchMode::Exact,
+            -1 => MatchMode::NextSmaller,
+            1 => MatchMode::NextLarger,
+            2 => MatchMode::Wildcard,
+            _ => return "#VALUE!".to_string(),
+        };
+
+        let values = self.range_values(&cells);
+        let Some(index) = find_match(&lookup, &values, mode, search_mode < 0.0) else {
+            return match parts.get(3).filter(|part| !part.trim().is_empty()) {
+                Some(part) => self.evaluate_arg(part, current_row, current_col),
+                None => "#N/A".to_string(),
+            };
+        };
+        // The return range lines up with the lookup range: a column of keys picks a row
+        let cell = if lookup_range.cols() == 1 && lookup_range.rows() > 1 {
+            return_range.at(index, 0)
+        } else {
+            return_range.at(0, index)
+        };
+        match cell {
+            Some((row, col)) => self.evaluate_cell(row, col),
+            None => "#VALUE!".to_string(),
+        }
+    }
+
+    /// VLOOKUP(value, table, col_index, [range_lookup]) and HLOOKUP(value, table, row_index,
+    /// [range_lookup]): look down the first column (or along the first row) of the table.
+    /// range_lookup TRUE (the default) expects sorted data and takes the last value not above
+    /// the lookup value; FALSE needs an exact match, with wildcards.
+    pub fn evaluate_table_lookup(&mut self, args: &str, current_row: usize, current_col: usize, vertical: bool) -> String {
+        let parts = self.split_function_args(args);
+        if !(3..=4).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let lookup = self.evaluate_arg(parts[0], current_row, current_col);
+        let table = match self.resolve_range(parts[1], current_row, current_col) {
+            Ok(table) => table,
+            Err(e) => return e,
+        };
+        let Some(index) = self.evaluate_arg_as_number(parts[2], current_row, current_col) else {
+            return "#ERROR".to_string();
+        };
+        let sorted = match parts.get(3) {
+            Some(part) => match self.evaluate_arg_as_bool(part, current_row, current_col) {
+                Some(sorted) => sorted,
+                None => return "#ERROR".to_string(),
+            },
+            None => true,
+        };
+        let span = if vertical { table.cols() } else { table.rows() };
+        if index < 1.0 {
+            return "#VALUE!".to_string();
+        }
+        let index = index as usize;
+        if index > span {
+            return "#REF!".to_string();
+        }
+
+        let keys = if vertical { table.col(0) } else { table.row(0) };
+        let cells = keys.map(|keys| keys.cells()).unwrap_or_default();
+        let values = self.range_values(&cells);
+        let mode = if sorted { MatchMode::SortedAscending } else { MatchMode::Wildcard };
+        let Some(position) = find_match(&lookup, &values, mode, false) else {
+            return "#N/A".to_string();
+        };
+        let cell = if vertical { table.at(position, index - 1) } else { table.at(index - 1, position) };
+        match cell {
+            Some((row, col)) => self.evaluate_cell(row, col),
+            None => "#REF!".to_string(),
+        }
+    }
+
+    /// LOOKUP(value, lookup_vector, [result_vector]) - approximate match in sorted data. With
+    /// no result vector, a 2D range is searched along its longer side and the result taken
+    /// from its last row or column.
+    pub fn evaluate_lookup(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let lookup = self.evaluate_arg(parts[0], current_row, current_col);
+        let range = match self.resolve_range(parts[1], current_row, current_col) {
+            Ok(range) => range,
+            Err(e) => return e,
+        };
+        let (keys, results) = match parts.get(2) {
+            Some(part) => match self.resolve_range(part, current_row, current_col) {
+                Ok(results) => (range.vector(), results.vector()),
+                Err(e) => return e,
+            },
+            None if range.cols() > range.rows() => {
+                (range.row(0).map(|r| r.cells()), range.row(range.rows() - 1).map(|r| r.cells()))
+            }
+            None => (range.col(0).map(|c| c.cells()), range.col(range.cols() - 1).map(|c| c.cells())),
+        };
+        let (Some(keys), Some(results)) = (keys, results) else {
+            return "#N/A".to_string();
+        };
+        let values = self.range_values(&keys);
+        match find_match(&lookup, &values, MatchMode::SortedAscending, false).and_then(|i| results.get(i)) {
+            Some(&(row, col)) => self.evaluate_cell(row, col),
+            None => "#N/A".to_string(),
+        }
+    }
+
+    /// CHOOSE(index, value1, value2, ...)
+    pub fn evaluate_choose(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() < 2 {
+            return "#ERROR".to_string();
+        }
+        match self.evaluate_arg_as_number(parts[0], current_row, current_col) {
+            Some(index) if index >= 1.0 && (index as usize) < parts.len() => {
+                self.evaluate_arg(parts[index as usize], current_row, current_col)
+            }
+            Some(_) => "#VALUE!".to_string(),
+            None => "#ERROR".to_string(),
+        }
+    }
+
+    /// ROW([reference]), COLUMN([reference]), ROWS(range) and COLUMNS(range)
+    pub fn evaluate_row_col(&mut self, args: &str, current_row: usize, current_col: usize, rows: bool, count: bool) -> String {
+        let range = if args.trim().is_empty() && !count {
+            CellRange::cell(current_row, current_col)
+        } else {
+            match self.resolve_range(args, current_row, current_col) {
+                Ok(range) => range,
+                Err(e) => return e,
+            }
+        };
+        let value = match (rows, count) {
+            (true, false) => range.start_row + 1,
+            (false, false) => range.start_col + 1,
+            (true, true) => range.rows(),
+            (false, true) => range.cols(),
+        };
+        format!("{}", value)
+    }
+
+    /// ADDRESS(row, column, [abs_num], [a1], [sheet_text]) - abs_num 1 is `$A$1`, 2 `A$1`,
+    /// 3 `$A1` and 4 `A1`; a1 = FALSE gives R1C1 style
+    pub fn evaluate_address(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=5).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let (Some(row), Some(col)) = (
+            self.evaluate_arg_as_number(parts[0], current_row, current_col),
+            self.evaluate_arg_as_number(parts[1], current_row, current_col),
+        ) else {
+            return "#ERROR".to_string();
+        };
+        let abs_num = match parts.get(2) {
+            Some(part) => self.evaluate_arg_as_number(part, current_row, current_col).unwrap_or(0.0) as i64,
+            None => 1,
+        };
+        let a1 = match parts.get(3) {
+            Some(part) => self.evaluate_arg_as_bool(part, current_row, current_col).unwrap_or(true),
+            None => true,
+        };
+        if row < 1.0 || col < 1.0 || !(1..=4).contains(&abs_num) {
+            return "#VALUE!".to_string();
+        }
+        let (row, col) = (row as usize, col as usize);
+        let (abs_row, abs_col) = (abs_num <= 2, abs_num % 2 == 1);
+        let address = if a1 {
+            let dollar = |absolute: bool| if absolute { "$" } else { "" };
+            format!("{}{}{}{}", dollar(abs_col), Spreadsheet::col_name(col - 1), dollar(abs_row), row)
+        } else {
+            let part = |absolute: bool, n: usize| if absolute { n.to_string() } else { format!("[{}]", n) };
+            format!("R{}C{}", part(abs_row, row), part(abs_col, col))
+        };
+        match parts.get(4) {
+            Some(part) => {
+                let sheet = self.evaluate_arg(part, current_row, current_col);
+                if sheet.contains(|c: char| !c.is_alphanumeric() && c != '_') {
+                    format!("'{}'!{}", sheet, address)
+                } else {
+                    format!("{}!{}", sheet, address)
+                }
+            }
+            None => address,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_find_match_modes() {
+        let sorted = values(&["10", "20", "30", "40"]);
+        assert_eq!(find_match("25", &sorted, MatchMode::SortedAscending, false), Some(1));
+        assert_eq!(find_match("5", &sorted, MatchMode::SortedAscending, false), None);
+        assert_eq!(find_match("25", &sorted, MatchMode::NextLarger, false), Some(2));
+        assert_eq!(find_match("25", &sorted, MatchMode::NextSmaller, false), Some(1));
+        assert_eq!(find_match("25", &sorted, MatchMode::Exact, false), None);
+        let descending = values(&["40", "30", "20"]);
+        assert_eq!(find_match("25", &descending, MatchMode::SortedDescending, false), Some(1));
+        let names = values(&["apple", "Banana", "cherry", "banana"]);
+        assert_eq!(find_match("BANANA", &names, MatchMode::Exact, false), Some(1));
+        assert_eq!(find_match("banana", &names, MatchMode::Exact, true), Some(3));
+        assert_eq!(find_match("ch*", &names, MatchMode::Wildcard, false), Some(2));
+        assert_eq!(find_match("?pple", &names, MatchMode::Wildcard, false), Some(0));
+        assert!(wildcard_match("100~%", "100%"));
+        assert!(!wildcard_match("a~*", "ab"));
+    }
+
+    #[test]
+    fn test_lookup_functions() {
+        let mut sheet = Spreadsheet::new();
+        for (row, (name, price)) in [("Apple", "1.2"), ("Banana", "0.5"), ("Cherry", "3")].iter().enumerate() {
+            sheet.set_cell(row, 0, name.to_string());
+            sheet.set_cell(row, 1, price.to_string());
+        }
+        let eval = |sheet: &mut Spreadsheet, formula: &str| sheet.evaluate_formula(formula, 9, 9);
+        assert_eq!(eval(&mut sheet, "=INDEX(A1:B3,2,2)"), "0.5");
+        assert_eq!(eval(&mut sheet, "=INDEX(A1:B3,4,1)"), "#REF!");
+        assert_eq!(eval(&mut sheet, "=MATCH(\"cherry\",A1:A3,0)"), "3");
+        assert_eq!(eval(&mut sheet, "=MATCH(\"B*\",A1:A3,0)"), "2");
+        assert_eq!(eval(&mut sheet, "=MATCH(\"Kiwi\",A1:A3,0)"), "#N/A");
+        assert_eq!(eval(&mut sheet, "=INDEX(B1:B3,MATCH(\"Cherry\",A1:A3,0))"), "3");
+        assert_eq!(eval(&mut sheet, "=XLOOKUP(\"Banana\",A1:A3,B1:B3)"), "0.5");
+        assert_eq!(eval(&mut sheet, "=XLOOKUP(\"Kiwi\",A1:A3,B1:B3,\"none\")"), "none");
+        assert_eq!(eval(&mut sheet, "=XLOOKUP(2,B1:B3,A1:A3,,1)"), "Cherry");
+        assert_eq!(eval(&mut sheet, "=XLOOKUP(2,B1:B3,A1:A3,,-1)"), "Apple");
+        assert_eq!(eval(&mut sheet, "=VLOOKUP(\"Banana\",A1:B3,2,FALSE)"), "0.5");
+        assert_eq!(eval(&mut sheet, "=VLOOKUP(\"Banana\",A1:B3,3,FALSE)"), "#REF!");
+        assert_eq!(eval(&mut sheet, "=HLOOKUP(\"Apple\",A1:B3,3,FALSE)"), "Cherry");
+        assert_eq!(eval(&mut sheet, "=LOOKUP(\"Bz\",A1:A3,B1:B3)"), "0.5");
+        assert_eq!(eval(&mut sheet, "=CHOOSE(2,\"a\",\"b\",\"c\")"), "b");
+        assert_eq!(eval(&mut sheet, "=CHOOSE(4,\"a\",\"b\",\"c\")"), "#VALUE!");
+        assert_eq!(eval(&mut sheet, "=ROW(B3)"), "3");
+        assert_eq!(eval(&mut sheet, "=COLUMN()"), "10");
+        assert_eq!(eval(&mut sheet, "=ROWS(A1:B3)"), "3");
+        assert_eq!(eval(&mut sheet, "=COLUMNS(A1:B3)"), "2");
+        assert_eq!(eval(&mut sheet, "=ADDRESS(2,3)"), "$C$2");
+        assert_eq!(eval(&mut sheet, "=ADDRESS(2,3,4,FALSE)"), "R[2]C[3]");
+        assert_eq!(eval(&mut sheet, "=ADDRESS(2,3,2,TRUE,\"My Sheet\")"), "'My Sheet'!C$2");
+        assert_eq!(eval(&mut sheet, "=OFFSET(A1,2,1)"), "3");
+        assert_eq!(eval(&mut sheet, "=INDIRECT(\"A2\")"), "Banana");
+    }
+}
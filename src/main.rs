@@ -1,13 +1,34 @@
+mod aggregate;
+mod array;
+mod conditional;
 mod constants;
+mod date;
+mod expression;
+mod fill;
+mod filter;
 mod formula;
 mod input;
+mod lookup;
+mod names;
+mod native;
+mod number_format;
+mod outline;
+mod pivot;
+mod range;
 mod save;
 mod settings;
+mod shell;
+mod sort;
 mod spreadsheet;
+mod stats;
 mod style;
+mod text;
+mod text_functions;
+mod theme;
 mod types;
 mod ui;
 mod update;
+mod xlsx;
 
 use std::io::{self, Read};
 
@@ -23,7 +44,7 @@ use ratatui::{backend::CrosstermBackend, Terminal};
 #[command(name = "xl")]
 #[command(about = "A terminal-based spreadsheet application")]
 struct Args {
-    /// File to open (supports CSV, TSV, and Excel files)
+    /// File to open (supports CSV, TSV, Excel and .xl files)
     #[arg(short, long)]
     file: Option<String>,
     /// Print version information and exit
@@ -111,8 +132,14 @@ fn main() -> io::Result<()> {
 
     // Now create and populate the spreadsheet
     let mut spreadsheet = crate::spreadsheet::Spreadsheet::new();
-    spreadsheet.dark_mode = settings.dark_mode;
+    spreadsheet.theme = settings.theme();
+    spreadsheet.color_depth = theme::ColorDepth::from_setting(&settings.color_depth);
     spreadsheet.hide_update_prompt = settings.hide_update_prompt;
+    spreadsheet.mouse_enabled = settings.mouse;
+    spreadsheet.autofit_on_load = settings.autofit_on_load;
+    spreadsheet.shell.timeout = std::time::Duration::from_secs(settings.shell_timeout);
+    spreadsheet.shell.policy = shell::ShellPolicy::from_setting(&settings.shell_policy);
+    spreadsheet.shell.allowlist = settings.shell_allowlist.clone();
     
     if let Some(data) = piped_data {
         // Load data from the buffer we read earlier
@@ -0,0 +1,558 @@
+use serde::{Deserialize, Serialize};
+
+use crate::array::Array;
+use crate::expression::{has_operators, matching_paren, single_call, split_args, strip_parens};
+use crate::spreadsheet::Spreadsheet;
+
+/// How many levels deep defined names may refer to other names
+const MAX_NAME_DEPTH: usize = 16;
+
+/// A table made with format-as-table, named so formulas can refer to its columns by header
+/// as in `Table1[Amount]`. Its last row is found afresh each time, so it grows with its data.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DataTable {
+    pub name: String,
+    pub header_row: usize,
+    pub min_col: usize,
+    pub max_col: usize,
+}
+
+/// Whether text can be a defined name or a LET/LAMBDA parameter: a letter or underscore
+/// followed by letters, digits, underscores and dots, and not a cell reference or TRUE/FALSE
+pub fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && Spreadsheet::parse_cell_reference(name).is_none()
+        && !name.eq_ignore_ascii_case("TRUE")
+        && !name.eq_ignore_ascii_case("FALSE")
+}
+
+/// Replace the names in a formula with whatever `lookup` gives for them. It is told whether
+/// the name is being called like a function. Quoted text, numbers, references and error
+/// values are left alone.
+pub fn replace_names(formula: &str, lookup: impl Fn(&str, bool) -> Option<String>) -> String {
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | '#');
+    let mut replaced = String::with_capacity(formula.len());
+    let mut quoted = false;
+    let mut i = 0;
+    while let Some(c) = formula[i..].chars().next() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if quoted || !is_token_char(c) {
+            replaced.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+        // Error values such as #N/A and #DIV/0! run on through their punctuation
+        let end = if c == '#' {
+            let rest = &formula[i + 1..];
+            i + 1 + rest.find(|c: char| !(c.is_ascii_alphanumeric() || "/!?._#".contains(c))).unwrap_or(rest.len())
+        } else {
+            formula[i..].find(|c: char| !is_token_char(c)).map_or(formula.len(), |len| i + len)
+        };
+        let token = &formula[i..end];
+        let call = formula[end..].trim_start().starts_with('(');
+        match lookup(token, call).filter(|_| is_valid_name(token)) {
+            Some(text) => replaced.push_str(&text),
+            None => replaced.push_str(token),
+        }
+        i = end;
+    }
+    replaced
+}
+
+/// Whether a formula is a LAMBDA definition
+pub fn is_lambda(formula: &str) -> bool {
+    single_call(formula.trim()).is_some_and(|(name, _)| name == "LAMBDA")
+}
+
+/// `LAMBDA(params, body)(args)`: the lambda's argument text and the call's
+fn lambda_call(expr: &str) -> Option<(&str, &str)> {
+    let open = expr.find('(')?;
+    if !expr[..open].trim().eq_ignore_ascii_case("LAMBDA") {
+        return None;
+    }
+    let close = matching_paren(expr, open)?;
+    let call = strip_parens(expr[close + 1..].trim())?;
+    Some((&expr[open + 1..close], call))
+}
+
+/// Formula text that can stand in for a name, in parentheses when it has operators
+fn as_operand(formula: &str) -> String {
+    let formula = formula.trim();
+    if has_operators(formula) && strip_parens(formula).is_none() && !formula.starts_with(['-', '+']) {
+        format!("({})", formula)
+    } else {
+        formula.to_string()
+    }
+}
+
+/// A value written so a formula reads it back unchanged
+fn literal(value: &str) -> String {
+    let upper = value.to_uppercase();
+    if value.parse::<f64>().is_ok_and(f64::is_finite) || upper == "TRUE" || upper == "FALSE" || value.starts_with('#') {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+}
+
+/// The first LET or LAMBDA call in a formula, outside quoted text: where its name starts,
+/// its opening and closing parentheses, and whether it is a LET
+fn scoped_call(formula: &str) -> Option<(usize, usize, usize, bool)> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | '#');
+    let mut quoted = false;
+    let mut previous = ' ';
+    for (i, c) in formula.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if !quoted && !is_name_char(previous) && c.is_ascii_alphabetic() {
+            let end = formula[i..].find(|c: char| !is_name_char(c)).map_or(formula.len(), |len| i + len);
+            let name = &formula[i..end];
+            let is_let = name.eq_ignore_ascii_case("LET");
+            if (is_let || name.eq_ignore_ascii_case("LAMBDA")) && formula[end..].trim_start().starts_with('(') {
+                let open = end + formula[end..].find('(')?;
+                if let Some(close) = matching_paren(formula, open) {
+                    return Some((i, open, close, is_let));
+                }
+            }
+        }
+        previous = c;
+    }
+    None
+}
+
+/// Replace each bound name with its value; later bindings hide earlier ones of the same name.
+/// The names a nested LET or LAMBDA declares are its own: they are left alone, and so is
+/// whatever they cover in its body.
+fn substitute(formula: &str, bindings: &[(String, String)]) -> String {
+    let Some((start, open, close, is_let)) = scoped_call(formula) else {
+        return replace_names(formula, |name, call| {
+            let (_, value) = bindings.iter().rev().find(|(bound, _)| bound.eq_ignore_ascii_case(name))?;
+            (!call || is_lambda(value)).then(|| value.clone())
+        });
+    };
+    let parts = split_args(&formula[open + 1..close]);
+    let (body, declared) = parts.split_last().expect("split_args gives at least one part");
+    let mut scope = bindings.to_vec();
+    let mut args = Vec::new();
+    for (i, part) in declared.iter().enumerate() {
+        if is_let && i % 2 == 1 {
+            // A LET value sees the names declared before it in the same LET
+            args.push(substitute(part, &scope));
+        } else {
+            args.push(part.to_string());
+            scope.retain(|(bound, _)| !bound.eq_ignore_ascii_case(part.trim()));
+        }
+    }
+    args.push(substitute(body, &scope));
+    format!(
+        "{}{}{}){}",
+        substitute(&formula[..start], bindings),
+        &formula[start..=open],
+        args.join(","),
+        substitute(&formula[close + 1..], bindings)
+    )
+}
+
+/// A defined name's formula from an xlsx file, without the `=`, Excel's prefixes for newer
+/// functions and the sheet name. None when it refers to another sheet.
+pub fn import_name_formula(formula: &str, sheet: &str) -> Option<String> {
+    let formula = formula.trim().trim_start_matches('=');
+    let mut imported = String::with_capacity(formula.len());
+    let mut quoted = false;
+    let mut chars = formula.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\'' if !quoted => {
+                // A quoted sheet name, with '' for a quote inside
+                let mut name = String::new();
+                while let Some(c) = chars.next() {
+                    if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                        break;
+                    }
+                    name.push(c);
+                }
+                if chars.next() != Some('!') || name != sheet {
+                    return None;
+                }
+                continue;
+            }
+            '!' if !quoted => {
+                let start = imported
+                    .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .map_or(0, |i| i + 1);
+                if imported[start..] != *sheet {
+                    return None;
+                }
+                imported.truncate(start);
+                continue;
+            }
+            _ => {}
+        }
+        imported.push(c);
+    }
+    Some(imported.replace("_xlfn.", "").replace("_xlpm.", "").replace("_xlws.", ""))
+}
+
+impl Spreadsheet {
+    /// What a defined name refers to, matching the name in any case
+    pub fn defined_name(&self, name: &str) -> Option<&str> {
+        self.defined_names
+            .iter()
+            .find(|(defined, _)| defined.eq_ignore_ascii_case(name))
+            .map(|(_, refers_to)| refers_to.as_str())
+    }
+
+    /// Define (or redefine) a workbook-level name for a range, value or formula
+    pub fn define_name(&mut self, name: &str, refers_to: &str) -> Result<(), String> {
+        let refers_to = refers_to.trim().trim_start_matches('=').trim();
+        if !is_valid_name(name) || Self::get_available_formulas().contains(&name.to_uppercase()) {
+            return Err(format!("Invalid name: {}", name));
+        }
+        if refers_to.is_empty() {
+            return Err(format!("Nothing for {} to refer to", name));
+        }
+        self.defined_names.retain(|(defined, _)| !defined.eq_ignore_ascii_case(name));
+        self.defined_names.push((name.to_string(), refers_to.to_string()));
+        self.defined_names.sort_by_key(|(defined, _)| defined.to_lowercase());
+        self.refresh_spills();
+        Ok(())
+    }
+
+    /// `:name <name> [refers to]` - name a range, value or LAMBDA; without a reference the
+    /// name is given to the selection
+    pub fn name_command(&mut self, arg: &str) -> Result<(), String> {
+        let (name, refers_to) = match arg.split_once(char::is_whitespace) {
+            Some((name, refers_to)) => (name, refers_to.trim().to_string()),
+            None => (arg, self.selection_ref()),
+        };
+        self.define_name(name, &refers_to)
+    }
+
+    /// `:unname <name>`
+    pub fn unname_command(&mut self, arg: &str) -> Result<(), String> {
+        let count = self.defined_names.len();
+        self.defined_names.retain(|(defined, _)| !defined.eq_ignore_ascii_case(arg));
+        if self.defined_names.len() == count {
+            return Err(format!("No name {}", arg));
+        }
+        self.refresh_spills();
+        Ok(())
+    }
+
+    /// The defined names and what they refer to, for `:name` on its own
+    pub fn names_summary(&self) -> String {
+        if self.defined_names.is_empty() {
+            return "No names defined".to_string();
+        }
+        let names: Vec<String> = self
+            .defined_names
+            .iter()
+            .map(|(name, refers_to)| format!("{} = {}", name, refers_to))
+            .collect();
+        format!("Names: {}", names.join(", "))
+    }
+
+    /// `:goto <cell|range|name>` - move to a cell, or select a range
+    pub fn goto_command(&mut self, arg: &str) -> Result<(), String> {
+        let target = self.defined_name(arg).unwrap_or(arg).to_string();
+        let Some(range) = self.parse_range(&target) else {
+            return Err(format!("Not a cell, range or name of one: {}", arg));
+        };
+        if range.end_row >= self.num_rows || range.end_col >= self.num_cols {
+            return Err(format!("Cell {} is out of range", target.to_uppercase()));
+        }
+        self.cursor_row = range.start_row;
+        self.cursor_col = range.start_col;
+        self.selection_anchor = (!range.is_single_cell()).then_some((range.end_row, range.end_col));
+        Ok(())
+    }
+
+    /// Whether accepting an autocomplete suggestion should open a call: true for functions
+    /// and LAMBDA names, false for names of ranges and values
+    pub fn suggestion_takes_args(&self, suggestion: &str) -> bool {
+        self.defined_name(suggestion).is_none_or(is_lambda)
+    }
+
+    /// Whether any names or tables are defined for formulas to use
+    pub fn has_names(&self) -> bool {
+        !self.defined_names.is_empty() || !self.tables.is_empty()
+    }
+
+    /// Replace each defined name and structured table reference in a formula with what it
+    /// refers to. #NAME? when names refer to each other in a loop.
+    pub fn expand_names(&self, formula: &str) -> Result<String, String> {
+        let mut expanded = formula.to_string();
+        for _ in 0..MAX_NAME_DEPTH {
+            let next = replace_names(&expanded, |name, call| {
+                let refers_to = self.defined_name(name)?;
+                (!call || is_lambda(refers_to)).then(|| as_operand(refers_to))
+            });
+            if next == expanded {
+                return self.expand_table_refs(&next);
+            }
+            expanded = next;
+        }
+        Err("#NAME?".to_string())
+    }
+
+    /// Name a table by its header row, keeping the name it already has
+    pub fn register_table(&mut self, header_row: usize, min_col: usize, max_col: usize) -> String {
+        if let Some(table) = self.tables.iter_mut().find(|table| (table.header_row, table.min_col) == (header_row, min_col)) {
+            table.max_col = max_col;
+            return table.name.clone();
+        }
+        let number = (1..).find(|n| !self.tables.iter().any(|table| table.name == format!("Table{}", n))).unwrap_or(1);
+        let name = format!("Table{}", number);
+        self.tables.push(DataTable { name: name.clone(), header_row, min_col, max_col });
+        name
+    }
+
+    /// Replace each structured reference such as `Table1[Amount]` with the range of that
+    /// column's data. #REF! for a column the table doesn't have.
+    pub fn expand_table_refs(&self, formula: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(formula.len());
+        let mut quoted = false;
+        let mut rest = formula;
+        while let Some(c) = rest.chars().next() {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            if c == '[' && !quoted && let Some(close) = rest.find(']') {
+                let start = expanded
+                    .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .map_or(0, |i| i + 1);
+                if let Some(table) = self.tables.iter().find(|table| table.name.eq_ignore_ascii_case(&expanded[start..])) {
+                    let header = rest[1..close].trim();
+                    let col = (table.min_col..=table.max_col)
+                        .find(|&col| self.get_cell(table.header_row, col).trim().eq_ignore_ascii_case(header))
+                        .ok_or_else(|| "#REF!".to_string())?;
+                    let last_row = self
+                        .detect_table_region(table.header_row, table.min_col)
+                        .map_or(table.header_row, |(_, (max_row, _))| max_row);
+                    let name = Self::col_name(col);
+                    expanded.truncate(start);
+                    expanded.push_str(&format!("{}{}:{}{}", name, table.header_row + 2, name, last_row.max(table.header_row + 1) + 1));
+                    rest = &rest[close + 1..];
+                    continue;
+                }
+            }
+            expanded.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        Ok(expanded)
+    }
+
+    /// A formula with LET and LAMBDA written as Excel stores them in files
+    pub fn to_excel_formula(&self, formula: &str) -> String {
+        let upper = formula.to_uppercase();
+        let mut parameters = Vec::new();
+        for (function, step) in [("LAMBDA(", 1), ("LET(", 2)] {
+            for (open, _) in upper.match_indices(function) {
+                let open = open + function.len() - 1;
+                let Some(close) = matching_paren(formula, open) else {
+                    continue;
+                };
+                let parts = self.split_function_args(&formula[open + 1..close]);
+                let names = &parts[..parts.len() - 1];
+                parameters.extend(names.iter().step_by(step).map(|name| name.trim().to_string()));
+            }
+        }
+        replace_names(formula, |name, call| match name.to_uppercase().as_str() {
+            "LET" | "LAMBDA" if call => Some(format!("_xlfn.{}", name)),
+            _ if !call && parameters.iter().any(|parameter| parameter.eq_ignore_ascii_case(name)) => {
+                Some(format!("_xlpm.{}", name))
+            }
+            _ => None,
+        })
+    }
+
+    /// What a name stands for inside LET or a LAMBDA. Ranges, arrays and LAMBDAs keep their
+    /// formula; anything else is worked out once and stands as its value.
+    fn bound_value(&mut self, formula: &str, current_row: usize, current_col: usize) -> String {
+        let formula = formula.trim();
+        if is_lambda(formula) || self.is_array_expression(formula) || self.parse_range(formula).is_some() {
+            as_operand(formula)
+        } else {
+            literal(&self.evaluate_expression(formula, current_row, current_col))
+        }
+    }
+
+    /// The formula a LET or LAMBDA call works out, with its names replaced by their
+    /// formulas; None for anything else. Used to tell whether the result is an array.
+    pub fn bound_formula(&self, expr: &str) -> Option<String> {
+        let (names, values, body) = if let Some((lambda, args)) = lambda_call(expr) {
+            let parts = self.split_function_args(lambda);
+            let (body, names) = parts.split_last()?;
+            let values = if args.trim().is_empty() { Vec::new() } else { self.split_function_args(args) };
+            (names.to_vec(), values, *body)
+        } else {
+            let (_, args) = single_call(expr).filter(|(name, _)| name == "LET")?;
+            let parts = self.split_function_args(args);
+            let (body, pairs) = parts.split_last()?;
+            let names = pairs.iter().step_by(2).copied().collect();
+            let values = pairs.iter().skip(1).step_by(2).copied().collect();
+            (names, values, *body)
+        };
+        let mut bindings: Vec<(String, String)> = Vec::new();
+        for (name, value) in names.iter().zip(values) {
+            let value = as_operand(&substitute(value, &bindings));
+            bindings.push((name.trim().to_string(), value));
+        }
+        Some(substitute(body, &bindings))
+    }
+
+    /// LET(name1, value1, [name2, value2, ...], calculation)
+    pub fn evaluate_let(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if parts.len() < 3 || parts.len().is_multiple_of(2) {
+            return Err("#ERROR".to_string());
+        }
+        let mut bindings: Vec<(String, String)> = Vec::new();
+        for pair in parts[..parts.len() - 1].chunks(2) {
+            let name = pair[0].trim();
+            if !is_valid_name(name) {
+                return Err("#NAME?".to_string());
+            }
+            let value = self.bound_value(&substitute(pair[1], &bindings), current_row, current_col);
+            bindings.push((name.to_string(), value));
+        }
+        let body = substitute(parts[parts.len() - 1], &bindings);
+        self.evaluate_array_expression(&body, current_row, current_col)
+    }
+
+    /// A LAMBDA applied to arguments, as in `LAMBDA(x, x*2)(A1)` or a call to a named LAMBDA.
+    /// None when the expression isn't one.
+    pub fn evaluate_lambda_call(&mut self, expr: &str, current_row: usize, current_col: usize) -> Option<Result<Array, String>> {
+        let (lambda, args) = lambda_call(expr)?;
+        let parts = self.split_function_args(lambda);
+        let (body, parameters) = parts.split_last()?;
+        let values = if args.trim().is_empty() { Vec::new() } else { self.split_function_args(args) };
+        if values.len() != parameters.len() {
+            return Some(Err("#VALUE!".to_string()));
+        }
+        let mut bindings = Vec::new();
+        for (parameter, value) in parameters.iter().zip(values) {
+            if !is_valid_name(parameter.trim()) {
+                return Some(Err("#NAME?".to_string()));
+            }
+            bindings.push((parameter.trim().to_string(), self.bound_value(value, current_row, current_col)));
+        }
+        Some(self.evaluate_array_expression(&substitute(body, &bindings), current_row, current_col))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defined_names() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(1, 0, "100".to_string());
+        sheet.set_cell(2, 0, "200".to_string());
+        sheet.set_cell(2, 1, "0.2".to_string());
+        sheet.name_command("TaxRate B3").unwrap();
+        sheet.name_command("Sales A2:A3").unwrap();
+        sheet.name_command("Total =SUM(Sales)").unwrap();
+        sheet.name_command("WithTax LAMBDA(x, x*(1+TaxRate))").unwrap();
+
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 9, 9);
+        assert_eq!(eval("=taxrate*10"), "2");
+        assert_eq!(eval("=SUM(Sales)"), "300");
+        assert_eq!(eval("=Total/2"), "150");
+        assert_eq!(eval("=WithTax(A2)"), "120");
+        assert_eq!(eval("=WithTax(1,2)"), "#VALUE!");
+        assert_eq!(eval("=\"TaxRate\""), "TaxRate");
+        assert_eq!(eval("=Missing+1"), "#NAME?");
+
+        // A spilling name, and a name loop
+        sheet.set_cell(0, 3, "=Sales*2".to_string());
+        assert_eq!(sheet.evaluate_cell(1, 3), "400");
+        sheet.name_command("Loop Loop+1").unwrap();
+        assert_eq!(sheet.evaluate_formula("=Loop", 9, 9), "#NAME?");
+
+        assert!(sheet.name_command("B2 C3").is_err());
+        assert!(sheet.name_command("Sum C3").is_err());
+        assert!(sheet.suggestion_takes_args("WithTax"));
+        assert!(!sheet.suggestion_takes_args("TaxRate"));
+
+        sheet.goto_command("sales").unwrap();
+        assert_eq!(sheet.get_selection_range(), Some(((1, 0), (2, 0))));
+        assert!(sheet.goto_command("Total").is_err());
+        sheet.unname_command("Total").unwrap();
+        assert_eq!(sheet.defined_name("total"), None);
+    }
+
+    #[test]
+    fn test_table_references() {
+        let mut sheet = Spreadsheet::new();
+        for (row, values) in [["Item", "Amount"], ["pen", "2"], ["ink", "5"]].iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                sheet.set_cell(row + 1, col + 1, value.to_string());
+            }
+        }
+        sheet.cursor_row = 2;
+        sheet.cursor_col = 1;
+        sheet.format_as_table();
+        assert_eq!(sheet.tables[0].name, "Table1");
+
+        assert_eq!(sheet.evaluate_formula("=SUM(Table1[Amount])", 9, 9), "7");
+        assert_eq!(sheet.evaluate_formula("=COUNTA(table1[ item ])", 9, 9), "2");
+        assert_eq!(sheet.evaluate_formula("=Table1[Price]", 9, 9), "#REF!");
+        assert_eq!(sheet.evaluate_formula("=\"Table1[Amount]\"", 9, 9), "Table1[Amount]");
+
+        // The table grows with its data
+        sheet.set_cell(4, 1, "cap".to_string());
+        sheet.set_cell(4, 2, "10".to_string());
+        assert_eq!(sheet.evaluate_formula("=SUM(Table1[Amount])", 9, 9), "17");
+    }
+
+    #[test]
+    fn test_let_and_lambda() {
+        let mut sheet = Spreadsheet::new();
+        for (row, value) in ["3", "4", "5"].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+        }
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 9, 9);
+
+        assert_eq!(eval("=LET(x, 2, y, x*10, x+y)"), "22");
+        assert_eq!(eval("=LET(r, A1:A3, SUM(r)/COUNT(r))"), "4");
+        assert_eq!(eval("=LET(name, \"Ann\", \"Hi \"&name)"), "Hi Ann");
+        assert_eq!(eval("=LET(x, -2, x^2)"), "4");
+        assert_eq!(eval("=LET(1x, 2, 3)"), "#NAME?");
+        assert_eq!(eval("=LAMBDA(a, b, a*b)(6, 7)"), "42");
+        assert_eq!(eval("=LET(sq, LAMBDA(n, n*n), sq(A2)+sq(2))"), "20");
+        assert_eq!(eval("=LAMBDA(x, x)"), "#CALC!");
+
+        // An inner LET or LAMBDA's own names shadow the outer ones
+        assert_eq!(eval("=LET(x,5,LET(x,2,x)+x)"), "7");
+        assert_eq!(eval("=LAMBDA(x,LET(x,2,x))(5)"), "2");
+        assert_eq!(eval("=LET(x,5,LET(y,x+1,x,y*2,x+y))"), "18");
+        assert_eq!(eval("=LET(f,LAMBDA(x,x*10),x,3,f(x))"), "30");
+        assert_eq!(eval("=LET(x,1,LAMBDA(y,x+y)(2))"), "3");
+
+        sheet.set_cell(0, 2, "=LET(r, A1:A3, r*r)".to_string());
+        assert_eq!(sheet.evaluate_cell(2, 2), "25");
+    }
+
+    #[test]
+    fn test_excel_name_formulas() {
+        let sheet = Spreadsheet::new();
+        assert_eq!(
+            sheet.to_excel_formula("=LAMBDA(x, LET(y, x*2, y+x))(A1)"),
+            "=_xlfn.LAMBDA(_xlpm.x, _xlfn.LET(_xlpm.y, _xlpm.x*2, _xlpm.y+_xlpm.x))(A1)"
+        );
+        assert_eq!(import_name_formula("=Sheet1!$B$3", "Sheet1").as_deref(), Some("$B$3"));
+        assert_eq!(import_name_formula("='My Data'!$A$1:$A$5", "My Data").as_deref(), Some("$A$1:$A$5"));
+        assert_eq!(import_name_formula("Other!$A$1", "Sheet1"), None);
+        assert_eq!(
+            import_name_formula("_xlfn.LAMBDA(_xlpm.x, _xlpm.x*Sheet1!$B$1)", "Sheet1").as_deref(),
+            Some("LAMBDA(x, x*$B$1)")
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::conditional::ConditionalRule;
+use crate::constants::{DEFAULT_COLS, DEFAULT_ROWS};
+use crate::names::DataTable;
+use crate::outline::OutlineGroup;
+use crate::pivot::PivotTable;
+use crate::spreadsheet::Spreadsheet;
+use crate::theme::{color_to_string, parse_color};
+use crate::types::{CellStyle, DataType, TextAlignment, VerticalAlignment};
+
+/// Bumped whenever the layout of `.xl` files changes incompatibly
+const NATIVE_VERSION: u32 = 1;
+
+/// On-disk form of a sheet in xl's own `.xl` format (JSON)
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct NativeDocument {
+    version: u32,
+    cells: Vec<NativeCell>,
+    styles: Vec<NativeStyle>,
+    col_widths: BTreeMap<usize, u16>,
+    row_heights: BTreeMap<usize, u16>,
+    frozen_rows: usize,
+    frozen_cols: usize,
+    frozen_top_row: usize,
+    frozen_left_col: usize,
+    hidden_rows: Vec<usize>,
+    hidden_cols: Vec<usize>,
+    row_groups: Vec<OutlineGroup>,
+    col_groups: Vec<OutlineGroup>,
+    pivots: Vec<PivotTable>,
+    /// Conditional formatting rules in `:cf` syntax
+    conditional_rules: Vec<String>,
+    /// Defined names and what they refer to
+    names: BTreeMap<String, String>,
+    tables: Vec<DataTable>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NativeCell {
+    row: usize,
+    col: usize,
+    value: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct NativeStyle {
+    row: usize,
+    col: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bg: Option<String>,
+    bold: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    alignment: Option<TextAlignment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vertical_alignment: Option<VerticalAlignment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_type: Option<DataType>,
+    wrap: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number_format: Option<String>,
+}
+
+impl Spreadsheet {
+    /// Serialize the sheet, its styles and view settings to `.xl` JSON
+    pub fn to_native(&self) -> String {
+        let mut cells: Vec<NativeCell> = self
+            .cells
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(&(row, col), value)| NativeCell { row, col, value: value.clone() })
+            .collect();
+        cells.sort_by_key(|cell| (cell.row, cell.col));
+
+        let mut styles: Vec<NativeStyle> = self
+            .cell_styles
+            .iter()
+            .filter(|(_, style)| !style.is_default())
+            .map(|(&(row, col), style)| NativeStyle {
+                row,
+                col,
+                fg: style.fg.map(color_to_string),
+                bg: style.bg.map(color_to_string),
+                bold: style.bold,
+                alignment: style.alignment,
+                vertical_alignment: style.vertical_alignment,
+                data_type: style.data_type,
+                wrap: style.wrap,
+                number_format: style.number_format.clone(),
+            })
+            .collect();
+        styles.sort_by_key(|style| (style.row, style.col));
+
+        let document = NativeDocument {
+            version: NATIVE_VERSION,
+            cells,
+            styles,
+            col_widths: self.col_widths.iter().map(|(&k, &v)| (k, v)).collect(),
+            row_heights: self.row_heights.iter().map(|(&k, &v)| (k, v)).collect(),
+            frozen_rows: self.frozen_rows,
+            frozen_cols: self.frozen_cols,
+            frozen_top_row: self.frozen_top_row,
+            frozen_left_col: self.frozen_left_col,
+            hidden_rows: self.hidden_rows.iter().copied().collect(),
+            hidden_cols: self.hidden_cols.iter().copied().collect(),
+            row_groups: self.row_groups.clone(),
+            col_groups: self.col_groups.clone(),
+            pivots: self.pivots.clone(),
+            conditional_rules: self.conditional_rules.iter().map(ConditionalRule::to_spec).collect(),
+            names: self.defined_names.iter().cloned().collect(),
+            tables: self.tables.clone(),
+        };
+        serde_json::to_string_pretty(&document).unwrap_or_default()
+    }
+
+    /// Replace the sheet with the contents of `.xl` JSON
+    pub fn load_native_str(&mut self, contents: &str) -> io::Result<()> {
+        let document: NativeDocument = serde_json::from_str(contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if document.version > NATIVE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("File was written by a newer xl (format version {})", document.version),
+            ));
+        }
+
+        self.clear_workbook();
+        for cell in document.cells {
+            self.set_cell(cell.row, cell.col, cell.value);
+        }
+        for style in document.styles {
+            self.cell_styles.insert(
+                (style.row, style.col),
+                CellStyle {
+                    fg: style.fg.as_deref().and_then(parse_color),
+                    bg: style.bg.as_deref().and_then(parse_color),
+                    bold: style.bold,
+                    alignment: style.alignment,
+                    vertical_alignment: style.vertical_alignment,
+                    data_type: style.data_type,
+                    wrap: style.wrap,
+                    number_format: style.number_format,
+                },
+            );
+        }
+        self.col_widths = document.col_widths.into_iter().collect();
+        self.row_heights = document.row_heights.into_iter().collect();
+        self.frozen_rows = document.frozen_rows;
+        self.frozen_cols = document.frozen_cols;
+        self.frozen_top_row = document.frozen_top_row;
+        self.frozen_left_col = document.frozen_left_col;
+        self.hidden_rows = document.hidden_rows.into_iter().collect();
+        self.hidden_cols = document.hidden_cols.into_iter().collect();
+        self.row_groups = document.row_groups;
+        self.col_groups = document.col_groups;
+        self.pivots = document.pivots;
+        self.conditional_rules = document
+            .conditional_rules
+            .iter()
+            .filter_map(|spec| ConditionalRule::parse(spec, None).ok())
+            .collect();
+        for (name, refers_to) in document.names {
+            let _ = self.define_name(&name, &refers_to);
+        }
+        self.tables = document.tables;
+
+        let (max_row, max_col) = self.get_data_bounds();
+        self.num_rows = (max_row + 1).max(DEFAULT_ROWS);
+        self.num_cols = (max_col + 1).max(DEFAULT_COLS);
+        Ok(())
+    }
+
+    pub fn load_native(&mut self, filepath: &str) -> io::Result<()> {
+        let contents = fs::read_to_string(filepath)?;
+        self.load_native_str(&contents)
+    }
+
+    pub fn write_native(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_native())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    #[test]
+    fn test_native_round_trip() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "Name".to_string());
+        sheet.set_cell(1, 1, "=1+2".to_string());
+        sheet.set_cell_bold(0, 0, true);
+        sheet.set_cell_bg(0, 0, Some(Color::Rgb(10, 20, 30)));
+        sheet.set_cell_wrap(1, 1, true);
+        sheet.set_cell_number_format(1, 1, Some("#,##0.00".to_string()));
+        sheet.col_widths.insert(0, 20);
+        sheet.row_heights.insert(1, 3);
+        sheet.frozen_rows = 1;
+        sheet.frozen_cols = 2;
+        sheet.frozen_left_col = 1;
+        sheet.hidden_cols.insert(4);
+        sheet.row_groups.push(OutlineGroup { start: 3, end: 5, collapsed: true });
+        sheet.conditional_command("A1:A9 >5 fg=red").unwrap();
+        sheet.name_command("Total B2").unwrap();
+
+        let mut loaded = Spreadsheet::new();
+        loaded.load_native_str(&sheet.to_native()).unwrap();
+        assert_eq!(loaded.get_cell(0, 0), "Name");
+        assert_eq!(loaded.get_cell(1, 1), "=1+2");
+        let style = loaded.get_cell_style(0, 0);
+        assert!(style.bold);
+        assert_eq!(style.bg, Some(Color::Rgb(10, 20, 30)));
+        assert!(loaded.get_cell_style(1, 1).wrap);
+        assert_eq!(loaded.get_cell_style(1, 1).number_format.as_deref(), Some("#,##0.00"));
+        assert_eq!(loaded.get_col_width(0), 20);
+        assert_eq!(loaded.get_row_height(1), 3);
+        assert_eq!((loaded.frozen_rows, loaded.frozen_cols, loaded.frozen_left_col), (1, 2, 1));
+        assert!(loaded.is_col_hidden(4));
+        assert!(loaded.is_row_hidden(4));
+        assert_eq!(loaded.row_groups, sheet.row_groups);
+        assert_eq!(loaded.conditional_rules, sheet.conditional_rules);
+        assert_eq!(loaded.defined_names, sheet.defined_names);
+    }
+
+    #[test]
+    fn test_native_rejects_garbage() {
+        let mut sheet = Spreadsheet::new();
+        assert!(sheet.load_native_str("not json").is_err());
+        assert!(sheet.load_native_str(r#"{"version": 99}"#).is_err());
+    }
+}
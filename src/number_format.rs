@@ -0,0 +1,493 @@
+use ratatui::style::Color;
+
+use crate::date::{is_date_serial, parse_date_time, Date, MONTHS, WEEKDAYS};
+
+/// A value rendered through a number format, with the colour a `[Red]`-style tag asked for
+#[derive(Clone, PartialEq, Debug)]
+pub struct Formatted {
+    pub text: String,
+    pub color: Option<Color>,
+}
+
+/// One piece of a format section
+#[derive(Clone, PartialEq, Debug)]
+enum Part {
+    Literal(String),
+    /// A format character: digit placeholders, separators, date/time letters, `@`
+    Code(char),
+    /// `E+` / `E-` in scientific formats
+    Exponent(char),
+    /// `AM/PM` (true) or `A/P` (false)
+    AmPm(bool),
+    /// `[h]`, `[m]`, `[s]`: elapsed time that doesn't wrap at 24h/60m
+    Elapsed(char),
+    General,
+}
+
+/// Excel's built-in number formats, by the id xlsx files refer to them with
+pub fn builtin_format(id: u32) -> Option<&'static str> {
+    Some(match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "m/d/yyyy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yyyy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mm:ss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+fn color_tag(name: &str) -> Option<Color> {
+    Some(match name.to_lowercase().as_str() {
+        "black" => Color::Black,
+        "blue" => Color::Blue,
+        "cyan" => Color::Cyan,
+        "green" => Color::Green,
+        "magenta" => Color::Magenta,
+        "red" => Color::Red,
+        "white" => Color::White,
+        "yellow" => Color::Yellow,
+        _ => return None,
+    })
+}
+
+/// Split a format code into its `;`-separated sections, ignoring `;` inside quotes and brackets
+fn split_sections(code: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let (mut start, mut quoted, mut bracket, mut escaped) = (0, false, false, false);
+    for (i, c) in code.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '[' if !quoted => bracket = true,
+            ']' if !quoted => bracket = false,
+            ';' if !quoted && !bracket => {
+                sections.push(&code[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    sections.push(&code[start..]);
+    sections
+}
+
+/// Break a section into parts, pulling out its colour tag
+fn parse_section(section: &str) -> (Vec<Part>, Option<Color>) {
+    let chars: Vec<char> = section.chars().collect();
+    let mut parts = Vec::new();
+    let mut color = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().collect();
+        let rest_upper = rest.to_uppercase();
+        match c {
+            '"' => {
+                let end = chars[i + 1..].iter().position(|&c| c == '"').map_or(chars.len(), |p| i + 1 + p);
+                parts.push(Part::Literal(chars[i + 1..end].iter().collect()));
+                i = end + 1;
+                continue;
+            }
+            '\\' if i + 1 < chars.len() => {
+                parts.push(Part::Literal(chars[i + 1].to_string()));
+                i += 2;
+                continue;
+            }
+            // `_x` leaves a space as wide as x; `*x` repeats x to fill the cell, which we skip
+            '_' if i + 1 < chars.len() => {
+                parts.push(Part::Literal(" ".to_string()));
+                i += 2;
+                continue;
+            }
+            '*' if i + 1 < chars.len() => {
+                i += 2;
+                continue;
+            }
+            '[' => {
+                let end = chars[i..].iter().position(|&c| c == ']').map_or(chars.len(), |p| i + p);
+                let inner: String = chars[i + 1..end].iter().collect();
+                let lower = inner.to_lowercase();
+                if let Some(tag) = color_tag(&inner) {
+                    color = Some(tag);
+                } else if let Some(currency) = inner.strip_prefix('$') {
+                    // [$€-407]: a currency symbol with a locale id
+                    let symbol = currency.split('-').next().unwrap_or("");
+                    parts.push(Part::Literal(symbol.to_string()));
+                } else if let Some(unit @ ('h' | 'm' | 's')) = lower.chars().next()
+                    && lower.chars().all(|c| c == unit)
+                {
+                    parts.push(Part::Elapsed(unit));
+                }
+                // Anything else ([>100] conditions, [Color10]) is ignored
+                i = end + 1;
+                continue;
+            }
+            _ if rest_upper.starts_with("GENERAL") => {
+                parts.push(Part::General);
+                i += 7;
+                continue;
+            }
+            _ if rest_upper.starts_with("AM/PM") => {
+                parts.push(Part::AmPm(true));
+                i += 5;
+                continue;
+            }
+            _ if rest_upper.starts_with("A/P") => {
+                parts.push(Part::AmPm(false));
+                i += 3;
+                continue;
+            }
+            'E' | 'e' if matches!(chars.get(i + 1), Some('+') | Some('-')) => {
+                parts.push(Part::Exponent(chars[i + 1]));
+                i += 2;
+                continue;
+            }
+            '0' | '#' | '?' | '.' | ',' | '%' | '@' => parts.push(Part::Code(c)),
+            'y' | 'Y' | 'm' | 'M' | 'd' | 'D' | 'h' | 'H' | 's' | 'S' => parts.push(Part::Code(c.to_ascii_lowercase())),
+            _ => parts.push(Part::Literal(c.to_string())),
+        }
+        i += 1;
+    }
+    (parts, color)
+}
+
+fn is_date_section(parts: &[Part]) -> bool {
+    parts.iter().any(|part| {
+        matches!(part, Part::Code('y' | 'm' | 'd' | 'h' | 's') | Part::AmPm(_) | Part::Elapsed(_))
+    })
+}
+
+/// True when a format code shows dates or times
+pub fn is_date_format(code: &str) -> bool {
+    is_date_section(&parse_section(split_sections(code)[0]).0)
+}
+
+/// Group digits in threes with commas
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// Like Excel's General: up to 10 significant digits, no float noise
+fn general(value: f64) -> String {
+    if value != 0.0 && (value.abs() >= 1e11 || value.abs() < 1e-9) {
+        return format_digits(value, &parse_section("0.#####E+00").0);
+    }
+    let rounded = format!("{:.9}", value);
+    let trimmed = rounded.trim_end_matches('0').trim_end_matches('.');
+    if trimmed == "-0" { "0".to_string() } else { trimmed.to_string() }
+}
+
+/// Render a number through the digit placeholders of a section
+fn format_digits(value: f64, parts: &[Part]) -> String {
+    let codes: Vec<&Part> = parts
+        .iter()
+        .filter(|part| matches!(part, Part::Code('0' | '#' | '?' | '.' | ',') | Part::Exponent(_)))
+        .collect();
+    let point = codes.iter().position(|part| **part == Part::Code('.'));
+    let exponent = codes.iter().position(|part| matches!(part, Part::Exponent(_)));
+    let int_end = point.or(exponent).unwrap_or(codes.len());
+    let frac_end = exponent.unwrap_or(codes.len());
+    let is_digit = |part: &&&Part| matches!(part, Part::Code('0' | '#' | '?'));
+
+    let int_codes = &codes[..int_end];
+    let frac_codes = point.map_or(&codes[0..0], |p| &codes[p + 1..frac_end]);
+    let int_min = int_codes.iter().filter(|part| ***part == Part::Code('0')).count();
+    let frac_max = frac_codes.iter().filter(is_digit).count();
+    let frac_min = frac_codes.iter().filter(|part| ***part == Part::Code('0')).count();
+    // A comma between digit placeholders groups thousands; trailing commas divide by 1000
+    let last_digit = int_codes.iter().rposition(|part| is_digit(&part));
+    let thousands = last_digit.is_some_and(|last| int_codes[..last].contains(&&Part::Code(',')));
+    let scale_commas = last_digit.map_or(0, |last| int_codes[last + 1..].len());
+    let mut value = value / 1000f64.powi(scale_commas as i32);
+
+    let mut exponent_text = String::new();
+    if let Some(e) = exponent {
+        let sign = match codes[e] {
+            Part::Exponent(sign) => *sign,
+            _ => '+',
+        };
+        let exp_digits = codes[e + 1..].iter().filter(is_digit).count().max(1);
+        let int_digits = int_codes.iter().filter(is_digit).count().max(1) as i32;
+        let mut power = if value == 0.0 { 0 } else { value.abs().log10().floor() as i32 - (int_digits - 1) };
+        let mut mantissa = value / 10f64.powi(power);
+        // Rounding can carry the mantissa up to the next power of ten
+        if format!("{:.*}", frac_max, mantissa.abs()).parse::<f64>().unwrap_or(0.0) >= 10f64.powi(int_digits) {
+            power += 1;
+            mantissa = value / 10f64.powi(power);
+        }
+        value = mantissa;
+        let sign_text = if power < 0 { "-" } else if sign == '+' { "+" } else { "" };
+        exponent_text = format!("E{}{:0width$}", sign_text, power.abs(), width = exp_digits);
+    }
+
+    let rounded = format!("{:.*}", frac_max, value.abs());
+    let (int_text, frac_text) = rounded.split_once('.').unwrap_or((&rounded, ""));
+    let mut frac = frac_text.to_string();
+    while frac.len() > frac_min && frac.ends_with('0') {
+        frac.pop();
+    }
+    let mut int = if int_text == "0" && int_min == 0 { String::new() } else { int_text.to_string() };
+    while int.len() < int_min {
+        int.insert(0, '0');
+    }
+    if thousands {
+        int = group_thousands(&int);
+    }
+
+    let mut text = int;
+    if point.is_some() {
+        text.push('.');
+        text.push_str(&frac);
+    }
+    text.push_str(&exponent_text);
+    text
+}
+
+fn format_number_section(value: f64, parts: &[Part]) -> String {
+    let percent = parts.iter().filter(|part| **part == Part::Code('%')).count();
+    let value = value * 100f64.powi(percent as i32);
+    let mut text = String::new();
+    let mut number_written = false;
+    for part in parts {
+        match part {
+            Part::Literal(literal) => text.push_str(literal),
+            Part::General => text.push_str(&general(value)),
+            Part::Code('%') => text.push('%'),
+            Part::Code('0' | '#' | '?' | '.' | ',') | Part::Exponent(_) if !number_written => {
+                text.push_str(&format_digits(value, parts));
+                number_written = true;
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+fn format_date_section(serial: f64, parts: &[Part]) -> String {
+    let days = serial.floor();
+    let mut seconds = ((serial - days) * 86400.0).round() as i64;
+    let mut days = days as i64;
+    if seconds >= 86400 {
+        days += 1;
+        seconds -= 86400;
+    }
+    let date = Date::from_serial(days);
+    let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let twelve_hour = parts.iter().any(|part| matches!(part, Part::AmPm(_)));
+
+    // Runs of the same letter: "yyyy", "mm", "d"...
+    let mut runs: Vec<(Option<char>, usize, &Part)> = Vec::new();
+    for part in parts {
+        match (part, runs.last_mut()) {
+            (Part::Code(c), Some((Some(last), count, _))) if last == c => *count += 1,
+            (Part::Code(c), _) => runs.push((Some(*c), 1, part)),
+            _ => runs.push((None, 1, part)),
+        }
+    }
+
+    let mut text = String::new();
+    for (i, &(letter, count, part)) in runs.iter().enumerate() {
+        let code_before = runs[..i].iter().rev().find_map(|(letter, _, part)| {
+            letter.or(match part {
+                Part::Elapsed(unit) => Some(*unit),
+                _ => None,
+            })
+        });
+        let code_after = runs[i + 1..].iter().find_map(|(letter, _, _)| *letter);
+        let two = |n: i64| if count >= 2 { format!("{:02}", n) } else { n.to_string() };
+        match (letter, part) {
+            (Some('y'), _) if count <= 2 => text.push_str(&format!("{:02}", date.year.rem_euclid(100))),
+            (Some('y'), _) => text.push_str(&format!("{:04}", date.year)),
+            // m after h or before s means minutes
+            (Some('m'), _) if count <= 2 && (code_before == Some('h') || code_after == Some('s')) => {
+                text.push_str(&two(minute))
+            }
+            (Some('m'), _) => {
+                let name = MONTHS[date.month as usize - 1];
+                text.push_str(&match count {
+                    1 | 2 => two(date.month as i64),
+                    3 => name[..3].to_string(),
+                    4 => name.to_string(),
+                    _ => name[..1].to_string(),
+                });
+            }
+            (Some('d'), _) => {
+                let name = WEEKDAYS[date.weekday()];
+                text.push_str(&match count {
+                    1 | 2 => two(date.day as i64),
+                    3 => name[..3].to_string(),
+                    _ => name.to_string(),
+                });
+            }
+            (Some('h'), _) if twelve_hour => text.push_str(&two(if hour % 12 == 0 { 12 } else { hour % 12 })),
+            (Some('h'), _) => text.push_str(&two(hour)),
+            (Some('s'), _) => text.push_str(&two(second)),
+            (Some(c), _) => text.push(c),
+            (None, Part::Literal(literal)) => text.push_str(literal),
+            (None, Part::AmPm(full)) => {
+                let am = hour < 12;
+                text.push_str(match (full, am) {
+                    (true, true) => "AM",
+                    (true, false) => "PM",
+                    (false, true) => "A",
+                    (false, false) => "P",
+                });
+            }
+            (None, Part::Elapsed(unit)) => {
+                let total = days * 86400 + seconds;
+                let elapsed = match unit {
+                    'h' => total / 3600,
+                    'm' => total / 60,
+                    _ => total,
+                };
+                text.push_str(&elapsed.to_string());
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Render a cell value through an Excel number format code such as `#,##0.00`, `0.0%`,
+/// `[Red]-#,##0`, `€#,##0.00`, `yyyy-mm-dd hh:mm` or `0.00E+00`. Text that isn't a number
+/// only changes if the code has a text section (`@`), or is a date format and the text a date.
+pub fn format_value(value: &str, code: &str) -> Formatted {
+    let sections = split_sections(code);
+    // Dates typed as text (say, loaded from CSV) still go through a date format
+    let number = value.trim().parse::<f64>().ok().or_else(|| {
+        is_date_format(code).then(|| parse_date_time(value)).flatten().map(|(serial, _)| serial)
+    });
+    let Some(number) = number else {
+        // Text goes through the fourth section, or a lone section that has an @
+        let text_section = sections.get(3).copied().or_else(|| {
+            (sections.len() == 1 && sections[0].contains('@')).then_some(sections[0])
+        });
+        return match text_section {
+            Some(section) if !value.is_empty() => {
+                let (parts, color) = parse_section(section);
+                let text = parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Code('@') => value.to_string(),
+                        Part::Literal(literal) => literal.clone(),
+                        _ => String::new(),
+                    })
+                    .collect();
+                Formatted { text, color }
+            }
+            _ => Formatted { text: value.to_string(), color: None },
+        };
+    };
+
+    // positive;negative;zero - a negative section supplies its own sign
+    let (section, magnitude) = match sections.len() {
+        1 => (sections[0], number),
+        _ if number < 0.0 => (sections[1], number.abs()),
+        n if n >= 3 && number == 0.0 => (sections[2], number),
+        _ => (sections[0], number),
+    };
+    let (parts, color) = parse_section(section);
+    if parts.is_empty() {
+        return Formatted { text: String::new(), color };
+    }
+    let text = if is_date_section(&parts) {
+        if !is_date_serial(number) {
+            "#######".to_string()
+        } else {
+            format_date_section(number, &parts)
+        }
+    } else {
+        let text = format_number_section(magnitude.abs(), &parts);
+        // With a single section, negatives get a minus unless they round to zero
+        if magnitude < 0.0 && text.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            format!("-{}", text)
+        } else {
+            text
+        }
+    };
+    Formatted { text, color }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(value: &str, code: &str) -> String {
+        format_value(value, code).text
+    }
+
+    #[test]
+    fn test_number_formats() {
+        assert_eq!(fmt("1234567.891", "#,##0.00"), "1,234,567.89");
+        assert_eq!(fmt("0.5", "0.0%"), "50.0%");
+        assert_eq!(fmt("-1234", "#,##0"), "-1,234");
+        assert_eq!(fmt("1234.5", "€#,##0.00"), "€1,234.50");
+        assert_eq!(fmt("1234.5", "[$€-407]#,##0.00"), "€1,234.50");
+        assert_eq!(fmt("12345", "0.00E+00"), "1.23E+04");
+        assert_eq!(fmt("0.00012", "0.00E+00"), "1.20E-04");
+        assert_eq!(fmt("7", "000"), "007");
+        assert_eq!(fmt("0.25", "#.##"), ".25");
+        assert_eq!(fmt("1.5", "0.0#"), "1.5");
+        assert_eq!(fmt("1234567", "#,##0,\"K\""), "1,235K");
+        assert_eq!(fmt("3", "0 \"units\""), "3 units");
+        assert_eq!(fmt("2.5", "General"), "2.5");
+        assert_eq!(fmt("123456789012", "General"), "1.23457E+11");
+    }
+
+    #[test]
+    fn test_sections_and_colors() {
+        let negative = format_value("-1234", "#,##0;[Red]-#,##0");
+        assert_eq!(negative.text, "-1,234");
+        assert_eq!(negative.color, Some(Color::Red));
+        assert_eq!(fmt("-5", "0;(0)"), "(5)");
+        assert_eq!(fmt("0", "0;-0;\"zero\""), "zero");
+        assert_eq!(fmt("hello", "0;-0;0;\"Text: \"@"), "Text: hello");
+        assert_eq!(fmt("hello", "0.00"), "hello");
+        assert_eq!(format_value("5", "[Blue]0").color, Some(Color::Blue));
+    }
+
+    #[test]
+    fn test_date_formats() {
+        // 45306.75 is 2024-01-15 18:00
+        assert_eq!(fmt("45306.75", "yyyy-mm-dd hh:mm"), "2024-01-15 18:00");
+        assert_eq!(fmt("45306.75", "m/d/yy h:mm AM/PM"), "1/15/24 6:00 PM");
+        assert_eq!(fmt("45306", "dddd, mmmm d"), "Monday, January 15");
+        assert_eq!(fmt("45306", "ddd d-mmm-yyyy"), "Mon 15-Jan-2024");
+        assert_eq!(fmt("1.5", "[h]:mm"), "36:00");
+        assert_eq!(fmt("0.000694444", "mm:ss"), "01:00");
+        assert_eq!(fmt("2024-01-15", "mmm d, yyyy"), "Jan 15, 2024");
+        assert_eq!(fmt("1", "yyyy-mm-dd"), "1900-01-01");
+        assert_eq!(fmt("60", "yyyy-mm-dd"), "1900-02-29");
+    }
+}
@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+
+use crate::spreadsheet::Spreadsheet;
+use crate::types::RowColumnSelectMode;
+
+/// A collapsible outline group of rows or columns (`start..=end`)
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct OutlineGroup {
+    pub start: usize,
+    pub end: usize,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl OutlineGroup {
+    pub fn contains(&self, index: usize) -> bool {
+        index >= self.start && index <= self.end
+    }
+}
+
+/// Move an index to account for an inserted (at `at`) or deleted (`at`) row/column
+pub fn shift_index(index: usize, at: usize, inserted: bool) -> Option<usize> {
+    match (inserted, index.cmp(&at)) {
+        (_, std::cmp::Ordering::Less) => Some(index),
+        (true, _) => Some(index + 1),
+        (false, std::cmp::Ordering::Equal) => None,
+        (false, std::cmp::Ordering::Greater) => Some(index - 1),
+    }
+}
+
+fn shift_groups(groups: &mut Vec<OutlineGroup>, at: usize, inserted: bool) {
+    groups.retain_mut(|group| {
+        if inserted {
+            if group.start >= at {
+                group.start += 1;
+            }
+            if group.end >= at {
+                group.end += 1;
+            }
+            true
+        } else if group.start == at && group.end == at {
+            false
+        } else {
+            if group.start > at {
+                group.start -= 1;
+            }
+            if group.end >= at {
+                group.end -= 1;
+            }
+            true
+        }
+    });
+}
+
+/// Innermost group that `index` belongs to, or whose +/- button sits at `index` (the line after it)
+fn group_at(groups: &[OutlineGroup], index: usize) -> Option<usize> {
+    groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| group.contains(index))
+        .min_by_key(|(_, group)| group.end - group.start)
+        .or_else(|| groups.iter().enumerate().find(|(_, group)| group.end + 1 == index))
+        .map(|(i, _)| i)
+}
+
+/// Character drawn in the header gutter: the +/- button after a group, a bar alongside it
+fn outline_mark(groups: &[OutlineGroup], index: usize, bar: char) -> char {
+    if let Some(group) = groups.iter().find(|group| group.end + 1 == index) {
+        return if group.collapsed { '+' } else { '−' };
+    }
+    if groups.iter().any(|group| !group.collapsed && group.contains(index)) {
+        return bar;
+    }
+    ' '
+}
+
+impl Spreadsheet {
+    /// Hidden by hand, folded away in a collapsed group, or filtered out
+    pub fn is_row_hidden(&self, row: usize) -> bool {
+        self.is_row_filtered(row) || self.is_row_hidden_by_user(row)
+    }
+
+    /// Hidden by hand or by a collapsed group (what SUBTOTAL 101-111 leave out)
+    pub fn is_row_hidden_by_user(&self, row: usize) -> bool {
+        self.hidden_rows.contains(&row)
+            || self.row_groups.iter().any(|group| group.collapsed && group.contains(row))
+    }
+
+    pub fn is_col_hidden(&self, col: usize) -> bool {
+        self.hidden_cols.contains(&col)
+            || self.col_groups.iter().any(|group| group.collapsed && group.contains(col))
+    }
+
+    /// Nearest row that isn't hidden, searching from `row` in the direction of `step`
+    pub fn next_visible_row(&self, row: usize, step: isize) -> Option<usize> {
+        let mut row = row as isize;
+        while row >= 0 && (row as usize) < self.num_rows {
+            if !self.is_row_hidden(row as usize) {
+                return Some(row as usize);
+            }
+            row += step.signum();
+        }
+        None
+    }
+
+    /// Nearest column that isn't hidden, searching from `col` in the direction of `step`
+    pub fn next_visible_col(&self, col: usize, step: isize) -> Option<usize> {
+        let mut col = col as isize;
+        while col >= 0 && (col as usize) < self.num_cols {
+            if !self.is_col_hidden(col as usize) {
+                return Some(col as usize);
+            }
+            col += step.signum();
+        }
+        None
+    }
+
+    /// Put the cursor back on a visible cell after rows or columns have been hidden
+    pub fn ensure_cursor_visible(&mut self) {
+        if let Some(row) = self
+            .next_visible_row(self.cursor_row, 1)
+            .or_else(|| self.next_visible_row(self.cursor_row, -1))
+        {
+            self.cursor_row = row;
+        }
+        if let Some(col) = self
+            .next_visible_col(self.cursor_col, 1)
+            .or_else(|| self.next_visible_col(self.cursor_col, -1))
+        {
+            self.cursor_col = col;
+        }
+    }
+
+    /// Hide the rows or columns selected in row/column select mode
+    pub fn hide_selected(&mut self) {
+        if let Some((min_row, max_row)) = self.selected_rows {
+            self.hidden_rows.extend(min_row..=max_row);
+        } else if let Some((min_col, max_col)) = self.selected_cols {
+            self.hidden_cols.extend(min_col..=max_col);
+        }
+        self.exit_row_column_select_mode();
+        self.ensure_cursor_visible();
+    }
+
+    /// Unhide hidden rows/columns inside the selection, or right next to it
+    pub fn unhide_selected(&mut self) {
+        fn unhide(hidden: &mut std::collections::BTreeSet<usize>, min: usize, max: usize) {
+            hidden.retain(|&index| index < min || index > max);
+            let mut before = min;
+            while before > 0 && hidden.remove(&(before - 1)) {
+                before -= 1;
+            }
+            let mut after = max + 1;
+            while hidden.remove(&after) {
+                after += 1;
+            }
+        }
+
+        if let Some((min_row, max_row)) = self.selected_rows {
+            unhide(&mut self.hidden_rows, min_row, max_row);
+        } else if let Some((min_col, max_col)) = self.selected_cols {
+            unhide(&mut self.hidden_cols, min_col, max_col);
+        }
+    }
+
+    pub fn unhide_all(&mut self) {
+        match self.row_column_select_mode {
+            RowColumnSelectMode::ColumnSelect => self.hidden_cols.clear(),
+            _ => self.hidden_rows.clear(),
+        }
+    }
+
+    /// Group the selected rows or columns into an outline group
+    pub fn group_selected(&mut self) {
+        if let Some((start, end)) = self.selected_rows {
+            if !self.row_groups.iter().any(|group| group.start == start && group.end == end) {
+                self.row_groups.push(OutlineGroup { start, end, collapsed: false });
+            }
+        } else if let Some((start, end)) = self.selected_cols
+            && !self.col_groups.iter().any(|group| group.start == start && group.end == end)
+        {
+            self.col_groups.push(OutlineGroup { start, end, collapsed: false });
+        }
+    }
+
+    /// Remove the outline groups that lie within the selection
+    pub fn ungroup_selected(&mut self) {
+        if let Some((min, max)) = self.selected_rows {
+            self.row_groups.retain(|group| group.start < min || group.end > max);
+        } else if let Some((min, max)) = self.selected_cols {
+            self.col_groups.retain(|group| group.start < min || group.end > max);
+        }
+    }
+
+    /// Fold (`Some(true)`), unfold (`Some(false)`) or toggle (`None`) the group at the cursor
+    pub fn fold_group_at_cursor(&mut self, collapse: Option<bool>) {
+        let (groups, index) = match self.row_column_select_mode {
+            RowColumnSelectMode::ColumnSelect => (&mut self.col_groups, self.cursor_col),
+            _ => (&mut self.row_groups, self.cursor_row),
+        };
+        if let Some(i) = group_at(groups, index) {
+            let group = &mut groups[i];
+            group.collapsed = collapse.unwrap_or(!group.collapsed);
+            // Leave the cursor on the group's button when it folds away
+            if group.collapsed && group.contains(index) {
+                let button = group.end + 1;
+                match self.row_column_select_mode {
+                    RowColumnSelectMode::ColumnSelect => {
+                        self.cursor_col = button.min(self.num_cols - 1);
+                        self.selected_cols = Some((self.cursor_col, self.cursor_col));
+                    }
+                    _ => {
+                        self.cursor_row = button.min(self.num_rows - 1);
+                        self.selected_rows = Some((self.cursor_row, self.cursor_row));
+                    }
+                }
+            }
+        }
+        self.ensure_cursor_visible();
+    }
+
+    /// Fold or unfold every group of the current kind (vim's zM / zR)
+    pub fn fold_all_groups(&mut self, collapse: bool) {
+        let groups = match self.row_column_select_mode {
+            RowColumnSelectMode::ColumnSelect => &mut self.col_groups,
+            _ => &mut self.row_groups,
+        };
+        for group in groups.iter_mut() {
+            group.collapsed = collapse;
+        }
+        self.ensure_cursor_visible();
+    }
+
+    /// Gutter mark for a row header
+    pub fn row_outline_mark(&self, row: usize) -> char {
+        outline_mark(&self.row_groups, row, '│')
+    }
+
+    /// Gutter mark for a column header
+    pub fn col_outline_mark(&self, col: usize) -> char {
+        outline_mark(&self.col_groups, col, '─')
+    }
+
+    /// Keep hidden rows and row groups attached to their rows when one is inserted or deleted at `at`
+    pub fn shift_row_outline(&mut self, at: usize, inserted: bool) {
+        self.hidden_rows = self.hidden_rows.iter().filter_map(|&row| shift_index(row, at, inserted)).collect();
+        shift_groups(&mut self.row_groups, at, inserted);
+    }
+
+    pub fn shift_col_outline(&mut self, at: usize, inserted: bool) {
+        self.hidden_cols = self.hidden_cols.iter().filter_map(|&col| shift_index(col, at, inserted)).collect();
+        shift_groups(&mut self.col_groups, at, inserted);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hide_and_unhide_rows() {
+        let mut sheet = Spreadsheet::new();
+        sheet.cursor_row = 2;
+        sheet.enter_row_select_mode();
+        sheet.selected_rows = Some((2, 3));
+        sheet.hide_selected();
+        assert!(sheet.is_row_hidden(2) && sheet.is_row_hidden(3));
+        assert_eq!(sheet.cursor_row, 4);
+
+        // Moving up from row 4 skips straight to row 1
+        sheet.move_cursor(-1, 0, false);
+        assert_eq!(sheet.cursor_row, 1);
+
+        // Selecting the rows either side and unhiding brings them back
+        sheet.selected_rows = Some((1, 4));
+        sheet.unhide_selected();
+        assert!(sheet.hidden_rows.is_empty());
+
+        // Unhiding next to a selected row works too
+        sheet.hidden_rows.extend([5, 6]);
+        sheet.selected_rows = Some((4, 4));
+        sheet.unhide_selected();
+        assert!(sheet.hidden_rows.is_empty());
+    }
+
+    #[test]
+    fn test_fold_and_unfold_groups() {
+        let mut sheet = Spreadsheet::new();
+        sheet.enter_row_select_mode();
+        sheet.selected_rows = Some((1, 3));
+        sheet.group_selected();
+        sheet.cursor_row = 2;
+
+        sheet.fold_group_at_cursor(Some(true));
+        assert!((1..=3).all(|row| sheet.is_row_hidden(row)));
+        assert_eq!(sheet.cursor_row, 4);
+        assert_eq!(sheet.row_outline_mark(4), '+');
+
+        sheet.fold_group_at_cursor(Some(false));
+        assert!(!sheet.is_row_hidden(2));
+        assert_eq!(sheet.row_outline_mark(2), '│');
+        assert_eq!(sheet.row_outline_mark(4), '−');
+
+        sheet.fold_all_groups(true);
+        assert!(sheet.is_row_hidden(1));
+        sheet.selected_rows = Some((0, 5));
+        sheet.ungroup_selected();
+        assert!(!sheet.is_row_hidden(1));
+    }
+
+    #[test]
+    fn test_row_header_fits_mark_and_number() {
+        let mut sheet = Spreadsheet::new();
+        sheet.num_rows = 100_000;
+        assert_eq!(sheet.row_header_width(), 6);
+        sheet.row_groups.push(OutlineGroup { start: 2, end: 4, collapsed: false });
+        assert_eq!(sheet.row_header_width(), 7);
+        sheet.num_rows = 10;
+        assert_eq!(sheet.row_header_width(), 5);
+    }
+
+    #[test]
+    fn test_hidden_columns_and_shifting() {
+        let mut sheet = Spreadsheet::new();
+        sheet.hidden_cols.insert(1);
+        sheet.move_cursor(0, 1, false);
+        assert_eq!(sheet.cursor_col, 2);
+        assert!(!sheet.displayed_cols(200).contains(&1));
+
+        sheet.row_groups.push(OutlineGroup { start: 2, end: 4, collapsed: false });
+        sheet.hidden_rows.insert(6);
+        sheet.shift_row_outline(3, true);
+        assert_eq!(sheet.row_groups[0], OutlineGroup { start: 2, end: 5, collapsed: false });
+        assert!(sheet.hidden_rows.contains(&7));
+        sheet.shift_row_outline(7, false);
+        assert!(sheet.hidden_rows.is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sort::SortKey;
+use crate::spreadsheet::Spreadsheet;
+
+/// How a pivot value field is summarised
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    Sum,
+    Count,
+    Average,
+    Min,
+    Max,
+    DistinctCount,
+}
+
+impl Aggregation {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sum" => Some(Self::Sum),
+            "count" => Some(Self::Count),
+            "avg" | "average" => Some(Self::Average),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "distinct" | "countd" => Some(Self::DistinctCount),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Sum => "Sum",
+            Self::Count => "Count",
+            Self::Average => "Average",
+            Self::Min => "Min",
+            Self::Max => "Max",
+            Self::DistinctCount => "Distinct Count",
+        }
+    }
+
+    /// Summarise evaluated cell values. Count and distinct count look at every non-empty value;
+    /// the others only at numbers.
+    pub fn apply(self, values: &[&str]) -> String {
+        let values: Vec<&str> = values.iter().copied().filter(|v| !v.is_empty()).collect();
+        let numbers: Vec<f64> = values.iter().filter_map(|v| v.trim().parse().ok()).collect();
+        let result = match self {
+            Self::Count => values.len() as f64,
+            Self::DistinctCount => values.iter().collect::<BTreeSet<_>>().len() as f64,
+            Self::Sum => numbers.iter().sum(),
+            Self::Average if numbers.is_empty() => return "#DIV/0!".to_string(),
+            Self::Average => numbers.iter().sum::<f64>() / numbers.len() as f64,
+            Self::Min | Self::Max if numbers.is_empty() => 0.0,
+            Self::Min => numbers.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Max => numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        };
+        format!("{}", result)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PivotValue {
+    pub col: usize,
+    pub aggregation: Aggregation,
+}
+
+/// A pivot table definition. Live pivots are kept on the sheet and rebuilt when their source changes.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PivotTable {
+    /// Header row and columns of the source table; data runs down to the end of the table
+    pub header_row: usize,
+    pub min_col: usize,
+    pub max_col: usize,
+    pub max_row: usize,
+    pub row_keys: Vec<usize>,
+    #[serde(default)]
+    pub col_keys: Vec<usize>,
+    pub values: Vec<PivotValue>,
+    /// Top-left cell of the output
+    pub dest: (usize, usize),
+    /// Rows and columns written last time, so a shrinking result doesn't leave stale cells
+    #[serde(default)]
+    pub extent: (usize, usize),
+}
+
+/// Source rows grouped by (row key, column key)
+type PivotBuckets<'a> = HashMap<(Vec<String>, Vec<String>), Vec<&'a Vec<String>>>;
+
+fn compare_keys(a: &[String], b: &[String]) -> Ordering {
+    let key = SortKey::new(0);
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| key.compare(x, y))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn distinct_sorted(keys: impl Iterator<Item = Vec<String>>) -> Vec<Vec<String>> {
+    let mut distinct: Vec<Vec<String>> = Vec::new();
+    for key in keys {
+        if !distinct.contains(&key) {
+            distinct.push(key);
+        }
+    }
+    distinct.sort_by(|a, b| compare_keys(a, b));
+    distinct
+}
+
+impl Spreadsheet {
+    /// Work out a pivot's output grid from the current source data
+    pub fn compute_pivot(&mut self, pivot: &PivotTable) -> Vec<Vec<String>> {
+        let header: Vec<String> = (0..=pivot.max_col).map(|col| self.evaluate_cell(pivot.header_row, col)).collect();
+        let name = |col: usize| match header.get(col) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => Self::col_name(col),
+        };
+
+        // Evaluate the source rows once, skipping blank ones
+        let rows: Vec<Vec<String>> = (pivot.header_row + 1..=pivot.max_row)
+            .map(|row| (0..=pivot.max_col).map(|col| self.evaluate_cell(row, col)).collect::<Vec<_>>())
+            .filter(|values| values[pivot.min_col..].iter().any(|v| !v.is_empty()))
+            .collect();
+        let key_of = |values: &Vec<String>, cols: &[usize]| cols.iter().map(|&c| values[c].clone()).collect::<Vec<_>>();
+        let row_groups = distinct_sorted(rows.iter().map(|values| key_of(values, &pivot.row_keys)));
+        let col_groups = distinct_sorted(rows.iter().map(|values| key_of(values, &pivot.col_keys)));
+
+        // Group the source rows by (row key, column key)
+        let mut buckets: PivotBuckets = HashMap::new();
+        for values in &rows {
+            buckets
+                .entry((key_of(values, &pivot.row_keys), key_of(values, &pivot.col_keys)))
+                .or_default()
+                .push(values);
+        }
+        let summarise = |matching: &[&Vec<String>], value: &PivotValue| {
+            let column: Vec<&str> = matching.iter().map(|values| values[value.col].as_str()).collect();
+            value.aggregation.apply(&column)
+        };
+
+        let value_label = |value: &PivotValue| format!("{} of {}", value.aggregation.label(), name(value.col));
+        let mut header_line: Vec<String> = pivot.row_keys.iter().map(|&col| name(col)).collect();
+        for col_group in &col_groups {
+            for value in &pivot.values {
+                header_line.push(match (pivot.col_keys.is_empty(), pivot.values.len()) {
+                    (true, _) => value_label(value),
+                    (false, 1) => col_group.join(" / "),
+                    (false, _) => format!("{} {}", col_group.join(" / "), value_label(value)),
+                });
+            }
+        }
+        if !pivot.col_keys.is_empty() {
+            for value in &pivot.values {
+                header_line.push(if pivot.values.len() == 1 {
+                    "Grand Total".to_string()
+                } else {
+                    format!("Total {}", value_label(value))
+                });
+            }
+        }
+
+        let mut output = vec![header_line];
+        let mut lines: Vec<(Vec<String>, Option<&Vec<String>>)> =
+            row_groups.iter().map(|group| (group.clone(), Some(group))).collect();
+        let mut total_label = vec![String::new(); pivot.row_keys.len()];
+        if let Some(first) = total_label.first_mut() {
+            *first = "Grand Total".to_string();
+        }
+        lines.push((total_label, None));
+
+        for (labels, row_group) in lines {
+            let mut line = labels;
+            let in_row = |key: &Vec<String>| row_group.is_none_or(|group| key == group);
+            for col_group in &col_groups {
+                let matching: Vec<&Vec<String>> = buckets
+                    .iter()
+                    .filter(|((row_key, col_key), _)| in_row(row_key) && col_key == col_group)
+                    .flat_map(|(_, values)| values.iter().copied())
+                    .collect();
+                for value in &pivot.values {
+                    line.push(if matching.is_empty() { String::new() } else { summarise(&matching, value) });
+                }
+            }
+            if !pivot.col_keys.is_empty() {
+                let matching: Vec<&Vec<String>> = buckets
+                    .iter()
+                    .filter(|((row_key, _), _)| in_row(row_key))
+                    .flat_map(|(_, values)| values.iter().copied())
+                    .collect();
+                for value in &pivot.values {
+                    line.push(summarise(&matching, value));
+                }
+            }
+            output.push(line);
+        }
+        output
+    }
+
+    /// Write (or rewrite) a pivot's output, clearing whatever its previous output covered
+    pub fn write_pivot(&mut self, pivot: &mut PivotTable) {
+        let output = self.compute_pivot(pivot);
+        let (dest_row, dest_col) = pivot.dest;
+        for row in 0..pivot.extent.0 {
+            for col in 0..pivot.extent.1 {
+                self.set_cell(dest_row + row, dest_col + col, String::new());
+            }
+        }
+        for (row, line) in output.iter().enumerate() {
+            for (col, value) in line.iter().enumerate() {
+                self.set_cell(dest_row + row, dest_col + col, value.clone());
+            }
+        }
+        let width = output.iter().map(|line| line.len()).max().unwrap_or(0);
+        for col in 0..width {
+            self.set_cell_bold(dest_row, dest_col + col, true);
+            self.set_cell_bold(dest_row + output.len() - 1, dest_col + col, true);
+        }
+        pivot.extent = (output.len(), width);
+        self.num_rows = self.num_rows.max(dest_row + output.len());
+        self.num_cols = self.num_cols.max(dest_col + width);
+    }
+
+    /// Rebuild every live pivot from its current source data
+    pub fn refresh_pivots(&mut self) {
+        let mut pivots = std::mem::take(&mut self.pivots);
+        for pivot in pivots.iter_mut() {
+            // Pick up rows added below the source table
+            if let Some((_, (max_row, _))) = self.detect_table_region(pivot.header_row, pivot.min_col) {
+                pivot.max_row = pivot.max_row.max(max_row);
+            }
+            let before = pivot.extent;
+            let output = self.compute_pivot(pivot);
+            let unchanged = before == (output.len(), output.iter().map(|line| line.len()).max().unwrap_or(0))
+                && output.iter().enumerate().all(|(row, line)| {
+                    line.iter()
+                        .enumerate()
+                        .all(|(col, value)| self.get_cell(pivot.dest.0 + row, pivot.dest.1 + col) == value)
+                });
+            if !unchanged {
+                self.write_pivot(pivot);
+            }
+        }
+        self.pivots = pivots;
+    }
+
+    /// A pivot field given as a column letter or a header name
+    fn pivot_column(&mut self, field: &str, header_row: usize, min_col: usize, max_col: usize) -> Result<usize, String> {
+        if let Some(col) = (min_col..=max_col).find(|&col| self.evaluate_cell(header_row, col).eq_ignore_ascii_case(field)) {
+            return Ok(col);
+        }
+        Self::parse_cell_reference(&format!("{}1", field))
+            .map(|(_, col)| col)
+            .filter(|col| (min_col..=max_col).contains(col))
+            .ok_or_else(|| format!("Unknown pivot field: {}", field))
+    }
+
+    /// `:pivot rows=A cols=B values=sum(C),count(D) [to=H1] [live]` - summarise the selection
+    /// (or the table around the cursor). `:pivot refresh` rebuilds live pivots and
+    /// `:pivot remove` stops the pivot under the cursor from refreshing.
+    pub fn pivot_command(&mut self, arg: &str) -> Result<(), String> {
+        match arg.to_lowercase().as_str() {
+            "refresh" => {
+                self.refresh_pivots();
+                return Ok(());
+            }
+            "remove" => {
+                let (row, col) = (self.cursor_row, self.cursor_col);
+                let before = self.pivots.len();
+                self.pivots.retain(|pivot| {
+                    !(row >= pivot.dest.0
+                        && row < pivot.dest.0 + pivot.extent.0
+                        && col >= pivot.dest.1
+                        && col < pivot.dest.1 + pivot.extent.1)
+                });
+                return if self.pivots.len() < before { Ok(()) } else { Err("No live pivot here".to_string()) };
+            }
+            _ => {}
+        }
+
+        let ((header_row, min_col), (max_row, max_col)) = self
+            .get_selection_range()
+            .or_else(|| self.detect_table_region(self.cursor_row, self.cursor_col))
+            .ok_or_else(|| "No table here to pivot".to_string())?;
+        if max_row <= header_row {
+            return Err("Pivot needs a header row and data".to_string());
+        }
+
+        let mut pivot = PivotTable {
+            header_row,
+            min_col,
+            max_col,
+            max_row,
+            row_keys: Vec::new(),
+            col_keys: Vec::new(),
+            values: Vec::new(),
+            dest: (header_row, max_col + 2),
+            extent: (0, 0),
+        };
+        let mut live = false;
+        for token in arg.split_whitespace() {
+            let (key, list) = token.split_once('=').unwrap_or((token, ""));
+            let fields = list.split(',').map(str::trim).filter(|f| !f.is_empty());
+            match key.to_lowercase().as_str() {
+                "rows" => {
+                    for field in fields {
+                        pivot.row_keys.push(self.pivot_column(field, header_row, min_col, max_col)?);
+                    }
+                }
+                "cols" => {
+                    for field in fields {
+                        pivot.col_keys.push(self.pivot_column(field, header_row, min_col, max_col)?);
+                    }
+                }
+                "values" => {
+                    for field in fields {
+                        // sum(C), or a bare column which is summed
+                        let (aggregation, field) = match field.split_once('(') {
+                            Some((name, rest)) => (
+                                Aggregation::parse(name).ok_or_else(|| format!("Unknown aggregation: {}", name))?,
+                                rest.trim_end_matches(')'),
+                            ),
+                            None => (Aggregation::Sum, field),
+                        };
+                        let col = self.pivot_column(field, header_row, min_col, max_col)?;
+                        pivot.values.push(PivotValue { col, aggregation });
+                    }
+                }
+                "to" => {
+                    pivot.dest = Self::parse_cell_reference(list).ok_or_else(|| format!("Bad destination: {}", list))?;
+                }
+                "live" => live = true,
+                _ => return Err(format!("Unknown pivot option: {}", token)),
+            }
+        }
+        if pivot.row_keys.is_empty() {
+            return Err("Pivot needs rows=<field>".to_string());
+        }
+        if pivot.values.is_empty() {
+            return Err("Pivot needs values=<agg>(<field>)".to_string());
+        }
+        let (dest_row, dest_col) = pivot.dest;
+        if dest_row <= max_row && dest_col <= max_col && dest_row >= header_row && dest_col >= min_col {
+            return Err("Pivot output would overwrite its source".to_string());
+        }
+
+        self.write_pivot(&mut pivot);
+        if live {
+            self.pivots.push(pivot);
+        }
+        self.clear_selection();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sales_sheet() -> Spreadsheet {
+        let mut sheet = Spreadsheet::new();
+        let data = [
+            ["Region", "Product", "Amount"],
+            ["East", "Pen", "10"],
+            ["West", "Pen", "5"],
+            ["East", "Ink", "7"],
+            ["East", "Pen", "3"],
+        ];
+        for (row, values) in data.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                sheet.set_cell(row, col, value.to_string());
+            }
+        }
+        sheet
+    }
+
+    fn grid(sheet: &Spreadsheet, rows: usize, cols: usize, dest: (usize, usize)) -> Vec<Vec<String>> {
+        (0..rows)
+            .map(|r| (0..cols).map(|c| sheet.get_cell(dest.0 + r, dest.1 + c).to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_pivot_sum_by_row_key() {
+        let mut sheet = sales_sheet();
+        sheet.pivot_command("rows=Region values=sum(Amount),count(B) to=F1").unwrap();
+        assert_eq!(
+            grid(&sheet, 4, 3, (0, 5)),
+            [
+                ["Region", "Sum of Amount", "Count of Product"],
+                ["East", "20", "3"],
+                ["West", "5", "1"],
+                ["Grand Total", "25", "4"],
+            ]
+        );
+        assert!(sheet.get_cell_style(0, 5).bold);
+        assert!(sheet.pivots.is_empty());
+        assert!(sheet.pivot_command("rows=Nope values=C").is_err());
+        assert!(sheet.pivot_command("rows=A values=C to=B2").is_err());
+    }
+
+    #[test]
+    fn test_pivot_with_column_keys() {
+        let mut sheet = sales_sheet();
+        sheet.pivot_command("rows=A cols=B values=C").unwrap();
+        // Default destination is two columns right of the table
+        assert_eq!(
+            grid(&sheet, 4, 4, (0, 4)),
+            [
+                ["Region", "Ink", "Pen", "Grand Total"],
+                ["East", "7", "13", "20"],
+                ["West", "", "5", "5"],
+                ["Grand Total", "7", "18", "25"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_live_pivot_refreshes() {
+        let mut sheet = sales_sheet();
+        sheet.pivot_command("rows=A values=avg(C),distinct(B) to=F1 live").unwrap();
+        assert_eq!(sheet.get_cell(2, 6), "5");
+        sheet.set_cell(5, 0, "North".to_string());
+        sheet.set_cell(5, 2, "1".to_string());
+        sheet.refresh_pivots();
+        assert_eq!(sheet.get_cell(2, 5), "North");
+        assert_eq!(sheet.get_cell(4, 5), "Grand Total");
+        assert_eq!(sheet.get_cell(2, 7), "0");
+
+        // Removing a row shrinks the output without leaving stale cells behind
+        sheet.set_cell(5, 0, String::new());
+        sheet.set_cell(5, 2, String::new());
+        sheet.refresh_pivots();
+        assert_eq!(sheet.get_cell(3, 5), "Grand Total");
+        assert_eq!(sheet.get_cell(4, 5), "");
+
+        // Edits flag the pivots for a rebuild; moving the cursor doesn't
+        sheet.cells_changed = false;
+        sheet.move_cursor(1, 0, false);
+        assert!(!sheet.cells_changed);
+        sheet.set_cell(6, 0, "x".to_string());
+        assert!(sheet.cells_changed);
+
+        sheet.cursor_row = 1;
+        sheet.cursor_col = 5;
+        sheet.pivot_command("remove").unwrap();
+        assert!(sheet.pivots.is_empty());
+    }
+
+    #[test]
+    fn test_loading_a_file_drops_live_pivots() {
+        let mut sheet = sales_sheet();
+        sheet.pivot_command("rows=A values=C to=D1 live").unwrap();
+        sheet.hidden_rows.insert(2);
+        sheet.frozen_rows = 1;
+        let path = std::env::temp_dir().join(format!("xl_pivot_reload_{}.csv", std::process::id()));
+        std::fs::write(&path, "a,b,c,KEEP\n").unwrap();
+        sheet.load_from_file(path.to_str().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // The old workbook's pivot must not write over the new file's cells
+        sheet.refresh_pivots();
+        assert_eq!(sheet.get_cell(0, 3), "KEEP");
+        assert!(sheet.pivots.is_empty() && sheet.hidden_rows.is_empty());
+        assert_eq!(sheet.frozen_rows, 0);
+    }
+}
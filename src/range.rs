@@ -0,0 +1,283 @@
+use crate::spreadsheet::Spreadsheet;
+
+/// A rectangular block of cells (0-based, inclusive), as a formula refers to it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CellRange {
+    pub start_row: usize,
+    pub start_col: usize,
+    pub end_row: usize,
+    pub end_col: usize,
+}
+
+impl CellRange {
+    /// The range between two corners, in either order
+    pub fn new((row_a, col_a): (usize, usize), (row_b, col_b): (usize, usize)) -> Self {
+        Self {
+            start_row: row_a.min(row_b),
+            start_col: col_a.min(col_b),
+            end_row: row_a.max(row_b),
+            end_col: col_a.max(col_b),
+        }
+    }
+
+    pub fn cell(row: usize, col: usize) -> Self {
+        Self::new((row, col), (row, col))
+    }
+
+    pub fn rows(&self) -> usize {
+        self.end_row - self.start_row + 1
+    }
+
+    pub fn cols(&self) -> usize {
+        self.end_col - self.start_col + 1
+    }
+
+    pub fn is_single_cell(&self) -> bool {
+        self.rows() == 1 && self.cols() == 1
+    }
+
+    /// Every cell, row by row
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        (self.start_row..=self.end_row)
+            .flat_map(|row| (self.start_col..=self.end_col).map(move |col| (row, col)))
+            .collect()
+    }
+
+    /// The cell at a 0-based offset from the top-left corner, if it lies inside the range
+    pub fn at(&self, row_offset: usize, col_offset: usize) -> Option<(usize, usize)> {
+        (row_offset < self.rows() && col_offset < self.cols())
+            .then(|| (self.start_row + row_offset, self.start_col + col_offset))
+    }
+
+    /// The `index`th (0-based) row of the range
+    pub fn row(&self, index: usize) -> Option<Self> {
+        (index < self.rows()).then(|| Self::new((self.start_row + index, self.start_col), (self.start_row + index, self.end_col)))
+    }
+
+    /// The `index`th (0-based) column of the range
+    pub fn col(&self, index: usize) -> Option<Self> {
+        (index < self.cols()).then(|| Self::new((self.start_row, self.start_col + index), (self.end_row, self.start_col + index)))
+    }
+
+    /// The cells of a one-row or one-column range, in order; None for a 2D block
+    pub fn vector(&self) -> Option<Vec<(usize, usize)>> {
+        (self.rows() == 1 || self.cols() == 1).then(|| self.cells())
+    }
+
+    /// Shift by whole rows and columns and optionally resize, as OFFSET does. None when
+    /// the result would fall off the top or left of the sheet.
+    pub fn offset(&self, rows: i64, cols: i64, height: Option<usize>, width: Option<usize>) -> Option<Self> {
+        let start_row = usize::try_from(self.start_row as i64 + rows).ok()?;
+        let start_col = usize::try_from(self.start_col as i64 + cols).ok()?;
+        let height = height.unwrap_or(self.rows());
+        let width = width.unwrap_or(self.cols());
+        if height == 0 || width == 0 {
+            return None;
+        }
+        Some(Self::new((start_row, start_col), (start_row + height - 1, start_col + width - 1)))
+    }
+}
+
+impl Spreadsheet {
+    /// Read a range written in a formula: `A1`, `$A$1`, `A1:C3`, whole columns (`A:A`, `B:D`),
+    /// whole rows (`3:3`) or a column from a cell down (`A2:A`)
+    pub fn parse_range(&self, text: &str) -> Option<CellRange> {
+        let text = text.trim().replace('$', "");
+        let corner = |part: &str| Self::parse_cell_reference(part.trim());
+        let Some((start, end)) = text.split_once(':') else {
+            return corner(&text).map(|(row, col)| CellRange::cell(row, col));
+        };
+        if let (Some(start), Some(end)) = (corner(start), corner(end)) {
+            return Some(CellRange::new(start, end));
+        }
+
+        // Whole columns and rows, and columns from a cell down, end at the last cell with data
+        let column = |part: &str| {
+            let part = part.trim();
+            let col = Self::parse_cell_reference(&format!("{}1", part)).map(|(_, col)| col);
+            col.filter(|_| part.chars().all(|c| c.is_ascii_alphabetic()))
+        };
+        let row = |part: &str| part.trim().parse::<usize>().ok().filter(|&row| row >= 1).map(|row| row - 1);
+        let (max_row, max_col) = self.get_data_bounds();
+        if let (Some(start), Some(end)) = (column(start), column(end)) {
+            Some(CellRange::new((0, start), (max_row, end)))
+        } else if let (Some(start), Some(end)) = (row(start), row(end)) {
+            Some(CellRange::new((start, 0), (end, max_col)))
+        } else if let (Some(start), Some(end)) = (corner(start), column(end)) {
+            Some(CellRange::new(start, (max_row.max(start.0), end)))
+        } else {
+            None
+        }
+    }
+
+    /// Resolve a formula argument to the range it refers to. Besides plain references this
+    /// evaluates the reference-returning functions OFFSET, INDIRECT and INDEX.
+    pub fn resolve_range(&mut self, arg: &str, current_row: usize, current_col: usize) -> Result<CellRange, String> {
+        let arg = arg.trim();
+        let upper = arg.to_uppercase();
+        for name in ["OFFSET(", "INDIRECT(", "INDEX("] {
+            if upper.starts_with(name) && upper.ends_with(')') {
+                let inner = &arg[name.len()..arg.len() - 1];
+                return match name {
+                    "OFFSET(" => self.offset_range(inner, current_row, current_col),
+                    "INDIRECT(" => self.indirect_range(inner, current_row, current_col),
+                    _ => self.index_range(inner, current_row, current_col),
+                };
+            }
+        }
+        self.parse_range(arg).ok_or_else(|| "#REF!".to_string())
+    }
+
+    /// OFFSET(reference, rows, cols, [height], [width])
+    pub fn offset_range(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<CellRange, String> {
+        let parts = self.split_function_args(args);
+        if !(3..=5).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let base = self.resolve_range(parts[0], current_row, current_col)?;
+        let mut numbers = Vec::new();
+        for part in &parts[1..] {
+            let number = self
+                .evaluate_arg_as_number(part, current_row, current_col)
+                .ok_or_else(|| "#ERROR".to_string())?;
+            numbers.push(number.trunc() as i64);
+        }
+        let size = |n: Option<&i64>| match n {
+            Some(&n) if n < 1 => Err("#REF!".to_string()),
+            Some(&n) => Ok(Some(n as usize)),
+            None => Ok(None),
+        };
+        base.offset(numbers[0], numbers[1], size(numbers.get(2))?, size(numbers.get(3))?)
+            .ok_or_else(|| "#REF!".to_string())
+    }
+
+    /// INDIRECT(ref_text, [a1]) - a reference written as text, A1-style or (with a1 = FALSE) R1C1
+    pub fn indirect_range(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<CellRange, String> {
+        let parts = self.split_function_args(args);
+        if !(1..=2).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let a1 = match parts.get(1) {
+            Some(part) => self.evaluate_arg_as_bool(part, current_row, current_col).ok_or_else(|| "#ERROR".to_string())?,
+            None => true,
+        };
+        let range = if a1 {
+            self.parse_range(&text)
+        } else {
+            let corner = |part: &str| {
+                let (row, col) = part.trim().to_uppercase().strip_prefix('R')?.split_once('C').map(|(r, c)| (r.to_string(), c.to_string()))?;
+                let (row, col): (usize, usize) = (row.parse().ok()?, col.parse().ok()?);
+                (row >= 1 && col >= 1).then(|| (row - 1, col - 1))
+            };
+            match text.split_once(':') {
+                Some((start, end)) => corner(start).zip(corner(end)).map(|(start, end)| CellRange::new(start, end)),
+                None => corner(&text).map(|(row, col)| CellRange::cell(row, col)),
+            }
+        };
+        range.ok_or_else(|| "#REF!".to_string())
+    }
+
+    /// INDEX(range, row_num, [col_num]) as a reference. A 0 row or column number picks the
+    /// whole column or row; a single index into a one-row range counts along the row.
+    pub fn index_range(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<CellRange, String> {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let range = self.resolve_range(parts[0], current_row, current_col)?;
+        let mut indexes = Vec::new();
+        for part in &parts[1..] {
+            let index = self
+                .evaluate_arg_as_number(part, current_row, current_col)
+                .ok_or_else(|| "#ERROR".to_string())?;
+            if index < 0.0 {
+                return Err("#VALUE!".to_string());
+            }
+            indexes.push(index as usize);
+        }
+        let (row, col) = match indexes[..] {
+            [index] if range.rows() == 1 => (1, index),
+            [index] => (index, if range.cols() == 1 { 1 } else { 0 }),
+            [row, col] => (row, col),
+            _ => unreachable!(),
+        };
+        let rows = if row == 0 { Some(range) } else { range.row(row - 1) };
+        let picked = match rows {
+            Some(rows) if col == 0 => Some(rows),
+            Some(rows) => rows.col(col - 1),
+            None => None,
+        };
+        picked.ok_or_else(|| "#REF!".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_range_shape() {
+        let sheet = Spreadsheet::new();
+        let range = CellRange::new((3, 2), (1, 0));
+        assert_eq!((range.start_row, range.start_col, range.end_row, range.end_col), (1, 0, 3, 2));
+        assert_eq!((range.rows(), range.cols()), (3, 3));
+        assert_eq!(range.at(1, 2), Some((2, 2)));
+        assert_eq!(range.at(3, 0), None);
+        assert_eq!(range.row(0), sheet.parse_range("A2:C2"));
+        assert_eq!(range.col(2), sheet.parse_range("C2:C4"));
+        assert!(range.vector().is_none());
+        assert_eq!(range.offset(1, 1, Some(1), None), sheet.parse_range("B3:D3"));
+        assert!(range.offset(-2, 0, None, None).is_none());
+    }
+
+    #[test]
+    fn test_whole_column_and_row_ranges() {
+        let mut sheet = Spreadsheet::new();
+        for (cell, value) in [("A1", "Amount"), ("A2", "5"), ("A3", "7"), ("B2", "1"), ("C4", "x")] {
+            let (row, col) = Spreadsheet::parse_cell_reference(cell).unwrap();
+            sheet.set_cell(row, col, value.to_string());
+        }
+        assert_eq!(sheet.parse_range("A:A"), sheet.parse_range("A1:A4"));
+        assert_eq!(sheet.parse_range("$B:$C"), sheet.parse_range("B1:C4"));
+        assert_eq!(sheet.parse_range("2:3"), sheet.parse_range("A2:C3"));
+        assert_eq!(sheet.parse_range("A2:A"), sheet.parse_range("A2:A4"));
+        assert_eq!(sheet.parse_range("A9:B"), sheet.parse_range("A9:B9"));
+        assert_eq!(sheet.parse_range("A:3"), None);
+
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 0, 5);
+        assert_eq!(eval("=SUM(A:A)"), "12");
+        assert_eq!(eval("=COUNTA(A2:A)"), "2");
+        assert_eq!(eval("=SUM(2:2)"), "6");
+        assert_eq!(eval("=MAX(A:B)*2"), "14");
+        assert_eq!(eval("=SUMIF(A:A,\">5\")"), "7");
+
+        // The range follows the data as it grows. A formula inside its own range reads
+        // itself as #REF!, which SUM skips, instead of recursing forever.
+        sheet.set_cell(9, 0, "10".to_string());
+        sheet.set_cell(0, 1, "=SUM(B:B)".to_string());
+        sheet.set_cell(0, 2, "=C1+1".to_string());
+        assert_eq!(sheet.evaluate_formula("=SUM(A:A)", 0, 5), "22");
+        assert_eq!(sheet.evaluate_cell(0, 1), "1");
+        assert_eq!(sheet.evaluate_cell(0, 2), "#REF!");
+    }
+
+    #[test]
+    fn test_resolve_reference_functions() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "B3".to_string());
+        // Expected results are references, or an error value when not a reference
+        let check = |sheet: &mut Spreadsheet, arg: &str, expected: &str| {
+            let expected = sheet.parse_range(expected).ok_or_else(|| expected.to_string());
+            assert_eq!(sheet.resolve_range(arg, 0, 5), expected, "{}", arg);
+        };
+        check(&mut sheet, "$A$1:b2", "A1:B2");
+        check(&mut sheet, "OFFSET(A1,1,2,2,2)", "C2:D3");
+        check(&mut sheet, "INDIRECT(A1)", "B3");
+        check(&mut sheet, "INDIRECT(\"R2C3:R4C4\",FALSE)", "C2:D4");
+        check(&mut sheet, "INDEX(A1:C5,0,2)", "B1:B5");
+        check(&mut sheet, "OFFSET(INDEX(A1:C5,2,2),-1,-1)", "A1");
+        check(&mut sheet, "OFFSET(A1,-1,0)", "#REF!");
+        check(&mut sheet, "INDEX(A1:C5,6,1)", "#REF!");
+    }
+}
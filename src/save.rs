@@ -1,6 +1,7 @@
 use std::fs::File;
 use std::io::{self, Write};
 
+use crate::number_format::{format_value, is_date_format};
 use crate::spreadsheet::Spreadsheet;
 use crate::types::SaveFormat;
 
@@ -39,37 +40,47 @@ impl Spreadsheet {
     }
 
     pub fn save_to_file(&mut self) -> io::Result<()> {
-        let (max_row, max_col) = self.get_data_bounds();
+        let filename = format!("{}.{}", self.save_filename, self.save_format.extension());
+        match self.save_format {
+            SaveFormat::Csv => self.write_delimited(&filename, ',')?,
+            SaveFormat::Tsv => self.write_delimited(&filename, '\t')?,
+            SaveFormat::Xlsx => self.write_xlsx(&filename)?,
+            SaveFormat::Native => self.write_native(&filename)?,
+        }
+
+        self.remember_trust(std::path::Path::new(&filename));
+        self.save_message = Some(format!("Saved to {}", filename));
+        Ok(())
+    }
 
-        let extension = match self.save_format {
-            SaveFormat::Csv => "csv",
-            SaveFormat::Tsv => "tsv",
-        };
-        let separator = match self.save_format {
-            SaveFormat::Csv => ',',
-            SaveFormat::Tsv => '\t',
-        };
+    /// A cell as written to CSV/TSV: its content, with dates and times written as shown
+    fn delimited_value(&self, row: usize, col: usize) -> String {
+        let content = self.get_cell(row, col);
+        match self.get_cell_style(row, col).number_format {
+            Some(code) if is_date_format(&code) && content.parse::<f64>().is_ok() => format_value(content, &code).text,
+            _ => content.to_string(),
+        }
+    }
 
-        let filename = format!("{}.{}", self.save_filename, extension);
-        let mut file = File::create(&filename)?;
+    fn write_delimited(&self, filename: &str, separator: char) -> io::Result<()> {
+        let (max_row, max_col) = self.get_data_bounds();
+        let mut file = File::create(filename)?;
 
         for row in 0..=max_row {
             let mut row_data = Vec::new();
             for col in 0..=max_col {
-                let content = self.get_cell(row, col);
-                let escaped = if self.save_format == SaveFormat::Csv
+                let content = self.delimited_value(row, col);
+                let escaped = if separator == ','
                     && (content.contains(',') || content.contains('"') || content.contains('\n'))
                 {
                     format!("\"{}\"", content.replace('"', "\"\""))
                 } else {
-                    content.to_string()
+                    content
                 };
                 row_data.push(escaped);
             }
             writeln!(file, "{}", row_data.join(&separator.to_string()))?;
         }
-
-        self.save_message = Some(format!("Saved to {}", filename));
         Ok(())
     }
 }
@@ -89,4 +100,24 @@ mod tests {
         sheet.set_cell(2, 10, "test2".to_string());
         assert_eq!(sheet.get_data_bounds(), (5, 10));
     }
+
+    #[test]
+    fn test_csv_dates_round_trip() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell_from_input(0, 0, "2024-03-01".to_string());
+        sheet.set_cell_from_input(1, 0, "2024-03-15".to_string());
+        sheet.set_cell(2, 0, "=A2-A1".to_string());
+        let path = std::env::temp_dir().join(format!("xl_dates_{}", std::process::id()));
+        sheet.save_filename = path.to_string_lossy().into_owned();
+        sheet.save_format = SaveFormat::Csv;
+        sheet.save_to_file().unwrap();
+
+        let filename = format!("{}.csv", sheet.save_filename);
+        let mut loaded = Spreadsheet::new();
+        loaded.load_from_file(&filename).unwrap();
+        let _ = std::fs::remove_file(&filename);
+        assert_eq!(loaded.get_cell(0, 0), sheet.get_cell(0, 0));
+        assert_eq!(loaded.get_cell_style(1, 0).number_format.as_deref(), Some("yyyy-mm-dd"));
+        assert_eq!(loaded.evaluate_cell(2, 0), "14");
+    }
 }
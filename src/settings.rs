@@ -2,11 +2,44 @@ use std::fs;
 use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Default)]
+use crate::shell::DEFAULT_SHELL_TIMEOUT;
+use crate::theme::Theme;
+
+#[derive(Debug, Clone)]
 pub struct Settings {
     pub dark_mode: bool,
     /// If true, do not show the "new version available" prompt
     pub hide_update_prompt: bool,
+    /// Name of the colour theme; empty means the builtin light/dark theme per `dark_mode`
+    pub theme: String,
+    /// Terminal colour support: auto, truecolor, 256 or 16
+    pub color_depth: String,
+    /// If false, the mouse is left to the terminal (native text selection)
+    pub mouse: bool,
+    /// Fit column widths to their content when a file is opened
+    pub autofit_on_load: bool,
+    /// Seconds a SHELL formula's command may run before it is killed
+    pub shell_timeout: u64,
+    /// When SHELL formulas may run: never, prompt, allowlist or always
+    pub shell_policy: String,
+    /// Commands SHELL may always run, matched as prefixes
+    pub shell_allowlist: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            dark_mode: false,
+            hide_update_prompt: false,
+            theme: String::new(),
+            color_depth: String::new(),
+            mouse: true,
+            autofit_on_load: false,
+            shell_timeout: DEFAULT_SHELL_TIMEOUT,
+            shell_policy: String::new(),
+            shell_allowlist: Vec::new(),
+        }
+    }
 }
 
 impl Settings {
@@ -51,6 +84,32 @@ impl Settings {
                         "hide_update_prompt" => {
                             settings.hide_update_prompt = value == "true" || value == "1";
                         }
+                        "theme" => {
+                            settings.theme = value.to_string();
+                        }
+                        "color_depth" => {
+                            settings.color_depth = value.to_string();
+                        }
+                        "mouse" => {
+                            settings.mouse = value == "true" || value == "1";
+                        }
+                        "autofit_on_load" => {
+                            settings.autofit_on_load = value == "true" || value == "1";
+                        }
+                        "shell_timeout" => {
+                            settings.shell_timeout = value.parse().unwrap_or(DEFAULT_SHELL_TIMEOUT);
+                        }
+                        "shell_policy" => {
+                            settings.shell_policy = value.to_string();
+                        }
+                        "shell_allowlist" => {
+                            settings.shell_allowlist = value
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|prefix| !prefix.is_empty())
+                                .map(str::to_string)
+                                .collect();
+                        }
                         _ => {} // Ignore unknown keys
                     }
                 }
@@ -73,6 +132,15 @@ impl Settings {
         writeln!(file, "# xl spreadsheet settings")?;
         writeln!(file, "dark_mode={}", self.dark_mode)?;
         writeln!(file, "hide_update_prompt={}", self.hide_update_prompt)?;
+        writeln!(file, "theme={}", self.theme)?;
+        let color_depth = if self.color_depth.is_empty() { "auto" } else { &self.color_depth };
+        writeln!(file, "color_depth={}", color_depth)?;
+        writeln!(file, "mouse={}", self.mouse)?;
+        writeln!(file, "autofit_on_load={}", self.autofit_on_load)?;
+        writeln!(file, "shell_timeout={}", self.shell_timeout)?;
+        let shell_policy = if self.shell_policy.is_empty() { "prompt" } else { &self.shell_policy };
+        writeln!(file, "shell_policy={}", shell_policy)?;
+        writeln!(file, "shell_allowlist={}", self.shell_allowlist.join(","))?;
 
         Ok(())
     }
@@ -83,6 +151,46 @@ impl Settings {
         let _ = self.save(); // Ignore errors on save
     }
 
+    /// Update and save the colour theme name
+    pub fn set_theme(&mut self, theme: &str) {
+        self.theme = theme.to_string();
+        let _ = self.save(); // Ignore errors on save
+    }
+
+    /// Update and save whether xl captures the mouse
+    pub fn set_mouse(&mut self, mouse: bool) {
+        self.mouse = mouse;
+        let _ = self.save(); // Ignore errors on save
+    }
+
+    /// Update and save the SHELL command timeout
+    pub fn set_shell_timeout(&mut self, seconds: u64) {
+        self.shell_timeout = seconds;
+        let _ = self.save(); // Ignore errors on save
+    }
+
+    /// Update and save when SHELL formulas may run
+    pub fn set_shell_policy(&mut self, policy: &str) {
+        self.shell_policy = policy.to_string();
+        let _ = self.save(); // Ignore errors on save
+    }
+
+    /// Update and save the commands SHELL may always run
+    pub fn set_shell_allowlist(&mut self, allowlist: &[String]) {
+        self.shell_allowlist = allowlist.to_vec();
+        let _ = self.save(); // Ignore errors on save
+    }
+
+    /// Resolve the configured theme, falling back to the builtin light/dark theme
+    pub fn theme(&self) -> Theme {
+        if !self.theme.is_empty()
+            && let Ok(theme) = Theme::load(&self.theme)
+        {
+            return theme;
+        }
+        if self.dark_mode { Theme::dark() } else { Theme::light() }
+    }
+
     /// Update and save the "don't show update prompt again" setting
     pub fn set_hide_update_prompt(&mut self, hide: bool) {
         self.hide_update_prompt = hide;
@@ -99,6 +207,9 @@ mod tests {
         let settings = Settings::default();
         assert!(!settings.dark_mode);
         assert!(!settings.hide_update_prompt);
+        assert_eq!(settings.theme().name, "light");
+        assert!(settings.mouse);
+        assert_eq!(settings.shell_timeout, DEFAULT_SHELL_TIMEOUT);
     }
 
     #[test]
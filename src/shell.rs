@@ -0,0 +1,774 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::array::Array;
+use crate::conditional::RuleCondition;
+use crate::expression::matching_paren;
+use crate::spreadsheet::Spreadsheet;
+
+/// Seconds a SHELL command may run before it is killed, unless ~/.xlrc says otherwise
+pub const DEFAULT_SHELL_TIMEOUT: u64 = 30;
+
+/// What a SHELL cell shows until its command has finished for the first time
+pub const PENDING: &str = "#BUSY!";
+
+/// What a SHELL cell shows when the policy doesn't let its command run
+pub const BLOCKED: &str = "#BLOCKED!";
+
+/// Characters that let a shell run more than the command they follow
+const SHELL_METACHARACTERS: [char; 11] = [';', '&', '|', '`', '$', '<', '>', '(', ')', '\n', '\r'];
+
+/// How often a worker checks whether its command has exited
+const WAIT_STEP: Duration = Duration::from_millis(10);
+
+/// A finished command: its cell, its job number and what it gave
+type ShellMessage = ((usize, usize), u64, Result<Array, String>);
+
+/// When SHELL formulas may run their commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellPolicy {
+    /// Never; every SHELL cell shows #BLOCKED!
+    Never,
+    /// In new sheets and trusted files, and allowlisted commands anywhere
+    Prompt,
+    /// Only allowlisted commands
+    Allowlist,
+    /// Always, whatever file they come from
+    Always,
+}
+
+impl ShellPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "never" => Some(ShellPolicy::Never),
+            "prompt" => Some(ShellPolicy::Prompt),
+            "allowlist" => Some(ShellPolicy::Allowlist),
+            "always" => Some(ShellPolicy::Always),
+            _ => None,
+        }
+    }
+
+    /// Parse the `shell_policy` setting; empty (or anything unknown) is `prompt`
+    pub fn from_setting(value: &str) -> Self {
+        Self::parse(value).unwrap_or(ShellPolicy::Prompt)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShellPolicy::Never => "never",
+            ShellPolicy::Prompt => "prompt",
+            ShellPolicy::Allowlist => "allowlist",
+            ShellPolicy::Always => "always",
+        }
+    }
+}
+
+/// The command behind one SHELL cell and its last output
+struct ShellEntry {
+    command: String,
+    /// Run again this long after each finish, for SHELL(command, interval_secs)
+    interval: Option<Duration>,
+    /// None until the first run finishes; later runs keep showing the previous output
+    result: Option<Result<Array, String>>,
+    /// The job running now, with the flag that kills it
+    running: Option<(u64, Arc<AtomicBool>)>,
+    finished: Instant,
+}
+
+/// SHELL commands run on worker threads, their output kept per cell so the grid can be
+/// redrawn every frame without running anything again
+pub struct ShellJobs {
+    entries: HashMap<(usize, usize), ShellEntry>,
+    next_job: u64,
+    sender: Sender<ShellMessage>,
+    receiver: Receiver<ShellMessage>,
+    pub timeout: Duration,
+    pub policy: ShellPolicy,
+    pub allowlist: Vec<String>,
+    /// Whether the workbook's commands may run under the prompt policy: true for new
+    /// sheets, files without SHELL formulas and trusted files
+    pub trusted: bool,
+    /// The file opened and the hash of its content, for :trust
+    source: Option<(PathBuf, u64)>,
+    /// The opened file's commands that may not run, listed in a banner until dismissed
+    pub blocked: Vec<String>,
+    /// Where trusted files are recorded; None trusts nothing
+    pub trust_store: Option<PathBuf>,
+}
+
+impl Default for ShellJobs {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            entries: HashMap::new(),
+            next_job: 0,
+            sender,
+            receiver,
+            timeout: Duration::from_secs(DEFAULT_SHELL_TIMEOUT),
+            policy: ShellPolicy::Prompt,
+            allowlist: Vec::new(),
+            trusted: true,
+            source: None,
+            blocked: Vec::new(),
+            trust_store: dirs::home_dir().map(|home| home.join(".xl").join("trusted")),
+        }
+    }
+}
+
+impl ShellJobs {
+    /// Whether the policy lets a command run
+    pub fn allows(&self, command: &str) -> bool {
+        match self.policy {
+            ShellPolicy::Never => false,
+            ShellPolicy::Prompt => self.trusted || is_allowlisted(command, &self.allowlist),
+            ShellPolicy::Allowlist => is_allowlisted(command, &self.allowlist),
+            ShellPolicy::Always => true,
+        }
+    }
+}
+
+/// Whether a command starts with an allowlisted prefix, in whole words, and has nothing
+/// that could chain another command on: `git log` allows `git log -3` but not `git logout`
+/// or `git log; rm -rf ~`
+pub fn is_allowlisted(command: &str, allowlist: &[String]) -> bool {
+    let command = command.trim();
+    !command.contains(SHELL_METACHARACTERS)
+        && allowlist.iter().map(|prefix| prefix.trim()).any(|prefix| {
+            !prefix.is_empty()
+                && command
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
+}
+
+/// FNV-1a, so a file's hash doesn't change from one build of xl to the next
+fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// A file as the trust store knows it: its full path and the hash of its content
+fn fingerprint(path: &Path) -> Option<(PathBuf, u64)> {
+    let path = fs::canonicalize(path).ok()?;
+    let hash = content_hash(&fs::read(&path).ok()?);
+    Some((path, hash))
+}
+
+/// Whether the trust store (one `hash path` line per file) has this path with this content
+fn is_trusted(store: &Path, path: &Path, hash: u64) -> bool {
+    let line = format!("{:016x} {}", hash, path.display());
+    fs::read_to_string(store).is_ok_and(|trusted| trusted.lines().any(|trusted| trusted == line))
+}
+
+/// Record a file as trusted, replacing any earlier hash kept for its path
+fn trust(store: &Path, path: &Path, hash: u64) -> io::Result<()> {
+    let path = path.display().to_string();
+    let mut lines: Vec<String> = fs::read_to_string(store)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.split_once(' ').is_none_or(|(_, trusted)| trusted != path))
+        .map(str::to_string)
+        .collect();
+    lines.push(format!("{:016x} {}", hash, path));
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(store, lines.join("\n") + "\n")
+}
+
+/// The command a SHELL argument gives, without its quotes (single or double)
+/// Byte offsets of SHELL in an upper-cased formula, leaving out longer names that
+/// start or end with it
+fn shell_names(upper: &str) -> Vec<usize> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    upper
+        .match_indices("SHELL")
+        .map(|(at, _)| at)
+        .filter(|&at| !upper[..at].ends_with(is_name_char) && !upper[at + 5..].starts_with(is_name_char))
+        .collect()
+}
+
+fn command_text(arg: &str) -> String {
+    let arg = arg.trim();
+    if arg.len() >= 2 && ((arg.starts_with('"') && arg.ends_with('"')) || (arg.starts_with('\'') && arg.ends_with('\''))) {
+        arg[1..arg.len() - 1].to_string()
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Turn a command's output into an array. Output where most lines have several
+/// whitespace-separated fields becomes a table; other output gives one line per row.
+pub fn parse_output(stdout: &str) -> Array {
+    let lines: Vec<&str> = stdout.trim().lines().filter(|line| !line.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return vec![vec!["OK".to_string()]];
+    }
+
+    // Consider it tabular if at least 50% of non-empty lines have multiple columns
+    let multi_col_lines = lines.iter().filter(|line| line.split_whitespace().nth(1).is_some()).count();
+    if multi_col_lines * 2 < lines.len() {
+        return lines.iter().map(|line| vec![line.trim().to_string()]).collect();
+    }
+
+    // Parse as table - split by whitespace, padding short rows
+    let mut rows: Array = lines
+        .iter()
+        .map(|line| line.split_whitespace().map(str::to_string).collect())
+        .collect();
+    let width = rows.iter().map(Vec::len).max().unwrap_or(1);
+    for row in &mut rows {
+        row.resize(width, String::new());
+    }
+    rows
+}
+
+/// Read a child's pipe to the end on its own thread, so a chatty command can't fill
+/// the pipe and stall while we wait for it to exit
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// Run a command through the shell, killing it once it passes the timeout or is cancelled
+/// Kill a command's shell along with everything it started
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    // SAFETY: kill only sends a signal; the group is the one the child was spawned in
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+fn run_command(command: &str, timeout: Duration, cancel: &AtomicBool) -> Result<Array, String> {
+    let mut shell = if cfg!(target_os = "windows") {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    };
+    // Its own process group, so stopping it reaches every process of a pipeline
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut shell, 0);
+    let mut child = shell
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("#ERROR: {}", e))?;
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| format!("#ERROR: {}", e))? {
+            break status;
+        }
+        let timed_out = started.elapsed() >= timeout;
+        if timed_out || cancel.load(Ordering::Relaxed) {
+            kill_process_group(&mut child);
+            let _ = child.wait();
+            return Err(if timed_out {
+                format!("#ERROR: timed out after {}s", timeout.as_secs())
+            } else {
+                "#ERROR: cancelled".to_string()
+            });
+        }
+        thread::sleep(WAIT_STEP);
+    };
+
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        return Err(format!("#ERROR: {}", stderr.trim()));
+    }
+    Ok(parse_output(&stdout.join().unwrap_or_default()))
+}
+
+impl Spreadsheet {
+    /// SHELL(command, [interval_secs]) - run a shell command on a worker thread and spill
+    /// its output. Shows #BUSY! until the first run finishes; with an interval the command
+    /// runs again that many seconds after each finish.
+    pub fn evaluate_shell(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        let command = parts.first().map(|arg| command_text(arg)).unwrap_or_default();
+        if command.is_empty() {
+            return Err("#ERROR".to_string());
+        }
+        if !self.shell.allows(&command) {
+            return Err(BLOCKED.to_string());
+        }
+        let interval = match parts.get(1) {
+            Some(part) => match self.evaluate_arg_as_number(part, current_row, current_col) {
+                Some(secs) if secs > 0.0 && secs.is_finite() => Some(Duration::from_secs_f64(secs)),
+                _ => return Err("#VALUE!".to_string()),
+            },
+            None => None,
+        };
+
+        let cell = (current_row, current_col);
+        if self.shell.entries.get(&cell).is_none_or(|entry| entry.command != command) {
+            self.shell.entries.insert(
+                cell,
+                ShellEntry { command, interval, result: None, running: None, finished: Instant::now() },
+            );
+            self.start_shell_job(cell);
+        }
+        let entry = self.shell.entries.get_mut(&cell).expect("entry was just checked");
+        entry.interval = interval;
+        entry.result.clone().unwrap_or_else(|| Err(PENDING.to_string()))
+    }
+
+    /// Run a cell's command on a new worker thread, killing any run still going
+    fn start_shell_job(&mut self, cell: (usize, usize)) {
+        let shell = &mut self.shell;
+        let Some(entry) = shell.entries.get_mut(&cell) else {
+            return;
+        };
+        if let Some((_, cancel)) = entry.running.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        shell.next_job += 1;
+        let job = shell.next_job;
+        let cancel = Arc::new(AtomicBool::new(false));
+        entry.running = Some((job, cancel.clone()));
+
+        let command = entry.command.clone();
+        let timeout = shell.timeout;
+        let sender = shell.sender.clone();
+        thread::spawn(move || {
+            let result = run_command(&command, timeout, &cancel);
+            let _ = sender.send((cell, job, result));
+        });
+    }
+
+    /// Take in finished commands and start periodic ones that are due. Called each frame.
+    pub fn poll_shell_jobs(&mut self) {
+        let mut changed = false;
+        while let Ok((cell, job, result)) = self.shell.receiver.try_recv() {
+            // A run that was replaced by a newer one is dropped
+            if let Some(entry) = self.shell.entries.get_mut(&cell)
+                && entry.running.as_ref().is_some_and(|(running, _)| *running == job)
+            {
+                entry.result = Some(result);
+                entry.running = None;
+                entry.finished = Instant::now();
+                changed = true;
+            }
+        }
+
+        let due: Vec<(usize, usize)> = self
+            .shell
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry.running.is_none() && entry.interval.is_some_and(|interval| entry.finished.elapsed() >= interval)
+            })
+            .map(|(&cell, _)| cell)
+            .collect();
+        for cell in due {
+            // Forget cells whose SHELL formula has since been edited away
+            if self.get_cell(cell.0, cell.1).to_uppercase().contains("SHELL(") {
+                self.start_shell_job(cell);
+            } else {
+                self.shell.entries.remove(&cell);
+            }
+        }
+
+        if changed {
+            self.refresh_spills();
+        }
+    }
+
+    /// Run SHELL commands again: the one at the cursor (or whose output it is on),
+    /// those in the selection ("sel"), or every one ("all")
+    pub fn refresh_command(&mut self, arg: &str) -> Result<(), String> {
+        let cells: Vec<(usize, usize)> = match arg.to_lowercase().as_str() {
+            "all" => self.shell.entries.keys().copied().collect(),
+            "sel" | "selection" => {
+                let ((min_row, min_col), (max_row, max_col)) = self
+                    .get_selection_range()
+                    .unwrap_or(((self.cursor_row, self.cursor_col), (self.cursor_row, self.cursor_col)));
+                self.shell
+                    .entries
+                    .keys()
+                    .filter(|(row, col)| (min_row..=max_row).contains(row) && (min_col..=max_col).contains(col))
+                    .copied()
+                    .collect()
+            }
+            "" => {
+                let cursor = (self.cursor_row, self.cursor_col);
+                let cell = if self.shell.entries.contains_key(&cursor) {
+                    Some(cursor)
+                } else {
+                    self.spill_anchor(cursor.0, cursor.1)
+                };
+                cell.into_iter().filter(|cell| self.shell.entries.contains_key(cell)).collect()
+            }
+            _ => return Err(format!("Usage: refresh [sel|all], not '{}'", arg)),
+        };
+        if cells.is_empty() {
+            return Err("No SHELL formula to refresh".to_string());
+        }
+        for cell in cells {
+            self.start_shell_job(cell);
+        }
+        Ok(())
+    }
+
+    /// Every formula the workbook can evaluate: its cells, defined names and
+    /// conditional-format rules
+    fn formula_texts(&self) -> Vec<&str> {
+        let mut cells: Vec<(&(usize, usize), &String)> =
+            self.cells.iter().filter(|(_, content)| content.starts_with('=')).collect();
+        cells.sort();
+        let rules = self.conditional_rules.iter().filter_map(|rule| match &rule.condition {
+            RuleCondition::Formula(formula) => Some(formula.as_str()),
+            _ => None,
+        });
+        cells
+            .into_iter()
+            .map(|(_, content)| content.as_str())
+            .chain(self.defined_names.iter().map(|(_, refers_to)| refers_to.as_str()))
+            .chain(rules)
+            .collect()
+    }
+
+    /// Whether any formula in the workbook names SHELL, called directly or not
+    pub fn uses_shell(&self) -> bool {
+        self.formula_texts().iter().any(|text| !shell_names(&text.to_ascii_uppercase()).is_empty())
+    }
+
+    /// The command of each SHELL call in the workbook, once each
+    pub fn shell_commands(&self) -> Vec<String> {
+        let mut commands = Vec::new();
+        for content in self.formula_texts() {
+            for at in shell_names(&content.to_ascii_uppercase()) {
+                let open = at + 5;
+                if !content[open..].starts_with('(') {
+                    continue;
+                }
+                let Some(close) = matching_paren(content, open) else {
+                    continue;
+                };
+                let command = self.split_function_args(&content[open + 1..close]).first().map(|arg| command_text(arg));
+                if let Some(command) = command.filter(|command| !command.is_empty() && !commands.contains(command)) {
+                    commands.push(command);
+                }
+            }
+        }
+        commands
+    }
+
+    /// Stop and forget the previous workbook's commands before another is loaded, and
+    /// run nothing until `check_shell_trust` has looked at the new one
+    pub fn distrust_shell(&mut self) {
+        for entry in self.shell.entries.values() {
+            if let Some((_, cancel)) = &entry.running {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+        self.shell.entries.clear();
+        self.shell.trusted = false;
+        self.shell.source = None;
+        self.shell.blocked.clear();
+    }
+
+    /// Work out whether a workbook just loaded from `path` (None for piped data) may run
+    /// its SHELL commands, and list the ones that may not for the banner. Call before
+    /// anything evaluates the new cells.
+    pub fn check_shell_trust(&mut self, path: Option<&Path>) {
+        let commands = self.shell_commands();
+        self.shell.source = path.and_then(fingerprint);
+        // SHELL can hide behind a name or a rule, so any mention at all needs trust
+        self.shell.trusted = !self.uses_shell()
+            || match (&self.shell.trust_store, &self.shell.source) {
+                (Some(store), Some((path, hash))) => is_trusted(store, path, *hash),
+                _ => false,
+            };
+        self.shell.blocked = commands;
+        self.update_blocked();
+    }
+
+    /// Drop commands the policy now lets run from the banner
+    fn update_blocked(&mut self) {
+        let blocked = std::mem::take(&mut self.shell.blocked);
+        self.shell.blocked = blocked.into_iter().filter(|command| !self.shell.allows(command)).collect();
+    }
+
+    /// After saving to `path`, trust the file if the sheet's commands could already run,
+    /// so opening it again doesn't block them
+    pub fn remember_trust(&mut self, path: &Path) {
+        if !self.shell.trusted || !self.uses_shell() {
+            return;
+        }
+        if let (Some(store), Some((path, hash))) = (&self.shell.trust_store, fingerprint(path)) {
+            let _ = trust(store, &path, hash);
+            self.shell.source = Some((path, hash));
+        }
+    }
+
+    /// :trust - let the opened file's SHELL commands run, and remember the file with its
+    /// current content so they run when it is opened again
+    pub fn trust_command(&mut self) -> Result<String, String> {
+        if let (Some(store), Some((path, hash))) = (&self.shell.trust_store, &self.shell.source) {
+            trust(store, path, *hash).map_err(|e| format!("Could not record trust: {}", e))?;
+        }
+        self.shell.trusted = true;
+        self.update_blocked();
+        if self.shell.blocked.is_empty() {
+            Ok("Trusted; SHELL commands will run".to_string())
+        } else {
+            Err(format!("Trusted, but shell_policy={} still blocks its commands", self.shell.policy.name()))
+        }
+    }
+
+    /// :shell - show the SHELL settings, or change them: `timeout [secs]`, `policy <name>`,
+    /// `allow <prefix>` or `disallow <prefix>`; `hide` dismisses the blocked-commands banner
+    pub fn shell_command(&mut self, arg: &str) -> Result<String, String> {
+        let (action, value) = arg.split_once(char::is_whitespace).map_or((arg, ""), |(action, value)| (action, value.trim()));
+        match (action.to_lowercase().as_str(), value) {
+            ("", _) => Ok(format!(
+                "SHELL policy: {}, timeout: {}s, allowlist: {}{}",
+                self.shell.policy.name(),
+                self.shell.timeout.as_secs(),
+                if self.shell.allowlist.is_empty() { "(empty)".to_string() } else { self.shell.allowlist.join(", ") },
+                if self.shell.trusted { "" } else { ", this file is not trusted" }
+            )),
+            ("timeout", "") => Ok(format!("SHELL timeout: {}s", self.shell.timeout.as_secs())),
+            ("timeout", secs) => {
+                let secs: u64 = secs.parse().ok().filter(|&secs| secs > 0).ok_or("Timeout must be a whole number of seconds")?;
+                self.shell.timeout = Duration::from_secs(secs);
+                crate::settings::Settings::load().set_shell_timeout(secs);
+                Ok(format!("SHELL timeout: {}s", secs))
+            }
+            ("policy", policy) => {
+                let policy = ShellPolicy::parse(policy).ok_or("Policies: never, prompt, allowlist, always")?;
+                self.shell.policy = policy;
+                crate::settings::Settings::load().set_shell_policy(policy.name());
+                self.update_blocked();
+                Ok(format!("SHELL policy: {}", policy.name()))
+            }
+            ("allow", prefix) if !prefix.is_empty() => {
+                if !self.shell.allowlist.iter().any(|allowed| allowed == prefix) {
+                    self.shell.allowlist.push(prefix.to_string());
+                }
+                crate::settings::Settings::load().set_shell_allowlist(&self.shell.allowlist);
+                self.update_blocked();
+                Ok(format!("SHELL allowlist: {}", self.shell.allowlist.join(", ")))
+            }
+            ("disallow", prefix) if !prefix.is_empty() => {
+                let before = self.shell.allowlist.len();
+                self.shell.allowlist.retain(|allowed| allowed != prefix);
+                if self.shell.allowlist.len() == before {
+                    return Err(format!("'{}' is not on the allowlist", prefix));
+                }
+                crate::settings::Settings::load().set_shell_allowlist(&self.shell.allowlist);
+                Ok(format!("Removed '{}' from the SHELL allowlist", prefix))
+            }
+            ("hide", "") => {
+                self.shell.blocked.clear();
+                Ok("Blocked SHELL commands stay blocked; :trust runs them".to_string())
+            }
+            _ => Err("Usage: shell [timeout [secs] | policy <name> | allow <prefix> | disallow <prefix> | hide]".to_string()),
+        }
+    }
+
+    /// Wait for every running SHELL command to finish
+    #[cfg(test)]
+    pub fn wait_for_shell(&mut self) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.shell.entries.values().any(|entry| entry.running.is_some()) && Instant::now() < deadline {
+            thread::sleep(WAIT_STEP);
+            self.poll_shell_jobs();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(parse_output(""), vec![vec!["OK".to_string()]]);
+        assert_eq!(parse_output("one\ntwo words\nthree\n"), vec![vec!["one"], vec!["two words"], vec!["three"]]);
+        assert_eq!(parse_output("a 1\nb 2 x\nc"), vec![vec!["a", "1", ""], vec!["b", "2", "x"], vec!["c", "", ""]]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shell_runs_in_background() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "=SHELL(\"sleep 0.2; echo done\")".to_string());
+        let started = Instant::now();
+        assert_eq!(sheet.evaluate_cell(0, 0), PENDING);
+        assert!(started.elapsed() < Duration::from_millis(150));
+
+        // Redrawing doesn't start the command again
+        sheet.refresh_spills();
+        assert_eq!(sheet.evaluate_cell(0, 0), PENDING);
+        assert_eq!(sheet.shell.next_job, 1);
+
+        sheet.wait_for_shell();
+        assert_eq!(sheet.evaluate_cell(0, 0), "done");
+        sheet.refresh_spills();
+        assert_eq!(sheet.evaluate_cell(0, 0), "done");
+        assert_eq!(sheet.shell.next_job, 1);
+
+        // Refreshing runs it again, keeping the old output until the new one arrives
+        sheet.refresh_command("").unwrap();
+        assert_eq!(sheet.shell.next_job, 2);
+        sheet.refresh_spills();
+        assert_eq!(sheet.evaluate_cell(0, 0), "done");
+        assert!(sheet.refresh_command("sel").is_ok());
+        sheet.cursor_row = 5;
+        assert!(sheet.refresh_command("").is_err());
+        assert!(sheet.refresh_command("bogus").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_shell_timeout_and_interval() {
+        let mut sheet = Spreadsheet::new();
+        sheet.shell.timeout = Duration::from_millis(100);
+        sheet.set_cell(0, 0, "=SHELL(\"sleep 5\")".to_string());
+        let started = Instant::now();
+        sheet.evaluate_cell(0, 0);
+        sheet.wait_for_shell();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(sheet.evaluate_cell(0, 0).starts_with("#ERROR: timed out"));
+
+        // An interval reruns the command once it is due
+        sheet.set_cell(1, 0, "=SHELL(\"echo tick\", 0.05)".to_string());
+        sheet.evaluate_cell(1, 0);
+        sheet.wait_for_shell();
+        assert_eq!(sheet.evaluate_cell(1, 0), "tick");
+        thread::sleep(Duration::from_millis(60));
+        sheet.poll_shell_jobs();
+        assert_eq!(sheet.shell.next_job, 3);
+        sheet.set_cell(2, 0, "=SHELL(\"echo\", 0)".to_string());
+        assert_eq!(sheet.evaluate_cell(2, 0), "#VALUE!");
+
+        // Stopping a command stops the processes it started too
+        let dir = std::env::temp_dir().join(format!("xl_group_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("ran");
+        sheet.set_cell(3, 0, format!("=SHELL(\"(sleep 1; touch {}) & wait\")", marker.display()));
+        sheet.evaluate_cell(3, 0);
+        sheet.wait_for_shell();
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_shell_policy() {
+        let allowlist = vec!["date".to_string(), "git log".to_string()];
+        assert!(is_allowlisted("date", &allowlist));
+        assert!(is_allowlisted("git log -3", &allowlist));
+        assert!(!is_allowlisted("git logout", &allowlist));
+        assert!(!is_allowlisted("date; rm -rf ~", &allowlist));
+        assert!(!is_allowlisted("date $(whoami)", &allowlist));
+        assert_eq!(ShellPolicy::from_setting(""), ShellPolicy::Prompt);
+        assert_eq!(ShellPolicy::parse("Never"), Some(ShellPolicy::Never));
+
+        let mut sheet = Spreadsheet::new();
+        sheet.shell.allowlist = allowlist;
+        sheet.shell.policy = ShellPolicy::Never;
+        sheet.set_cell(0, 0, "=SHELL(\"date\")".to_string());
+        assert_eq!(sheet.evaluate_cell(0, 0), BLOCKED);
+        sheet.shell.policy = ShellPolicy::Allowlist;
+        sheet.refresh_spills();
+        assert_eq!(sheet.evaluate_cell(0, 0), PENDING);
+        sheet.set_cell(1, 0, "=SHELL(\"whoami\")".to_string());
+        assert_eq!(sheet.evaluate_cell(1, 0), BLOCKED);
+        sheet.set_cell(2, 0, "=IF(TRUE, SHELL(\"echo a\"), MYSHELL(\"x\"))".to_string());
+        assert_eq!(sheet.shell_commands(), vec!["date", "whoami", "echo a"]);
+        sheet.wait_for_shell();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_trusted_files() {
+        let dir = std::env::temp_dir().join(format!("xl_trust_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("report.csv");
+        fs::write(&file, "name,\"=SHELL(\"\"echo hi\"\")\"\n").unwrap();
+
+        // An untrusted file opens with its commands blocked and listed
+        let mut sheet = Spreadsheet::new();
+        sheet.shell.trust_store = Some(dir.join("trusted"));
+        sheet.load_from_file(file.to_str().unwrap()).unwrap();
+        assert_eq!(sheet.shell.blocked, vec!["echo hi"]);
+        assert_eq!(sheet.evaluate_cell(0, 1), BLOCKED);
+
+        sheet.trust_command().unwrap();
+        assert!(sheet.shell.blocked.is_empty());
+        sheet.refresh_spills();
+        sheet.evaluate_cell(0, 1);
+        sheet.wait_for_shell();
+        assert_eq!(sheet.evaluate_cell(0, 1), "hi");
+
+        // Trust is remembered for the same content only
+        sheet.load_from_file(file.to_str().unwrap()).unwrap();
+        assert!(sheet.shell.trusted && sheet.shell.blocked.is_empty());
+        fs::write(&file, "name,\"=SHELL(\"\"echo bye\"\")\"\n").unwrap();
+        sheet.load_from_file(file.to_str().unwrap()).unwrap();
+        assert!(!sheet.shell.trusted);
+        assert_eq!(sheet.shell.blocked, vec!["echo bye"]);
+
+        // Fitting columns on load doesn't run an untrusted file's commands either
+        let marker = dir.join("ran");
+        let probe = dir.join("probe.csv");
+        fs::write(&probe, format!("\"=SHELL(\"\"touch {}\"\")\"\n", marker.display())).unwrap();
+        sheet.autofit_on_load = true;
+        sheet.load_from_file(probe.to_str().unwrap()).unwrap();
+        sheet.evaluate_cell(0, 0);
+        thread::sleep(Duration::from_millis(100));
+        assert!(!marker.exists());
+        assert_eq!(sheet.evaluate_cell(0, 0), BLOCKED);
+
+        // SHELL hidden behind a defined name or a conditional-format rule still needs trust
+        let mut source = Spreadsheet::new();
+        source.name_command("Run LAMBDA(c, SHELL(c))").unwrap();
+        source.set_cell(0, 0, format!("=Run(\"touch {}\")", marker.display()));
+        let named = dir.join("named.xl");
+        source.write_native(named.to_str().unwrap()).unwrap();
+        sheet.load_from_file(named.to_str().unwrap()).unwrap();
+        assert!(!sheet.shell.trusted);
+        assert_eq!(sheet.evaluate_cell(0, 0), BLOCKED);
+
+        let mut source = Spreadsheet::new();
+        source.set_cell(0, 0, "1".to_string());
+        source.conditional_command(&format!("A1 formula =LEN(SHELL(\"touch {}\"))>0", marker.display())).unwrap();
+        let ruled = dir.join("ruled.xl");
+        source.write_native(ruled.to_str().unwrap()).unwrap();
+        sheet.load_from_file(ruled.to_str().unwrap()).unwrap();
+        assert!(!sheet.shell.trusted);
+        let stats = sheet.conditional_stats();
+        sheet.conditional_format(0, 0, "1", &stats);
+        thread::sleep(Duration::from_millis(100));
+        assert!(!marker.exists());
+
+        // Piped data is never trusted up front
+        sheet.load_from_buffer(b"=SHELL(\"ls\")").unwrap();
+        assert_eq!(sheet.shell.blocked, vec!["ls"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
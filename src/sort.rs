@@ -0,0 +1,277 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::spreadsheet::Spreadsheet;
+
+/// How values in a sort key column are compared
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortOrdering {
+    /// Numbers (numerically) before text (lexically), like Excel
+    Auto,
+    /// Numbers only; text sorts after every number
+    Numeric,
+    /// Plain string comparison, even for numbers
+    Lexical,
+    /// Runs of digits compare as numbers, so "item2" sorts before "item10"
+    Natural,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SortKey {
+    pub col: usize,
+    pub descending: bool,
+    pub ordering: SortOrdering,
+    pub case_sensitive: bool,
+}
+
+impl SortKey {
+    pub fn new(col: usize) -> Self {
+        Self {
+            col,
+            descending: false,
+            ordering: SortOrdering::Auto,
+            case_sensitive: false,
+        }
+    }
+
+    /// Compare two displayed values. Empty cells always go last, whichever the direction.
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match (a.is_empty(), b.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            _ => {}
+        }
+
+        let ordering = match self.ordering {
+            SortOrdering::Auto | SortOrdering::Numeric => {
+                match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+                    (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => self.compare_text(a, b),
+                }
+            }
+            SortOrdering::Lexical => self.compare_text(a, b),
+            SortOrdering::Natural => self.compare_natural(a, b),
+        };
+
+        if self.descending { ordering.reverse() } else { ordering }
+    }
+
+    fn compare_text(&self, a: &str, b: &str) -> Ordering {
+        if self.case_sensitive {
+            a.cmp(b)
+        } else {
+            a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b))
+        }
+    }
+
+    fn compare_natural(&self, a: &str, b: &str) -> Ordering {
+        let (a_chunks, b_chunks) = (natural_chunks(a), natural_chunks(b));
+        for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+            let x_digits = x.starts_with(|c: char| c.is_ascii_digit());
+            let y_digits = y.starts_with(|c: char| c.is_ascii_digit());
+            let ordering = if x_digits && y_digits {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            } else {
+                self.compare_text(x, y)
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        a_chunks.len().cmp(&b_chunks.len())
+    }
+}
+
+/// Split text into alternating runs of digits and non-digits
+fn natural_chunks(s: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut in_digits = None;
+    for (i, c) in s.char_indices() {
+        let is_digit = c.is_ascii_digit();
+        if in_digits.is_some_and(|d| d != is_digit) {
+            chunks.push(&s[start..i]);
+            start = i;
+        }
+        in_digits = Some(is_digit);
+    }
+    if start < s.len() {
+        chunks.push(&s[start..]);
+    }
+    chunks
+}
+
+impl Spreadsheet {
+    /// Sort the rows of a block by the given keys. Styles and row heights move with their
+    /// rows, and formulas are re-pointed as if each row had been cut and pasted.
+    pub fn sort_range(
+        &mut self,
+        (min_row, min_col): (usize, usize),
+        (max_row, max_col): (usize, usize),
+        keys: &[SortKey],
+        has_header: bool,
+    ) {
+        let first = if has_header { min_row + 1 } else { min_row };
+        if first >= max_row || keys.is_empty() {
+            return;
+        }
+
+        // Evaluate the key columns once up front
+        let mut rows: Vec<(usize, Vec<String>)> = (first..=max_row)
+            .map(|row| (row, keys.iter().map(|key| self.evaluate_cell(row, key.col)).collect()))
+            .collect();
+        rows.sort_by(|(_, a), (_, b)| {
+            keys.iter()
+                .enumerate()
+                .map(|(i, key)| key.compare(&a[i], &b[i]))
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        self.cells_changed = true;
+        let mut values = HashMap::new();
+        let mut styles = HashMap::new();
+        let mut heights = HashMap::new();
+        for row in first..=max_row {
+            for col in min_col..=max_col {
+                if let Some(value) = self.cells.remove(&(row, col)) {
+                    values.insert((row, col), value);
+                }
+                if let Some(style) = self.cell_styles.remove(&(row, col)) {
+                    styles.insert((row, col), style);
+                }
+            }
+            if let Some(height) = self.row_heights.remove(&row) {
+                heights.insert(row, height);
+            }
+        }
+
+        for (offset, (source, _)) in rows.iter().enumerate() {
+            let (source, dest) = (*source, first + offset);
+            for col in min_col..=max_col {
+                if let Some(value) = values.remove(&(source, col)) {
+                    let value = if value.starts_with('=') {
+                        Self::adjust_formula_references(&value, dest as isize - source as isize, 0)
+                    } else {
+                        value
+                    };
+                    self.cells.insert((dest, col), value);
+                }
+                if let Some(style) = styles.remove(&(source, col)) {
+                    self.cell_styles.insert((dest, col), style);
+                }
+            }
+            if let Some(height) = heights.remove(&source) {
+                self.row_heights.insert(dest, height);
+            }
+        }
+    }
+
+    /// `:sort` - sort the selection (or the table around the cursor). The argument lists
+    /// key columns, each optionally followed by `asc`/`desc`, `auto`/`num`/`text`/`natural`
+    /// and `case`; `header`/`noheader` says whether the first row is a header.
+    pub fn sort_command(&mut self, arg: &str) -> Result<(), String> {
+        let Some((start, end)) = self
+            .get_selection_range()
+            .or_else(|| self.detect_table_region(self.cursor_row, self.cursor_col))
+        else {
+            return Err("Nothing to sort".to_string());
+        };
+
+        // Without an explicit choice, a bold first row (as format_as_table leaves it) is a header
+        let mut has_header = (start.1..=end.1).all(|col| self.get_cell_style(start.0, col).bold);
+        let mut keys: Vec<SortKey> = Vec::new();
+        for token in arg.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+            let lower = token.to_lowercase();
+            match (lower.as_str(), keys.last_mut()) {
+                ("header", _) => has_header = true,
+                ("noheader", _) => has_header = false,
+                ("asc", Some(key)) => key.descending = false,
+                ("desc", Some(key)) => key.descending = true,
+                ("auto", Some(key)) => key.ordering = SortOrdering::Auto,
+                ("num", Some(key)) => key.ordering = SortOrdering::Numeric,
+                ("text", Some(key)) => key.ordering = SortOrdering::Lexical,
+                ("natural", Some(key)) => key.ordering = SortOrdering::Natural,
+                ("case", Some(key)) => key.case_sensitive = true,
+                _ => {
+                    let col = Self::parse_cell_reference(&format!("{}1", token))
+                        .map(|(_, col)| col)
+                        .ok_or_else(|| format!("Unknown sort option: {}", token))?;
+                    if col < start.1 || col > end.1 {
+                        return Err(format!("Column {} is outside the sorted range", token.to_uppercase()));
+                    }
+                    keys.push(SortKey::new(col));
+                }
+            }
+        }
+        if keys.is_empty() {
+            // Default to the cursor column when it is inside the block
+            keys.push(SortKey::new(self.cursor_col.clamp(start.1, end.1)));
+        }
+
+        self.sort_range(start, end, &keys, has_header);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(sheet: &Spreadsheet, col: usize, rows: std::ops::RangeInclusive<usize>) -> Vec<String> {
+        rows.map(|row| sheet.get_cell(row, col).to_string()).collect()
+    }
+
+    #[test]
+    fn test_sort_key_orderings() {
+        let mut key = SortKey::new(0);
+        assert_eq!(key.compare("10", "9"), Ordering::Greater);
+        assert_eq!(key.compare("5", "apple"), Ordering::Less);
+        assert_eq!(key.compare("", "apple"), Ordering::Greater);
+        key.ordering = SortOrdering::Lexical;
+        assert_eq!(key.compare("10", "9"), Ordering::Less);
+        key.ordering = SortOrdering::Natural;
+        assert_eq!(key.compare("item10", "item9"), Ordering::Greater);
+        key.descending = true;
+        assert_eq!(key.compare("item10", "item9"), Ordering::Less);
+        assert_eq!(key.compare("", "item9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_sort_multiple_keys_with_header() {
+        let mut sheet = Spreadsheet::new();
+        let data = [["Team", "Score"], ["b", "3"], ["a", "5"], ["b", "7"], ["a", "1"]];
+        for (row, values) in data.iter().enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                sheet.set_cell(row, col, value.to_string());
+            }
+        }
+        sheet.row_heights.insert(2, 3);
+        sheet.set_cell_bold(2, 1, true);
+
+        sheet.sort_command("header A, B desc").unwrap();
+        assert_eq!(column(&sheet, 0, 0..=4), ["Team", "a", "a", "b", "b"]);
+        assert_eq!(column(&sheet, 1, 0..=4), ["Score", "5", "1", "7", "3"]);
+        // Row 2 ("a", "5") moved to row 1 along with its height and style
+        assert_eq!(sheet.get_row_height(1), 3);
+        assert!(sheet.get_cell_style(1, 1).bold);
+    }
+
+    #[test]
+    fn test_sort_keeps_formulas_pointing_at_their_row() {
+        let mut sheet = Spreadsheet::new();
+        for (row, value) in ["3", "1", "2"].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+            sheet.set_cell(row, 1, format!("=A{}*10", row + 1));
+        }
+        sheet.sort_command("A noheader").unwrap();
+        assert_eq!(column(&sheet, 0, 0..=2), ["1", "2", "3"]);
+        assert_eq!(sheet.evaluate_cell(0, 1), "10");
+        assert_eq!(sheet.evaluate_cell(2, 1), "30");
+        assert!(sheet.sort_command("Z").is_err());
+    }
+}
@@ -1,9 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use ratatui::layout::Rect;
 
+use crate::array::Spills;
+use crate::conditional::ConditionalRule;
 use crate::constants::{DEFAULT_COLS, DEFAULT_ROWS};
-use crate::types::{CellStyle, RowColumnSelectMode, SaveFormat, VisualSubMode};
+use crate::date::parse_date_time;
+use crate::filter::{AutoFilter, FilterPopup};
+use crate::formula::formula_references;
+use crate::names::DataTable;
+use crate::number_format::{format_value, is_date_format};
+use crate::outline::OutlineGroup;
+use crate::pivot::PivotTable;
+use crate::shell::ShellJobs;
+use crate::style::data_type_format;
+use crate::theme::{ColorDepth, Theme};
+use crate::types::{CellStyle, DataType, GridLayout, MouseDrag, RowColumnSelectMode, SaveFormat, VisualSubMode};
 use crate::update::UpdateInfo;
 
 /// Represents copied/cut cell data with relative positions
@@ -44,6 +56,42 @@ pub struct Spreadsheet {
     pub cell_styles: HashMap<(usize, usize), CellStyle>,
     pub col_widths: HashMap<usize, u16>,
     pub row_heights: HashMap<usize, u16>,
+    /// Fit column widths to their content whenever a file is loaded
+    pub autofit_on_load: bool,
+    // Freeze panes: this many leading rows/columns stay on screen while the rest scrolls
+    pub frozen_rows: usize,
+    pub frozen_cols: usize,
+    // First row/column of the frozen panes; the ones before them stay out of view while frozen
+    pub frozen_top_row: usize,
+    pub frozen_left_col: usize,
+    // AutoFilter: rows hidden by the filter, and the value popup while it's open
+    pub auto_filter: Option<AutoFilter>,
+    pub filtered_rows: HashSet<usize>,
+    pub filter_popup: Option<FilterPopup>,
+    // Rows/columns hidden by hand, and collapsible outline groups
+    pub hidden_rows: BTreeSet<usize>,
+    pub hidden_cols: BTreeSet<usize>,
+    pub row_groups: Vec<OutlineGroup>,
+    pub col_groups: Vec<OutlineGroup>,
+    // Live pivot tables, rebuilt when their source data changes
+    pub pivots: Vec<PivotTable>,
+    // Set whenever cell contents change, so the input loop knows to rebuild the pivots
+    pub cells_changed: bool,
+    // Dynamic array results spilled from their formulas, reworked each frame
+    pub spills: Spills,
+    // Formula cells part way through evaluation, so a formula that reads itself gets #REF!
+    // rather than recursing forever (easy to do with whole-column references)
+    pub evaluating: HashSet<(usize, usize)>,
+    // Workbook-level defined names and what they refer to, sorted by name
+    pub defined_names: Vec<(String, String)>,
+    // Tables made with format-as-table, for structured references like Table1[Amount]
+    pub tables: Vec<DataTable>,
+    // SHELL formulas' commands, run in the background with their output kept per cell
+    pub shell: ShellJobs,
+    // Conditional formatting, applied at render time; earlier rules win
+    pub conditional_rules: Vec<ConditionalRule>,
+    pub rule_index: usize,
+    pub editing_rule: Option<usize>,
     // Save mode
     pub save_mode: bool,
     pub save_format: SaveFormat,
@@ -57,8 +105,11 @@ pub struct Spreadsheet {
     pub row_column_select_mode: RowColumnSelectMode,
     pub selected_rows: Option<(usize, usize)>, // (min_row, max_row)
     pub selected_cols: Option<(usize, usize)>, // (min_col, max_col)
-    // Dark mode
-    pub dark_mode: bool,
+    /// First key of a two-key command (`z` in `zc`/`zo`)
+    pub pending_key: Option<char>,
+    // Colour theme
+    pub theme: Theme,
+    pub color_depth: ColorDepth,
     // Find mode
     pub find_mode: bool,
     pub find_query: String,
@@ -81,6 +132,12 @@ pub struct Spreadsheet {
     pub formula_suggestions: Vec<String>,
     pub formula_suggestion_index: usize,
     pub formula_prefix: String,
+    // Mouse
+    pub mouse_enabled: bool,
+    pub mouse_drag: Option<MouseDrag>,
+    /// Set by wheel scrolling so the view isn't snapped back to the cursor until the next key press
+    pub mouse_scrolled: bool,
+    pub grid_layout: GridLayout,
 }
 
 impl Spreadsheet {
@@ -108,6 +165,28 @@ impl Spreadsheet {
             cell_styles: HashMap::new(),
             col_widths: HashMap::new(),
             row_heights: HashMap::new(),
+            autofit_on_load: false,
+            frozen_rows: 0,
+            frozen_cols: 0,
+            frozen_top_row: 0,
+            frozen_left_col: 0,
+            auto_filter: None,
+            filtered_rows: HashSet::new(),
+            filter_popup: None,
+            hidden_rows: BTreeSet::new(),
+            hidden_cols: BTreeSet::new(),
+            row_groups: Vec::new(),
+            col_groups: Vec::new(),
+            pivots: Vec::new(),
+            cells_changed: false,
+            spills: Spills::default(),
+            evaluating: HashSet::new(),
+            defined_names: Vec::new(),
+            tables: Vec::new(),
+            shell: ShellJobs::default(),
+            conditional_rules: Vec::new(),
+            rule_index: 0,
+            editing_rule: None,
             save_mode: false,
             save_format: SaveFormat::Csv,
             save_filename: String::from("spreadsheet"),
@@ -118,7 +197,9 @@ impl Spreadsheet {
             row_column_select_mode: RowColumnSelectMode::None,
             selected_rows: None,
             selected_cols: None,
-            dark_mode: false,
+            pending_key: None,
+            theme: Theme::light(),
+            color_depth: ColorDepth::TrueColor,
             find_mode: false,
             find_query: String::new(),
             find_matches: Vec::new(),
@@ -135,6 +216,10 @@ impl Spreadsheet {
             formula_suggestions: Vec::new(),
             formula_suggestion_index: 0,
             formula_prefix: String::new(),
+            mouse_enabled: true,
+            mouse_drag: None,
+            mouse_scrolled: false,
+            grid_layout: GridLayout::default(),
         }
     }
 
@@ -148,6 +233,7 @@ impl Spreadsheet {
         self.command_mode = false;
         self.command_buffer.clear();
         self.command_message = None;
+        self.editing_rule = None;
     }
 
     /// Parse a cell reference like "A1", "B23", "AA5" and return (row, col)
@@ -204,16 +290,170 @@ impl Spreadsheet {
             return true; // Signal to quit
         }
 
-        // Try to parse as cell reference
-        if let Some((row, col)) = Self::parse_cell_reference(&cmd) {
-            if row < self.num_rows && col < self.num_cols {
-                self.cursor_row = row;
-                self.cursor_col = col;
-                self.selection_anchor = None;
-                self.command_message = None;
-                self.exit_command_mode();
+        // Commands that take an argument keep the argument's original case
+        let raw = self.command_buffer.trim().to_string();
+        let (name, arg) = match raw.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name.to_uppercase(), arg.trim().to_string()),
+            None => (raw.to_uppercase(), String::new()),
+        };
+
+        if name == "AUTOFIT" {
+            if arg.eq_ignore_ascii_case("all") {
+                self.autofit_all_columns();
+            } else {
+                self.autofit_selected_columns();
+            }
+            self.exit_command_mode();
+            return false;
+        }
+
+        if name == "MOUSE" {
+            self.mouse_enabled = match arg.to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => !self.mouse_enabled,
+            };
+            let mut settings = crate::settings::Settings::load();
+            settings.set_mouse(self.mouse_enabled);
+            self.exit_command_mode();
+            return false;
+        }
+
+        if name == "FREEZE" {
+            self.freeze_panes();
+            self.exit_command_mode();
+            return false;
+        }
+
+        if name == "UNFREEZE" {
+            self.unfreeze_panes();
+            self.exit_command_mode();
+            return false;
+        }
+
+        if name == "AUTOFILTER" {
+            match self.toggle_auto_filter() {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "FILTER" {
+            match self.filter_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "FILL" {
+            match self.fill_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "CF" {
+            match self.conditional_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "FORMAT" {
+            match self.number_format_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "PIVOT" {
+            match self.pivot_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "SORT" {
+            match self.sort_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "NAME" {
+            if arg.is_empty() {
+                self.command_message = Some(self.names_summary());
             } else {
-                self.command_message = Some(format!("Cell {} is out of range", cmd));
+                match self.name_command(&arg) {
+                    Ok(()) => self.exit_command_mode(),
+                    Err(e) => self.command_message = Some(e),
+                }
+            }
+            return false;
+        }
+
+        if name == "UNNAME" {
+            match self.unname_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "GOTO" {
+            match self.goto_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "REFRESH" {
+            match self.refresh_command(&arg) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
+            }
+            return false;
+        }
+
+        if name == "SHELL" {
+            match self.shell_command(&arg) {
+                Ok(message) | Err(message) => self.command_message = Some(message),
+            }
+            return false;
+        }
+
+        if name == "TRUST" {
+            match self.trust_command() {
+                Ok(message) | Err(message) => self.command_message = Some(message),
+            }
+            return false;
+        }
+
+        if name == "THEME" {
+            if arg.is_empty() {
+                self.command_message = Some(format!("Themes: {}", Theme::available().join(", ")));
+            } else {
+                match self.set_theme(&arg) {
+                    Ok(()) => self.exit_command_mode(),
+                    Err(e) => self.command_message = Some(e),
+                }
+            }
+            return false;
+        }
+
+        // Try to parse as cell reference or defined name
+        if Self::parse_cell_reference(&cmd).is_some() || self.defined_name(&raw).is_some() {
+            match self.goto_command(&raw) {
+                Ok(()) => self.exit_command_mode(),
+                Err(e) => self.command_message = Some(e),
             }
         } else {
             self.command_message = Some(format!("Unknown command: {}", cmd));
@@ -287,14 +527,18 @@ impl Spreadsheet {
             (self.cursor_row, self.cursor_col, self.cursor_row, self.cursor_col)
         };
 
+        // Copying leaves out rows hidden by a filter; a cut moves the whole block
+        let rows: Vec<usize> = (min_row..=max_row)
+            .filter(|&row| is_cut || !self.is_row_filtered(row))
+            .collect();
+
         // Collect cell data with relative positions
         let mut cells_data = Vec::new();
-        for row in min_row..=max_row {
+        for (rel_row, &row) in rows.iter().enumerate() {
             for col in min_col..=max_col {
-                let rel_row = row - min_row;
                 let rel_col = col - min_col;
                 let value = self.get_cell(row, col).to_string();
-                let style = self.cell_styles.get(&(row, col)).copied();
+                let style = self.cell_styles.get(&(row, col)).cloned();
                 
                 // Only include non-empty cells or cells with styles
                 if !value.is_empty() || style.is_some() {
@@ -305,12 +549,12 @@ impl Spreadsheet {
 
         // Build tab-separated text for system clipboard
         let mut clipboard_text = String::new();
-        for row in min_row..=max_row {
+        for &row in &rows {
             let mut row_values = Vec::new();
             for col in min_col..=max_col {
                 row_values.push(self.get_cell(row, col).to_string());
             }
-            if row > min_row {
+            if row != rows[0] {
                 clipboard_text.push('\n');
             }
             clipboard_text.push_str(&row_values.join("\t"));
@@ -346,6 +590,7 @@ impl Spreadsheet {
     }
 
     fn paste_internal(&mut self, clipboard_data: ClipboardData) {
+        self.cells_changed = true;
         let dest_row = self.cursor_row;
         let dest_col = self.cursor_col;
 
@@ -381,7 +626,7 @@ impl Spreadsheet {
 
             // Set cell style
             if let Some(s) = style {
-                self.cell_styles.insert((new_row, new_col), *s);
+                self.cell_styles.insert((new_row, new_col), s.clone());
             }
         }
 
@@ -396,6 +641,7 @@ impl Spreadsheet {
     }
 
     fn paste_text(&mut self, text: &str) {
+        self.cells_changed = true;
         let dest_row = self.cursor_row;
         let dest_col = self.cursor_col;
 
@@ -428,10 +674,20 @@ impl Spreadsheet {
     }
 
     pub fn toggle_dark_mode(&mut self) {
-        self.dark_mode = !self.dark_mode;
-        // Save the setting to the config file
+        self.theme = if self.theme.dark { Theme::light() } else { Theme::dark() };
+        // Save the setting to the config file; toggling replaces any named theme
         let mut settings = crate::settings::Settings::load();
-        settings.set_dark_mode(self.dark_mode);
+        settings.theme.clear();
+        settings.set_dark_mode(self.theme.dark);
+    }
+
+    /// Switch to a named theme and remember it in the config file
+    pub fn set_theme(&mut self, name: &str) -> Result<(), String> {
+        self.theme = Theme::load(name)?;
+        let mut settings = crate::settings::Settings::load();
+        settings.dark_mode = self.theme.dark;
+        settings.set_theme(&self.theme.name);
+        Ok(())
     }
 
     pub fn col_name(col: usize) -> String {
@@ -459,6 +715,8 @@ impl Spreadsheet {
     }
 
     pub fn set_cell(&mut self, row: usize, col: usize, value: String) {
+        self.cells_changed = true;
+        self.refresh_spills();
         if value.is_empty() {
             self.cells.remove(&(row, col));
         } else {
@@ -478,8 +736,19 @@ impl Spreadsheet {
         let new_row = (self.cursor_row as isize + dr).max(0) as usize;
         let new_col = (self.cursor_col as isize + dc).max(0) as usize;
 
-        self.cursor_row = new_row.min(self.num_rows - 1);
-        self.cursor_col = new_col.min(self.num_cols - 1);
+        // Step over hidden rows/columns; stay put if there is nothing visible that way
+        let new_row = new_row.min(self.num_rows - 1);
+        let new_col = new_col.min(self.num_cols - 1);
+        self.cursor_row = if dr != 0 {
+            self.next_visible_row(new_row, dr).unwrap_or(self.cursor_row)
+        } else {
+            new_row
+        };
+        self.cursor_col = if dc != 0 {
+            self.next_visible_col(new_col, dc).unwrap_or(self.cursor_col)
+        } else {
+            new_col
+        };
     }
 
     /// Find the rightmost column with data in the given row
@@ -545,7 +814,7 @@ impl Spreadsheet {
     /// Jump to the last data row in the current column (down)
     pub fn jump_to_last_row(&mut self) {
         if let Some(row) = self.find_last_row_in_col(self.cursor_col) {
-            self.cursor_row = row;
+            self.cursor_row = self.next_visible_row(row, -1).unwrap_or(row);
             self.selection_anchor = None;
         }
     }
@@ -553,7 +822,7 @@ impl Spreadsheet {
     /// Jump to the first data row in the current column (up)
     pub fn jump_to_first_row(&mut self) {
         if let Some(row) = self.find_first_row_in_col(self.cursor_col) {
-            self.cursor_row = row;
+            self.cursor_row = self.next_visible_row(row, 1).unwrap_or(row);
             self.selection_anchor = None;
         }
     }
@@ -598,6 +867,10 @@ impl Spreadsheet {
         let mut rows_with_data = std::collections::HashSet::new();
 
         for row in min_row..=max_row {
+            // Stats describe what's on screen, so rows hidden by a filter don't count
+            if self.is_row_hidden(row) {
+                continue;
+            }
             for col in min_col..=max_col {
                 let content = self.get_cell(row, col);
                 if !content.is_empty() {
@@ -646,7 +919,7 @@ impl Spreadsheet {
 
     pub fn start_editing(&mut self) {
         self.editing = true;
-        self.edit_buffer = self.get_cell(self.cursor_row, self.cursor_col).to_string();
+        self.edit_buffer = self.edit_text(self.cursor_row, self.cursor_col);
         self.formula_mode = self.edit_buffer.starts_with('=');
         self.selecting_ref = false;
         self.ref_anchor = None;
@@ -678,11 +951,83 @@ impl Spreadsheet {
             }
         }
 
-        self.set_cell(self.cursor_row, self.cursor_col, self.edit_buffer.clone());
+        self.set_cell_from_input(self.cursor_row, self.cursor_col, self.edit_buffer.clone());
         self.reset_editing_state();
         self.move_cursor(dr, dc, false);
     }
 
+    /// Store typed input. Dates and times become serial numbers shown in the format they were
+    /// typed in, and formulas on date or time cells pick up those cells' format, like Excel.
+    pub fn set_cell_from_input(&mut self, row: usize, col: usize, value: String) {
+        let style = self.get_cell_style(row, col);
+        let current = style.number_format.as_deref().or_else(|| style.data_type.and_then(data_type_format));
+        let is_text = style.data_type == Some(DataType::Text) || current == Some("@");
+        let keeps_format = current.is_some_and(|code| is_date_format(code) || code == "@");
+
+        if value.starts_with('=') {
+            if current.is_none() && let Some(code) = self.inherited_date_format(&value) {
+                self.set_cell_number_format(row, col, Some(code));
+            }
+        } else if !is_text && let Some((serial, code)) = parse_date_time(&value) {
+            if !keeps_format {
+                self.set_cell_number_format(row, col, Some(code.to_string()));
+            }
+            self.set_cell(row, col, serial.to_string());
+            return;
+        }
+        self.set_cell(row, col, value);
+    }
+
+    /// The date or time format a formula's result should show in. Functions that return dates
+    /// get a date format; plain arithmetic takes the format of the first cell it references,
+    /// except that subtracting one date from another gives a number of days.
+    fn inherited_date_format(&self, formula: &str) -> Option<String> {
+        let date_format = |(row, col): (usize, usize)| {
+            self.cell_styles.get(&(row, col))?.number_format.clone().filter(|code| is_date_format(code))
+        };
+        let references = formula_references(formula);
+        let first = references.first().and_then(|&(_, cell)| date_format(cell));
+
+        let body = formula.trim_start_matches('=').trim_start();
+        let function = body.split('(').next().filter(|name| body.len() > name.len());
+        if let Some(function) = function {
+            return match function.trim().to_uppercase().as_str() {
+                "TODAY" | "DATE" | "DATEVALUE" => Some("yyyy-mm-dd".to_string()),
+                "NOW" => Some("yyyy-mm-dd hh:mm".to_string()),
+                "TIME" | "TIMEVALUE" => Some("h:mm".to_string()),
+                "EDATE" | "EOMONTH" | "WORKDAY" => first.or_else(|| Some("yyyy-mm-dd".to_string())),
+                _ => None,
+            };
+        }
+
+        let code = first?;
+        let has_days = |code: &str| code.contains(['y', 'd']);
+        let date_difference = has_days(&code)
+            && references[1..]
+                .iter()
+                .any(|&(op, cell)| op == '-' && date_format(cell).is_some_and(|code| has_days(&code)));
+        (!date_difference).then_some(code)
+    }
+
+    /// What editing a cell starts with: its formula or value, with dates and times written out
+    pub fn edit_text(&self, row: usize, col: usize) -> String {
+        let value = self.get_cell(row, col);
+        let is_date = self.get_cell_style(row, col).number_format.is_some_and(|code| is_date_format(&code));
+        let Some(serial) = value.parse::<f64>().ok().filter(|&serial| is_date && serial >= 0.0) else {
+            return value.to_string();
+        };
+        let seconds = (serial.fract() * 86400.0).round() as i64 % 60;
+        let time = if seconds == 0 { "h:mm" } else { "h:mm:ss" };
+        let code = if serial.fract() == 0.0 {
+            "yyyy-mm-dd".to_string()
+        } else if serial < 1.0 {
+            time.to_string()
+        } else {
+            format!("yyyy-mm-dd {}", time)
+        };
+        format_value(value, &code).text
+    }
+
     pub fn finish_editing(&mut self) {
         self.finish_editing_with_move(1, 0);
     }
@@ -706,6 +1051,7 @@ impl Spreadsheet {
     }
 
     pub fn delete_cell(&mut self) {
+        self.cells_changed = true;
         if let Some(((min_row, min_col), (max_row, max_col))) = self.get_selection_range() {
             for row in min_row..=max_row {
                 for col in min_col..=max_col {
@@ -721,40 +1067,149 @@ impl Spreadsheet {
     pub fn get_available_formulas() -> Vec<String> {
         vec![
             "ABS".to_string(),
+            "ADDRESS".to_string(),
             "AND".to_string(),
-            "AVG".to_string(),
+            "AVERAGE".to_string(),
             "AVERAGEIF".to_string(),
+            "AVERAGEIFS".to_string(),
+            "AVG".to_string(),
+            "CHAR".to_string(),
+            "CHOOSE".to_string(),
+            "CLEAN".to_string(),
+            "CODE".to_string(),
+            "COLUMN".to_string(),
+            "COLUMNS".to_string(),
             "CONCAT".to_string(),
             "CONCATENATE".to_string(),
             "CORREL".to_string(),
             "COUNT".to_string(),
             "COUNTA".to_string(),
             "COUNTIF".to_string(),
+            "COUNTIFS".to_string(),
+            "COVAR".to_string(),
+            "COVARIANCE.P".to_string(),
+            "COVARIANCE.S".to_string(),
+            "DATE".to_string(),
+            "DATEDIF".to_string(),
+            "DATEVALUE".to_string(),
+            "DAY".to_string(),
+            "EDATE".to_string(),
+            "EOMONTH".to_string(),
+            "EXACT".to_string(),
+            "FILTER".to_string(),
+            "FIND".to_string(),
+            "FORECAST".to_string(),
+            "FORECAST.LINEAR".to_string(),
+            "FV".to_string(),
+            "GEOMEAN".to_string(),
+            "HLOOKUP".to_string(),
+            "HOUR".to_string(),
             "IF".to_string(),
             "IFERROR".to_string(),
+            "INDEX".to_string(),
+            "INDIRECT".to_string(),
             "INT".to_string(),
-            "LEN".to_string(),
+            "INTERCEPT".to_string(),
+            "IRR".to_string(),
+            "KURT".to_string(),
+            "LAMBDA".to_string(),
+            "LARGE".to_string(),
             "LEFT".to_string(),
+            "LEN".to_string(),
+            "LET".to_string(),
+            "LOOKUP".to_string(),
             "LOWER".to_string(),
+            "MATCH".to_string(),
             "MAX".to_string(),
+            "MAXIFS".to_string(),
             "MEDIAN".to_string(),
             "MID".to_string(),
             "MIN".to_string(),
+            "MINIFS".to_string(),
+            "MINUTE".to_string(),
             "MOD".to_string(),
+            "MODE".to_string(),
+            "MODE.SNGL".to_string(),
+            "MONTH".to_string(),
+            "NETWORKDAYS".to_string(),
+            "NORM.DIST".to_string(),
+            "NORM.INV".to_string(),
             "NOT".to_string(),
+            "NOW".to_string(),
+            "NPER".to_string(),
+            "NPV".to_string(),
+            "OFFSET".to_string(),
             "OR".to_string(),
+            "PERCENTILE".to_string(),
+            "PERCENTILE.EXC".to_string(),
+            "PERCENTILE.INC".to_string(),
+            "PMT".to_string(),
             "POWER".to_string(),
             "PRODUCT".to_string(),
             "PROPER".to_string(),
+            "PV".to_string(),
+            "QUARTILE".to_string(),
+            "QUARTILE.EXC".to_string(),
+            "QUARTILE.INC".to_string(),
+            "RANK".to_string(),
+            "RANK.EQ".to_string(),
+            "RATE".to_string(),
+            "REGEXEXTRACT".to_string(),
+            "REGEXMATCH".to_string(),
+            "REGEXREPLACE".to_string(),
+            "REGEXTEST".to_string(),
+            "REPLACE".to_string(),
+            "REPT".to_string(),
             "RIGHT".to_string(),
             "ROUND".to_string(),
+            "ROW".to_string(),
+            "ROWS".to_string(),
+            "RSQ".to_string(),
+            "SEARCH".to_string(),
+            "SECOND".to_string(),
+            "SEQUENCE".to_string(),
             "SHELL".to_string(),
+            "SKEW".to_string(),
+            "SLOPE".to_string(),
+            "SMALL".to_string(),
+            "SORT".to_string(),
+            "SORTBY".to_string(),
             "SQRT".to_string(),
+            "STDEV".to_string(),
+            "STDEV.P".to_string(),
+            "STDEV.S".to_string(),
+            "STDEVP".to_string(),
+            "SUBSTITUTE".to_string(),
             "SUM".to_string(),
             "SUMIF".to_string(),
+            "SUMIFS".to_string(),
+            "SUMPRODUCT".to_string(),
+            "TEXT".to_string(),
+            "TEXTAFTER".to_string(),
+            "TEXTBEFORE".to_string(),
+            "TEXTJOIN".to_string(),
+            "TEXTSPLIT".to_string(),
+            "TIME".to_string(),
+            "TIMEVALUE".to_string(),
+            "TODAY".to_string(),
+            "TRANSPOSE".to_string(),
             "TRIM".to_string(),
+            "UNICODE".to_string(),
+            "UNIQUE".to_string(),
             "UPPER".to_string(),
+            "VALUE".to_string(),
+            "VAR".to_string(),
+            "VAR.P".to_string(),
+            "VAR.S".to_string(),
+            "VARP".to_string(),
             "VLOOKUP".to_string(),
+            "WEEKDAY".to_string(),
+            "WEEKNUM".to_string(),
+            "WORKDAY".to_string(),
+            "XIRR".to_string(),
+            "XLOOKUP".to_string(),
+            "XNPV".to_string(),
+            "YEAR".to_string(),
         ]
     }
 
@@ -768,9 +1223,11 @@ impl Spreadsheet {
         let prefix_upper = self.formula_prefix.to_uppercase();
         let all_formulas = Self::get_available_formulas();
         
+        let names = self.defined_names.iter().map(|(name, _)| name.clone());
         let mut suggestions: Vec<String> = all_formulas
             .into_iter()
-            .filter(|f| f.starts_with(&prefix_upper))
+            .chain(names)
+            .filter(|f| f.to_uppercase().starts_with(&prefix_upper))
             .collect();
         
         // Sort alphabetically for better UX
@@ -924,6 +1381,7 @@ impl Spreadsheet {
 
     pub fn exit_row_column_select_mode(&mut self) {
         self.row_column_select_mode = RowColumnSelectMode::None;
+        self.pending_key = None;
         self.selected_rows = None;
         self.selected_cols = None;
     }
@@ -952,6 +1410,7 @@ impl Spreadsheet {
             for col in (min_col..=max_col).rev() {
                 self.delete_column(col);
             }
+            self.apply_filters();
             // Adjust cursor position
             if self.cursor_col >= min_col {
                 if self.cursor_col <= max_col {
@@ -993,8 +1452,12 @@ impl Spreadsheet {
     }
 
     fn insert_row_after(&mut self, row: usize) {
+        self.cells_changed = true;
         // Increase num_rows
         self.num_rows += 1;
+        self.shift_row_outline(row + 1, true);
+        self.shift_conditional_rows(row + 1, true);
+        self.shift_filter_rows(row + 1, true);
         // Shift all cells below (and including) row+1 down by 1
         // We need to iterate from the bottom to avoid overwriting
         for r in (row + 1..self.num_rows - 1).rev() {
@@ -1014,8 +1477,12 @@ impl Spreadsheet {
     }
 
     fn insert_column_after(&mut self, col: usize) {
+        self.cells_changed = true;
         // Increase num_cols
         self.num_cols += 1;
+        self.shift_col_outline(col + 1, true);
+        self.shift_conditional_cols(col + 1, true);
+        self.shift_filter_cols(col + 1, true);
         // Shift all cells to the right of col down by 1
         // We need to iterate from the right to avoid overwriting
         for c in (col + 1..self.num_cols - 1).rev() {
@@ -1035,6 +1502,7 @@ impl Spreadsheet {
     }
 
     fn delete_row(&mut self, row: usize) {
+        self.cells_changed = true;
         // Remove all cells in this row
         for col in 0..self.num_cols {
             self.cells.remove(&(row, col));
@@ -1042,6 +1510,9 @@ impl Spreadsheet {
         }
         // Remove row height if set
         self.row_heights.remove(&row);
+        self.shift_row_outline(row, false);
+        self.shift_conditional_rows(row, false);
+        self.shift_filter_rows(row, false);
         // Shift all cells below this row up
         for r in (row + 1)..self.num_rows {
             for col in 0..self.num_cols {
@@ -1067,6 +1538,7 @@ impl Spreadsheet {
     }
 
     fn delete_column(&mut self, col: usize) {
+        self.cells_changed = true;
         // Remove all cells in this column
         for row in 0..self.num_rows {
             self.cells.remove(&(row, col));
@@ -1074,6 +1546,9 @@ impl Spreadsheet {
         }
         // Remove column width if set
         self.col_widths.remove(&col);
+        self.shift_col_outline(col, false);
+        self.shift_conditional_cols(col, false);
+        self.shift_filter_cols(col, false);
         // Shift all cells to the right of this column left
         for c in (col + 1)..self.num_cols {
             for row in 0..self.num_rows {
@@ -1098,20 +1573,41 @@ impl Spreadsheet {
         }
     }
 
-    pub fn visible_cols(&self, width: u16) -> usize {
-        let row_num_width = 5;
-        let available = width.saturating_sub(row_num_width) as i32;
-        let mut used = 0i32;
-        let mut count = 0;
-        for col in self.scroll_col..self.num_cols {
-            let col_w = self.get_col_width(col) as i32;
-            if used + col_w > available {
+    /// Width of the row number column: the widest row number plus the outline gutter
+    pub fn row_header_width(&self) -> u16 {
+        let digits = self.num_rows.max(1).to_string().len();
+        let gutter = usize::from(!self.row_groups.is_empty());
+        (digits + gutter).max(5) as u16
+    }
+
+    /// Columns drawn in a grid `width` wide: the frozen columns, then the scrolled ones
+    pub fn displayed_cols(&self, width: u16) -> Vec<usize> {
+        let available = width.saturating_sub(self.row_header_width()) as u32;
+        let frozen = self.frozen_cols.min(self.num_cols);
+        let mut used = 0u32;
+        let mut cols = Vec::new();
+        for col in (self.frozen_left_col.min(frozen)..frozen)
+            .chain(self.scroll_col.max(frozen)..self.num_cols)
+            .filter(|&col| !self.is_col_hidden(col))
+        {
+            let col_w = self.get_col_width(col) as u32;
+            if used + col_w > available && !cols.is_empty() {
                 break;
             }
             used += col_w;
-            count += 1;
+            cols.push(col);
         }
-        count.max(1)
+        cols
+    }
+
+    /// Rows drawn in a grid `height` tall: the frozen rows, then the scrolled ones
+    pub fn displayed_rows(&self, height: u16) -> Vec<usize> {
+        let frozen = self.frozen_rows.min(self.num_rows);
+        (self.frozen_top_row.min(frozen)..frozen)
+            .chain(self.scroll_row.max(frozen)..self.num_rows)
+            .filter(|&row| !self.is_row_hidden(row))
+            .take(self.visible_rows(height))
+            .collect()
     }
 
     pub fn visible_rows(&self, height: u16) -> usize {
@@ -1122,24 +1618,116 @@ impl Spreadsheet {
         s.parse::<f64>().is_ok()
     }
 
+    /// Scroll the view without moving the cursor (mouse wheel)
+    pub fn scroll_by(&mut self, dr: isize, dc: isize) {
+        self.scroll_row = (self.scroll_row as isize + dr).clamp(0, self.num_rows as isize - 1) as usize;
+        self.scroll_col = (self.scroll_col as isize + dc).clamp(0, self.num_cols as isize - 1) as usize;
+        self.mouse_scrolled = true;
+    }
+
     pub fn adjust_scroll(&mut self, area: Rect) {
-        let visible_cols = self.visible_cols(area.width);
-        let visible_rows = self.visible_rows(area.height);
+        // Cells above and left of the frozen panes can't be reached until they're unfrozen, as in Excel
+        let frozen_rows = self.frozen_rows.min(self.num_rows);
+        let frozen_cols = self.frozen_cols.min(self.num_cols);
+        let top_row = self.frozen_top_row.min(frozen_rows);
+        let left_col = self.frozen_left_col.min(frozen_cols);
+        self.cursor_row = self.cursor_row.max(top_row);
+        self.cursor_col = self.cursor_col.max(left_col);
+        if self.mouse_scrolled {
+            return;
+        }
 
-        if self.cursor_col < self.scroll_col {
-            self.scroll_col = self.cursor_col;
-        } else if self.cursor_col >= self.scroll_col + visible_cols {
-            self.scroll_col = self.cursor_col - visible_cols + 1;
+        // Only the region below/right of the frozen panes scrolls
+        self.scroll_row = self.scroll_row.max(frozen_rows);
+        self.scroll_col = self.scroll_col.max(frozen_cols);
+
+        if self.cursor_col >= frozen_cols {
+            if self.cursor_col < self.scroll_col {
+                self.scroll_col = self.cursor_col;
+            } else {
+                // Hidden columns take no space
+                let shown_width = |col: usize| if self.is_col_hidden(col) { 0 } else { self.get_col_width(col) as u32 };
+                let frozen_width: u32 = (left_col..frozen_cols).map(shown_width).sum();
+                let available = (area.width.saturating_sub(self.row_header_width()) as u32).saturating_sub(frozen_width);
+                // Scroll right just far enough for the cursor column to fit
+                let mut start = self.cursor_col;
+                let mut used = shown_width(start);
+                while start > self.scroll_col && used + shown_width(start - 1) <= available {
+                    start -= 1;
+                    used += shown_width(start);
+                }
+                self.scroll_col = start;
+            }
         }
 
-        if self.cursor_row < self.scroll_row {
-            self.scroll_row = self.cursor_row;
-        } else if self.cursor_row >= self.scroll_row + visible_rows {
-            self.scroll_row = self.cursor_row - visible_rows + 1;
+        let frozen_shown = (top_row..frozen_rows).filter(|&row| !self.is_row_hidden(row)).count();
+        let visible_rows = self.visible_rows(area.height).saturating_sub(frozen_shown).max(1);
+        if self.cursor_row >= frozen_rows {
+            if self.cursor_row < self.scroll_row {
+                self.scroll_row = self.cursor_row;
+            } else {
+                // Hidden rows take no space, so count only the shown ones down to the cursor
+                let mut start = self.cursor_row;
+                let mut shown = 1;
+                while start > self.scroll_row {
+                    if !self.is_row_hidden(start - 1) {
+                        if shown == visible_rows {
+                            break;
+                        }
+                        shown += 1;
+                    }
+                    start -= 1;
+                }
+                self.scroll_row = start;
+            }
         }
     }
 
+    /// Freeze the rows above and the columns left of the cursor, starting from the top-left
+    /// cell on screen as Excel does (at that cell, or at A1, this unfreezes)
+    pub fn freeze_panes(&mut self) {
+        let top_row = if self.frozen_rows > 0 { self.frozen_top_row } else { self.scroll_row };
+        let left_col = if self.frozen_cols > 0 { self.frozen_left_col } else { self.scroll_col };
+        (self.frozen_top_row, self.frozen_rows) = if self.cursor_row > top_row { (top_row, self.cursor_row) } else { (0, 0) };
+        (self.frozen_left_col, self.frozen_cols) = if self.cursor_col > left_col { (left_col, self.cursor_col) } else { (0, 0) };
+        self.scroll_row = self.scroll_row.max(self.frozen_rows);
+        self.scroll_col = self.scroll_col.max(self.frozen_cols);
+    }
+
+    pub fn unfreeze_panes(&mut self) {
+        self.frozen_rows = 0;
+        self.frozen_cols = 0;
+        self.frozen_top_row = 0;
+        self.frozen_left_col = 0;
+    }
+
+    /// Empty the sheet before another file is loaded into it, dropping everything that
+    /// belonged to the previous workbook
+    pub fn clear_workbook(&mut self) {
+        self.cells_changed = true;
+        self.cells.clear();
+        self.cell_styles.clear();
+        self.col_widths.clear();
+        self.row_heights.clear();
+        self.unfreeze_panes();
+        self.auto_filter = None;
+        self.filtered_rows.clear();
+        self.filter_popup = None;
+        self.hidden_rows.clear();
+        self.hidden_cols.clear();
+        self.row_groups.clear();
+        self.col_groups.clear();
+        self.pivots.clear();
+        self.conditional_rules.clear();
+        self.rule_index = 0;
+        self.editing_rule = None;
+        self.defined_names.clear();
+        self.tables.clear();
+        self.refresh_spills();
+    }
+
     pub fn load_from_file(&mut self, filepath: &str) -> std::io::Result<()> {
+        self.distrust_shell();
         let path = std::path::Path::new(filepath);
         let extension = path
             .extension()
@@ -1151,11 +1739,18 @@ impl Spreadsheet {
             "csv" => self.load_csv(filepath),
             "tsv" => self.load_tsv(filepath),
             "xlsx" | "xls" => self.load_excel(filepath),
+            "xl" => self.load_native(filepath),
             _ => Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 format!("Unsupported file format: {}", extension),
             )),
+        }?;
+
+        self.check_shell_trust(Some(path));
+        if self.autofit_on_load {
+            self.autofit_all_columns();
         }
+        Ok(())
     }
 
     fn load_csv(&mut self, filepath: &str) -> std::io::Result<()> {
@@ -1172,14 +1767,15 @@ impl Spreadsheet {
             .has_headers(false)
             .from_path(filepath)?;
 
-        self.cells.clear();
+        self.clear_workbook();
         let mut row_idx = 0;
 
         for result in reader.records() {
             let record = result?;
             for (col_idx, field) in record.iter().enumerate() {
                 if !field.is_empty() {
-                    self.set_cell(row_idx, col_idx, field.to_string());
+                    // Dates are written as shown, so read them back the way typed dates are
+                    self.set_cell_from_input(row_idx, col_idx, field.to_string());
                 }
             }
             row_idx += 1;
@@ -1216,10 +1812,15 @@ impl Spreadsheet {
             .worksheet_range(&sheet_names[0])
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
 
-        self.cells.clear();
+        self.clear_workbook();
 
+        // The range starts at the first used cell, not necessarily A1
+        let (start_row, start_col) = range.start().map_or((0, 0), |(row, col)| (row as usize, col as usize));
+        let mut dates = Vec::new();
         for (row_idx, row) in range.rows().enumerate() {
+            let row_idx = start_row + row_idx;
             for (col_idx, cell) in row.iter().enumerate() {
+                let col_idx = start_col + col_idx;
                 let value = match cell {
                     Data::Empty => continue,
                     Data::String(s) => s.clone(),
@@ -1234,11 +1835,18 @@ impl Spreadsheet {
                     Data::Int(i) => i.to_string(),
                     Data::Bool(b) => b.to_string(),
                     Data::Error(e) => format!("#ERROR: {:?}", e),
+                    // Dates are kept as serial numbers; their number format shows them as dates
                     Data::DateTime(dt) => {
-                        // Format datetime as string
-                        format!("{}", dt)
+                        dates.push((row_idx, col_idx, dt.as_f64()));
+                        dt.as_f64().to_string()
                     }
-                    Data::DateTimeIso(s) => s.clone(),
+                    Data::DateTimeIso(s) => match parse_date_time(s) {
+                        Some((serial, _)) => {
+                            dates.push((row_idx, col_idx, serial));
+                            serial.to_string()
+                        }
+                        None => s.clone(),
+                    },
                     Data::DurationIso(s) => s.clone(),
                 };
 
@@ -1248,6 +1856,39 @@ impl Spreadsheet {
             }
         }
 
+        // Defined names, as far as they refer to the sheet that was read
+        let names: Vec<(String, String)> = workbook
+            .defined_names()
+            .iter()
+            .filter(|(name, _)| !name.starts_with("_xlnm."))
+            .filter_map(|(name, formula)| Some((name.clone(), crate::names::import_name_formula(formula, &sheet_names[0])?)))
+            .collect();
+        for (name, refers_to) in names {
+            let _ = self.define_name(&name, &refers_to);
+        }
+
+        // calamine doesn't expose number formats, so read them from the xlsx parts directly
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"))
+            && let Ok(formats) = crate::xlsx::read_number_formats(path)
+        {
+            for ((row, col), code) in formats {
+                self.set_cell_number_format(row, col, Some(code));
+            }
+        }
+        // Dates from files without number formats (.xls) still need to show as dates
+        for (row, col, serial) in dates {
+            if self.get_cell_style(row, col).number_format.is_none() {
+                let code = if serial < 1.0 {
+                    "h:mm:ss"
+                } else if serial.fract() == 0.0 {
+                    "yyyy-mm-dd"
+                } else {
+                    "yyyy-mm-dd hh:mm:ss"
+                };
+                self.set_cell_number_format(row, col, Some(code.to_string()));
+            }
+        }
+
         // Update dimensions based on loaded data
         let (max_row, max_col) = self.get_data_bounds();
         self.num_rows = (max_row + 1).max(DEFAULT_ROWS);
@@ -1258,10 +1899,11 @@ impl Spreadsheet {
 
     /// Load spreadsheet data from a byte buffer (e.g., from piped stdin)
     pub fn load_from_buffer(&mut self, buffer: &[u8]) -> std::io::Result<()> {
+        self.distrust_shell();
         // Convert to string for processing
         let buffer_str = String::from_utf8_lossy(buffer);
         
-        self.cells.clear();
+        self.clear_workbook();
         let mut row_idx = 0;
         
         // Process the buffered data line by line
@@ -1289,7 +1931,12 @@ impl Spreadsheet {
         let (max_row, max_col) = self.get_data_bounds();
         self.num_rows = (max_row + 1).max(DEFAULT_ROWS);
         self.num_cols = (max_col + 1).max(DEFAULT_COLS);
-        
+
+        // Piped data can't be trusted: its SHELL commands wait for :trust
+        self.check_shell_trust(None);
+        if self.autofit_on_load {
+            self.autofit_all_columns();
+        }
         Ok(())
     }
 }
@@ -1443,4 +2090,99 @@ drwxr-xr-x  3 user group  96 Jan 23 14:20 .git
         assert_eq!(sheet.get_cell(1, 0), "日本語");
         assert_eq!(sheet.get_cell(1, 1), "テスト");
     }
+
+    #[test]
+    fn test_freeze_panes_keep_leading_rows_and_cols() {
+        let mut sheet = Spreadsheet::new();
+        sheet.cursor_row = 2;
+        sheet.cursor_col = 1;
+        sheet.command_buffer = "freeze".to_string();
+        sheet.execute_command();
+        assert_eq!((sheet.frozen_rows, sheet.frozen_cols), (2, 1));
+
+        // Move far down and right: the frozen rows/columns stay first in the view
+        sheet.cursor_row = 60;
+        sheet.cursor_col = 20;
+        let area = Rect::new(0, 0, 80, 30);
+        sheet.adjust_scroll(area);
+        let rows = sheet.displayed_rows(area.height);
+        let cols = sheet.displayed_cols(area.width);
+        assert_eq!(&rows[..2], &[0, 1]);
+        assert_eq!(cols[0], 0);
+        assert!(rows.contains(&60));
+        assert!(cols.contains(&20));
+        assert_eq!(*cols.last().unwrap(), 20);
+
+        // Moving into the frozen panes leaves the scroll alone; the first unfrozen cell scrolls back
+        sheet.cursor_row = 0;
+        sheet.cursor_col = 0;
+        sheet.adjust_scroll(area);
+        assert_eq!((sheet.scroll_row, sheet.scroll_col), (40, 15));
+        sheet.cursor_row = 2;
+        sheet.cursor_col = 1;
+        sheet.adjust_scroll(area);
+        assert_eq!((sheet.scroll_row, sheet.scroll_col), (2, 1));
+
+        sheet.command_buffer = "unfreeze".to_string();
+        sheet.execute_command();
+        assert_eq!((sheet.frozen_rows, sheet.frozen_cols), (0, 0));
+
+        // Scrolled down and right, the panes start from the top-left cell on screen
+        let mut sheet = Spreadsheet::new();
+        (sheet.scroll_row, sheet.scroll_col) = (40, 5);
+        (sheet.cursor_row, sheet.cursor_col) = (45, 7);
+        sheet.freeze_panes();
+        assert_eq!((sheet.frozen_top_row, sheet.frozen_rows), (40, 45));
+        assert_eq!((sheet.frozen_left_col, sheet.frozen_cols), (5, 7));
+        sheet.cursor_row = 80;
+        sheet.adjust_scroll(area);
+        let rows = sheet.displayed_rows(area.height);
+        assert_eq!(&rows[..5], &[40, 41, 42, 43, 44]);
+        assert!(rows.contains(&80));
+        assert_eq!(sheet.displayed_cols(area.width)[..2], [5, 6]);
+        // The rows above the panes are out of reach until they're unfrozen
+        (sheet.cursor_row, sheet.cursor_col) = (0, 0);
+        sheet.adjust_scroll(area);
+        assert_eq!((sheet.cursor_row, sheet.cursor_col), (40, 5));
+        // Freezing at the top-left cell on screen unfreezes
+        sheet.freeze_panes();
+        assert_eq!((sheet.frozen_rows, sheet.frozen_cols, sheet.frozen_top_row), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_date_entry_and_arithmetic() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell_from_input(0, 0, "2024-03-05".to_string());
+        sheet.set_cell_from_input(1, 0, "3/15/2024".to_string());
+        assert_eq!(sheet.get_cell(0, 0), "45356");
+        assert_eq!(sheet.display_value(0, 0), "2024-03-05");
+        assert_eq!(sheet.display_value(1, 0), "3/15/2024");
+        assert_eq!(sheet.edit_text(1, 0), "2024-03-15");
+
+        // Date minus date is a number of days; date plus days is still a date
+        sheet.set_cell_from_input(2, 0, "=A2-A1".to_string());
+        assert_eq!(sheet.display_value(2, 0), "10");
+        sheet.set_cell_from_input(3, 0, "=A1+30".to_string());
+        assert_eq!(sheet.display_value(3, 0), "2024-04-04");
+
+        // Time logs: end minus start shows as a duration
+        sheet.set_cell_from_input(0, 1, "9:15".to_string());
+        sheet.set_cell_from_input(0, 2, "17:45".to_string());
+        sheet.set_cell_from_input(0, 3, "=C1-B1".to_string());
+        assert_eq!(sheet.display_value(0, 3), "8:30");
+        assert_eq!(sheet.edit_text(0, 1), "9:15");
+
+        // Functions that return dates are shown as dates; date parts are plain numbers
+        sheet.set_cell_from_input(6, 0, "=DATE(2024,3,5)".to_string());
+        assert_eq!(sheet.display_value(6, 0), "2024-03-05");
+        sheet.set_cell_from_input(7, 0, "=EDATE(A2,1)".to_string());
+        assert_eq!(sheet.display_value(7, 0), "4/15/2024");
+        sheet.set_cell_from_input(8, 0, "=YEAR(A1)".to_string());
+        assert_eq!(sheet.display_value(8, 0), "2024");
+
+        // Text cells keep what was typed
+        sheet.set_cell_data_type(5, 0, Some(DataType::Text));
+        sheet.set_cell_from_input(5, 0, "2024-03-05".to_string());
+        assert_eq!(sheet.get_cell(5, 0), "2024-03-05");
+    }
 }
@@ -0,0 +1,386 @@
+use std::f64::consts::PI;
+
+use crate::spreadsheet::Spreadsheet;
+
+/// Statistical functions by name, with the Excel 2007 names as aliases
+const STATISTICS: &[&str] = &[
+    "STDEV.S", "STDEV", "STDEV.P", "STDEVP", "VAR.S", "VAR", "VAR.P", "VARP", "MODE.SNGL", "MODE",
+    "PERCENTILE.INC", "PERCENTILE", "PERCENTILE.EXC", "QUARTILE.INC", "QUARTILE", "QUARTILE.EXC",
+    "RANK.EQ", "RANK", "LARGE", "SMALL", "COVARIANCE.S", "COVARIANCE.P", "COVAR", "SLOPE", "INTERCEPT",
+    "RSQ", "FORECAST.LINEAR", "FORECAST", "GEOMEAN", "SKEW", "KURT", "NORM.DIST", "NORM.INV",
+];
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sum of squared deviations from the mean
+fn sum_squares(values: &[f64]) -> f64 {
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum()
+}
+
+/// Variance, dividing by n - 1 for a sample or n for a whole population
+fn variance(values: &[f64], sample: bool) -> Option<f64> {
+    let n = values.len() as f64 - if sample { 1.0 } else { 0.0 };
+    (n > 0.0).then(|| sum_squares(values) / n)
+}
+
+/// The k-th value (0-based, fractional ranks interpolate) of sorted data
+fn interpolate(sorted: &[f64], rank: f64) -> f64 {
+    let low = rank.floor() as usize;
+    let high = (low + 1).min(sorted.len() - 1);
+    sorted[low] + (rank - low as f64) * (sorted[high] - sorted[low])
+}
+
+/// PERCENTILE.INC (inclusive) or PERCENTILE.EXC of sorted data; None when k is out of range
+fn percentile(sorted: &[f64], k: f64, inclusive: bool) -> Option<f64> {
+    let n = sorted.len() as f64;
+    let rank = if inclusive { k * (n - 1.0) } else { k * (n + 1.0) - 1.0 };
+    let valid = if inclusive { (0.0..=1.0).contains(&k) } else { k > 0.0 && k < 1.0 && rank >= 0.0 && rank <= n - 1.0 };
+    (valid && !sorted.is_empty()).then(|| interpolate(sorted, rank))
+}
+
+/// Error function: a series of positive terms near zero, a continued fraction in the tails
+fn erf(x: f64) -> f64 {
+    if x.abs() >= 3.0 {
+        return x.signum() * (1.0 - erfc_tail(x.abs()));
+    }
+    let (mut term, mut sum, mut n) = (x, x, 0.0);
+    while term.abs() > 1e-17 * sum.abs() {
+        n += 1.0;
+        term *= 2.0 * x * x / (2.0 * n + 1.0);
+        sum += term;
+    }
+    2.0 / PI.sqrt() * (-x * x).exp() * sum
+}
+
+/// erfc(x) for x >= 3
+fn erfc_tail(x: f64) -> f64 {
+    let mut fraction = x;
+    for k in (1..=60).rev() {
+        fraction = x + (k as f64 / 2.0) / fraction;
+    }
+    (-x * x).exp() / PI.sqrt() / fraction
+}
+
+/// Standard normal cumulative distribution
+pub fn norm_cdf(z: f64) -> f64 {
+    let x = z / 2f64.sqrt();
+    if x <= -3.0 { 0.5 * erfc_tail(-x) } else { 0.5 * (1.0 + erf(x)) }
+}
+
+/// Standard normal quantile: Acklam's rational approximation, polished with a Halley step
+pub fn norm_inv(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2, 1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2, 6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838, -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    let x = if p < 0.02425 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.02425 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+    let error = norm_cdf(x) - p;
+    let u = error * (2.0 * PI).sqrt() * (x * x / 2.0).exp();
+    x - u / (1.0 + x * u / 2.0)
+}
+
+impl Spreadsheet {
+    /// Pairs of numbers from two equally-sized ranges, skipping positions where either
+    /// isn't a number
+    fn collect_number_pairs(&mut self, ys: &str, xs: &str, current_row: usize, current_col: usize) -> Result<Vec<(f64, f64)>, String> {
+        let ys = self.resolve_range(ys, current_row, current_col)?.cells();
+        let xs = self.resolve_range(xs, current_row, current_col)?.cells();
+        if ys.len() != xs.len() {
+            return Err("#N/A".to_string());
+        }
+        let mut pairs = Vec::new();
+        for (&(y_row, y_col), &(x_row, x_col)) in ys.iter().zip(&xs) {
+            if let (Some(y), Some(x)) = (self.get_cell_value_at(y_row, y_col), self.get_cell_value_at(x_row, x_col)) {
+                pairs.push((y, x));
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Dispatch a statistical function; None when `name` isn't one
+    pub fn evaluate_statistic(&mut self, name: &str, args: &str, current_row: usize, current_col: usize) -> Option<String> {
+        if !STATISTICS.contains(&name) {
+            return None;
+        }
+        let parts = self.split_function_args(args);
+        let result = match name {
+            "COVARIANCE.S" | "COVARIANCE.P" | "COVAR" | "SLOPE" | "INTERCEPT" | "RSQ" => {
+                self.evaluate_regression(name, &parts, current_row, current_col)
+            }
+            "FORECAST.LINEAR" | "FORECAST" => self.evaluate_forecast(&parts, current_row, current_col),
+            "NORM.DIST" | "NORM.INV" => self.evaluate_normal(name, &parts, current_row, current_col),
+            "PERCENTILE.INC" | "PERCENTILE" | "PERCENTILE.EXC" | "QUARTILE.INC" | "QUARTILE" | "QUARTILE.EXC"
+            | "LARGE" | "SMALL" => self.evaluate_order_statistic(name, &parts, current_row, current_col),
+            "RANK.EQ" | "RANK" => self.evaluate_rank(&parts, current_row, current_col),
+            _ => self.evaluate_moment(name, &parts, current_row, current_col),
+        };
+        Some(match result {
+            Ok(value) if value.is_finite() => format!("{}", value),
+            Ok(_) => "#NUM!".to_string(),
+            Err(e) => e,
+        })
+    }
+
+    /// STDEV, VAR, MODE, GEOMEAN, SKEW and KURT over any mix of ranges and numbers
+    fn evaluate_moment(&mut self, name: &str, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        let values = self
+            .collect_number_args(parts, current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let div0 = || "#DIV/0!".to_string();
+        let n = values.len() as f64;
+        match name {
+            "STDEV.S" | "STDEV" => variance(&values, true).map(f64::sqrt).ok_or_else(div0),
+            "STDEV.P" | "STDEVP" => variance(&values, false).map(f64::sqrt).ok_or_else(div0),
+            "VAR.S" | "VAR" => variance(&values, true).ok_or_else(div0),
+            "VAR.P" | "VARP" => variance(&values, false).ok_or_else(div0),
+            "MODE.SNGL" | "MODE" => {
+                // The most frequent value; ties go to the one that appears first
+                let mut best: Option<(f64, usize)> = None;
+                for &value in &values {
+                    let count = values.iter().filter(|&&v| v == value).count();
+                    if count > 1 && best.is_none_or(|(_, c)| count > c) {
+                        best = Some((value, count));
+                    }
+                }
+                best.map(|(value, _)| value).ok_or_else(|| "#N/A".to_string())
+            }
+            "GEOMEAN" => {
+                if values.is_empty() || values.iter().any(|&v| v <= 0.0) {
+                    return Err("#NUM!".to_string());
+                }
+                Ok((values.iter().map(|v| v.ln()).sum::<f64>() / n).exp())
+            }
+            "SKEW" | "KURT" => {
+                let min_count = if name == "SKEW" { 3.0 } else { 4.0 };
+                let sd = variance(&values, true).map(f64::sqrt).unwrap_or(0.0);
+                if n < min_count || sd == 0.0 {
+                    return Err(div0());
+                }
+                let m = mean(&values);
+                let power = if name == "SKEW" { 3 } else { 4 };
+                let sum: f64 = values.iter().map(|v| ((v - m) / sd).powi(power)).sum();
+                Ok(if name == "SKEW" {
+                    n / ((n - 1.0) * (n - 2.0)) * sum
+                } else {
+                    n * (n + 1.0) / ((n - 1.0) * (n - 2.0) * (n - 3.0)) * sum
+                        - 3.0 * (n - 1.0).powi(2) / ((n - 2.0) * (n - 3.0))
+                })
+            }
+            _ => Err("#ERROR".to_string()),
+        }
+    }
+
+    /// PERCENTILE, QUARTILE, LARGE and SMALL(array, k)
+    fn evaluate_order_statistic(&mut self, name: &str, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        if parts.len() != 2 {
+            return Err("#ERROR".to_string());
+        }
+        let mut values = self
+            .collect_number_args(&parts[..1], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let k = self
+            .evaluate_arg_as_number(parts[1], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        values.sort_by(|a, b| a.total_cmp(b));
+        let result = match name {
+            "PERCENTILE.INC" | "PERCENTILE" => percentile(&values, k, true),
+            "PERCENTILE.EXC" => percentile(&values, k, false),
+            "QUARTILE.INC" | "QUARTILE" if (0.0..=4.0).contains(&k.trunc()) => percentile(&values, k.trunc() / 4.0, true),
+            "QUARTILE.EXC" if (1.0..=3.0).contains(&k.trunc()) => percentile(&values, k.trunc() / 4.0, false),
+            "LARGE" | "SMALL" if k >= 1.0 && (k.ceil() as usize) <= values.len() => {
+                let index = k.ceil() as usize - 1;
+                Some(if name == "SMALL" { values[index] } else { values[values.len() - 1 - index] })
+            }
+            _ => None,
+        };
+        result.ok_or_else(|| "#NUM!".to_string())
+    }
+
+    /// RANK.EQ(number, ref, [order]) - 1 for the largest (or, with order non-zero, the smallest)
+    fn evaluate_rank(&mut self, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        if !(2..=3).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let number = self
+            .evaluate_arg_as_number(parts[0], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let values = self
+            .collect_number_args(&parts[1..2], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let ascending = match parts.get(2) {
+            Some(part) => self.evaluate_arg_as_number(part, current_row, current_col).is_some_and(|o| o != 0.0),
+            None => false,
+        };
+        if !values.contains(&number) {
+            return Err("#N/A".to_string());
+        }
+        let ahead = values.iter().filter(|&&v| if ascending { v < number } else { v > number }).count();
+        Ok(ahead as f64 + 1.0)
+    }
+
+    /// COVARIANCE.S/P, SLOPE, INTERCEPT and RSQ(known_ys, known_xs)
+    fn evaluate_regression(&mut self, name: &str, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        if parts.len() != 2 {
+            return Err("#ERROR".to_string());
+        }
+        let pairs = self.collect_number_pairs(parts[0], parts[1], current_row, current_col)?;
+        let div0 = || "#DIV/0!".to_string();
+        if pairs.is_empty() {
+            return Err(div0());
+        }
+        let ys: Vec<f64> = pairs.iter().map(|&(y, _)| y).collect();
+        let xs: Vec<f64> = pairs.iter().map(|&(_, x)| x).collect();
+        let (mean_y, mean_x) = (mean(&ys), mean(&xs));
+        let products: f64 = pairs.iter().map(|(y, x)| (y - mean_y) * (x - mean_x)).sum();
+        let (ss_x, ss_y) = (sum_squares(&xs), sum_squares(&ys));
+        let n = pairs.len() as f64;
+        match name {
+            "COVARIANCE.S" if n < 2.0 => Err(div0()),
+            "COVARIANCE.S" => Ok(products / (n - 1.0)),
+            "COVARIANCE.P" | "COVAR" => Ok(products / n),
+            _ if ss_x == 0.0 => Err(div0()),
+            "SLOPE" => Ok(products / ss_x),
+            "INTERCEPT" => Ok(mean_y - products / ss_x * mean_x),
+            _ if ss_y == 0.0 => Err(div0()),
+            _ => Ok(products * products / (ss_x * ss_y)),
+        }
+    }
+
+    /// FORECAST.LINEAR(x, known_ys, known_xs) - the least-squares line's value at x
+    fn evaluate_forecast(&mut self, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        if parts.len() != 3 {
+            return Err("#ERROR".to_string());
+        }
+        let x = self
+            .evaluate_arg_as_number(parts[0], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let slope = self.evaluate_regression("SLOPE", &parts[1..], current_row, current_col)?;
+        let intercept = self.evaluate_regression("INTERCEPT", &parts[1..], current_row, current_col)?;
+        Ok(intercept + slope * x)
+    }
+
+    /// NORM.DIST(x, mean, standard_dev, cumulative) and NORM.INV(probability, mean, standard_dev)
+    fn evaluate_normal(&mut self, name: &str, parts: &[&str], current_row: usize, current_col: usize) -> Result<f64, String> {
+        let arity = if name == "NORM.DIST" { 4 } else { 3 };
+        if parts.len() != arity {
+            return Err("#ERROR".to_string());
+        }
+        let mut numbers = Vec::new();
+        for part in &parts[..3] {
+            numbers.push(
+                self.evaluate_arg_as_number(part, current_row, current_col)
+                    .ok_or_else(|| "#ERROR".to_string())?,
+            );
+        }
+        let (x, mean, sd) = (numbers[0], numbers[1], numbers[2]);
+        if sd <= 0.0 {
+            return Err("#NUM!".to_string());
+        }
+        if name == "NORM.INV" {
+            if x <= 0.0 || x >= 1.0 {
+                return Err("#NUM!".to_string());
+            }
+            return Ok(mean + sd * norm_inv(x));
+        }
+        let cumulative = self
+            .evaluate_arg_as_bool(parts[3], current_row, current_col)
+            .ok_or_else(|| "#ERROR".to_string())?;
+        let z = (x - mean) / sd;
+        Ok(if cumulative { norm_cdf(z) } else { (-z * z / 2.0).exp() / (sd * (2.0 * PI).sqrt()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &str, expected: f64) {
+        let value: f64 = actual.parse().unwrap_or_else(|_| panic!("not a number: {}", actual));
+        assert!((value - expected).abs() <= 1e-9 * expected.abs().max(1.0), "{} != {}", actual, expected);
+    }
+
+    fn sample_sheet() -> Spreadsheet {
+        let mut sheet = Spreadsheet::new();
+        // A1:A8 holds 2, 4, 4, 4, 5, 5, 7, 9 with a text cell and a blank mixed in at A9:A10
+        for (row, value) in ["2", "4", "4", "4", "5", "5", "7", "9", "n/a"].iter().enumerate() {
+            sheet.set_cell(row, 0, value.to_string());
+        }
+        // B1:B5 against C1:C5 for the regression functions
+        for (row, (y, x)) in [("1", "1"), ("3", "2"), ("2", "3"), ("5", "4"), ("4", "5")].iter().enumerate() {
+            sheet.set_cell(row, 1, y.to_string());
+            sheet.set_cell(row, 2, x.to_string());
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_spread_and_shape() {
+        let mut sheet = sample_sheet();
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 20, 5);
+        assert_eq!(eval("=STDEV.P(A1:A10)"), "2");
+        assert_close(&eval("=STDEV.S(A1:A10)"), 2.138089935299395);
+        assert_close(&eval("=STDEV(A1:A4,A5:A10)"), 2.138089935299395);
+        assert_close(&eval("=VAR.S(A1:A10)"), 4.571428571428571);
+        assert_eq!(eval("=VAR.P(A1:A10)"), "4");
+        assert_eq!(eval("=VAR.S(A1)"), "#DIV/0!");
+        assert_eq!(eval("=MODE(A1:A10)"), "4");
+        assert_eq!(eval("=MODE(1,2,3)"), "#N/A");
+        assert_close(&eval("=GEOMEAN(A1:A10)"), 4.603215596046737);
+        assert_eq!(eval("=GEOMEAN(A1:A8,-1)"), "#NUM!");
+        assert_close(&eval("=SKEW(A1:A10)"), 0.8184875533567997);
+        assert_close(&eval("=KURT(A1:A10)"), 0.940625);
+    }
+
+    #[test]
+    fn test_order_statistics() {
+        let mut sheet = sample_sheet();
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 20, 5);
+        assert_eq!(eval("=PERCENTILE.INC(A1:A10,0.3)"), "4");
+        assert_close(&eval("=PERCENTILE(A1:A10,0.9)"), 7.6);
+        assert_close(&eval("=PERCENTILE.EXC(A1:A10,0.25)"), 4.0);
+        assert_eq!(eval("=PERCENTILE.EXC(A1:A10,0.05)"), "#NUM!");
+        assert_eq!(eval("=QUARTILE(A1:A10,1)"), "4");
+        assert_close(&eval("=QUARTILE.INC(A1:A10,3)"), 5.5);
+        assert_close(&eval("=QUARTILE.EXC(A1:A10,3)"), 6.5);
+        assert_eq!(eval("=LARGE(A1:A10,2)"), "7");
+        assert_eq!(eval("=SMALL(A1:A10,3)"), "4");
+        assert_eq!(eval("=SMALL(A1:A10,9)"), "#NUM!");
+        assert_eq!(eval("=RANK.EQ(5,A1:A10)"), "3");
+        assert_eq!(eval("=RANK(5,A1:A10,1)"), "5");
+        assert_eq!(eval("=RANK(6,A1:A10)"), "#N/A");
+    }
+
+    #[test]
+    fn test_regression_and_normal() {
+        let mut sheet = sample_sheet();
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 20, 5);
+        assert_close(&eval("=COVARIANCE.P(B1:B5,C1:C5)"), 1.6);
+        assert_eq!(eval("=COVARIANCE.S(B1:B5,C1:C5)"), "2");
+        assert_close(&eval("=SLOPE(B1:B5,C1:C5)"), 0.8);
+        assert_close(&eval("=INTERCEPT(B1:B5,C1:C5)"), 0.6);
+        assert_close(&eval("=RSQ(B1:B5,C1:C5)"), 0.64);
+        assert_close(&eval("=FORECAST.LINEAR(6,B1:B5,C1:C5)"), 5.4);
+        assert_eq!(eval("=SLOPE(B1:B5,C1:C4)"), "#N/A");
+        assert_close(&eval("=NORM.DIST(42,40,1.5,TRUE)"), 0.908788780274132);
+        assert_close(&eval("=NORM.DIST(42,40,1.5,FALSE)"), 0.109340049783996);
+        assert_close(&eval("=NORM.DIST(-5,0,1,TRUE)"), 2.8665157186802404e-7);
+        assert_close(&eval("=NORM.INV(0.908788780274132,40,1.5)"), 42.0);
+        assert_close(&eval("=NORM.INV(0.001,0,1)"), -3.090232306167813);
+        assert_eq!(eval("=NORM.INV(1,0,1)"), "#NUM!");
+    }
+}
@@ -1,12 +1,39 @@
 use ratatui::style::Color;
 
+use crate::conditional::{ConditionalRule, RuleCondition};
 use crate::constants::{
     DEFAULT_COL_WIDTH, DEFAULT_ROW_HEIGHT, MAX_COL_WIDTH, MAX_ROW_HEIGHT,
     MIN_COL_WIDTH, MIN_ROW_HEIGHT,
 };
+use crate::number_format::{format_value, Formatted};
 use crate::spreadsheet::Spreadsheet;
+use crate::text::{display_width, wrap_text};
 use crate::types::{CellStyle, DataType, TextAlignment, VerticalAlignment};
 
+/// The number format code a data type stands for; Text cells are shown as-is
+pub fn data_type_format(data_type: DataType) -> Option<&'static str> {
+    match data_type {
+        DataType::Text => None,
+        DataType::Number => Some("General"),
+        DataType::Currency => Some("$#,##0.00"),
+        DataType::Percentage => Some("0.0%"),
+        DataType::Date => Some("yyyy-mm-dd"),
+        DataType::Time => Some("hh:mm:ss"),
+    }
+}
+
+/// Format an evaluated value by the cell's number format, falling back to its data type
+pub fn format_cell(value: &str, style: &CellStyle) -> Formatted {
+    let code = style
+        .number_format
+        .as_deref()
+        .or_else(|| style.data_type.and_then(data_type_format));
+    match code {
+        Some(code) if !value.is_empty() => format_value(value, code),
+        _ => Formatted { text: value.to_string(), color: None },
+    }
+}
+
 impl Spreadsheet {
     pub fn get_col_width(&self, col: usize) -> u16 {
         *self.col_widths.get(&col).unwrap_or(&DEFAULT_COL_WIDTH)
@@ -35,67 +62,64 @@ impl Spreadsheet {
     }
 
     pub fn get_cell_style(&self, row: usize, col: usize) -> CellStyle {
-        self.cell_styles.get(&(row, col)).copied().unwrap_or_default()
+        self.cell_styles.get(&(row, col)).cloned().unwrap_or_default()
     }
 
-    pub fn set_cell_fg(&mut self, row: usize, col: usize, color: Option<Color>) {
-        let mut style = self.get_cell_style(row, col);
-        style.fg = color;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
+    /// Store a cell's style, dropping it entirely once nothing is set
+    fn store_cell_style(&mut self, row: usize, col: usize, style: CellStyle) {
+        if style.is_default() {
             self.cell_styles.remove(&(row, col));
         } else {
             self.cell_styles.insert((row, col), style);
         }
     }
 
+    pub fn set_cell_fg(&mut self, row: usize, col: usize, color: Option<Color>) {
+        let mut style = self.get_cell_style(row, col);
+        style.fg = color;
+        self.store_cell_style(row, col, style);
+    }
+
     pub fn set_cell_bg(&mut self, row: usize, col: usize, color: Option<Color>) {
         let mut style = self.get_cell_style(row, col);
         style.bg = color;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
-            self.cell_styles.remove(&(row, col));
-        } else {
-            self.cell_styles.insert((row, col), style);
-        }
+        self.store_cell_style(row, col, style);
     }
 
     pub fn set_cell_bold(&mut self, row: usize, col: usize, bold: bool) {
         let mut style = self.get_cell_style(row, col);
         style.bold = bold;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
-            self.cell_styles.remove(&(row, col));
-        } else {
-            self.cell_styles.insert((row, col), style);
-        }
+        self.store_cell_style(row, col, style);
     }
 
     pub fn set_cell_alignment(&mut self, row: usize, col: usize, alignment: Option<TextAlignment>) {
         let mut style = self.get_cell_style(row, col);
         style.alignment = alignment;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
-            self.cell_styles.remove(&(row, col));
-        } else {
-            self.cell_styles.insert((row, col), style);
-        }
+        self.store_cell_style(row, col, style);
     }
 
     pub fn set_cell_vertical_alignment(&mut self, row: usize, col: usize, vertical_alignment: Option<VerticalAlignment>) {
         let mut style = self.get_cell_style(row, col);
         style.vertical_alignment = vertical_alignment;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
-            self.cell_styles.remove(&(row, col));
-        } else {
-            self.cell_styles.insert((row, col), style);
-        }
+        self.store_cell_style(row, col, style);
     }
 
     pub fn set_cell_data_type(&mut self, row: usize, col: usize, data_type: Option<DataType>) {
         let mut style = self.get_cell_style(row, col);
         style.data_type = data_type;
-        if style.fg.is_none() && style.bg.is_none() && !style.bold && style.alignment.is_none() && style.vertical_alignment.is_none() && style.data_type.is_none() {
-            self.cell_styles.remove(&(row, col));
-        } else {
-            self.cell_styles.insert((row, col), style);
-        }
+        self.store_cell_style(row, col, style);
+    }
+
+    pub fn set_cell_number_format(&mut self, row: usize, col: usize, number_format: Option<String>) {
+        let mut style = self.get_cell_style(row, col);
+        style.number_format = number_format;
+        self.store_cell_style(row, col, style);
+    }
+
+    pub fn set_cell_wrap(&mut self, row: usize, col: usize, wrap: bool) {
+        let mut style = self.get_cell_style(row, col);
+        style.wrap = wrap;
+        self.store_cell_style(row, col, style);
     }
 
     pub fn apply_data_type_to_selection(&mut self, data_type: Option<DataType>) {
@@ -125,14 +149,12 @@ impl Spreadsheet {
         }
     }
 
-    pub fn format_as_table(&mut self) {
-        // Auto-detect table bounds starting from cursor position
-        let start_row = self.cursor_row;
-        let start_col = self.cursor_col;
-        
+    /// Find the block of data around a cell the way Excel does for tables: the run of
+    /// filled cells in the cell's row, extended up and down while any of those columns has data
+    pub fn detect_table_region(&self, start_row: usize, start_col: usize) -> Option<((usize, usize), (usize, usize))> {
         // Check if starting cell has data
         if self.get_cell(start_row, start_col).is_empty() {
-            return;
+            return None;
         }
         
         // Find the leftmost column with data in the header row (scan left from cursor)
@@ -188,7 +210,20 @@ impl Spreadsheet {
                 break;
             }
         }
+
+        Some(((min_row, min_col), (max_row, max_col)))
+    }
+
+    pub fn format_as_table(&mut self) {
+        // Auto-detect table bounds starting from cursor position
+        let Some(((min_row, min_col), (max_row, max_col))) =
+            self.detect_table_region(self.cursor_row, self.cursor_col)
+        else {
+            return;
+        };
         
+        self.register_table(min_row, min_col, max_col);
+
         // Auto-select the detected table range
         self.selection_anchor = Some((min_row, min_col));
         self.cursor_row = max_row;
@@ -201,24 +236,28 @@ impl Spreadsheet {
             self.set_cell_fg(min_row, col, Some(header_fg));
         }
         
-        // Apply alternating row colors (zebra striping)
-        // Light gray for even rows, white for odd rows (starting from row after header)
         let header_bg = Color::Rgb(240, 240, 240); // Light gray for header
         let even_row_bg = Color::Rgb(250, 250, 250); // Very light gray for even rows
-        let odd_row_bg = Color::White; // White for odd rows
-        
+
         // Set header background
         for col in min_col..=max_col {
             self.set_cell_bg(min_row, col, Some(header_bg));
         }
-        
-        // Apply zebra striping to data rows
-        for row in (min_row + 1)..=max_row {
-            let is_even = (row - min_row - 1) % 2 == 0;
-            let bg_color = if is_even { even_row_bg } else { odd_row_bg };
-            for col in min_col..=max_col {
-                self.set_cell_bg(row, col, Some(bg_color));
-            }
+
+        // Zebra striping is a conditional rule, so the stripes stay alternating when rows are
+        // sorted, inserted or deleted
+        if max_row > min_row {
+            let (start, end) = ((min_row + 1, min_col), (max_row, max_col));
+            self.conditional_rules
+                .retain(|rule| !(rule.condition == RuleCondition::Stripes && rule.start == start && rule.end == end));
+            self.conditional_rules.push(ConditionalRule {
+                start,
+                end,
+                condition: RuleCondition::Stripes,
+                fg: None,
+                bg: Some(even_row_bg),
+                bold: false,
+            });
         }
     }
 
@@ -291,6 +330,119 @@ impl Spreadsheet {
             self.set_cell_bold(self.cursor_row, self.cursor_col, bold);
         }
     }
+
+    /// `:format <code>` sets an Excel number format code on the selection; no code clears it
+    pub fn number_format_command(&mut self, arg: &str) -> Result<(), String> {
+        let code = if arg.is_empty() { None } else { Some(arg.to_string()) };
+        let ((min_row, min_col), (max_row, max_col)) = self
+            .get_selection_range()
+            .unwrap_or(((self.cursor_row, self.cursor_col), (self.cursor_row, self.cursor_col)));
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                self.set_cell_number_format(row, col, code.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn text wrapping on for the selection, or off if the cursor cell already wraps
+    pub fn toggle_wrap_selection(&mut self) {
+        let wrap = !self.get_cell_style(self.cursor_row, self.cursor_col).wrap;
+        if let Some(((min_row, min_col), (max_row, max_col))) = self.get_selection_range() {
+            for row in min_row..=max_row {
+                for col in min_col..=max_col {
+                    self.set_cell_wrap(row, col, wrap);
+                }
+            }
+        } else {
+            self.set_cell_wrap(self.cursor_row, self.cursor_col, wrap);
+        }
+    }
+
+    /// The text a cell shows: its evaluated value formatted by the cell's number format
+    pub fn display_value(&mut self, row: usize, col: usize) -> String {
+        let evaluated = self.evaluate_cell(row, col);
+        format_cell(&evaluated, &self.get_cell_style(row, col)).text
+    }
+
+    /// Set a row's height to the number of lines its wrapped cells need
+    pub fn autofit_row_height(&mut self, row: usize) {
+        let wrapped: Vec<usize> = self
+            .cell_styles
+            .iter()
+            .filter(|((r, _), style)| *r == row && style.wrap)
+            .map(|((_, c), _)| *c)
+            .collect();
+
+        let mut lines = 1;
+        for col in wrapped {
+            let text = self.display_value(row, col);
+            let width = self.get_col_width(col).saturating_sub(1) as usize;
+            lines = lines.max(wrap_text(&text, width).len());
+        }
+        self.set_row_height(row, lines.min(u16::MAX as usize) as u16);
+    }
+
+    /// Size a column to the widest value it displays (or back to the default when empty)
+    pub fn autofit_column(&mut self, col: usize) {
+        let rows: Vec<usize> = self
+            .cells
+            .keys()
+            .filter(|(_, c)| *c == col)
+            .map(|(r, _)| *r)
+            .collect();
+
+        let mut widest = 0;
+        for row in rows {
+            let text = self.display_value(row, col);
+            widest = widest.max(display_width(&text));
+        }
+
+        if widest == 0 {
+            self.set_col_width(col, DEFAULT_COL_WIDTH);
+        } else {
+            // One extra column for the gutter space every cell starts with
+            self.set_col_width(col, (widest + 1).min(u16::MAX as usize) as u16);
+        }
+    }
+
+    /// Auto-fit the selected columns (column select mode or the cell selection), or the cursor column
+    pub fn autofit_selected_columns(&mut self) {
+        let (min_col, max_col) = if let Some((min_col, max_col)) = self.selected_cols {
+            (min_col, max_col)
+        } else if let Some(((_, min_col), (_, max_col))) = self.get_selection_range() {
+            (min_col, max_col)
+        } else {
+            (self.cursor_col, self.cursor_col)
+        };
+        for col in min_col..=max_col {
+            self.autofit_column(col);
+        }
+    }
+
+    /// Auto-fit every column that contains data
+    pub fn autofit_all_columns(&mut self) {
+        let mut cols: Vec<usize> = self.cells.keys().map(|(_, c)| *c).collect();
+        cols.sort_unstable();
+        cols.dedup();
+        for col in cols {
+            self.autofit_column(col);
+        }
+    }
+
+    /// Auto-fit the height of every row in the selection (or the cursor row)
+    pub fn autofit_selected_row_heights(&mut self) {
+        let (min_row, max_row) = if let Some((min_row, max_row)) = self.selected_rows {
+            (min_row, max_row)
+        } else if let Some(((min_row, _), (max_row, _))) = self.get_selection_range() {
+            (min_row, max_row)
+        } else {
+            (self.cursor_row, self.cursor_row)
+        };
+        for row in min_row..=max_row {
+            self.autofit_row_height(row);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -322,6 +474,49 @@ mod tests {
         sheet.set_cell_fg(0, 0, Some(Color::Red));
         let style = sheet.get_cell_style(0, 0);
         assert_eq!(style.fg, Some(Color::Red));
+
+        // Clearing the only attribute drops the stored style
+        sheet.set_cell_fg(0, 0, None);
+        assert!(!sheet.cell_styles.contains_key(&(0, 0)));
+    }
+
+    #[test]
+    fn test_autofit_column() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "Description".to_string());
+        sheet.set_cell(1, 0, "A much longer description".to_string());
+        sheet.set_cell(0, 1, "日本語".to_string());
+        sheet.set_cell(0, 2, "=1/3".to_string());
+        sheet.set_col_width(3, 20);
+
+        sheet.autofit_all_columns();
+        assert_eq!(sheet.get_col_width(0), 26);
+        // Wide characters take two columns each
+        assert_eq!(sheet.get_col_width(1), 7);
+        // Formulas are measured by their evaluated value
+        let evaluated = sheet.evaluate_cell(0, 2);
+        assert_eq!(sheet.get_col_width(2), (evaluated.len() + 1) as u16);
+
+        // An empty column goes back to the default width
+        sheet.cursor_col = 3;
+        sheet.autofit_selected_columns();
+        assert_eq!(sheet.get_col_width(3), DEFAULT_COL_WIDTH);
+    }
+
+    #[test]
+    fn test_autofit_row_height_for_wrapped_text() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "one two three four five".to_string());
+        sheet.set_cell_wrap(0, 0, true);
+        sheet.set_col_width(0, 10);
+
+        sheet.autofit_row_height(0);
+        // 9 usable columns: "one two", "three", "four five"
+        assert_eq!(sheet.get_row_height(0), 3);
+
+        sheet.set_cell_wrap(0, 0, false);
+        sheet.autofit_row_height(0);
+        assert_eq!(sheet.get_row_height(0), DEFAULT_ROW_HEIGHT);
     }
 }
 
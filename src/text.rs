@@ -0,0 +1,160 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Number of terminal columns a string occupies
+pub fn display_width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// Cut a string down to at most `width` terminal columns
+pub fn truncate_to_width(s: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut used = 0;
+    for ch in s.chars() {
+        let w = ch.width().unwrap_or(0);
+        if used + w > width {
+            break;
+        }
+        used += w;
+        result.push(ch);
+    }
+    result
+}
+
+/// Truncate or pad with spaces so the string is exactly `width` columns wide
+pub fn fit_to_width(s: &str, width: usize) -> String {
+    let mut result = truncate_to_width(s, width);
+    let used = display_width(&result);
+    result.push_str(&" ".repeat(width - used));
+    result
+}
+
+/// Split a line into consecutive pieces of the given widths. A wide character that
+/// would straddle two pieces is replaced by padding.
+pub fn split_by_widths(line: &str, widths: &[usize]) -> Vec<String> {
+    let mut chars = line.chars().peekable();
+    let mut pieces = Vec::with_capacity(widths.len());
+    for &width in widths {
+        let mut piece = String::new();
+        let mut used = 0;
+        while let Some(&ch) = chars.peek() {
+            let w = ch.width().unwrap_or(0);
+            if used + w > width {
+                break;
+            }
+            used += w;
+            piece.push(ch);
+            chars.next();
+        }
+        if used < width && chars.peek().is_some_and(|ch| ch.width().unwrap_or(0) > width - used) {
+            chars.next();
+        }
+        piece.push_str(&" ".repeat(width - used));
+        pieces.push(piece);
+    }
+    pieces
+}
+
+/// Word-wrap text into lines no wider than `width`, breaking words that don't fit on a line
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word = word.to_string();
+            loop {
+                let line_width = display_width(&line);
+                let sep = if line.is_empty() { 0 } else { 1 };
+                if line_width + sep + display_width(&word) <= width {
+                    if sep == 1 {
+                        line.push(' ');
+                    }
+                    line.push_str(&word);
+                    break;
+                }
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                    continue;
+                }
+                // The word alone is wider than a line: hard-break it
+                let head = truncate_to_width(&word, width);
+                let head = if head.is_empty() { word.chars().take(1).collect() } else { head };
+                word = word[head.len()..].to_string();
+                lines.push(head);
+                if word.is_empty() {
+                    break;
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Make a number fit in `width` columns the way Excel does: drop decimals first,
+/// then fall back to `###` rather than showing a misleading truncated value
+pub fn fit_number(formatted: &str, width: usize) -> String {
+    if display_width(formatted) <= width {
+        return formatted.to_string();
+    }
+    if let Ok(num) = formatted.parse::<f64>()
+        && formatted.contains('.')
+        && !formatted.contains(['e', 'E'])
+    {
+        let int_digits = formatted.find('.').unwrap_or(formatted.len());
+        // Keep the integer part plus as many decimals as fit after the point
+        if int_digits < width {
+            let decimals = width - int_digits - 1;
+            let rounded = format!("{:.*}", decimals, num);
+            if display_width(&rounded) <= width {
+                return rounded;
+            }
+        } else if int_digits == width {
+            let rounded = format!("{:.0}", num);
+            if display_width(&rounded) <= width {
+                return rounded;
+            }
+        }
+    }
+    "#".repeat(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_width_and_truncate() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(truncate_to_width("日本語", 5), "日本");
+        assert_eq!(fit_to_width("ab", 4), "ab  ");
+        assert_eq!(fit_to_width("abcdef", 4), "abcd");
+    }
+
+    #[test]
+    fn test_split_by_widths() {
+        assert_eq!(split_by_widths(" hello world", &[5, 5, 5]), vec![" hell", "o wor", "ld   "]);
+        // A wide character that doesn't fit the first piece is dropped rather than split
+        assert_eq!(split_by_widths("a日b", &[2, 2]), vec!["a ", "b "]);
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("the quick brown fox", 10), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("short", 10), vec!["short"]);
+        assert_eq!(wrap_text("", 10), vec![""]);
+    }
+
+    #[test]
+    fn test_fit_number() {
+        assert_eq!(fit_number("123", 5), "123");
+        assert_eq!(fit_number("3.14159265", 6), "3.1416");
+        assert_eq!(fit_number("12345.678", 5), "12346");
+        assert_eq!(fit_number("1234567", 5), "#####");
+        assert_eq!(fit_number("$1,234,567.00", 5), "#####");
+    }
+}
@@ -0,0 +1,551 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::array::Array;
+use crate::date::parse_date_time;
+use crate::lookup::wildcard_match;
+use crate::number_format::format_value;
+use crate::spreadsheet::Spreadsheet;
+
+fn bool_text(value: bool) -> String {
+    if value { "TRUE" } else { "FALSE" }.to_string()
+}
+
+/// Character position (0-based) of `needle` in `haystack` at or after `start`. SEARCH-style
+/// lookups ignore case and accept wildcards.
+fn find_position(haystack: &str, needle: &str, start: usize, search: bool) -> Option<usize> {
+    let chars: Vec<char> = haystack.chars().collect();
+    if start > chars.len() {
+        return None;
+    }
+    if search && needle.contains(['*', '?', '~']) {
+        // The first position where some stretch of text matches the pattern
+        return (start..=chars.len()).find(|&i| {
+            (i..=chars.len()).any(|j| wildcard_match(needle, &chars[i..j].iter().collect::<String>()))
+        });
+    }
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.len() > chars.len() {
+        return None;
+    }
+    let same = |a: &char, b: &char| if search { a.to_lowercase().eq(b.to_lowercase()) } else { a == b };
+    (start..=chars.len() - needle.len()).find(|&i| chars[i..i + needle.len()].iter().zip(&needle).all(|(a, b)| same(a, b)))
+}
+
+/// Byte offsets of each non-overlapping occurrence of `delimiter`
+fn delimiter_positions(text: &str, delimiter: &str, ignore_case: bool) -> Vec<(usize, usize)> {
+    if delimiter.is_empty() {
+        return Vec::new();
+    }
+    let (haystack, needle) = if ignore_case {
+        (text.to_lowercase(), delimiter.to_lowercase())
+    } else {
+        (text.to_string(), delimiter.to_string())
+    };
+    // Lowercasing can change byte lengths; fall back to exact matching when it does
+    let (haystack, needle) = if haystack.len() == text.len() { (haystack, needle) } else { (text.to_string(), delimiter.to_string()) };
+    haystack.match_indices(&needle).map(|(i, m)| (i, i + m.len())).collect()
+}
+
+/// Split text at any of several delimiters
+fn split_any<'a>(text: &'a str, delimiters: &[String], ignore_case: bool) -> Vec<&'a str> {
+    let mut cuts: Vec<(usize, usize)> = delimiters.iter().flat_map(|d| delimiter_positions(text, d, ignore_case)).collect();
+    cuts.sort();
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (begin, end) in cuts {
+        if begin >= start {
+            pieces.push(&text[start..begin]);
+            start = end;
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+/// TEXTSPLIT's grid: rows split at `row_delimiters`, then columns at `col_delimiters`, with
+/// short rows padded
+pub fn split_text(text: &str, col_delimiters: &[String], row_delimiters: &[String], ignore_empty: bool, ignore_case: bool, pad: &str) -> Vec<Vec<String>> {
+    let rows: Vec<&str> = if row_delimiters.is_empty() { vec![text] } else { split_any(text, row_delimiters, ignore_case) };
+    let mut grid: Vec<Vec<String>> = rows
+        .into_iter()
+        .filter(|row| !(ignore_empty && row.is_empty()))
+        .map(|row| {
+            split_any(row, col_delimiters, ignore_case)
+                .into_iter()
+                .filter(|piece| !(ignore_empty && piece.is_empty()))
+                .map(str::to_string)
+                .collect()
+        })
+        .collect();
+    let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+    for row in &mut grid {
+        row.resize(width, pad.to_string());
+    }
+    grid
+}
+
+/// A regex from a formula argument; case_insensitive mirrors Excel's case_sensitivity 1
+fn build_regex(pattern: &str, case_insensitive: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern).case_insensitive(case_insensitive).build().map_err(|_| "#VALUE!".to_string())
+}
+
+/// REGEXEXTRACT's result: the first match (mode 0), every match (1) or the first match's
+/// capture groups (2)
+pub fn regex_extract(text: &str, regex: &Regex, mode: i64) -> Result<Vec<String>, String> {
+    let found: Vec<String> = match mode {
+        0 => regex.find(text).map(|m| m.as_str().to_string()).into_iter().collect(),
+        1 => regex.find_iter(text).map(|m| m.as_str().to_string()).collect(),
+        2 => match regex.captures(text) {
+            Some(captures) => captures.iter().skip(1).map(|group| group.map_or(String::new(), |g| g.as_str().to_string())).collect(),
+            None => Vec::new(),
+        },
+        _ => return Err("#VALUE!".to_string()),
+    };
+    if found.is_empty() { Err("#N/A".to_string()) } else { Ok(found) }
+}
+
+/// A number from text the way VALUE reads it: plain, with thousands separators, a currency
+/// sign, a percent sign or parentheses for negatives, or a date or time
+pub fn parse_number_text(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Ok(number) = text.parse::<f64>() {
+        return Some(number);
+    }
+    let (negative, body) = match text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        },
+    };
+    let (percent, body) = match body.trim().strip_suffix('%') {
+        Some(rest) => (true, rest),
+        None => (false, body.trim()),
+    };
+    let body = body.trim_start_matches(['$', '€', '£', '¥']).replace(',', "");
+    match body.trim().parse::<f64>() {
+        Ok(number) => {
+            let number = if percent { number / 100.0 } else { number };
+            Some(if negative { -number } else { number })
+        }
+        Err(_) => parse_date_time(text).map(|(serial, _)| serial),
+    }
+}
+
+impl Spreadsheet {
+    /// The text of each argument, expanding ranges into their cell values row by row
+    fn collect_text_args(&mut self, parts: &[&str], current_row: usize, current_col: usize) -> Vec<String> {
+        let mut texts = Vec::new();
+        for part in parts {
+            let range = if part.contains(':') || part.trim().contains('(') {
+                self.resolve_range(part, current_row, current_col).ok()
+            } else {
+                None
+            };
+            match range {
+                Some(range) => {
+                    for (row, col) in range.cells() {
+                        texts.push(self.evaluate_cell(row, col));
+                    }
+                }
+                None => texts.push(self.evaluate_arg(part, current_row, current_col)),
+            }
+        }
+        texts
+    }
+
+    /// An optional whole-number argument
+    pub fn optional_int(&mut self, part: Option<&&str>, default: i64, current_row: usize, current_col: usize) -> Result<i64, String> {
+        match part.filter(|part| !part.trim().is_empty()) {
+            Some(part) => self
+                .evaluate_arg_as_number(part, current_row, current_col)
+                .map(|n| n.trunc() as i64)
+                .ok_or_else(|| "#VALUE!".to_string()),
+            None => Ok(default),
+        }
+    }
+
+    /// An optional TRUE/FALSE argument
+    pub fn optional_bool(&mut self, part: Option<&&str>, default: bool, current_row: usize, current_col: usize) -> Result<bool, String> {
+        match part.filter(|part| !part.trim().is_empty()) {
+            Some(part) => self.evaluate_arg_as_bool(part, current_row, current_col).ok_or_else(|| "#VALUE!".to_string()),
+            None => Ok(default),
+        }
+    }
+
+    /// FIND(find_text, within_text, [start_num]) and SEARCH(...): 1-based position of the
+    /// text. FIND is case-sensitive; SEARCH ignores case and accepts `*` and `?` wildcards.
+    pub fn evaluate_find(&mut self, args: &str, current_row: usize, current_col: usize, search: bool) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let needle = self.evaluate_arg(parts[0], current_row, current_col);
+        let haystack = self.evaluate_arg(parts[1], current_row, current_col);
+        let start = match self.optional_int(parts.get(2), 1, current_row, current_col) {
+            Ok(start) if start >= 1 => start as usize - 1,
+            Ok(_) => return "#VALUE!".to_string(),
+            Err(e) => return e,
+        };
+        match find_position(&haystack, &needle, start, search) {
+            Some(position) => format!("{}", position + 1),
+            None => "#VALUE!".to_string(),
+        }
+    }
+
+    /// SUBSTITUTE(text, old_text, new_text, [instance_num])
+    pub fn evaluate_substitute(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(3..=4).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let old = self.evaluate_arg(parts[1], current_row, current_col);
+        let new = self.evaluate_arg(parts[2], current_row, current_col);
+        if old.is_empty() {
+            return text;
+        }
+        match self.optional_int(parts.get(3), 0, current_row, current_col) {
+            Ok(0) if parts.len() == 3 => text.replace(&old, &new),
+            Ok(instance) if instance >= 1 => match text.match_indices(&old).nth(instance as usize - 1) {
+                Some((i, _)) => format!("{}{}{}", &text[..i], new, &text[i + old.len()..]),
+                None => text,
+            },
+            Ok(_) => "#VALUE!".to_string(),
+            Err(e) => e,
+        }
+    }
+
+    /// REPLACE(old_text, start_num, num_chars, new_text)
+    pub fn evaluate_replace(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() != 4 {
+            return "#ERROR".to_string();
+        }
+        let text: Vec<char> = self.evaluate_arg(parts[0], current_row, current_col).chars().collect();
+        let (Some(start), Some(count)) = (
+            self.evaluate_arg_as_number(parts[1], current_row, current_col),
+            self.evaluate_arg_as_number(parts[2], current_row, current_col),
+        ) else {
+            return "#VALUE!".to_string();
+        };
+        if start < 1.0 || count < 0.0 {
+            return "#VALUE!".to_string();
+        }
+        let new = self.evaluate_arg(parts[3], current_row, current_col);
+        let start = (start as usize - 1).min(text.len());
+        let end = start.saturating_add(count as usize).min(text.len());
+        format!("{}{}{}", text[..start].iter().collect::<String>(), new, text[end..].iter().collect::<String>())
+    }
+
+    /// REPT(text, number_times)
+    pub fn evaluate_rept(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() != 2 {
+            return "#ERROR".to_string();
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        match self.evaluate_arg_as_number(parts[1], current_row, current_col) {
+            // Excel caps a cell at 32,767 characters
+            Some(times) if times >= 0.0 && text.chars().count() as f64 * times.trunc() <= 32_767.0 => text.repeat(times as usize),
+            _ => "#VALUE!".to_string(),
+        }
+    }
+
+    /// TEXT(value, format_text) - a value formatted with a number format code
+    pub fn evaluate_text(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() != 2 {
+            return "#ERROR".to_string();
+        }
+        let value = self.evaluate_arg(parts[0], current_row, current_col);
+        let code = self.evaluate_arg(parts[1], current_row, current_col);
+        format_value(&value, &code).text
+    }
+
+    /// VALUE(text) - the number text stands for
+    pub fn evaluate_value(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let text = self.evaluate_arg(args, current_row, current_col);
+        match parse_number_text(&text) {
+            Some(number) => format!("{}", number),
+            None => "#VALUE!".to_string(),
+        }
+    }
+
+    /// TEXTJOIN(delimiter, ignore_empty, text1, [text2], ...)
+    pub fn evaluate_textjoin(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() < 3 {
+            return "#ERROR".to_string();
+        }
+        let delimiter = self.evaluate_arg(parts[0], current_row, current_col);
+        let Some(ignore_empty) = self.evaluate_arg_as_bool(parts[1], current_row, current_col) else {
+            return "#VALUE!".to_string();
+        };
+        let texts = self.collect_text_args(&parts[2..], current_row, current_col);
+        texts
+            .into_iter()
+            .filter(|text| !(ignore_empty && text.is_empty()))
+            .collect::<Vec<_>>()
+            .join(&delimiter)
+    }
+
+    /// TEXTSPLIT(text, col_delimiter, [row_delimiter], [ignore_empty], [match_mode], [pad_with]).
+    /// Delimiters may be a single string or a range of them.
+    pub fn textsplit_grid(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if !(2..=6).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let col_delimiters = self.collect_text_args(&parts[1..2], current_row, current_col);
+        let row_delimiters = match parts.get(2).filter(|part| !part.trim().is_empty()) {
+            Some(part) => self.collect_text_args(&[part], current_row, current_col),
+            None => Vec::new(),
+        };
+        let ignore_empty = self.optional_bool(parts.get(3), false, current_row, current_col)?;
+        let ignore_case = self.optional_int(parts.get(4), 0, current_row, current_col)? == 1;
+        let pad = match parts.get(5) {
+            Some(part) => self.evaluate_arg(part, current_row, current_col),
+            None => "#N/A".to_string(),
+        };
+        let grid = split_text(&text, &col_delimiters, &row_delimiters, ignore_empty, ignore_case, &pad);
+        if grid.first().is_none_or(Vec::is_empty) {
+            return Err("#CALC!".to_string());
+        }
+        Ok(grid)
+    }
+
+    /// TEXTBEFORE/TEXTAFTER(text, delimiter, [instance_num], [match_mode], [match_end],
+    /// [if_not_found]). A negative instance counts from the end; match_mode 1 ignores case;
+    /// match_end treats the end of the text as a delimiter.
+    pub fn evaluate_text_before_after(&mut self, args: &str, current_row: usize, current_col: usize, after: bool) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=6).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let delimiter = self.evaluate_arg(parts[1], current_row, current_col);
+        let options = (|| {
+            Ok::<_, String>((
+                self.optional_int(parts.get(2), 1, current_row, current_col)?,
+                self.optional_int(parts.get(3), 0, current_row, current_col)? == 1,
+                self.optional_bool(parts.get(4), false, current_row, current_col)?,
+            ))
+        })();
+        let (instance, ignore_case, match_end) = match options {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+        if instance == 0 || instance.unsigned_abs() as usize > text.len().max(1) {
+            return "#VALUE!".to_string();
+        }
+
+        let mut cuts = if delimiter.is_empty() {
+            (0..=text.len()).filter(|&i| text.is_char_boundary(i)).map(|i| (i, i)).collect()
+        } else {
+            delimiter_positions(&text, &delimiter, ignore_case)
+        };
+        if match_end {
+            // The ends of the text count as delimiters too
+            if instance > 0 {
+                cuts.push((text.len(), text.len()));
+            } else {
+                cuts.insert(0, (0, 0));
+            }
+        }
+        let cut = if instance > 0 {
+            cuts.get(instance as usize - 1)
+        } else {
+            cuts.len().checked_sub(instance.unsigned_abs() as usize).and_then(|i| cuts.get(i))
+        };
+        match cut {
+            Some(&(start, end)) => if after { text[end..].to_string() } else { text[..start].to_string() },
+            None => match parts.get(5) {
+                Some(part) => self.evaluate_arg(part, current_row, current_col),
+                None => "#N/A".to_string(),
+            },
+        }
+    }
+
+    /// EXACT(text1, text2) - case-sensitive comparison
+    pub fn evaluate_exact(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if parts.len() != 2 {
+            return "#ERROR".to_string();
+        }
+        let first = self.evaluate_arg(parts[0], current_row, current_col);
+        let second = self.evaluate_arg(parts[1], current_row, current_col);
+        bool_text(first == second)
+    }
+
+    /// CHAR(number) - the Latin-1 character for a code from 1 to 255
+    pub fn evaluate_char(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        match self.evaluate_arg_as_number(args, current_row, current_col) {
+            Some(code) if (1.0..256.0).contains(&code) => char::from_u32(code as u32).map(String::from).unwrap_or_default(),
+            _ => "#VALUE!".to_string(),
+        }
+    }
+
+    /// CODE(text) and UNICODE(text): the first character's code. CODE gives `?` (63) for
+    /// characters outside Latin-1.
+    pub fn evaluate_code(&mut self, args: &str, current_row: usize, current_col: usize, unicode: bool) -> String {
+        let text = self.evaluate_arg(args, current_row, current_col);
+        match text.chars().next() {
+            Some(c) if unicode || (c as u32) < 256 => format!("{}", c as u32),
+            Some(_) => "63".to_string(),
+            None => "#VALUE!".to_string(),
+        }
+    }
+
+    /// CLEAN(text) - strip the non-printable control characters 0-31
+    pub fn evaluate_clean(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let text = self.evaluate_arg(args, current_row, current_col);
+        text.chars().filter(|&c| c as u32 >= 32).collect()
+    }
+
+    /// REGEXMATCH(text, pattern, [case_sensitivity]) - TRUE when the pattern matches anywhere.
+    /// case_sensitivity 1 ignores case.
+    pub fn evaluate_regexmatch(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(2..=3).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let pattern = self.evaluate_arg(parts[1], current_row, current_col);
+        let regex = self
+            .optional_int(parts.get(2), 0, current_row, current_col)
+            .and_then(|case| build_regex(&pattern, case == 1));
+        match regex {
+            Ok(regex) => bool_text(regex.is_match(&text)),
+            Err(e) => e,
+        }
+    }
+
+    /// REGEXEXTRACT(text, pattern, [return_mode], [case_sensitivity]). Every match (mode 1)
+    /// spills down a column; capture groups (mode 2) spill across a row.
+    pub fn regexextract_array(&mut self, args: &str, current_row: usize, current_col: usize) -> Result<Array, String> {
+        let parts = self.split_function_args(args);
+        if !(2..=4).contains(&parts.len()) {
+            return Err("#ERROR".to_string());
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let pattern = self.evaluate_arg(parts[1], current_row, current_col);
+        let mode = self.optional_int(parts.get(2), 0, current_row, current_col)?;
+        let case = self.optional_int(parts.get(3), 0, current_row, current_col)?;
+        let found = regex_extract(&text, &build_regex(&pattern, case == 1)?, mode)?;
+        Ok(if mode == 2 { vec![found] } else { found.into_iter().map(|value| vec![value]).collect() })
+    }
+
+    /// REGEXREPLACE(text, pattern, replacement, [occurrence], [case_sensitivity]). The
+    /// replacement may refer to groups as `$1` or `${name}`; occurrence 0 replaces every
+    /// match, n the nth and -n the nth from the end.
+    pub fn evaluate_regexreplace(&mut self, args: &str, current_row: usize, current_col: usize) -> String {
+        let parts = self.split_function_args(args);
+        if !(3..=5).contains(&parts.len()) {
+            return "#ERROR".to_string();
+        }
+        let text = self.evaluate_arg(parts[0], current_row, current_col);
+        let pattern = self.evaluate_arg(parts[1], current_row, current_col);
+        let replacement = self.evaluate_arg(parts[2], current_row, current_col);
+        let options = self.optional_int(parts.get(3), 0, current_row, current_col).and_then(|occurrence| {
+            let case = self.optional_int(parts.get(4), 0, current_row, current_col)?;
+            Ok((occurrence, build_regex(&pattern, case == 1)?))
+        });
+        let (occurrence, regex) = match options {
+            Ok(options) => options,
+            Err(e) => return e,
+        };
+        if occurrence == 0 {
+            return regex.replace_all(&text, replacement.as_str()).into_owned();
+        }
+        let matches: Vec<_> = regex.captures_iter(&text).collect();
+        let index = if occurrence > 0 { occurrence as usize - 1 } else { matches.len().wrapping_sub(occurrence.unsigned_abs() as usize) };
+        match matches.get(index) {
+            Some(captures) => {
+                let whole = captures.get(0).expect("a match has group 0");
+                let mut replaced = String::new();
+                captures.expand(&replacement, &mut replaced);
+                format!("{}{}{}", &text[..whole.start()], replaced, &text[whole.end()..])
+            }
+            None => text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_and_replace_functions() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "Hello, World".to_string());
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 5, 5);
+        assert_eq!(eval("=FIND(\"o\",A1)"), "5");
+        assert_eq!(eval("=FIND(\"o\",A1,6)"), "9");
+        assert_eq!(eval("=FIND(\"w\",A1)"), "#VALUE!");
+        assert_eq!(eval("=SEARCH(\"w\",A1)"), "8");
+        assert_eq!(eval("=SEARCH(\"w?r\",A1)"), "8");
+        assert_eq!(eval("=SEARCH(\"l*d\",A1)"), "3");
+        assert_eq!(eval("=SUBSTITUTE(\"a-b-c\",\"-\",\"+\")"), "a+b+c");
+        assert_eq!(eval("=SUBSTITUTE(\"a-b-c\",\"-\",\"+\",2)"), "a-b+c");
+        assert_eq!(eval("=REPLACE(\"abcdef\",2,3,\"X\")"), "aXef");
+        assert_eq!(eval("=REPT(\"ab\",3)"), "ababab");
+        // Huge arguments give errors or clamp rather than overflowing
+        assert_eq!(eval("=REPT(\"ab\",9223372036854775808)"), "#VALUE!");
+        assert_eq!(eval("=REPT(\"\",1E20)"), "");
+        assert_eq!(eval("=REPLACE(\"abc\",2,1E20,\"x\")"), "ax");
+        assert_eq!(eval("=EXACT(\"abc\",\"ABC\")"), "FALSE");
+        assert_eq!(eval("=CHAR(65)"), "A");
+        assert_eq!(eval("=CODE(\"abc\")"), "97");
+        assert_eq!(eval("=UNICODE(\"€\")"), "8364");
+        assert_eq!(eval("=CODE(\"€\")"), "63");
+    }
+
+    #[test]
+    fn test_text_conversion_and_splitting() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "a".to_string());
+        sheet.set_cell(1, 0, String::new());
+        sheet.set_cell(2, 0, "c".to_string());
+        sheet.set_cell(3, 0, "1234.5".to_string());
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 5, 5);
+        assert_eq!(eval("=TEXT(A4,\"#,##0.00\")"), "1,234.50");
+        assert_eq!(eval("=TEXT(45292,\"yyyy-mm-dd\")"), "2024-01-01");
+        assert_eq!(eval("=VALUE(\"$1,234.50\")"), "1234.5");
+        assert_eq!(eval("=VALUE(\"12%\")"), "0.12");
+        assert_eq!(eval("=VALUE(\"2024-01-01\")"), "45292");
+        assert_eq!(eval("=VALUE(\"abc\")"), "#VALUE!");
+        assert_eq!(eval("=TEXTJOIN(\", \",TRUE,A1:A3)"), "a, c");
+        assert_eq!(eval("=TEXTJOIN(\"-\",FALSE,A1:A3,\"d\")"), "a--c-d");
+        assert_eq!(eval("=TEXTBEFORE(\"key=value=x\",\"=\")"), "key");
+        assert_eq!(eval("=TEXTAFTER(\"key=value=x\",\"=\",-1)"), "x");
+        assert_eq!(eval("=TEXTAFTER(\"Key=V\",\"KEY\",1,1)"), "=V");
+        assert_eq!(eval("=TEXTBEFORE(\"abc\",\"-\",1,0,FALSE,\"none\")"), "none");
+        assert_eq!(eval("=TEXTBEFORE(\"abc\",\"-\",1,0,TRUE)"), "abc");
+        assert_eq!(eval("=CLEAN(\"a\tb\")"), "ab");
+        let grid = sheet.textsplit_grid("\"a,b;c\",\",\",\";\"", 5, 5).unwrap();
+        assert_eq!(grid, vec![vec!["a", "b"], vec!["c", "#N/A"]]);
+    }
+
+    #[test]
+    fn test_regex_functions() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "2024-05-01 ERROR [db] timeout after 30s".to_string());
+        let mut eval = |formula: &str| sheet.evaluate_formula(formula, 5, 5);
+        assert_eq!(eval("=REGEXMATCH(A1,\"ERROR|WARN\")"), "TRUE");
+        assert_eq!(eval("=REGEXMATCH(A1,\"error\")"), "FALSE");
+        assert_eq!(eval("=REGEXMATCH(A1,\"error\",1)"), "TRUE");
+        assert_eq!(eval("=REGEXEXTRACT(A1,\"\\[(\\w+)\\]\")"), "[db]");
+        assert_eq!(eval("=REGEXEXTRACT(A1,\"\\[(\\w+)\\]\",2)"), "db");
+        assert_eq!(eval("=REGEXEXTRACT(A1,\"\\d+s\")"), "30s");
+        assert_eq!(eval("=REGEXEXTRACT(A1,\"FATAL\")"), "#N/A");
+        assert_eq!(eval("=REGEXEXTRACT(A1,\"(\")"), "#VALUE!");
+        assert_eq!(eval("=REGEXREPLACE(A1,\"\\d\",\"#\",-1)"), "2024-05-01 ERROR [db] timeout after 3#s");
+        assert_eq!(eval("=REGEXREPLACE(\"a1b22\",\"(\\d+)\",\"<$1>\")"), "a<1>b<22>");
+        assert_eq!(eval("=REGEXREPLACE(\"a1b22\",\"\\d+\",\"\",2)"), "a1b");
+        let all = sheet.regexextract_array("\"a1b22c333\",\"\\d+\",1", 5, 5).unwrap();
+        assert_eq!(all, vec![vec!["1"], vec!["22"], vec!["333"]]);
+    }
+}
@@ -0,0 +1,595 @@
+use std::fs;
+use std::path::PathBuf;
+
+use ratatui::buffer::Buffer;
+use ratatui::style::Color;
+
+/// Names of the themes that ship with xl
+pub const BUILTIN_THEMES: [&str; 6] = [
+    "light",
+    "dark",
+    "solarized-dark",
+    "solarized-light",
+    "gruvbox",
+    "high-contrast",
+];
+
+/// Colours used to draw the interface
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// Whether the theme has a dark background (drives the light/dark toggle label)
+    pub dark: bool,
+    pub header_bg: Color,
+    pub header_fg: Color,
+    pub selected_bg: Color,
+    pub selected_header_bg: Color,
+    pub grid: Color,
+    pub cell_bg: Color,
+    pub cell_fg: Color,
+    pub ref_selection_bg: Color,
+    pub ref_range_bg: Color,
+    pub find_match_bg: Color,
+    pub find_match_fg: Color,
+    pub formula_bar_bg: Color,
+    pub cell_name_bg: Color,
+    pub formula_bg: Color,
+    pub status_bar_bg: Color,
+    pub stats_bar_bg: Color,
+}
+
+/// How many colours the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl Theme {
+    /// Excel-like light theme (the default)
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+            dark: false,
+            header_bg: Color::Rgb(217, 217, 217),
+            header_fg: Color::Rgb(0, 0, 0),
+            selected_bg: Color::Rgb(180, 198, 231),
+            selected_header_bg: Color::Rgb(142, 169, 219),
+            grid: Color::Rgb(200, 200, 200),
+            cell_bg: Color::White,
+            cell_fg: Color::Rgb(0, 0, 0),
+            ref_selection_bg: Color::Rgb(198, 224, 180),
+            ref_range_bg: Color::Rgb(221, 235, 247),
+            find_match_bg: Color::Rgb(255, 255, 180),
+            find_match_fg: Color::Black,
+            formula_bar_bg: Color::Rgb(240, 240, 240),
+            cell_name_bg: Color::Rgb(200, 200, 200),
+            formula_bg: Color::White,
+            status_bar_bg: Color::Rgb(45, 45, 45),
+            stats_bar_bg: Color::Rgb(35, 35, 35),
+        }
+    }
+
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".to_string(),
+            dark: true,
+            header_bg: Color::Rgb(50, 50, 50),
+            header_fg: Color::Rgb(220, 220, 220),
+            selected_bg: Color::Rgb(60, 80, 120),
+            selected_header_bg: Color::Rgb(70, 100, 150),
+            grid: Color::Rgb(80, 80, 80),
+            cell_bg: Color::Rgb(25, 25, 25),
+            cell_fg: Color::Rgb(220, 220, 220),
+            ref_selection_bg: Color::Rgb(60, 100, 60),
+            ref_range_bg: Color::Rgb(50, 70, 90),
+            find_match_bg: Color::Rgb(120, 120, 60),
+            find_match_fg: Color::Black,
+            formula_bar_bg: Color::Rgb(40, 40, 40),
+            cell_name_bg: Color::Rgb(60, 60, 60),
+            formula_bg: Color::Rgb(30, 30, 30),
+            status_bar_bg: Color::Rgb(45, 45, 45),
+            stats_bar_bg: Color::Rgb(35, 35, 35),
+        }
+    }
+
+    pub fn solarized_dark() -> Self {
+        Self {
+            name: "solarized-dark".to_string(),
+            dark: true,
+            header_bg: Color::Rgb(7, 54, 66),
+            header_fg: Color::Rgb(147, 161, 161),
+            selected_bg: Color::Rgb(38, 139, 210),
+            selected_header_bg: Color::Rgb(42, 161, 152),
+            grid: Color::Rgb(88, 110, 117),
+            cell_bg: Color::Rgb(0, 43, 54),
+            cell_fg: Color::Rgb(131, 148, 150),
+            ref_selection_bg: Color::Rgb(133, 153, 0),
+            ref_range_bg: Color::Rgb(7, 54, 66),
+            find_match_bg: Color::Rgb(181, 137, 0),
+            find_match_fg: Color::Rgb(0, 43, 54),
+            formula_bar_bg: Color::Rgb(7, 54, 66),
+            cell_name_bg: Color::Rgb(88, 110, 117),
+            formula_bg: Color::Rgb(0, 43, 54),
+            status_bar_bg: Color::Rgb(7, 54, 66),
+            stats_bar_bg: Color::Rgb(0, 43, 54),
+        }
+    }
+
+    pub fn solarized_light() -> Self {
+        Self {
+            name: "solarized-light".to_string(),
+            dark: false,
+            header_bg: Color::Rgb(238, 232, 213),
+            header_fg: Color::Rgb(88, 110, 117),
+            selected_bg: Color::Rgb(147, 161, 161),
+            selected_header_bg: Color::Rgb(131, 148, 150),
+            grid: Color::Rgb(147, 161, 161),
+            cell_bg: Color::Rgb(253, 246, 227),
+            cell_fg: Color::Rgb(101, 123, 131),
+            ref_selection_bg: Color::Rgb(181, 200, 120),
+            ref_range_bg: Color::Rgb(238, 232, 213),
+            find_match_bg: Color::Rgb(181, 137, 0),
+            find_match_fg: Color::Rgb(253, 246, 227),
+            formula_bar_bg: Color::Rgb(238, 232, 213),
+            cell_name_bg: Color::Rgb(147, 161, 161),
+            formula_bg: Color::Rgb(253, 246, 227),
+            status_bar_bg: Color::Rgb(7, 54, 66),
+            stats_bar_bg: Color::Rgb(0, 43, 54),
+        }
+    }
+
+    pub fn gruvbox() -> Self {
+        Self {
+            name: "gruvbox".to_string(),
+            dark: true,
+            header_bg: Color::Rgb(60, 56, 54),
+            header_fg: Color::Rgb(235, 219, 178),
+            selected_bg: Color::Rgb(80, 73, 69),
+            selected_header_bg: Color::Rgb(69, 133, 136),
+            grid: Color::Rgb(102, 92, 84),
+            cell_bg: Color::Rgb(40, 40, 40),
+            cell_fg: Color::Rgb(235, 219, 178),
+            ref_selection_bg: Color::Rgb(152, 151, 26),
+            ref_range_bg: Color::Rgb(60, 56, 54),
+            find_match_bg: Color::Rgb(215, 153, 33),
+            find_match_fg: Color::Rgb(40, 40, 40),
+            formula_bar_bg: Color::Rgb(50, 48, 47),
+            cell_name_bg: Color::Rgb(80, 73, 69),
+            formula_bg: Color::Rgb(29, 32, 33),
+            status_bar_bg: Color::Rgb(50, 48, 47),
+            stats_bar_bg: Color::Rgb(29, 32, 33),
+        }
+    }
+
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast".to_string(),
+            dark: true,
+            header_bg: Color::Rgb(255, 255, 255),
+            header_fg: Color::Rgb(0, 0, 0),
+            selected_bg: Color::Rgb(0, 0, 255),
+            selected_header_bg: Color::Rgb(255, 255, 0),
+            grid: Color::Rgb(255, 255, 255),
+            cell_bg: Color::Rgb(0, 0, 0),
+            cell_fg: Color::Rgb(255, 255, 255),
+            ref_selection_bg: Color::Rgb(0, 160, 0),
+            ref_range_bg: Color::Rgb(0, 90, 0),
+            find_match_bg: Color::Rgb(255, 255, 0),
+            find_match_fg: Color::Rgb(0, 0, 0),
+            formula_bar_bg: Color::Rgb(0, 0, 0),
+            cell_name_bg: Color::Rgb(255, 255, 255),
+            formula_bg: Color::Rgb(0, 0, 0),
+            status_bar_bg: Color::Rgb(0, 0, 0),
+            stats_bar_bg: Color::Rgb(0, 0, 0),
+        }
+    }
+
+    /// Look up one of the themes that ship with xl
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "light" => Some(Self::light()),
+            "dark" => Some(Self::dark()),
+            "solarized-dark" | "solarized" => Some(Self::solarized_dark()),
+            "solarized-light" => Some(Self::solarized_light()),
+            "gruvbox" | "gruvbox-dark" => Some(Self::gruvbox()),
+            "high-contrast" | "contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// Directory holding user theme files
+    pub fn themes_dir() -> Option<PathBuf> {
+        // On macOS/Linux: ~/.xl/themes
+        dirs::home_dir().map(|home| home.join(".xl").join("themes"))
+    }
+
+    /// Load a theme by name: a file in the themes directory takes precedence over a builtin
+    pub fn load(name: &str) -> Result<Self, String> {
+        if let Some(dir) = Self::themes_dir() {
+            let path = dir.join(format!("{}.theme", name.trim()));
+            if path.exists() {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                return Self::parse(name.trim(), &contents);
+            }
+        }
+        Self::builtin(name).ok_or_else(|| format!("Unknown theme: {}", name.trim()))
+    }
+
+    /// Parse a theme file. The format mirrors ~/.xlrc: one `key = value` per line,
+    /// `#` comments, colours as `#rrggbb`, a colour name or a 0-255 palette index.
+    /// An optional `base = <theme>` line starts from a builtin theme.
+    pub fn parse(name: &str, contents: &str) -> Result<Self, String> {
+        let mut theme = Self::light();
+        for line in contents.lines() {
+            let line = line.trim();
+            if let Some(base) = line.strip_prefix("base").and_then(|rest| rest.trim().strip_prefix('=')) {
+                theme = Self::builtin(base).ok_or_else(|| format!("Unknown base theme: {}", base.trim()))?;
+            }
+        }
+        theme.name = name.to_string();
+
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Line {}: expected key = value", line_no + 1));
+            };
+            let key = key.trim();
+            let value = value.trim();
+            if key == "base" {
+                continue;
+            }
+            if key == "dark" {
+                theme.dark = value == "true" || value == "1";
+                continue;
+            }
+            let color = parse_color(value)
+                .ok_or_else(|| format!("Line {}: invalid colour '{}'", line_no + 1, value))?;
+            let slot = match key {
+                "header_bg" => &mut theme.header_bg,
+                "header_fg" => &mut theme.header_fg,
+                "selected_bg" => &mut theme.selected_bg,
+                "selected_header_bg" => &mut theme.selected_header_bg,
+                "grid" => &mut theme.grid,
+                "cell_bg" => &mut theme.cell_bg,
+                "cell_fg" => &mut theme.cell_fg,
+                "ref_selection_bg" => &mut theme.ref_selection_bg,
+                "ref_range_bg" => &mut theme.ref_range_bg,
+                "find_match_bg" => &mut theme.find_match_bg,
+                "find_match_fg" => &mut theme.find_match_fg,
+                "formula_bar_bg" => &mut theme.formula_bar_bg,
+                "cell_name_bg" => &mut theme.cell_name_bg,
+                "formula_bg" => &mut theme.formula_bg,
+                "status_bar_bg" => &mut theme.status_bar_bg,
+                "stats_bar_bg" => &mut theme.stats_bar_bg,
+                _ => return Err(format!("Line {}: unknown key '{}'", line_no + 1, key)),
+            };
+            *slot = color;
+        }
+
+        Ok(theme)
+    }
+
+    /// Names of all themes that can be selected: builtins plus files in the themes directory
+    pub fn available() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES.iter().map(|s| s.to_string()).collect();
+        if let Some(dir) = Self::themes_dir()
+            && let Ok(entries) = fs::read_dir(dir)
+        {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) == Some("theme")
+                    && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+                    && !names.iter().any(|n| n == stem)
+                {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+        names
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::light()
+    }
+}
+
+impl ColorDepth {
+    /// Guess the colour support of the terminal from the environment
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        Self::from_env(&colorterm, &term, &term_program)
+    }
+
+    fn from_env(colorterm: &str, term: &str, term_program: &str) -> Self {
+        let colorterm = colorterm.to_lowercase();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        if matches!(term_program, "iTerm.app" | "WezTerm" | "vscode" | "ghostty") {
+            return ColorDepth::TrueColor;
+        }
+        let term = term.to_lowercase();
+        if term.contains("direct") || term.contains("truecolor") {
+            ColorDepth::TrueColor
+        } else if term.contains("256") {
+            ColorDepth::Ansi256
+        } else if term.is_empty() || term == "dumb" || term == "linux" || term.starts_with("vt") {
+            ColorDepth::Ansi16
+        } else {
+            // Most modern terminals that don't advertise anything still handle 256 colours
+            ColorDepth::Ansi256
+        }
+    }
+
+    /// Parse the `color_depth` setting; `auto` (or anything unknown) detects from the environment
+    pub fn from_setting(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "truecolor" | "24bit" => ColorDepth::TrueColor,
+            "256" => ColorDepth::Ansi256,
+            "16" => ColorDepth::Ansi16,
+            _ => Self::detect(),
+        }
+    }
+
+    /// Convert a colour to one this terminal can display
+    pub fn adapt(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::TrueColor, c) => c,
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(rgb_to_ansi256(r, g, b)),
+            (ColorDepth::Ansi256, c) => c,
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => nearest_ansi16(r, g, b),
+            (ColorDepth::Ansi16, Color::Indexed(i)) if i >= 16 => {
+                let (r, g, b) = ansi256_to_rgb(i);
+                nearest_ansi16(r, g, b)
+            }
+            (ColorDepth::Ansi16, c) => c,
+        }
+    }
+
+    /// Rewrite every colour in a rendered frame for the terminal's colour depth
+    pub fn adapt_buffer(self, buffer: &mut Buffer) {
+        if self == ColorDepth::TrueColor {
+            return;
+        }
+        for cell in buffer.content.iter_mut() {
+            cell.fg = self.adapt(cell.fg);
+            cell.bg = self.adapt(cell.bg);
+        }
+    }
+}
+
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+fn nearest_ansi16(r: u8, g: u8, b: u8) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb, (r, g, b)))
+        .map(|(c, _)| *c)
+        .unwrap_or(Color::Reset)
+}
+
+fn cube_index(v: u8) -> usize {
+    CUBE_LEVELS
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, level)| (**level as i32 - v as i32).abs())
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Nearest entry of the xterm 256-colour palette (6x6x6 cube or grayscale ramp)
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+    let cube_code = 16 + 36 * ri + 6 * gi + bi;
+
+    let avg = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_step = if avg < 8 { 0 } else { ((avg - 8) / 10).min(23) };
+    let gray_level = (8 + gray_step * 10) as u8;
+    let gray_code = 232 + gray_step as usize;
+
+    if distance((gray_level, gray_level, gray_level), (r, g, b)) < distance(cube, (r, g, b)) {
+        gray_code as u8
+    } else {
+        cube_code as u8
+    }
+}
+
+fn ansi256_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16[index as usize].1,
+        16..=231 => {
+            let i = (index - 16) as usize;
+            (CUBE_LEVELS[i / 36], CUBE_LEVELS[(i / 6) % 6], CUBE_LEVELS[i % 6])
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// Parse a colour written as `#rrggbb`, `#rgb`, a colour name or a 0-255 palette index
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        // Only hex digits, which also keeps the byte slicing below on char boundaries
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+        return match hex.len() {
+            6 => Some(Color::Rgb(channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?)),
+            3 => {
+                let expand = |s: &str| channel(s).map(|v| v * 17);
+                Some(Color::Rgb(expand(&hex[0..1])?, expand(&hex[1..2])?, expand(&hex[2..3])?))
+            }
+            _ => None,
+        };
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+    let name = value.to_lowercase().replace(['_', '-', ' '], "");
+    let color = match name.as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Write a colour in a form `parse_color` reads back
+pub fn color_to_string(color: Color) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        Color::Indexed(i) => i.to_string(),
+        Color::Reset => "reset".to_string(),
+        Color::Black => "black".to_string(),
+        Color::Red => "red".to_string(),
+        Color::Green => "green".to_string(),
+        Color::Yellow => "yellow".to_string(),
+        Color::Blue => "blue".to_string(),
+        Color::Magenta => "magenta".to_string(),
+        Color::Cyan => "cyan".to_string(),
+        Color::Gray => "gray".to_string(),
+        Color::DarkGray => "dark_gray".to_string(),
+        Color::LightRed => "light_red".to_string(),
+        Color::LightGreen => "light_green".to_string(),
+        Color::LightYellow => "light_yellow".to_string(),
+        Color::LightBlue => "light_blue".to_string(),
+        Color::LightMagenta => "light_magenta".to_string(),
+        Color::LightCyan => "light_cyan".to_string(),
+        Color::White => "white".to_string(),
+    }
+}
+
+/// The RGB value a colour is drawn with (xterm defaults for the named colours)
+pub fn color_to_rgb(color: Color) -> (u8, u8, u8) {
+    match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(i) => ansi256_to_rgb(i),
+        Color::Reset => (0, 0, 0),
+        named => ANSI16
+            .iter()
+            .find(|(c, _)| *c == named)
+            .map(|(_, rgb)| *rgb)
+            .unwrap_or((0, 0, 0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color("#fff"), Some(Color::Rgb(255, 255, 255)));
+        assert_eq!(parse_color("dark_gray"), Some(Color::DarkGray));
+        assert_eq!(parse_color("42"), Some(Color::Indexed(42)));
+        assert_eq!(parse_color("#12"), None);
+        assert_eq!(parse_color("#aé123"), None);
+        assert_eq!(parse_color("#+f+f+f"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+
+        for color in [Color::Rgb(1, 2, 3), Color::Indexed(200), Color::DarkGray, Color::LightCyan] {
+            assert_eq!(parse_color(&color_to_string(color)), Some(color));
+        }
+        assert_eq!(color_to_rgb(Color::White), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_builtin_themes() {
+        for name in BUILTIN_THEMES {
+            let theme = Theme::builtin(name).unwrap();
+            assert_eq!(theme.name, name);
+        }
+        assert!(Theme::builtin("nope").is_none());
+        assert!(Theme::dark().dark);
+        assert!(!Theme::light().dark);
+    }
+
+    #[test]
+    fn test_parse_theme_file() {
+        let contents = "# my theme\nbase = gruvbox\nheader_bg = #112233\ncell_fg = white\n";
+        let theme = Theme::parse("mine", contents).unwrap();
+        assert_eq!(theme.name, "mine");
+        assert!(theme.dark);
+        assert_eq!(theme.header_bg, Color::Rgb(0x11, 0x22, 0x33));
+        assert_eq!(theme.cell_fg, Color::White);
+        // Untouched keys come from the base theme
+        assert_eq!(theme.grid, Theme::gruvbox().grid);
+
+        assert!(Theme::parse("bad", "header_bg = nope").is_err());
+        assert!(Theme::parse("bad", "bogus = #000000").is_err());
+    }
+
+    #[test]
+    fn test_color_depth_detection() {
+        assert_eq!(ColorDepth::from_env("truecolor", "xterm-256color", ""), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env("", "xterm-256color", "Apple_Terminal"), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_env("", "linux", ""), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn test_adapt_colors() {
+        assert_eq!(ColorDepth::TrueColor.adapt(Color::Rgb(1, 2, 3)), Color::Rgb(1, 2, 3));
+        assert_eq!(ColorDepth::Ansi256.adapt(Color::Rgb(255, 0, 0)), Color::Indexed(196));
+        assert_eq!(ColorDepth::Ansi256.adapt(Color::Rgb(128, 128, 128)), Color::Indexed(244));
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Rgb(250, 5, 5)), Color::LightRed);
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Indexed(196)), Color::LightRed);
+        assert_eq!(ColorDepth::Ansi16.adapt(Color::Cyan), Color::Cyan);
+    }
+}
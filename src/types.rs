@@ -1,6 +1,8 @@
 use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, PartialEq, Default)]
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TextAlignment {
     #[default]
     Left,
@@ -8,7 +10,8 @@ pub enum TextAlignment {
     Right,
 }
 
-#[derive(Clone, Copy, PartialEq, Default)]
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum VerticalAlignment {
     #[default]
     Top,
@@ -16,7 +19,8 @@ pub enum VerticalAlignment {
     Bottom,
 }
 
-#[derive(Clone, Copy, PartialEq, Default)]
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DataType {
     #[default]
     Text,
@@ -27,7 +31,7 @@ pub enum DataType {
     Time,
 }
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Default)]
 pub struct CellStyle {
     pub fg: Option<Color>,
     pub bg: Option<Color>,
@@ -35,6 +39,24 @@ pub struct CellStyle {
     pub alignment: Option<TextAlignment>,
     pub vertical_alignment: Option<VerticalAlignment>,
     pub data_type: Option<DataType>,
+    /// Wrap text across the row height instead of clipping or overflowing
+    pub wrap: bool,
+    /// Excel number format code such as `#,##0.00` or `yyyy-mm-dd`
+    pub number_format: Option<String>,
+}
+
+impl CellStyle {
+    /// True when the style has nothing set and doesn't need to be stored
+    pub fn is_default(&self) -> bool {
+        self.fg.is_none()
+            && self.bg.is_none()
+            && !self.bold
+            && self.alignment.is_none()
+            && self.vertical_alignment.is_none()
+            && self.data_type.is_none()
+            && !self.wrap
+            && self.number_format.is_none()
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -48,17 +70,100 @@ pub enum VisualSubMode {
     VerticalAlignment,
     FontSize,
     DataType,
+    /// List of conditional formatting rules
+    Rules,
 }
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum SaveFormat {
     Csv,
     Tsv,
+    Xlsx,
+    /// xl's own format: JSON keeping styles, sizes and view settings
+    Native,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+impl SaveFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            SaveFormat::Csv => "csv",
+            SaveFormat::Tsv => "tsv",
+            SaveFormat::Xlsx => "xlsx",
+            SaveFormat::Native => "xl",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum RowColumnSelectMode {
     None,
     RowSelect,
     ColumnSelect,
 }
+
+/// What the mouse is currently dragging
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MouseDrag {
+    /// Extending the cell selection from the given anchor
+    Cells(usize, usize),
+    /// Extending a row selection from the given row
+    Rows(usize),
+    /// Extending a column selection from the given column
+    Columns(usize),
+    /// Resizing a column: (column, starting x, starting width)
+    ColumnWidth(usize, u16, u16),
+    /// Extending a formula reference from the given anchor
+    Reference(usize, usize),
+}
+
+/// What lies under a screen position in the grid
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GridHit {
+    Cell(usize, usize),
+    RowHeader(usize),
+    ColumnHeader(usize),
+    /// The right edge of a column header, used to drag the column width
+    ColumnBorder(usize),
+    Outside,
+}
+
+/// Screen positions of the rows and columns drawn by the last render, used for mouse hit-testing
+#[derive(Clone, Default)]
+pub struct GridLayout {
+    pub header_y: u16,
+    pub row_header_x: u16,
+    pub row_header_width: u16,
+    /// (row, y, height) of each drawn row
+    pub rows: Vec<(usize, u16, u16)>,
+    /// (col, x, width) of each drawn column
+    pub cols: Vec<(usize, u16, u16)>,
+}
+
+impl GridLayout {
+    pub fn hit_test(&self, x: u16, y: u16) -> GridHit {
+        let row = self
+            .rows
+            .iter()
+            .find(|(_, row_y, height)| y >= *row_y && y < row_y + height)
+            .map(|(row, _, _)| *row);
+        let col = self
+            .cols
+            .iter()
+            .find(|(_, col_x, width)| x >= *col_x && x < col_x + width)
+            .map(|(col, col_x, width)| (*col, x == col_x + width - 1));
+        let in_row_header = x >= self.row_header_x && x < self.row_header_x + self.row_header_width;
+
+        if y == self.header_y {
+            return match col {
+                Some((col, true)) => GridHit::ColumnBorder(col),
+                Some((col, false)) => GridHit::ColumnHeader(col),
+                None => GridHit::Outside,
+            };
+        }
+        match (row, col) {
+            (Some(row), Some((col, _))) => GridHit::Cell(row, col),
+            (Some(row), None) if in_row_header => GridHit::RowHeader(row),
+            _ => GridHit::Outside,
+        }
+    }
+}
@@ -2,70 +2,67 @@ use ratatui::{
     layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table, Wrap},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Wrap},
     Frame,
 };
 
-use crate::constants::{
-    CELL_BG, CELL_FG, CELL_NAME_BG, FORMULA_BAR_BG, FORMULA_BG, GRID_COLOR, HEADER_BG, HEADER_FG,
-    REF_RANGE_BG, REF_SELECTION_BG, SELECTED_BG, SELECTED_HEADER_BG, FIND_MATCH_BG,
-    DARK_CELL_BG, DARK_CELL_FG, DARK_CELL_NAME_BG, DARK_FORMULA_BAR_BG, DARK_FORMULA_BG,
-    DARK_GRID_COLOR, DARK_HEADER_BG, DARK_HEADER_FG, DARK_REF_RANGE_BG, DARK_REF_SELECTION_BG,
-    DARK_SELECTED_BG, DARK_SELECTED_HEADER_BG, DARK_FIND_MATCH_BG,
-};
+use crate::conditional::data_bar;
+use crate::shell::ShellPolicy;
 use crate::spreadsheet::Spreadsheet;
-use crate::types::{DataType, RowColumnSelectMode, SaveFormat, TextAlignment, VerticalAlignment, VisualSubMode};
+use crate::style::format_cell;
+use crate::text::{display_width, fit_number, fit_to_width, split_by_widths, wrap_text};
+use crate::types::{DataType, GridLayout, RowColumnSelectMode, SaveFormat, TextAlignment, VerticalAlignment, VisualSubMode};
 
-fn format_cell_by_type(value: &str, data_type: DataType) -> String {
-    if value.is_empty() {
-        return value.to_string();
-    }
+/// A cell's text prepared for one row of the grid
+struct CellText {
+    content: String,
+    alignment: TextAlignment,
+    numeric: bool,
+    wrap: bool,
+    /// Laid-out lines, each padded to the column's width
+    lines: Vec<String>,
+}
 
-    match data_type {
-        DataType::Text => value.to_string(),
-        DataType::Number => {
-            if let Ok(num) = value.parse::<f64>() {
-                // Format number with appropriate decimal places
-                if num.fract() == 0.0 {
-                    format!("{:.0}", num)
-                } else {
-                    format!("{}", num)
-                }
-            } else {
-                value.to_string()
-            }
-        }
-        DataType::Currency => {
-            if let Ok(num) = value.parse::<f64>() {
-                if num.fract() == 0.0 {
-                    format!("${:.0}", num)
-                } else {
-                    format!("${:.2}", num)
-                }
-            } else {
-                value.to_string()
-            }
-        }
-        DataType::Percentage => {
-            if let Ok(num) = value.parse::<f64>() {
-                format!("{:.1}%", num * 100.0)
-            } else {
-                value.to_string()
-            }
+/// Place text within a column of `width` usable characters (plus the leading gutter space)
+fn align_line(content: &str, width: usize, alignment: TextAlignment) -> String {
+    let content_width = display_width(content);
+    let line = match alignment {
+        TextAlignment::Left => format!(" {}", content),
+        TextAlignment::Center => {
+            let padding = width.saturating_sub(content_width);
+            let left_pad = padding / 2;
+            format!("{}{}", " ".repeat(left_pad), content)
         }
-        DataType::Date => {
-            // For now, just return as-is. Could add date parsing/formatting later
-            value.to_string()
+        TextAlignment::Right => {
+            format!("{}{}", " ".repeat(width.saturating_sub(content_width)), content)
         }
-        DataType::Time => {
-            // For now, just return as-is. Could add time parsing/formatting later
-            value.to_string()
-        }
-    }
+    };
+    fit_to_width(&line, width + 1)
+}
+
+/// Stack a cell's lines into the row height, padding according to the vertical alignment
+fn stack_lines(lines: &[String], width: usize, height: usize, vertical_alignment: VerticalAlignment) -> String {
+    let height = height.max(1);
+    let empty_line = " ".repeat(width);
+    let shown = lines.len().min(height);
+    let spare = height - shown;
+    let top_lines = match vertical_alignment {
+        VerticalAlignment::Top => 0,
+        VerticalAlignment::Center => spare / 2,
+        VerticalAlignment::Bottom => spare,
+    };
+
+    let mut result: Vec<&str> = Vec::with_capacity(height);
+    result.extend(std::iter::repeat_n(empty_line.as_str(), top_lines));
+    result.extend(lines.iter().take(shown).map(|line| line.as_str()));
+    result.extend(std::iter::repeat_n(empty_line.as_str(), spare - top_lines));
+    result.join("\n")
 }
 
 pub fn render(f: &mut Frame, spreadsheet: &mut Spreadsheet) {
     let area = f.area();
+    // Array formulas are worked out again each frame, as every other formula is
+    spreadsheet.refresh_spills();
 
     // Check if we have selection stats to show
     let has_stats = spreadsheet.get_selection_stats().is_some();
@@ -122,39 +119,54 @@ pub fn render(f: &mut Frame, spreadsheet: &mut Spreadsheet) {
 
     spreadsheet.adjust_scroll(grid_area);
 
-    let visible_cols = spreadsheet.visible_cols(grid_area.width);
-    let visible_rows = spreadsheet.visible_rows(area.height);
+    let display_cols = spreadsheet.displayed_cols(grid_area.width);
+    let display_rows = spreadsheet.displayed_rows(area.height);
 
     render_formula_bar(f, spreadsheet, formula_bar_area);
     if let Some(autocomplete_area) = autocomplete_area {
         render_autocomplete(f, spreadsheet, autocomplete_area);
     }
-    render_grid(f, spreadsheet, grid_area, visible_cols, visible_rows);
+    render_grid(f, spreadsheet, grid_area, &display_cols, &display_rows);
     if let Some(stats_area) = stats_area {
         render_stats_bar(f, spreadsheet, stats_area);
     }
     render_status_bar(f, spreadsheet, status_area);
+    render_filter_popup(f, spreadsheet);
+    render_rules_popup(f, spreadsheet);
+    if !spreadsheet.shell.blocked.is_empty() {
+        render_shell_banner(f, spreadsheet, grid_area);
+    }
     
     // Render update prompt as floating widget in bottom right corner
     if has_update {
         render_update_bar(f, spreadsheet, area);
     }
+
+    // Map truecolor themes down to what the terminal can show
+    spreadsheet.color_depth.adapt_buffer(f.buffer_mut());
 }
 
-fn render_formula_bar(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
-    let cell_content = spreadsheet.get_cell(spreadsheet.cursor_row, spreadsheet.cursor_col);
+fn render_formula_bar(f: &mut Frame, spreadsheet: &mut Spreadsheet, area: Rect) {
+    let (row, col) = (spreadsheet.cursor_row, spreadsheet.cursor_col);
+    // A cell filled by a spill shows, dimmed, the formula that spills into it
+    let spill_anchor = spreadsheet
+        .get_cell(row, col)
+        .is_empty()
+        .then(|| spreadsheet.spill_anchor(row, col))
+        .flatten();
+    let cell_content = spreadsheet.get_cell(row, col);
     let display_content = if spreadsheet.editing {
         spreadsheet.edit_buffer.clone()
     } else {
-        cell_content.to_string()
+        match spill_anchor {
+            Some((anchor_row, anchor_col)) => spreadsheet.get_cell(anchor_row, anchor_col).to_string(),
+            None => cell_content.to_string(),
+        }
     };
 
-    // Choose colors based on dark mode
-    let (formula_bar_bg, cell_name_bg, formula_bg, grid_color, text_fg) = if spreadsheet.dark_mode {
-        (DARK_FORMULA_BAR_BG, DARK_CELL_NAME_BG, DARK_FORMULA_BG, DARK_GRID_COLOR, DARK_CELL_FG)
-    } else {
-        (FORMULA_BAR_BG, CELL_NAME_BG, FORMULA_BG, GRID_COLOR, CELL_FG)
-    };
+    let theme = &spreadsheet.theme;
+    let (formula_bar_bg, cell_name_bg, formula_bg, grid_color, text_fg) =
+        (theme.formula_bar_bg, theme.cell_name_bg, theme.formula_bg, theme.grid, theme.cell_fg);
 
     let cell_name_width = 12;
     let formula_bar_inner = Layout::horizontal([
@@ -188,8 +200,11 @@ fn render_formula_bar(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
     } else {
         format!(" {}", display_content)
     };
-    let formula = Paragraph::new(formula_display)
-        .style(Style::default().bg(formula_bg).fg(text_fg));
+    let mut formula_style = Style::default().bg(formula_bg).fg(text_fg);
+    if spill_anchor.is_some() && !spreadsheet.editing {
+        formula_style = formula_style.add_modifier(Modifier::DIM);
+    }
+    let formula = Paragraph::new(formula_display).style(formula_style);
     f.render_widget(formula, formula_bar_inner[2]);
 }
 
@@ -198,12 +213,9 @@ fn render_autocomplete(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
         return;
     }
 
-    // Choose colors based on dark mode
-    let (bg_color, fg_color, selected_bg, grid_color) = if spreadsheet.dark_mode {
-        (DARK_FORMULA_BAR_BG, DARK_CELL_FG, DARK_SELECTED_BG, DARK_GRID_COLOR)
-    } else {
-        (FORMULA_BAR_BG, CELL_FG, SELECTED_BG, GRID_COLOR)
-    };
+    let theme = &spreadsheet.theme;
+    let (bg_color, fg_color, selected_bg, grid_color) =
+        (theme.formula_bar_bg, theme.cell_fg, theme.selected_bg, theme.grid);
 
     // Show up to 5 suggestions
     let max_items = spreadsheet.formula_suggestions.len().min(5);
@@ -258,55 +270,59 @@ fn render_grid(
     f: &mut Frame,
     spreadsheet: &mut Spreadsheet,
     area: Rect,
-    visible_cols: usize,
-    visible_rows: usize,
+    display_cols: &[usize],
+    display_rows: &[usize],
 ) {
-    // Choose colors based on dark mode
-    let (header_bg, header_fg, selected_header_bg, selected_bg, grid_color, cell_bg, cell_fg, ref_selection_bg, ref_range_bg, find_match_bg) = 
-        if spreadsheet.dark_mode {
-            (DARK_HEADER_BG, DARK_HEADER_FG, DARK_SELECTED_HEADER_BG, DARK_SELECTED_BG, 
-             DARK_GRID_COLOR, DARK_CELL_BG, DARK_CELL_FG, DARK_REF_SELECTION_BG, DARK_REF_RANGE_BG, DARK_FIND_MATCH_BG)
-        } else {
-            (HEADER_BG, HEADER_FG, SELECTED_HEADER_BG, SELECTED_BG,
-             GRID_COLOR, CELL_BG, CELL_FG, REF_SELECTION_BG, REF_RANGE_BG, FIND_MATCH_BG)
-        };
+    let theme = spreadsheet.theme.clone();
+    let (header_bg, header_fg, selected_header_bg, selected_bg, grid_color, cell_bg, cell_fg, ref_selection_bg, ref_range_bg, find_match_bg) = (
+        theme.header_bg, theme.header_fg, theme.selected_header_bg, theme.selected_bg,
+        theme.grid, theme.cell_bg, theme.cell_fg, theme.ref_selection_bg, theme.ref_range_bg, theme.find_match_bg,
+    );
 
     let mut header_cells = vec![Cell::from("").style(Style::default().bg(header_bg))];
-    for col in spreadsheet.scroll_col..spreadsheet.scroll_col + visible_cols {
-        if col < spreadsheet.num_cols {
-            let is_current_col = if spreadsheet.selecting_ref {
-                col == spreadsheet.ref_cursor_col
-            } else {
-                col == spreadsheet.cursor_col
-            };
-            let is_selected_col = if let Some((min_col, max_col)) = spreadsheet.selected_cols {
-                col >= min_col && col <= max_col
-            } else {
-                false
-            };
-            let bg = if is_current_col || is_selected_col { 
-                selected_header_bg 
-            } else { 
-                header_bg 
-            };
-            header_cells.push(
-                Cell::from(Spreadsheet::col_name(col)).style(
-                    Style::default()
-                        .bg(bg)
-                        .fg(header_fg)
-                        .add_modifier(Modifier::BOLD),
-                ),
-            );
-        }
+    for &col in display_cols {
+        let is_current_col = if spreadsheet.selecting_ref {
+            col == spreadsheet.ref_cursor_col
+        } else {
+            col == spreadsheet.cursor_col
+        };
+        let is_selected_col = if let Some((min_col, max_col)) = spreadsheet.selected_cols {
+            col >= min_col && col <= max_col
+        } else {
+            false
+        };
+        let bg = if is_current_col || is_selected_col { 
+            selected_header_bg 
+        } else { 
+            header_bg 
+        };
+        // Outline groups get a mark in front of the column letter
+        let name = if spreadsheet.col_groups.is_empty() {
+            Spreadsheet::col_name(col)
+        } else {
+            format!("{}{}", spreadsheet.col_outline_mark(col), Spreadsheet::col_name(col))
+        };
+        header_cells.push(
+            Cell::from(name).style(
+                Style::default()
+                    .bg(bg)
+                    .fg(header_fg)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        );
     }
     let header = Row::new(header_cells).height(1);
 
-    let mut rows = Vec::new();
-    for row in spreadsheet.scroll_row..spreadsheet.scroll_row + visible_rows {
-        if row >= spreadsheet.num_rows {
-            break;
-        }
+    // Range-wide figures for conditional formatting (top N, scales, bars), gathered once per frame
+    let rule_stats = spreadsheet.conditional_stats();
+    // The spill range under the cursor is highlighted like a referenced range
+    let cursor_spill = spreadsheet
+        .spill_anchor(spreadsheet.cursor_row, spreadsheet.cursor_col)
+        .and_then(|(row, col)| spreadsheet.spill_range(row, col))
+        .filter(|range| !range.is_single_cell());
 
+    let mut rows = Vec::new();
+    for &row in display_rows {
         let is_current_row = if spreadsheet.selecting_ref {
             row == spreadsheet.ref_cursor_row
         } else {
@@ -323,7 +339,12 @@ fn render_grid(
             header_bg 
         };
 
-        let mut row_cells = vec![Cell::from(format!("{}", row + 1)).style(
+        let row_label = if spreadsheet.row_groups.is_empty() {
+            format!("{}", row + 1)
+        } else {
+            format!("{}{}", spreadsheet.row_outline_mark(row), row + 1)
+        };
+        let mut row_cells = vec![Cell::from(row_label).style(
             Style::default()
                 .bg(row_header_bg)
                 .fg(header_fg)
@@ -332,33 +353,129 @@ fn render_grid(
 
         let ref_range = spreadsheet.get_ref_range();
         let selection_range = spreadsheet.get_selection_range();
+        let row_height = spreadsheet.get_row_height(row);
 
-        for col in spreadsheet.scroll_col..spreadsheet.scroll_col + visible_cols {
-            if col >= spreadsheet.num_cols {
-                break;
-            }
-
+        // First pass: evaluate and format every visible cell in the row
+        let cols = display_cols;
+        let mut texts: Vec<CellText> = Vec::with_capacity(cols.len());
+        let mut formats = Vec::with_capacity(cols.len());
+        let mut number_colors = Vec::with_capacity(cols.len());
+        for &col in cols {
             let is_cursor = row == spreadsheet.cursor_row && col == spreadsheet.cursor_col;
-            let evaluated = {
-                // Evaluate cell - this may modify the spreadsheet for SHELL formulas
-                spreadsheet.evaluate_cell(row, col)
-            };
-
+            let editing_here = is_cursor && spreadsheet.editing;
+            // Evaluate cell, including values spilled by array formulas
+            let evaluated = spreadsheet.evaluate_cell(row, col);
             let cell_style = spreadsheet.get_cell_style(row, col);
-            
-            // Format content based on data type
-            let formatted_content = if let Some(data_type) = cell_style.data_type {
-                format_cell_by_type(&evaluated, data_type)
-            } else {
-                evaluated.clone()
-            };
+            formats.push(spreadsheet.conditional_format(row, col, &evaluated, &rule_stats));
+
+            // Format content by the cell's number format or data type
+            let formatted = format_cell(&evaluated, &cell_style);
+            number_colors.push(formatted.color);
+            let formatted_content = formatted.text;
 
-            let content = if is_cursor && spreadsheet.editing {
+            let content = if editing_here {
                 format!("{}_", spreadsheet.edit_buffer)
             } else {
                 formatted_content.clone()
             };
 
+            let is_number = Spreadsheet::is_numeric(&evaluated);
+            // Determine alignment: use cell style if set, otherwise use data type default, 
+            // or fall back to number/text detection
+            let alignment = cell_style.alignment.unwrap_or_else(|| {
+                if let Some(data_type) = cell_style.data_type {
+                    match data_type {
+                        DataType::Text => TextAlignment::Left,
+                        DataType::Number | DataType::Currency | DataType::Percentage => TextAlignment::Right,
+                        _ => if is_number { TextAlignment::Right } else { TextAlignment::Left },
+                    }
+                } else {
+                    // No data type set, use number/text detection
+                    if is_number {
+                        TextAlignment::Right
+                    } else {
+                        TextAlignment::Left
+                    }
+                }
+            });
+
+            texts.push(CellText {
+                lines: Vec::new(),
+                // Numbers never spill or wrap; they shrink to fewer decimals or show ###
+                numeric: !editing_here && Spreadsheet::is_numeric(&evaluated),
+                wrap: cell_style.wrap && !editing_here,
+                content,
+                alignment,
+            });
+        }
+
+        // Second pass: lay out text, letting long left-aligned text spill into empty neighbours
+        let inner_widths: Vec<usize> = cols
+            .iter()
+            .map(|&col| spreadsheet.get_col_width(col) as usize)
+            .collect();
+        let mut spilled = vec![false; cols.len()];
+        for i in 0..cols.len() {
+            if spilled[i] {
+                continue;
+            }
+            let width = inner_widths[i].saturating_sub(1);
+            let text = &texts[i];
+            let content_width = display_width(&text.content);
+
+            if text.content.is_empty() {
+                texts[i].lines = vec![String::new()];
+            } else if text.wrap {
+                texts[i].lines = wrap_text(&text.content, width)
+                    .iter()
+                    .map(|line| align_line(line, width, text.alignment))
+                    .collect();
+            } else if text.numeric {
+                let shown = fit_number(&text.content, width);
+                texts[i].lines = vec![align_line(&shown, width, text.alignment)];
+            } else if text.alignment == TextAlignment::Left && content_width > width {
+                // Spill across following cells while they are empty
+                let mut end = i + 1;
+                let mut total = inner_widths[i];
+                while end < cols.len() && total < content_width + 1 && texts[end].content.is_empty() {
+                    total += inner_widths[end];
+                    end += 1;
+                }
+                let line = format!(" {}", text.content);
+                let pieces = split_by_widths(&line, &inner_widths[i..end]);
+                for (offset, piece) in pieces.into_iter().enumerate() {
+                    texts[i + offset].lines = vec![piece];
+                    if offset > 0 {
+                        spilled[i + offset] = true;
+                    }
+                }
+            } else {
+                texts[i].lines = vec![align_line(&text.content, width, text.alignment)];
+            }
+        }
+
+        // AutoFilter buttons on the header row, filled in once a column is filtered
+        if let Some(filter) = spreadsheet.auto_filter.as_ref().filter(|filter| filter.header_row == row) {
+            for (i, &col) in cols.iter().enumerate() {
+                if col < filter.min_col || col > filter.max_col || inner_widths[i] < 2 {
+                    continue;
+                }
+                let button = if filter.criteria.contains_key(&col) { '▼' } else { '▾' };
+                let first = texts[i].lines.first().map(String::as_str).unwrap_or("");
+                let mut line = fit_to_width(first, inner_widths[i] - 1);
+                line.push(button);
+                match texts[i].lines.first_mut() {
+                    Some(first) => *first = line,
+                    None => texts[i].lines.push(line),
+                }
+            }
+        }
+
+        for (i, &col) in cols.iter().enumerate() {
+            let is_cursor = row == spreadsheet.cursor_row && col == spreadsheet.cursor_col;
+            let cell_style = spreadsheet.get_cell_style(row, col);
+            let conditional = formats[i];
+
             let is_in_ref_range = if let Some(((min_row, min_col), (max_row, max_col))) = ref_range
             {
                 row >= min_row && row <= max_row && col >= min_col && col <= max_col
@@ -385,112 +502,25 @@ fn render_grid(
                 false
             };
 
+            let is_in_cursor_spill = cursor_spill.is_some_and(|range| {
+                row >= range.start_row && row <= range.end_row && col >= range.start_col && col <= range.end_col
+            });
+
             let is_ref_cursor = spreadsheet.selecting_ref
                 && row == spreadsheet.ref_cursor_row
                 && col == spreadsheet.ref_cursor_col;
 
-            let col_width = spreadsheet.get_col_width(col);
-            let row_height = spreadsheet.get_row_height(row);
-
-            let is_number = Spreadsheet::is_numeric(&formatted_content);
-            // Determine alignment: use cell style if set, otherwise use data type default, 
-            // or fall back to number/text detection
-            let alignment = cell_style.alignment.unwrap_or_else(|| {
-                if let Some(data_type) = cell_style.data_type {
-                    match data_type {
-                        DataType::Text => TextAlignment::Left,
-                        DataType::Number | DataType::Currency | DataType::Percentage => TextAlignment::Right,
-                        _ => if is_number { TextAlignment::Right } else { TextAlignment::Left },
-                    }
-                } else {
-                    // No data type set, use number/text detection
-                    if is_number {
-                        TextAlignment::Right
-                    } else {
-                        TextAlignment::Left
-                    }
-                }
-            });
-            
             // Determine vertical alignment: use cell style if set, otherwise default to Top
             let vertical_alignment = cell_style.vertical_alignment.unwrap_or(VerticalAlignment::Top);
-            
-            let aligned_content = if !content.is_empty() {
-                let width = (col_width - 1) as usize;
-                let horizontal_line = match alignment {
-                    TextAlignment::Left => format!(" {}", content),
-                    TextAlignment::Center => {
-                        let padding = width.saturating_sub(content.len());
-                        let left_pad = padding / 2;
-                        let right_pad = padding - left_pad;
-                        format!("{}{}{}", " ".repeat(left_pad), content, " ".repeat(right_pad))
-                    }
-                    TextAlignment::Right => format!("{:>width$}", content, width = width),
-                };
-                
-                // Apply vertical alignment by padding with empty lines
-                let empty_line = " ".repeat(width + 1); // +1 for the leading space
-                let height = row_height as usize;
-                
-                if height == 1 {
-                    horizontal_line
-                } else {
-                    match vertical_alignment {
-                        VerticalAlignment::Top => {
-                            let mut result = horizontal_line;
-                            for _ in 1..height {
-                                result.push('\n');
-                                result.push_str(&empty_line);
-                            }
-                            result
-                        }
-                        VerticalAlignment::Center => {
-                            let top_lines = (height - 1) / 2;
-                            let bottom_lines = height - 1 - top_lines;
-                            let mut result = String::new();
-                            for _ in 0..top_lines {
-                                result.push_str(&empty_line);
-                                result.push('\n');
-                            }
-                            result.push_str(&horizontal_line);
-                            for _ in 0..bottom_lines {
-                                result.push('\n');
-                                result.push_str(&empty_line);
-                            }
-                            result
-                        }
-                        VerticalAlignment::Bottom => {
-                            let mut result = String::new();
-                            for _ in 1..height {
-                                result.push_str(&empty_line);
-                                result.push('\n');
-                            }
-                            result.push_str(&horizontal_line);
-                            result
-                        }
-                    }
-                }
-            } else {
-                // Empty content - still need to pad for vertical alignment if height > 1
-                let empty_line = " ".repeat((col_width - 1) as usize + 1);
-                let height = row_height as usize;
-                
-                if height == 1 {
-                    format!(" {}", content)
-                } else {
-                    let mut result = String::new();
-                    for i in 0..height {
-                        if i > 0 {
-                            result.push('\n');
-                        }
-                        result.push_str(&empty_line);
-                    }
-                    result
-                }
-            };
+            let aligned_content = stack_lines(
+                &texts[i].lines,
+                inner_widths[i],
+                row_height as usize,
+                vertical_alignment,
+            );
 
             // Use explicit foreground color if set, otherwise default based on dark mode
-            let fg_color = cell_style.fg.unwrap_or(cell_fg);
+            let fg_color = conditional.fg.or(number_colors[i]).or(cell_style.fg).unwrap_or(cell_fg);
             
             // Check if this cell is a find match (but not the cursor)
             let is_find_match = spreadsheet.is_find_match(row, col);
@@ -503,7 +533,7 @@ fn render_grid(
                 Style::default()
                     .bg(ref_selection_bg)
                     .fg(fg_color)
-            } else if is_in_ref_range {
+            } else if is_in_ref_range || is_in_cursor_spill {
                 Style::default()
                     .bg(ref_range_bg)
                     .fg(fg_color)
@@ -517,36 +547,82 @@ fn render_grid(
                     .bg(selected_bg)
                     .fg(fg_color)
             } else if is_find_match {
-                // Highlight find matches with light yellow background
+                // Highlight find matches with the theme's find colours
                 Style::default()
                     .bg(find_match_bg)
-                    .fg(Color::Black) // Use black text for visibility on yellow
+                    .fg(theme.find_match_fg)
             } else {
                 Style::default()
-                    .bg(cell_style.bg.unwrap_or(cell_bg))
+                    .bg(conditional.bg.or(cell_style.bg).unwrap_or(cell_bg))
                     .fg(fg_color)
             };
             
             // Apply bold modifier from cell style
-            if cell_style.bold {
+            if cell_style.bold || conditional.bold {
                 style = style.add_modifier(Modifier::BOLD);
             }
             // Cursor and ref cursor are always bold for visibility
             if is_cursor || is_ref_cursor {
                 style = style.add_modifier(Modifier::BOLD);
             }
+            // Underline the last frozen row to mark where the scrolling region starts
+            if row + 1 == spreadsheet.frozen_rows {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+
+            // Data bars draw block characters in the bar colour, with the value after them
+            let editing_here = is_cursor && spreadsheet.editing;
+            if let Some((fraction, bar_color)) = conditional.bar.filter(|_| !editing_here) {
+                let (bar, rest) = data_bar(fraction, texts[i].content.trim(), inner_widths[i]);
+                let mut lines = vec![Line::from(vec![
+                    Span::styled(bar, Style::default().fg(bar_color)),
+                    Span::raw(rest),
+                ])];
+                lines.resize(row_height.max(1) as usize, Line::from(""));
+                row_cells.push(Cell::from(Text::from(lines)).style(style));
+                continue;
+            }
 
             row_cells.push(Cell::from(aligned_content).style(style));
         }
-        let row_height = spreadsheet.get_row_height(row);
         rows.push(Row::new(row_cells).height(row_height));
     }
 
-    let mut widths = vec![Constraint::Length(5)];
-    for col in spreadsheet.scroll_col..spreadsheet.scroll_col + visible_cols {
+    let header_width = spreadsheet.row_header_width();
+    let mut widths = vec![Constraint::Length(header_width)];
+    for &col in display_cols {
         widths.push(Constraint::Length(spreadsheet.get_col_width(col)));
     }
 
+    // Remember where everything was drawn so mouse events can be mapped back to cells
+    let inner_bottom = area.y + area.height.saturating_sub(1);
+    let inner_right = area.x + area.width.saturating_sub(1);
+    let mut layout = GridLayout {
+        header_y: area.y + 1,
+        row_header_x: area.x + 1,
+        row_header_width: header_width,
+        ..GridLayout::default()
+    };
+    let mut y = area.y + 2;
+    for &row in display_rows {
+        if y >= inner_bottom {
+            break;
+        }
+        let height = spreadsheet.get_row_height(row).min(inner_bottom - y);
+        layout.rows.push((row, y, height));
+        y += height;
+    }
+    let mut x = area.x + 1 + header_width;
+    for &col in display_cols {
+        if x >= inner_right {
+            break;
+        }
+        let width = spreadsheet.get_col_width(col).min(inner_right - x);
+        layout.cols.push((col, x, width));
+        x += width;
+    }
+    spreadsheet.grid_layout = layout;
+
     let table = Table::new(rows, &widths)
         .header(header)
         .column_spacing(0)
@@ -592,14 +668,19 @@ fn render_stats_bar(f: &mut Frame, spreadsheet: &mut Spreadsheet, area: Rect) {
 
         let stats_line = Line::from(spans);
         f.render_widget(
-            Paragraph::new(stats_line).style(Style::default().bg(Color::Rgb(35, 35, 35))),
+            Paragraph::new(stats_line).style(Style::default().bg(spreadsheet.theme.stats_bar_bg)),
             area,
         );
     }
 }
 
 fn render_status_bar(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
-    let (mode, mode_style) = if spreadsheet.command_mode {
+    let (mode, mode_style) = if spreadsheet.filter_popup.is_some() {
+        (
+            " FILTER ",
+            Style::default().bg(Color::Rgb(0, 128, 128)).fg(Color::White),
+        )
+    } else if spreadsheet.command_mode {
         (
             " COMMAND ",
             Style::default().bg(Color::Rgb(138, 43, 226)).fg(Color::White),
@@ -651,7 +732,9 @@ fn render_status_bar(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
         )
     };
 
-    let status = if spreadsheet.command_mode {
+    let status = if spreadsheet.filter_popup.is_some() {
+        render_filter_status(mode, mode_style)
+    } else if spreadsheet.command_mode {
         render_command_status(spreadsheet, mode, mode_style)
     } else if spreadsheet.open_mode {
         render_open_status(spreadsheet, mode, mode_style)
@@ -672,7 +755,151 @@ fn render_status_bar(f: &mut Frame, spreadsheet: &Spreadsheet, area: Rect) {
     };
 
     f.render_widget(
-        Paragraph::new(status).style(Style::default().bg(Color::Rgb(45, 45, 45))),
+        Paragraph::new(status).style(Style::default().bg(spreadsheet.theme.status_bar_bg)),
+        area,
+    );
+}
+
+fn render_filter_status<'a>(mode: &'a str, mode_style: Style) -> Line<'a> {
+    Line::from(vec![
+        Span::styled(mode, mode_style),
+        Span::styled("  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("j/k", Style::default().fg(Color::Yellow)),
+        Span::styled(" Move  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("Space", Style::default().fg(Color::Yellow)),
+        Span::styled(" Toggle  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("a", Style::default().fg(Color::Yellow)),
+        Span::styled(" All/None  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("Enter", Style::default().fg(Color::Yellow)),
+        Span::styled(" Apply  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("Esc", Style::default().fg(Color::Yellow)),
+        Span::styled(" Cancel", Style::default().fg(Color::DarkGray)),
+    ])
+}
+
+/// The conditional formatting rules listed by Visual mode's `o`
+fn render_rules_popup(f: &mut Frame, spreadsheet: &Spreadsheet) {
+    if !spreadsheet.visual_mode || spreadsheet.visual_sub_mode != VisualSubMode::Rules {
+        return;
+    }
+    let theme = &spreadsheet.theme;
+    let frame = f.area();
+    let labels: Vec<String> = if spreadsheet.conditional_rules.is_empty() {
+        vec!["No rules - press n to add one".to_string()]
+    } else {
+        spreadsheet
+            .conditional_rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| format!("{}. {}", i + 1, rule.to_spec()))
+            .collect()
+    };
+    let widest = labels.iter().map(|label| display_width(label)).max().unwrap_or(0);
+    let width = (widest as u16 + 4).clamp(30, 70).min(frame.width);
+    let shown = labels.len().clamp(1, 10);
+    let height = (shown as u16 + 2).min(frame.height);
+    let area = Rect {
+        x: frame.width.saturating_sub(width) / 2,
+        y: (spreadsheet.grid_layout.header_y + 1).min(frame.height.saturating_sub(height)),
+        width,
+        height,
+    };
+
+    let start = spreadsheet.rule_index.saturating_sub(shown - 1);
+    let lines: Vec<Line> = labels
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(shown)
+        .map(|(i, label)| {
+            let selected = i == spreadsheet.rule_index && !spreadsheet.conditional_rules.is_empty();
+            let bg = if selected { theme.selected_bg } else { theme.cell_bg };
+            Line::from(Span::styled(
+                fit_to_width(label, width.saturating_sub(2) as usize),
+                Style::default().bg(bg).fg(theme.cell_fg),
+            ))
+        })
+        .collect();
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Conditional formatting ")
+                .border_style(Style::default().fg(theme.grid))
+                .style(Style::default().bg(theme.cell_bg)),
+        ),
+        area,
+    );
+}
+
+/// Checklist of a column's values, dropped down below its AutoFilter button
+fn render_filter_popup(f: &mut Frame, spreadsheet: &Spreadsheet) {
+    let Some(popup) = spreadsheet.filter_popup.as_ref() else {
+        return;
+    };
+    let theme = &spreadsheet.theme;
+    let frame = f.area();
+    let layout = &spreadsheet.grid_layout;
+
+    let labels: Vec<String> = popup
+        .items
+        .iter()
+        .map(|(value, checked)| {
+            let label = if value.is_empty() { "(Blanks)" } else { value.as_str() };
+            format!("[{}] {}", if *checked { 'x' } else { ' ' }, label)
+        })
+        .collect();
+    let widest = labels.iter().map(|label| display_width(label)).max().unwrap_or(0);
+    let width = (widest as u16 + 4).clamp(16, 40).min(frame.width);
+    let shown = popup.items.len().clamp(1, 10);
+    let height = (shown as u16 + 2).min(frame.height);
+
+    let header_row = spreadsheet.auto_filter.as_ref().map(|filter| filter.header_row);
+    let x = layout
+        .cols
+        .iter()
+        .find(|(col, _, _)| *col == popup.col)
+        .map(|(_, x, _)| *x)
+        .unwrap_or(layout.row_header_x);
+    let y = layout
+        .rows
+        .iter()
+        .find(|(row, _, _)| Some(*row) == header_row)
+        .map(|(_, y, h)| y + h)
+        .unwrap_or(layout.header_y + 1);
+    let area = Rect {
+        x: x.min(frame.width.saturating_sub(width)),
+        y: y.min(frame.height.saturating_sub(height)),
+        width,
+        height,
+    };
+
+    let start = popup.index.saturating_sub(shown - 1);
+    let lines: Vec<Line> = labels
+        .iter()
+        .enumerate()
+        .skip(start)
+        .take(shown)
+        .map(|(i, label)| {
+            let bg = if i == popup.index { theme.selected_bg } else { theme.cell_bg };
+            Line::from(Span::styled(
+                fit_to_width(label, width.saturating_sub(2) as usize),
+                Style::default().bg(bg).fg(theme.cell_fg),
+            ))
+        })
+        .collect();
+
+    f.render_widget(Clear, area);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Filter {} ", Spreadsheet::col_name(popup.col)))
+                .border_style(Style::default().fg(theme.grid))
+                .style(Style::default().bg(theme.cell_bg)),
+        ),
         area,
     );
 }
@@ -751,43 +978,41 @@ fn render_open_status<'a>(spreadsheet: &Spreadsheet, mode: &'a str, mode_style:
 
 fn render_save_status<'a>(spreadsheet: &Spreadsheet, mode: &'a str, mode_style: Style) -> Line<'a> {
     let msg = spreadsheet.save_message.as_deref().unwrap_or("");
-    let ext = match spreadsheet.save_format {
-        SaveFormat::Csv => ".csv",
-        SaveFormat::Tsv => ".tsv",
-    };
-    Line::from(vec![
+    let mut spans = vec![
         Span::styled(mode, mode_style),
         Span::styled("  File: ", Style::default().fg(Color::DarkGray)),
         Span::styled(
             format!("{}_", spreadsheet.save_filename),
             Style::default().fg(Color::White),
         ),
-        Span::styled(ext, Style::default().fg(Color::Cyan)),
-        Span::styled("  ", Style::default().fg(Color::DarkGray)),
-        Span::styled("1", Style::default().fg(Color::Yellow)),
         Span::styled(
-            if spreadsheet.save_format == SaveFormat::Csv {
-                "-CSV* "
-            } else {
-                "-CSV "
-            },
-            Style::default().fg(Color::DarkGray),
+            format!(".{}", spreadsheet.save_format.extension()),
+            Style::default().fg(Color::Cyan),
         ),
-        Span::styled("2", Style::default().fg(Color::Yellow)),
-        Span::styled(
-            if spreadsheet.save_format == SaveFormat::Tsv {
-                "-TSV* "
-            } else {
-                "-TSV "
-            },
+        Span::styled("  ", Style::default().fg(Color::DarkGray)),
+    ];
+    let formats = [
+        ("1", SaveFormat::Csv, "CSV"),
+        ("2", SaveFormat::Tsv, "TSV"),
+        ("3", SaveFormat::Xlsx, "XLSX"),
+        ("4", SaveFormat::Native, "XL"),
+    ];
+    for (key, format, label) in formats {
+        let marker = if spreadsheet.save_format == format { "*" } else { "" };
+        spans.push(Span::styled(key, Style::default().fg(Color::Yellow)));
+        spans.push(Span::styled(
+            format!("-{}{} ", label, marker),
             Style::default().fg(Color::DarkGray),
-        ),
+        ));
+    }
+    spans.extend([
         Span::styled("Enter", Style::default().fg(Color::White)),
         Span::styled("-Save ", Style::default().fg(Color::DarkGray)),
         Span::styled("Esc", Style::default().fg(Color::White)),
         Span::styled("-Cancel ", Style::default().fg(Color::DarkGray)),
         Span::styled(msg.to_string(), Style::default().fg(Color::Green)),
-    ])
+    ]);
+    Line::from(spans)
 }
 
 fn render_visual_status<'a>(
@@ -797,7 +1022,7 @@ fn render_visual_status<'a>(
 ) -> Line<'a> {
     match spreadsheet.visual_sub_mode {
         VisualSubMode::Main => {
-            let mode_label = if spreadsheet.dark_mode { "Dark" } else { "Light" };
+            let mode_label = if spreadsheet.theme.dark { "Dark" } else { "Light" };
             Line::from(vec![
                 Span::styled(mode, mode_style),
                 Span::styled("  f", Style::default().fg(Color::White)),
@@ -816,8 +1041,12 @@ fn render_visual_status<'a>(
                 Span::styled(" Height  ", Style::default().fg(Color::DarkGray)),
                 Span::styled("t", Style::default().fg(Color::White)),
                 Span::styled(" Type  ", Style::default().fg(Color::DarkGray)),
+                Span::styled("r", Style::default().fg(Color::White)),
+                Span::styled(" Wrap  ", Style::default().fg(Color::DarkGray)),
                 Span::styled("c", Style::default().fg(Color::White)),
                 Span::styled(" Clear  ", Style::default().fg(Color::DarkGray)),
+                Span::styled("o", Style::default().fg(Color::White)),
+                Span::styled(" Rules  ", Style::default().fg(Color::DarkGray)),
                 Span::styled("m", Style::default().fg(Color::White)),
                 Span::styled(format!(" {} ", mode_label), Style::default().fg(Color::DarkGray)),
                 Span::styled("Esc", Style::default().fg(Color::White)),
@@ -854,6 +1083,8 @@ fn render_visual_status<'a>(
             Span::styled("  Column Width: ", Style::default().fg(Color::DarkGray)),
             Span::styled("←→", Style::default().fg(Color::White)),
             Span::styled(" Adjust  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("a", Style::default().fg(Color::White)),
+            Span::styled(" Auto-fit  ", Style::default().fg(Color::DarkGray)),
             Span::styled(
                 format!("Current: {} ", spreadsheet.get_col_width(spreadsheet.cursor_col)),
                 Style::default().fg(Color::White),
@@ -866,6 +1097,8 @@ fn render_visual_status<'a>(
             Span::styled("  Row Height: ", Style::default().fg(Color::DarkGray)),
             Span::styled("↑↓", Style::default().fg(Color::White)),
             Span::styled(" Adjust  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("a", Style::default().fg(Color::White)),
+            Span::styled(" Auto-fit  ", Style::default().fg(Color::DarkGray)),
             Span::styled(
                 format!("Current: {} ", spreadsheet.get_row_height(spreadsheet.cursor_row)),
                 Style::default().fg(Color::White),
@@ -931,6 +1164,20 @@ fn render_visual_status<'a>(
             Span::styled("Esc", Style::default().fg(Color::White)),
             Span::styled(" Back", Style::default().fg(Color::DarkGray)),
         ]),
+        VisualSubMode::Rules => Line::from(vec![
+            Span::styled(mode, mode_style),
+            Span::styled("  Rules: ", Style::default().fg(Color::DarkGray)),
+            Span::styled("j/k", Style::default().fg(Color::White)),
+            Span::styled(" Move  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("e", Style::default().fg(Color::White)),
+            Span::styled(" Edit  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("d", Style::default().fg(Color::White)),
+            Span::styled(" Delete  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("n", Style::default().fg(Color::White)),
+            Span::styled(" New  ", Style::default().fg(Color::DarkGray)),
+            Span::styled("Esc", Style::default().fg(Color::White)),
+            Span::styled(" Back", Style::default().fg(Color::DarkGray)),
+        ]),
     }
 }
 
@@ -972,6 +1219,12 @@ fn render_row_select_status<'a>(
         Span::styled(" Delete  ", Style::default().fg(Color::DarkGray)),
         Span::styled("i", Style::default().fg(Color::White)),
         Span::styled(" Insert  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("h/u", Style::default().fg(Color::White)),
+        Span::styled(" Hide/Unhide  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("g/G", Style::default().fg(Color::White)),
+        Span::styled(" Group/Ungroup  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("zc/zo", Style::default().fg(Color::White)),
+        Span::styled(" Fold/Unfold  ", Style::default().fg(Color::DarkGray)),
         Span::styled("Esc", Style::default().fg(Color::White)),
         Span::styled(" Exit", Style::default().fg(Color::DarkGray)),
     ])
@@ -1004,6 +1257,12 @@ fn render_column_select_status<'a>(
         Span::styled(" Delete  ", Style::default().fg(Color::DarkGray)),
         Span::styled("i", Style::default().fg(Color::White)),
         Span::styled(" Insert  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("h/u", Style::default().fg(Color::White)),
+        Span::styled(" Hide/Unhide  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("g/G", Style::default().fg(Color::White)),
+        Span::styled(" Group/Ungroup  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("zc/zo", Style::default().fg(Color::White)),
+        Span::styled(" Fold/Unfold  ", Style::default().fg(Color::DarkGray)),
         Span::styled("Esc", Style::default().fg(Color::White)),
         Span::styled(" Exit", Style::default().fg(Color::DarkGray)),
     ])
@@ -1134,3 +1393,32 @@ fn render_update_bar(f: &mut Frame, spreadsheet: &Spreadsheet, terminal_area: Re
         inner_area,
     );
 }
+
+/// Banner across the top of the grid listing the SHELL commands the opened file may not run
+fn render_shell_banner(f: &mut Frame, spreadsheet: &Spreadsheet, grid_area: Rect) {
+    let key = |key: &'static str| Span::styled(key, Style::default().fg(Color::Yellow));
+    let hint = |hint: &'static str| Span::styled(hint, Style::default().fg(Color::DarkGray));
+    let mut spans = vec![
+        Span::styled(" SHELL ", Style::default().bg(Color::Rgb(190, 110, 0)).fg(Color::White)),
+        Span::styled(
+            format!("  Commands not run: {}  ", spreadsheet.shell.blocked.join(", ")),
+            Style::default().fg(Color::Rgb(255, 190, 90)),
+        ),
+    ];
+    if spreadsheet.shell.policy == ShellPolicy::Prompt {
+        spans.extend([key(":trust"), hint(" Run them  ")]);
+    }
+    spans.extend([key(":shell hide"), hint(" Dismiss")]);
+
+    let banner_area = Rect { height: 3.min(grid_area.height), ..grid_area };
+    f.render_widget(Clear, banner_area);
+    f.render_widget(
+        Paragraph::new(Line::from(spans)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Rgb(190, 110, 0)))
+                .style(Style::default().bg(Color::Rgb(45, 35, 25))),
+        ),
+        banner_area,
+    );
+}
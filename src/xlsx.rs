@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use rust_xlsxwriter::{Format, FormatAlign, Formula, Workbook};
+
+use crate::number_format::builtin_format;
+use crate::spreadsheet::Spreadsheet;
+use crate::style::data_type_format;
+use crate::theme::color_to_rgb;
+use crate::types::{CellStyle, TextAlignment, VerticalAlignment};
+
+/// Points per terminal line when exporting row heights
+const POINTS_PER_LINE: f64 = 15.0;
+
+fn xlsx_error(e: rust_xlsxwriter::XlsxError) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn xlsx_color(color: ratatui::style::Color) -> rust_xlsxwriter::Color {
+    let (r, g, b) = color_to_rgb(color);
+    rust_xlsxwriter::Color::RGB(((r as u32) << 16) | ((g as u32) << 8) | b as u32)
+}
+
+fn xlsx_format(style: &CellStyle) -> Format {
+    let mut format = Format::new();
+    if style.bold {
+        format = format.set_bold();
+    }
+    if let Some(fg) = style.fg {
+        format = format.set_font_color(xlsx_color(fg));
+    }
+    if let Some(bg) = style.bg {
+        format = format.set_background_color(xlsx_color(bg));
+    }
+    if let Some(alignment) = style.alignment {
+        format = format.set_align(match alignment {
+            TextAlignment::Left => FormatAlign::Left,
+            TextAlignment::Center => FormatAlign::Center,
+            TextAlignment::Right => FormatAlign::Right,
+        });
+    }
+    if let Some(vertical_alignment) = style.vertical_alignment {
+        format = format.set_align(match vertical_alignment {
+            VerticalAlignment::Top => FormatAlign::Top,
+            VerticalAlignment::Center => FormatAlign::VerticalCenter,
+            VerticalAlignment::Bottom => FormatAlign::Bottom,
+        });
+    }
+    if style.wrap {
+        format = format.set_text_wrap();
+    }
+    let number_format = style
+        .number_format
+        .as_deref()
+        .or_else(|| style.data_type.and_then(data_type_format));
+    if let Some(code) = number_format {
+        format = format.set_num_format(code);
+    }
+    format
+}
+
+fn read_zip_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> io::Result<String> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// The unescaped value of an XML attribute
+fn attribute(element: &BytesStart, reader: &Reader<&[u8]>, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .and_then(|attr| attr.decode_and_unescape_value(reader).ok())
+        .map(|value| value.into_owned())
+}
+
+/// Call `visit` with every start or empty element in a document, and whether it was empty
+fn for_each_element(xml: &str, mut visit: impl FnMut(&BytesStart, &Reader<&[u8]>, bool)) -> io::Result<()> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => visit(&element, &reader, false),
+            Ok(Event::Empty(element)) => visit(&element, &reader, true),
+            Ok(Event::Eof) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        }
+    }
+}
+
+/// Number format codes of the first worksheet's cells, which calamine doesn't expose.
+/// Cells left as General are skipped.
+pub fn read_number_formats(path: &Path) -> io::Result<HashMap<(usize, usize), String>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    // workbook.xml names the sheets in order; its rels file maps them to parts
+    let mut first_sheet = None;
+    for_each_element(&read_zip_entry(&mut archive, "xl/workbook.xml")?, |element, reader, _| {
+        if element.local_name().as_ref() == b"sheet" && first_sheet.is_none() {
+            first_sheet = attribute(element, reader, b"id");
+        }
+    })?;
+    let mut sheet_path = String::from("xl/worksheets/sheet1.xml");
+    if let Ok(rels) = read_zip_entry(&mut archive, "xl/_rels/workbook.xml.rels") {
+        for_each_element(&rels, |element, reader, _| {
+            if attribute(element, reader, b"Id") == first_sheet
+                && let Some(target) = attribute(element, reader, b"Target")
+            {
+                sheet_path = match target.strip_prefix('/') {
+                    Some(absolute) => absolute.to_string(),
+                    None => format!("xl/{}", target),
+                };
+            }
+        })?;
+    }
+
+    // styles.xml: custom codes by id, then the format id of each cell style (xf)
+    let mut codes: HashMap<u32, String> = HashMap::new();
+    let mut xf_formats: Vec<u32> = Vec::new();
+    if let Ok(styles) = read_zip_entry(&mut archive, "xl/styles.xml") {
+        let mut in_cell_xfs = false;
+        for_each_element(&styles, |element, reader, empty| match element.local_name().as_ref() {
+            b"numFmt" => {
+                let id = attribute(element, reader, b"numFmtId").and_then(|id| id.parse().ok());
+                if let (Some(id), Some(code)) = (id, attribute(element, reader, b"formatCode")) {
+                    codes.insert(id, code);
+                }
+            }
+            b"cellXfs" => in_cell_xfs = !empty,
+            b"cellStyles" | b"dxfs" => in_cell_xfs = false,
+            b"xf" if in_cell_xfs => {
+                let id = attribute(element, reader, b"numFmtId").and_then(|id| id.parse().ok());
+                xf_formats.push(id.unwrap_or(0));
+            }
+            _ => {}
+        })?;
+    }
+
+    let mut formats = HashMap::new();
+    for_each_element(&read_zip_entry(&mut archive, &sheet_path)?, |element, reader, _| {
+        if element.local_name().as_ref() != b"c" {
+            return;
+        }
+        let position = attribute(element, reader, b"r").and_then(|r| Spreadsheet::parse_cell_reference(&r));
+        let xf: Option<usize> = attribute(element, reader, b"s").and_then(|s| s.parse().ok());
+        let Some((position, id)) = position.zip(xf.and_then(|xf| xf_formats.get(xf).copied())) else {
+            return;
+        };
+        let code = codes.get(&id).map(String::as_str).or_else(|| builtin_format(id));
+        if let Some(code) = code.filter(|code| !code.eq_ignore_ascii_case("general")) {
+            formats.insert(position, code.to_string());
+        }
+    })?;
+    Ok(formats)
+}
+
+impl Spreadsheet {
+    /// Export the sheet as an Excel workbook, keeping formulas (with their current
+    /// results cached), styles, column widths, row heights and freeze panes
+    pub fn write_xlsx(&mut self, filename: &str) -> io::Result<()> {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+
+        let mut positions: Vec<(usize, usize)> = self
+            .cells
+            .keys()
+            .chain(self.cell_styles.keys())
+            .copied()
+            .collect();
+        positions.sort();
+        positions.dedup();
+
+        for (row, col) in positions {
+            let format = xlsx_format(&self.get_cell_style(row, col));
+            let (xrow, xcol) = (row as u32, col as u16);
+            let content = self.get_cell(row, col).to_string();
+            if content.is_empty() {
+                worksheet.write_blank(xrow, xcol, &format).map_err(xlsx_error)?;
+            } else if content.starts_with('=') {
+                let result = self.evaluate_cell(row, col);
+                let formula = Formula::new(self.to_excel_formula(&content)).set_result(result);
+                worksheet
+                    .write_formula_with_format(xrow, xcol, formula, &format)
+                    .map_err(xlsx_error)?;
+            } else if let Ok(number) = content.parse::<f64>() {
+                worksheet
+                    .write_number_with_format(xrow, xcol, number, &format)
+                    .map_err(xlsx_error)?;
+            } else {
+                worksheet
+                    .write_string_with_format(xrow, xcol, &content, &format)
+                    .map_err(xlsx_error)?;
+            }
+        }
+
+        for (&col, &width) in &self.col_widths {
+            worksheet.set_column_width(col as u16, width).map_err(xlsx_error)?;
+        }
+        for (&row, &height) in &self.row_heights {
+            worksheet
+                .set_row_height(row as u32, height as f64 * POINTS_PER_LINE)
+                .map_err(xlsx_error)?;
+        }
+        // Outline levels can't be written, so folded groups export as plain hidden rows/columns
+        let last_row = self.get_data_bounds().0.max(self.hidden_rows.last().copied().unwrap_or(0));
+        let last_row = self.row_groups.iter().map(|group| group.end).fold(last_row, usize::max);
+        for row in (0..=last_row).filter(|&row| self.is_row_hidden_by_user(row)) {
+            worksheet.set_row_hidden(row as u32).map_err(xlsx_error)?;
+        }
+        for col in (0..self.num_cols).filter(|&col| self.is_col_hidden(col)) {
+            worksheet.set_column_hidden(col as u16).map_err(xlsx_error)?;
+        }
+        if self.frozen_rows > 0 || self.frozen_cols > 0 {
+            // Excel counts the frozen rows and columns from the view's top-left cell
+            let (top_row, left_col) = (self.frozen_top_row.min(self.frozen_rows), self.frozen_left_col.min(self.frozen_cols));
+            worksheet
+                .set_top_left_cell(top_row as u32, left_col as u16)
+                .and_then(|worksheet| {
+                    worksheet.set_freeze_panes((self.frozen_rows - top_row) as u32, (self.frozen_cols - left_col) as u16)
+                })
+                .and_then(|worksheet| worksheet.set_freeze_panes_top_cell(self.frozen_rows as u32, self.frozen_cols as u16))
+                .map_err(xlsx_error)?;
+        }
+        let sheet_name = worksheet.name();
+
+        // Names of ranges get the sheet name and $ signs Excel expects
+        for (name, refers_to) in &self.defined_names {
+            let formula = match self.parse_range(refers_to) {
+                Some(range) => {
+                    let corner = |row: usize, col: usize| format!("${}${}", Self::col_name(col), row + 1);
+                    let mut formula = format!("={}!{}", sheet_name, corner(range.start_row, range.start_col));
+                    if !range.is_single_cell() {
+                        formula.push_str(&format!(":{}", corner(range.end_row, range.end_col)));
+                    }
+                    formula
+                }
+                None => format!("={}", self.to_excel_formula(refers_to)),
+            };
+            workbook.define_name(name, &formula).map_err(xlsx_error)?;
+        }
+
+        workbook.save(filename).map_err(xlsx_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{open_workbook, Data, Reader, Xlsx};
+
+    #[test]
+    fn test_write_xlsx() {
+        let mut sheet = Spreadsheet::new();
+        sheet.set_cell(0, 0, "Item".to_string());
+        sheet.set_cell(1, 0, "4".to_string());
+        sheet.set_cell(2, 0, "=A2*2".to_string());
+        sheet.set_cell_bold(0, 0, true);
+        sheet.set_cell_number_format(1, 0, Some("[Red]-#,##0.00".to_string()));
+        sheet.set_cell_number_format(2, 0, Some("0.00E+00".to_string()));
+        sheet.frozen_rows = 1;
+        sheet.name_command("Base A2").unwrap();
+        sheet.name_command("Double LAMBDA(x, x*2)").unwrap();
+
+        let path = std::env::temp_dir().join(format!("xl_export_{}.xlsx", std::process::id()));
+        let filename = path.to_str().unwrap().to_string();
+        sheet.write_xlsx(&filename).unwrap();
+
+        let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+        let range = workbook.worksheet_range("Sheet1").unwrap();
+        assert_eq!(range.get_value((0, 0)), Some(&Data::String("Item".to_string())));
+        assert_eq!(range.get_value((1, 0)), Some(&Data::Float(4.0)));
+        let formulas = workbook.worksheet_formula("Sheet1").unwrap();
+        assert_eq!(formulas.get_value((2, 0)).map(String::as_str), Some("A2*2"));
+        let names = workbook.defined_names().to_vec();
+        assert!(names.contains(&("Base".to_string(), "Sheet1!$A$2".to_string())));
+        assert!(names.contains(&("Double".to_string(), "_xlfn.LAMBDA(_xlpm.x, _xlpm.x*2)".to_string())));
+
+        let mut loaded = Spreadsheet::new();
+        loaded.load_from_file(&filename).unwrap();
+        assert_eq!(loaded.defined_name("base"), Some("$A$2"));
+        assert_eq!(loaded.defined_name("Double"), Some("LAMBDA(x, x*2)"));
+
+        let formats = read_number_formats(&path).unwrap();
+        assert_eq!(formats.get(&(1, 0)).map(String::as_str), Some("[Red]-#,##0.00"));
+        assert_eq!(formats.get(&(2, 0)).map(String::as_str), Some("0.00E+00"));
+        assert_eq!(formats.get(&(0, 0)), None);
+        std::fs::remove_file(&path).ok();
+    }
+}